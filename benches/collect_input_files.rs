@@ -0,0 +1,67 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::path::{Path, PathBuf};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const DIRECTORY_COUNT: usize = 50;
+const FILES_PER_DIRECTORY: usize = 100;
+
+/// Create a git repository with lots of files in a temporary directory
+fn setup_repository() -> PathBuf {
+    let base_dir = std::env::temp_dir().join(format!("beautytips-bench-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&base_dir);
+
+    for d in 0..DIRECTORY_COUNT {
+        let directory = base_dir.join(format!("dir{d}")).join("sub");
+        std::fs::create_dir_all(&directory).unwrap();
+        for f in 0..FILES_PER_DIRECTORY {
+            std::fs::write(directory.join(format!("file{f}.txt")), "content").unwrap();
+        }
+    }
+
+    std::process::Command::new("git")
+        .args(["init", "--quiet"])
+        .current_dir(&base_dir)
+        .status()
+        .unwrap();
+
+    base_dir
+}
+
+fn collect(base_dir: &Path, inputs: beautytips::InputFiles) {
+    let (_, files) = beautytips::collect_input_files(base_dir.to_path_buf(), inputs).unwrap();
+    assert_eq!(files.len(), DIRECTORY_COUNT * FILES_PER_DIRECTORY);
+}
+
+fn bench_collect_input_files(c: &mut Criterion) {
+    let base_dir = setup_repository();
+    let file_list = (0..DIRECTORY_COUNT)
+        .flat_map(|d| {
+            (0..FILES_PER_DIRECTORY).map(move |f| PathBuf::from(format!("dir{d}/sub/file{f}.txt")))
+        })
+        .collect::<Vec<_>>();
+
+    c.bench_function("collect all files", |b| {
+        b.iter(|| {
+            collect(
+                &base_dir,
+                beautytips::InputFiles::AllFiles(base_dir.clone()),
+            );
+        });
+    });
+    c.bench_function("collect file list", |b| {
+        b.iter(|| {
+            collect(
+                &base_dir,
+                beautytips::InputFiles::FileList(file_list.clone()),
+            );
+        });
+    });
+
+    std::fs::remove_dir_all(&base_dir).unwrap();
+}
+
+criterion_group!(benches, bench_collect_input_files);
+criterion_main!(benches);
@@ -0,0 +1,362 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::fmt::Display;
+
+use anyhow::Context;
+
+/// Is `c` allowed in the parts of an id?
+fn is_id_char(c: char) -> bool {
+    c == '_' || c.is_ascii_digit() || c.is_ascii_lowercase()
+}
+
+/// Is `part` a valid source or name of an id?
+///
+/// Parts are made up of lowercase ASCII letters, digits and `_`, with at
+/// least one letter.
+fn is_valid_part(part: &str) -> bool {
+    part.chars().all(is_id_char) && part.chars().any(|c| c.is_ascii_lowercase())
+}
+
+fn is_valid_id(id: &str) -> bool {
+    id.split_once('/')
+        .is_some_and(|(source, name)| is_valid_part(source) && is_valid_part(name))
+}
+
+/// The id of an action or group: `source/name`
+#[derive(
+    Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize,
+)]
+#[serde(try_from = "String", into = "String", expecting = "an action id")]
+pub struct ActionId(String);
+
+impl ActionId {
+    /// Create a new `ActionId`
+    ///
+    /// # Errors
+    ///
+    /// Complain when the `input` is not a valid Action Id
+    pub fn new(input: String) -> crate::Result<Self> {
+        if is_valid_id(&input) {
+            Ok(Self(input))
+        } else {
+            Err(anyhow::anyhow!(
+                "{input} is not a valid action id, expected `source/name` made up of lowercase ASCII letters, digits and `_`"
+            ))
+        }
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The part before the `/`, e.g. `rust` for `rust/check_fmt`
+    #[must_use]
+    pub fn source(&self) -> &str {
+        self.0.split_once('/').map_or("", |(source, _)| source)
+    }
+
+    /// The part after the `/`, e.g. `check_fmt` for `rust/check_fmt`
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.0.split_once('/').map_or("", |(_, name)| name)
+    }
+}
+
+impl std::ops::Deref for ActionId {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_str()
+    }
+}
+
+impl std::borrow::Borrow<str> for ActionId {
+    fn borrow(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq<str> for ActionId {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for ActionId {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl Display for ActionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<ActionId> for String {
+    fn from(value: ActionId) -> Self {
+        value.0
+    }
+}
+
+impl TryFrom<&str> for ActionId {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::new(value.to_string())
+    }
+}
+
+impl TryFrom<String> for ActionId {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl std::str::FromStr for ActionId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s.to_string())
+    }
+}
+
+impl schemars::JsonSchema for ActionId {
+    fn schema_name() -> String {
+        "ActionId".to_string()
+    }
+
+    fn json_schema(_gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schemars::schema::SchemaObject {
+            instance_type: Some(schemars::schema::InstanceType::String.into()),
+            string: Some(Box::new(schemars::schema::StringValidation {
+                pattern: Some("^[a-z0-9_]*[a-z][a-z0-9_]*/[a-z0-9_]*[a-z][a-z0-9_]*$".to_string()),
+                ..Default::default()
+            })),
+            metadata: Some(Box::new(schemars::schema::Metadata {
+                description: Some(
+                    "An action id like `source/name`, made up of lowercase ASCII letters, digits and `_`"
+                        .to_string(),
+                ),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// The prefix of selectors matching the tags of actions
+const TAG_PREFIX: &str = "tag:";
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+enum Selector {
+    Pattern(glob::Pattern),
+    Tag(String),
+}
+
+/// A glob pattern matching action ids (or group ids)
+///
+/// A selector without `/` matches that name in all sources. A selector like
+/// `tag:security` matches all actions with that tag instead.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Deserialize)]
+#[serde(try_from = "String", expecting = "an action id selector")]
+pub struct ActionSelector(Selector);
+
+impl schemars::JsonSchema for ActionSelector {
+    fn schema_name() -> String {
+        "ActionSelector".to_string()
+    }
+
+    fn json_schema(_gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schemars::schema::SchemaObject {
+            instance_type: Some(schemars::schema::InstanceType::String.into()),
+            metadata: Some(Box::new(schemars::schema::Metadata {
+                description: Some(
+                    "A glob pattern matching action ids or group names, e.g. `rust/*`, or `tag:<tag>` matching actions with that tag"
+                        .to_string(),
+                ),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+impl ActionSelector {
+    /// Create a new `ActionSelector`
+    ///
+    /// # Errors
+    ///
+    /// Complain if the selector is not valid: It may only contain characters
+    /// allowed in action ids and glob patterns, and at most one `/`. Tags
+    /// may not be empty or contain whitespace or `,`.
+    pub fn new(input: &str) -> crate::Result<Self> {
+        if let Some(tag) = input.strip_prefix(TAG_PREFIX) {
+            if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ',') {
+                return Err(anyhow::anyhow!(format!(
+                    "{input} is not a valid tag selector, expected `tag:<tag>`"
+                )));
+            }
+            return Ok(Self(Selector::Tag(tag.to_string())));
+        }
+        if input.is_empty()
+            || input.matches('/').count() > 1
+            || !input
+                .chars()
+                .all(|c| is_id_char(c) || "/*?[]!-".contains(c))
+        {
+            return Err(anyhow::anyhow!(format!(
+                "{input} is not a valid action selector, expected a glob pattern like `source/name`"
+            )));
+        }
+
+        let pattern = if input.contains('/') {
+            glob::Pattern::new(input).context("Failed to parse action selector")?
+        } else {
+            glob::Pattern::new(&format!("*/{input}")).context("Failed to parse action selector")?
+        };
+
+        Ok(Self(Selector::Pattern(pattern)))
+    }
+
+    /// A selector matching the actions tagged with `tag`
+    ///
+    /// # Errors
+    ///
+    /// Complain if the `tag` is not valid
+    pub fn from_tag(tag: &str) -> crate::Result<Self> {
+        Self::new(&format!("{TAG_PREFIX}{tag}"))
+    }
+
+    /// A selector matching exactly the action or group `id`
+    ///
+    /// # Panics
+    ///
+    /// Never, an escaped id is always a valid pattern.
+    #[must_use]
+    pub fn from_id(id: &ActionId) -> Self {
+        Self(Selector::Pattern(
+            glob::Pattern::new(&glob::Pattern::escape(id))
+                .expect("An escaped id is a valid pattern"),
+        ))
+    }
+
+    /// `true` if this matches the action or group id `input`
+    ///
+    /// Tag selectors never match an id.
+    #[must_use]
+    pub fn matches(&self, input: &str) -> bool {
+        match &self.0 {
+            Selector::Pattern(pattern) => pattern.matches_with(
+                input,
+                glob::MatchOptions {
+                    case_sensitive: true,
+                    require_literal_separator: true,
+                    require_literal_leading_dot: false,
+                },
+            ),
+            Selector::Tag(_) => false,
+        }
+    }
+
+    /// `true` if this matches the id or one of the tags of `action`
+    #[must_use]
+    pub fn matches_action(&self, action: &crate::ActionDefinition) -> bool {
+        match &self.0 {
+            Selector::Pattern(_) => self.matches(&action.id),
+            Selector::Tag(tag) => action.tags.contains(tag),
+        }
+    }
+}
+
+impl Display for ActionSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Selector::Pattern(pattern) => write!(f, "{pattern}"),
+            Selector::Tag(tag) => write!(f, "{TAG_PREFIX}{tag}"),
+        }
+    }
+}
+
+impl TryFrom<String> for ActionSelector {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(&value)
+    }
+}
+
+impl TryFrom<&str> for ActionSelector {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl std::str::FromStr for ActionSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_id() {
+        assert!(is_valid_id("foo/bar"));
+        assert!(is_valid_id("_a/_b"));
+        assert!(is_valid_id("1a/1b"));
+        assert!(is_valid_id("1___a_dsd_/b___342144_zdfj"));
+        assert!(!is_valid_id(""));
+        assert!(!is_valid_id("__1/bar"));
+        assert!(!is_valid_id("foo/1__"));
+        assert!(!is_valid_id("foo"));
+        assert!(!is_valid_id("_foo_"));
+        assert!(!is_valid_id("a/Bar"));
+        assert!(!is_valid_id("a/b/c"));
+        assert!(!is_valid_id("/bar"));
+
+        let id = ActionId::new("rust/check_fmt".to_string()).unwrap();
+        assert_eq!(id.source(), "rust");
+        assert_eq!(id.name(), "check_fmt");
+        assert_eq!(id.to_string(), "rust/check_fmt");
+    }
+
+    #[test]
+    fn test_action_selector() {
+        let selector = ActionSelector::new("check_*").unwrap();
+        assert!(selector.matches("rust/check_fmt"));
+        assert!(!selector.matches("rust/fix_fmt"));
+        assert!(!selector.matches("a/b/check_fmt"));
+        assert!(ActionSelector::new("rust/*")
+            .unwrap()
+            .matches("rust/fix_fmt"));
+        assert!(ActionSelector::new("[rt]*/check_[!f]*")
+            .unwrap()
+            .matches("toml/check_lint"));
+
+        assert!(ActionSelector::new("").is_err());
+        assert!(ActionSelector::new("Rust/*").is_err());
+        assert!(ActionSelector::new("a/b/c").is_err());
+        assert!(ActionSelector::new("rust/check fmt").is_err());
+
+        let selector = ActionSelector::new("tag:security").unwrap();
+        assert!(!selector.matches("tag/security"));
+        assert_eq!(selector.to_string(), "tag:security");
+        assert_eq!(selector, ActionSelector::from_tag("security").unwrap());
+        assert!(ActionSelector::new("tag:").is_err());
+        assert!(ActionSelector::new("tag:a b").is_err());
+    }
+}
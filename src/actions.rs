@@ -4,32 +4,675 @@
 use anyhow::Context;
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fmt::Write as _,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
 mod args;
+pub(crate) mod cache_dir;
+mod claims;
+mod condition;
+mod executor;
 pub(crate) mod inputs;
+mod list_file;
+mod output;
+pub(crate) mod reorder;
+mod report_channel;
+mod sandbox;
+mod script;
+pub(crate) mod temp_dir;
 
+pub use claims::Writes;
+pub use condition::Condition;
+pub use output::{ActionOutput, DEFAULT_SPILL_THRESHOLD};
+pub(crate) use report_channel::{channel, ActionUpdateReceiver, ActionUpdateSender};
+pub use report_channel::{ReportOverflow, DEFAULT_REPORT_CAPACITY};
+pub use sandbox::SandboxMode;
+
+/// How much failures of an action matter
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Severity {
+    /// Warnings are reported as errors
+    Error,
+    /// Results are reported as they are
+    #[default]
+    Warning,
+    /// Warnings and errors are reported as success, including their output
+    Info,
+}
+
+impl Severity {
+    /// Map the `result` of an action to this severity
+    pub(crate) fn apply(self, result: ActionResult) -> ActionResult {
+        match (self, result) {
+            (
+                Self::Error,
+                ActionResult::Warn {
+                    stdout,
+                    stderr,
+                    hint,
+                },
+            ) => {
+                let mut message = [stdout, stderr]
+                    .iter()
+                    .map(|o| String::from_utf8_lossy(&o.bytes()).trim().to_string())
+                    .filter(|o| !o.is_empty())
+                    .collect::<Vec<_>>();
+                if message.is_empty() {
+                    message.push("The action reported a warning".to_string());
+                }
+                ActionResult::Error {
+                    message: message.join("\n"),
+                    hint,
+                }
+            }
+            (Self::Info, ActionResult::Warn { stdout, stderr, .. }) => {
+                ActionResult::Ok { stdout, stderr }
+            }
+            (Self::Info, ActionResult::Error { message, .. }) => ActionResult::Ok {
+                stdout: ActionOutput::default(),
+                stderr: message.into_bytes().into(),
+            },
+            (_, result) => result,
+        }
+    }
+}
+
+/// How an action gets the list of its `files`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum FilesVia {
+    /// As arguments on the command line and in `BEAUTYTIPS_FILES` for scripts
+    #[default]
+    Arguments,
+    /// In a temporary file, NUL separated, passed via `{{files:listfile}}`
+    /// or `BEAUTYTIPS_FILES_LISTFILE` for scripts
+    File,
+}
+
+/// When an action runs, relative to the other actions
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Phase {
+    /// Before all slow actions, to report problems quickly
+    Fast,
+    /// Once all fast actions are done
+    #[default]
+    Slow,
+}
+
+/// When the output of an action is reported
+///
+/// This applies to the output in the `ActionResult` as well as to the output
+/// streamed while the action runs, so all reporters see the same.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum OutputCondition {
     Never,
     Success,
     Failure,
     Always,
+    /// Always, but only the first and last `SUMMARY_LINES` lines
+    Summary,
+}
+
+/// The number of lines kept at the start and at the end of the output of
+/// actions with `OutputCondition::Summary`
+const SUMMARY_LINES: usize = 10;
+
+impl OutputCondition {
+    /// `output` as reported for an action that `succeeded` or not
+    async fn apply(&self, succeeded: bool, output: ActionOutput) -> ActionOutput {
+        match self {
+            Self::Never => {
+                output.discard().await;
+                ActionOutput::default()
+            }
+            Self::Success if !succeeded => {
+                output.discard().await;
+                ActionOutput::default()
+            }
+            Self::Failure if succeeded => {
+                output.discard().await;
+                ActionOutput::default()
+            }
+            Self::Summary => summarize(output).await,
+            Self::Success | Self::Failure | Self::Always => output,
+        }
+    }
+
+    /// Output can be streamed before the result is known
+    fn streams(&self) -> bool {
+        *self == Self::Always
+    }
+}
+
+/// The first and last `SUMMARY_LINES` lines read from `reader`, or `None`
+/// if there are not more lines than that
+async fn summarize_lines(
+    mut reader: impl tokio::io::AsyncBufRead + Unpin,
+) -> std::io::Result<Option<Vec<u8>>> {
+    use tokio::io::AsyncBufReadExt;
+
+    let mut head = Vec::with_capacity(SUMMARY_LINES);
+    let mut tail = std::collections::VecDeque::with_capacity(SUMMARY_LINES);
+    let mut count = 0;
+    loop {
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line).await? == 0 {
+            break;
+        }
+        count += 1;
+        if head.len() < SUMMARY_LINES {
+            head.push(line);
+        } else {
+            if tail.len() == SUMMARY_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+    }
+    if count <= 2 * SUMMARY_LINES {
+        return Ok(None);
+    }
+
+    let omitted = count - 2 * SUMMARY_LINES;
+    let mut result = head.concat();
+    result.extend_from_slice(format!("[... {omitted} lines omitted ...]\n").as_bytes());
+    result.extend(tail.into_iter().flatten());
+    Ok(Some(result))
+}
+
+/// The first and last `SUMMARY_LINES` lines of `output`
+async fn summarize(output: ActionOutput) -> ActionOutput {
+    let summary = match &output {
+        ActionOutput::Inline(bytes) => summarize_lines(bytes.as_slice()).await,
+        ActionOutput::Spilled { path, .. } => match tokio::fs::File::open(path).await {
+            Ok(file) => summarize_lines(tokio::io::BufReader::new(file)).await,
+            Err(e) => Err(e),
+        },
+    };
+    match summary {
+        Ok(Some(summary)) => {
+            output.discard().await;
+            summary.into()
+        }
+        Ok(None) => output,
+        Err(e) => {
+            tracing::warn!("Failed to summarize output: {e}");
+            output
+        }
+    }
 }
 
 #[derive(Clone, Debug, Eq)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct ActionDefinition {
-    pub id: String,
+    pub id: crate::ActionId,
     pub description: String,
+    /// Free-form tags like `security`, selected by `tag:security`
+    pub tags: Vec<String>,
     pub run_sequentially: bool,
     pub command: Vec<String>,
+    /// An inline script to run instead of `command`
+    ///
+    /// The script is written into a temporary executable file. It gets the
+    /// placeholder values in `BEAUTYTIPS_<PLACEHOLDER>` environment variables,
+    /// with one file per line.
+    pub script: Option<String>,
+    /// Extra environment variables, values may use placeholders
     pub environment: Vec<(String, String)>,
+    /// Extra environment variables only set when running isolated, e.g. to
+    /// use a build directory of its own instead of the one the developer
+    /// uses. These override the `environment`.
+    pub isolated_environment: Vec<(String, String)>,
+    /// Directories (relative to the root directory) to put in front of the
+    /// `PATH`, e.g. `node_modules/.bin` to find project-local tools
+    pub path_prepend: Vec<PathBuf>,
+    /// Put the `PROJECT_TOOL_DIRECTORIES` that exist in front of the `PATH`,
+    /// after the `path_prepend` ones
+    pub project_tools: bool,
     pub show_output: OutputCondition,
     pub expected_exit_code: i32,
     pub input_filters: inputs::InputFilters,
+    /// Run `diff_command` when the action fails and attach its output
+    pub diff_on_failure: bool,
+    /// A command printing the changes needed to make the action pass
+    pub diff_command: Vec<String>,
+    /// Run the action in a sandbox instead of the working tree
+    pub sandbox: Option<SandboxMode>,
+    /// Apply the changes done in the sandbox to the working tree when the
+    /// action succeeded. Otherwise changes are reported as a warning.
+    pub sandbox_apply: bool,
+    /// The files the action may change
+    ///
+    /// Actions using the same files are not run at the same time when one
+    /// of them changes these files.
+    pub writes: Writes,
+    /// Only run the action when this is met, it is not applicable otherwise
+    pub enabled_when: Condition,
+    /// Run `full_command` instead of `command` when there are more `files`
+    /// than this
+    pub full_run_threshold: Option<usize>,
+    /// A command working on the whole project instead of a list of files
+    pub full_command: Vec<String>,
+    /// Report an error instead of running the action on more `files` than
+    /// this, unless the `full_command` runs
+    pub max_files: Option<usize>,
+    /// How the action gets the list of its `files`
+    pub files_via: FilesVia,
+    /// Run the invocations of the command (e.g. one per file for a `{{files}}`
+    /// placeholder) at the same time, instead of one after the other
+    ///
+    /// The parallel invocations of all actions share one limit of processes
+    /// to run at a time. Actions take turns in starting their next
+    /// invocation, so that no action can starve the others.
+    pub parallel_invocations: bool,
+    /// Run the command once for each value of this input, in that directory
+    /// (or the directory containing it) instead of the root directory
+    pub cwd_per_target: Option<String>,
+    /// A hint on how to fix failures, placeholders are expanded
+    pub hint: Option<String>,
+    /// Write the output of the command into this file (relative to the root
+    /// directory) instead of reporting it
+    pub stdout_file: Option<PathBuf>,
+    /// Write the error output of the command into this file (relative to the
+    /// root directory) instead of reporting it
+    pub stderr_file: Option<PathBuf>,
+    /// Run the action once for each combination of these values
+    ///
+    /// The value of `name` is available in the `{{matrix.name}}` placeholder.
+    /// The instances are reported with the values in their id, like
+    /// `mypy/check[3.10]`.
+    pub matrix: Vec<(String, Vec<String>)>,
+    /// The `matrix` values of this instance of the action, like `3.10` for
+    /// `mypy/check[3.10]`, set when expanding the matrix only
+    pub variant: Option<String>,
+    /// How much failures of the action matter
+    pub severity: Severity,
+    /// Warnings and errors of the action are reported as allowed failures,
+    /// which do not fail the run
+    pub allow_failure: bool,
+    /// Whether the action runs before or after the slow actions
+    pub phase: Phase,
+    /// Run the action before all other actions, skipping those that `needs`
+    /// it when it fails
+    pub gate: bool,
+    /// The gates that need to pass for the action to run
+    ///
+    /// Gates run at the same time, so gates needing other gates always run.
+    /// Gates that are not part of the run are ignored.
+    pub needs: Vec<crate::ActionId>,
+    /// Cancel the commands of the action still running after this long,
+    /// reporting an error
+    pub timeout: Option<std::time::Duration>,
+}
+
+impl ActionDefinition {
+    /// `true` if `result` of this action fails the run, taking its
+    /// `severity` and `allow_failure` into account
+    pub(crate) fn fails_run(&self, result: &ActionResult) -> bool {
+        !self.allow_failure
+            && self.severity != Severity::Info
+            && matches!(
+                result,
+                ActionResult::Warn { .. } | ActionResult::Error { .. }
+            )
+    }
+
+    /// The first gate of `needs` in `failed_gates`, which hold the ids of
+    /// the failed gates without their variants
+    pub(crate) fn failed_gate<'a>(
+        &'a self,
+        failed_gates: &HashSet<String>,
+    ) -> Option<&'a crate::ActionId> {
+        self.needs
+            .iter()
+            .find(|n| failed_gates.contains(n.as_str()))
+    }
+
+    /// Check the definition for problems that would make it fail at run time
+    ///
+    /// The executables are not looked up, as the tools an action needs may be
+    /// missing on machines never running it, use `validate_with` for that.
+    /// Placeholders must refer to builtin inputs, registered input generators
+    /// or inputs declared in the `input_filters`. So declare inputs of
+    /// generators registered after validating there.
+    ///
+    /// # Errors
+    ///
+    /// Reports all problems found at once.
+    pub fn validate(&self) -> crate::Result<()> {
+        self.validate_with(None)
+    }
+
+    /// Check the action like `validate`, and with `resolve_executables_in`
+    /// set also that the executables of its commands are found when running
+    /// in that root directory
+    ///
+    /// Actions not enabled in the root directory are not looked up.
+    ///
+    /// # Errors
+    ///
+    /// Reports all problems found at once.
+    pub fn validate_with(&self, resolve_executables_in: Option<&Path>) -> crate::Result<()> {
+        let mut problems = self.command_problems();
+        problems.extend(self.placeholder_problems());
+        problems.extend(self.input_filter_problems());
+        problems.extend(self.option_problems());
+        if let Some(root_directory) = resolve_executables_in {
+            problems.extend(self.executable_problems(root_directory));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(format!(
+                "{} is not valid:\n  * {}",
+                self.id,
+                problems.join("\n  * ")
+            )))
+        }
+    }
+
+    fn command_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        match (self.command.first(), &self.script) {
+            (Some(_), Some(_)) => problems.push("it has both a command and a script".to_string()),
+            // Actions without a command for this OS are fine
+            (None, None) if self.enabled_when.os.is_none() => {
+                problems.push("it has neither a command nor a script".to_string());
+            }
+            _ => {}
+        }
+
+        for (kind, command) in [
+            ("command", &self.command),
+            ("diff-command", &self.diff_command),
+            ("full-command", &self.full_command),
+        ] {
+            match command.first() {
+                Some(executable) if executable.trim().is_empty() => {
+                    problems.push(format!("the executable of the {kind} is empty"));
+                }
+                Some(executable) if executable.contains("{{") => problems.push(format!(
+                    "the executable '{executable}' of the {kind} can not use placeholders"
+                )),
+                _ => {}
+            }
+        }
+        problems
+    }
+
+    fn executable_problems(&self, root_directory: &Path) -> Vec<String> {
+        if !self.enabled_when.is_met(root_directory) {
+            return Vec::new();
+        }
+
+        let mut directories = self
+            .path_prepend
+            .iter()
+            .map(|d| root_directory.join(d))
+            .collect::<Vec<_>>();
+        if self.project_tools {
+            directories.extend(
+                PROJECT_TOOL_DIRECTORIES
+                    .iter()
+                    .map(|d| root_directory.join(d)),
+            );
+        }
+        let path = self
+            .environment
+            .iter()
+            .rev()
+            .find(|(k, v)| k == "PATH" && !v.contains("{{"))
+            .map(|(_, v)| OsString::from(v))
+            .or_else(|| std::env::var_os("PATH"))
+            .unwrap_or_default();
+        let path =
+            match std::env::join_paths(directories.into_iter().chain(std::env::split_paths(&path)))
+            {
+                Ok(path) => path,
+                Err(e) => return vec![format!("{e} in path-prepend")],
+            };
+        let environment = [(OsString::from("PATH"), path)];
+
+        let mut problems = Vec::new();
+        for (kind, command) in [
+            ("command", &self.command),
+            ("diff-command", &self.diff_command),
+            ("full-command", &self.full_command),
+        ] {
+            let Some(executable) = command.first() else {
+                continue;
+            };
+            let executable_path = Path::new(executable);
+            // Empty executables and placeholders are reported already, and
+            // relative paths depend on the target directory
+            if executable.trim().is_empty()
+                || executable.contains("{{")
+                || (self.cwd_per_target.is_some() && executable_path.components().count() > 1)
+            {
+                continue;
+            }
+            if executor::find_executable(executable_path, root_directory, &environment).is_none() {
+                problems.push(format!(
+                    "the executable '{executable}' of the {kind} is not found"
+                ));
+            }
+        }
+        problems
+    }
+
+    fn placeholder_problems(&self) -> Vec<String> {
+        let arguments = [
+            ("command", &self.command),
+            ("diff-command", &self.diff_command),
+            ("full-command", &self.full_command),
+        ]
+        .into_iter()
+        .flat_map(|(kind, command)| command.iter().skip(1).map(move |a| (kind, a)))
+        .chain(self.hint.iter().map(|h| ("hint", h)))
+        .chain(self.environment.iter().map(|(_, v)| ("environment", v)))
+        .chain(
+            self.isolated_environment
+                .iter()
+                .map(|(_, v)| ("isolated-environment", v)),
+        );
+
+        let known_inputs = inputs::known_inputs();
+        let is_known = |input: &str| {
+            known_inputs.iter().any(|i| i == input)
+                || self.input_filters.inputs().any(|i| i == input)
+        };
+
+        let mut problems = Vec::new();
+        let mut unknown = Vec::new();
+        for (kind, text) in arguments {
+            match args::placeholder_names(text) {
+                Ok(names) => unknown.extend(
+                    names
+                        .into_iter()
+                        .filter(|n| {
+                            n != cache_dir::CACHE_DIR_PLACEHOLDER
+                                && n != cache_dir::PROJECT_CACHE_DIR_PLACEHOLDER
+                                && n != list_file::LIST_FILE_PLACEHOLDER
+                                && n != temp_dir::TEMP_DIR_PLACEHOLDER
+                                && n != temp_dir::DIFF_FILE_PLACEHOLDER
+                                && !is_known(args::placeholder_input(n))
+                                && !n
+                                    .strip_prefix(MATRIX_PLACEHOLDER_PREFIX)
+                                    .is_some_and(|m| self.matrix.iter().any(|(k, _)| k == m))
+                        })
+                        .map(|n| format!("'{{{{{n}}}}}' in the {kind}")),
+                ),
+                Err(e) => problems.push(format!("{e} in the {kind}")),
+            }
+        }
+        if let Some(input) = &self.cwd_per_target {
+            if !input.is_empty() && !is_known(input) {
+                unknown.push(format!("'{input}' in cwd-per-target"));
+            }
+        }
+        if !unknown.is_empty() {
+            unknown.sort();
+            unknown.dedup();
+            problems.push(format!(
+                "it uses unknown inputs {}, known inputs are {}",
+                unknown.join(", "),
+                known_inputs.join(", ")
+            ));
+        }
+        problems
+    }
+
+    fn input_filter_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut inputs = self.input_filters.inputs().collect::<Vec<_>>();
+        inputs.sort();
+        for input in inputs {
+            for pattern in self.input_filters.patterns(input) {
+                let glob = pattern.to_string();
+                let glob = glob.trim_start_matches('!');
+                // Paths are matched relative to the root directory
+                if glob.starts_with('/') || glob.starts_with("./") {
+                    problems.push(format!(
+                        "the pattern '{pattern}' for '{input}' never matches, patterns are relative to the root directory"
+                    ));
+                }
+            }
+        }
+        if self.cwd_per_target.as_ref().is_some_and(String::is_empty) {
+            problems.push("the cwd-per-target input is empty".to_string());
+        }
+        problems
+    }
+
+    fn option_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.diff_on_failure && self.diff_command.is_empty() {
+            problems.push("it enables diff-on-failure, but has no diff-command".to_string());
+        }
+        if self.full_run_threshold.is_some() == self.full_command.is_empty() {
+            problems.push("it needs both a full-run-threshold and a full-command".to_string());
+        }
+        if self.script.is_some() && !self.full_command.is_empty() {
+            problems.push(
+                "it has a script, use BEAUTYTIPS_FILE_COUNT in it instead of a full-command"
+                    .to_string(),
+            );
+        }
+        let list_file = format!("{{{{{}}}}}", list_file::LIST_FILE_PLACEHOLDER);
+        if self.files_via != FilesVia::File
+            && [&self.command, &self.diff_command, &self.full_command]
+                .iter()
+                .any(|c| c.iter().any(|a| a.contains(&list_file)))
+        {
+            problems.push(format!(
+                "it uses {list_file}, but does not set `files-via = \"file\"`"
+            ));
+        }
+        if self.script.is_some()
+            && self
+                .sandbox
+                .as_ref()
+                .is_some_and(|s| *s != SandboxMode::Restricted)
+        {
+            problems.push("it has a script, scripts can not run in a copy sandbox".to_string());
+        }
+        for (kind, file) in [
+            ("stdout-file", &self.stdout_file),
+            ("stderr-file", &self.stderr_file),
+        ] {
+            if file.as_ref().is_some_and(|f| !f.is_relative()) {
+                problems.push(format!("the {kind} must be relative to the root directory"));
+            }
+            if file.as_ref().is_some_and(|f| {
+                f.components()
+                    .any(|c| matches!(c, std::path::Component::ParentDir))
+            }) {
+                problems.push(format!("the {kind} must be inside the root directory"));
+            }
+        }
+        if self.stdout_file.is_some() && self.stdout_file == self.stderr_file {
+            problems.push("the stdout-file and the stderr-file are the same".to_string());
+        }
+        for (name, values) in &self.matrix {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                problems.push(format!("'{name}' is not a valid name for a matrix value"));
+            }
+            if values.is_empty() {
+                problems.push(format!("the matrix has no values for '{name}'"));
+            }
+            if values.iter().any(String::is_empty) {
+                problems.push(format!("the matrix has an empty value for '{name}'"));
+            }
+            if values
+                .iter()
+                .any(|v| v.contains([',', '[', ']']) || v.contains(char::is_control))
+            {
+                problems.push(format!(
+                    "the matrix values of '{name}' can not contain `,`, `[`, `]` or control characters"
+                ));
+            }
+        }
+        problems
+    }
+
+    /// The instances of the action, one per combination of `matrix` values
+    ///
+    /// Actions without a matrix are returned as they are.
+    fn matrix_instances(self: &Arc<Self>) -> Vec<Arc<Self>> {
+        if self.matrix.is_empty() {
+            return vec![self.clone()];
+        }
+
+        let mut combinations: Vec<Vec<&String>> = vec![vec![]];
+        for (_, values) in &self.matrix {
+            combinations = combinations
+                .into_iter()
+                .flat_map(|c| {
+                    values.iter().map(move |v| {
+                        let mut c = c.clone();
+                        c.push(v);
+                        c
+                    })
+                })
+                .collect();
+        }
+
+        combinations
+            .into_iter()
+            .map(|values| {
+                let variant = values
+                    .iter()
+                    .map(|v| v.as_str())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let mut instance = self.as_ref().clone();
+                instance.variant = Some(variant);
+                instance.matrix = self
+                    .matrix
+                    .iter()
+                    .zip(values)
+                    .map(|((name, _), value)| (name.clone(), vec![value.clone()]))
+                    .collect();
+                Arc::new(instance)
+            })
+            .collect()
+    }
+}
+
+impl ActionDefinition {
+    /// The id the action is reported with, like `mypy/check[3.10]` for an
+    /// instance of an action with a matrix
+    #[must_use]
+    pub fn instance_id(&self) -> String {
+        match &self.variant {
+            Some(variant) => format!("{}[{variant}]", self.id),
+            None => self.id.to_string(),
+        }
+    }
 }
 
 impl PartialOrd for ActionDefinition {
@@ -40,49 +683,249 @@ impl PartialOrd for ActionDefinition {
 
 impl PartialEq for ActionDefinition {
     fn eq(&self, other: &Self) -> bool {
-        self.id.eq(&other.id)
+        (&self.id, &self.variant).eq(&(&other.id, &other.variant))
     }
 }
 
 impl Ord for ActionDefinition {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.id.cmp(&other.id)
+        (&self.id, &self.variant).cmp(&(&other.id, &other.variant))
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct ActionDefinitionIterator<'a> {
-    actions: Vec<&'a ActionDefinition>,
-    current_item: usize,
+/// A set of actions, in the order they were added in
+///
+/// Actions are identified by their id and shared between sets, so cloning
+/// a set is cheap.
+#[derive(Clone, Debug, Default)]
+pub struct ActionSet {
+    actions: Vec<Arc<ActionDefinition>>,
 }
 
-impl<'a> ActionDefinitionIterator<'a> {
+impl ActionSet {
+    /// Create a set of `actions`, ignoring actions with an id seen before
     #[must_use]
-    pub fn new(actions: Vec<&'a ActionDefinition>) -> Self {
+    pub fn new(actions: impl IntoIterator<Item = Arc<ActionDefinition>>) -> Self {
+        let mut seen = std::collections::HashSet::new();
         Self {
-            actions,
-            current_item: 0,
+            actions: actions
+                .into_iter()
+                .filter(|a| seen.insert((a.id.clone(), a.variant.clone())))
+                .collect(),
         }
     }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    #[must_use]
+    pub fn contains(&self, id: &str) -> bool {
+        self.actions.iter().any(|a| a.instance_id() == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ActionDefinition> + '_ {
+        self.actions.iter().map(AsRef::as_ref)
+    }
+
+    /// The ids of all actions, as they are reported
+    pub fn ids(&self) -> impl Iterator<Item = String> + '_ {
+        self.actions.iter().map(|a| a.instance_id())
+    }
+
+    /// The handles of all actions, by id
+    pub(crate) fn handles(&self) -> std::collections::HashMap<String, ActionHandle> {
+        self.actions
+            .iter()
+            .map(|a| (a.instance_id(), ActionHandle::from(a.as_ref())))
+            .collect()
+    }
+
+    /// The severities of all actions not reported as they are, by id
+    pub(crate) fn severities(&self) -> std::collections::HashMap<String, Severity> {
+        self.actions
+            .iter()
+            .filter(|a| a.severity != Severity::Warning)
+            .map(|a| (a.instance_id(), a.severity))
+            .collect()
+    }
+
+    /// The ids of all actions that are allowed to fail
+    pub(crate) fn allowed_failures(&self) -> std::collections::HashSet<String> {
+        self.actions
+            .iter()
+            .filter(|a| a.allow_failure)
+            .map(|a| a.instance_id())
+            .collect()
+    }
+
+    /// The actions `predicate` returns `true` for, e.g. those matching some
+    /// selector
+    #[must_use]
+    pub fn filter(&self, mut predicate: impl FnMut(&ActionDefinition) -> bool) -> Self {
+        Self {
+            actions: self
+                .actions
+                .iter()
+                .filter(|a| predicate(a))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// The actions `predicate` returns `true` for and all others
+    #[must_use]
+    pub fn partition(&self, mut predicate: impl FnMut(&ActionDefinition) -> bool) -> (Self, Self) {
+        let (matching, others) = self.actions.iter().cloned().partition(|a| predicate(a));
+        (Self { actions: matching }, Self { actions: others })
+    }
+
+    /// The actions of `self` followed by those of `other` not in `self`
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        Self::new(self.actions.iter().chain(other.actions.iter()).cloned())
+    }
+
+    /// The actions of `self` not in `other`
+    #[must_use]
+    pub fn difference(&self, other: &Self) -> Self {
+        self.filter(|a| !other.iter().any(|o| o == a))
+    }
+
+    /// Replace the actions with a `matrix` by one instance per combination
+    /// of its values
+    pub(crate) fn expand_matrix(&self) -> Self {
+        Self::new(
+            self.actions
+                .iter()
+                .flat_map(ActionDefinition::matrix_instances),
+        )
+    }
 }
 
-impl<'a> Iterator for ActionDefinitionIterator<'a> {
-    type Item = &'a ActionDefinition;
+impl FromIterator<Arc<ActionDefinition>> for ActionSet {
+    fn from_iter<T: IntoIterator<Item = Arc<ActionDefinition>>>(iter: T) -> Self {
+        Self::new(iter)
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let cur = self.current_item;
-        self.current_item += 1;
-        self.actions.get(cur).copied()
+impl FromIterator<ActionDefinition> for ActionSet {
+    fn from_iter<T: IntoIterator<Item = ActionDefinition>>(iter: T) -> Self {
+        Self::new(iter.into_iter().map(Arc::new))
     }
 }
 
-#[derive(Clone, Debug)]
+impl IntoIterator for ActionSet {
+    type Item = Arc<ActionDefinition>;
+    type IntoIter = std::vec::IntoIter<Arc<ActionDefinition>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.actions.into_iter()
+    }
+}
+
+/// Why an action was not applicable
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NotApplicableReason {
+    /// The `enabled_when` condition of the action is not met
+    ConditionNotMet,
+    /// An input has no files left after filtering
+    NoInputFiles {
+        /// The name of the input
+        input: String,
+        /// The number of files before filtering
+        candidates: usize,
+        /// The glob patterns used to filter the files
+        filters: Vec<String>,
+    },
+}
+
+impl std::fmt::Display for NotApplicableReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ConditionNotMet => write!(f, "enabled-when condition not met"),
+            Self::NoInputFiles {
+                input, candidates, ..
+            } if *candidates == 0 => write!(f, "no files for input '{input}'"),
+            Self::NoInputFiles {
+                input,
+                candidates,
+                filters,
+            } => write!(
+                f,
+                "all {candidates} files for input '{input}' filtered out by {}",
+                filters.join(", ")
+            ),
+        }
+    }
+}
+
+/// An action as passed to a `Reporter`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ActionHandle {
+    /// The id of the action, like `rust/check_fmt`, or `mypy/check[3.10]`
+    /// for a variant of an action with a matrix
+    pub id: String,
+    /// The part of the id before the `/`, e.g. `rust` for `rust/check_fmt`
+    pub source: String,
+    pub description: String,
+    pub tags: Vec<String>,
+}
+
+impl ActionHandle {
+    /// The handle of an action known by its `id` only
+    #[must_use]
+    pub fn from_id(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            source: id.split_once('/').map_or("", |(s, _)| s).to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&ActionDefinition> for ActionHandle {
+    fn from(action: &ActionDefinition) -> Self {
+        Self {
+            id: action.instance_id(),
+            source: action.id.source().to_string(),
+            description: action.description.clone(),
+            tags: action.tags.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for ActionHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ActionResult {
-    Ok { stdout: Vec<u8>, stderr: Vec<u8> },
+    Ok {
+        stdout: ActionOutput,
+        stderr: ActionOutput,
+    },
     Skipped,
-    NotApplicable,
-    Warn { stdout: Vec<u8>, stderr: Vec<u8> },
-    Error { message: String },
+    NotApplicable {
+        reason: NotApplicableReason,
+    },
+    Warn {
+        stdout: ActionOutput,
+        stderr: ActionOutput,
+        hint: Option<String>,
+    },
+    Error {
+        message: String,
+        hint: Option<String>,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -90,13 +933,30 @@ pub(crate) enum ActionUpdate {
     Started {
         action_id: String,
     },
+    Output {
+        action_id: String,
+        stdout: Vec<u8>,
+        stderr: Vec<u8>,
+    },
+    Detail {
+        action_id: String,
+        message: String,
+    },
     Done {
         action_id: String,
         result: ActionResult,
     },
 }
-pub(crate) type ActionUpdateSender = tokio::sync::mpsc::Sender<ActionUpdate>;
-pub(crate) type ActionUpdateReceiver = tokio::sync::mpsc::Receiver<ActionUpdate>;
+impl ActionUpdate {
+    pub(crate) fn action_id(&self) -> &str {
+        match self {
+            ActionUpdate::Started { action_id }
+            | ActionUpdate::Output { action_id, .. }
+            | ActionUpdate::Detail { action_id, .. }
+            | ActionUpdate::Done { action_id, .. } => action_id,
+        }
+    }
+}
 
 #[tracing::instrument]
 async fn report(sender: &ActionUpdateSender, message: ActionUpdate) {
@@ -106,34 +966,813 @@ async fn report(sender: &ActionUpdateSender, message: ActionUpdate) {
         .expect("Failed to send message to reporter");
 }
 
-pub(crate) async fn has_unfiltered_input(
+/// Settings shared by all actions of a run
+#[derive(Debug)]
+pub(crate) struct RunSettings {
+    /// Limits the invocations of actions with `parallel_invocations` running
+    /// at the same time
+    invocation_limit: Arc<tokio::sync::Semaphore>,
+    /// Report details on running the actions when above 0
+    verbosity: u8,
+    /// Runs the commands of the actions
+    executor: Arc<dyn executor::CommandExecutor>,
+    /// Set the `isolated_environment` of the actions
+    isolate: bool,
+    /// The temporary directory of the run, if set up
+    temp_directory: Option<PathBuf>,
+    /// The file holding the diff of the run, if set up
+    diff_file: Option<PathBuf>,
+    /// Run the slow actions even if fast actions failed
+    keep_going: bool,
+    /// Where outputs of actions too large to keep in memory go
+    spill: output::Spill,
+    /// Report the output of actions that stream it, see
+    /// `Reporter::wants_output_chunks`
+    stream_output: bool,
+}
+
+impl RunSettings {
+    pub(crate) fn new(jobs: usize, verbosity: u8) -> Self {
+        Self {
+            invocation_limit: Arc::new(tokio::sync::Semaphore::new(jobs.max(1))),
+            verbosity,
+            executor: Arc::new(executor::ProcessExecutor),
+            isolate: false,
+            temp_directory: None,
+            diff_file: None,
+            keep_going: false,
+            spill: output::Spill::default(),
+            stream_output: false,
+        }
+    }
+
+    /// Write outputs of actions larger than `threshold` bytes to files
+    /// instead of keeping them in memory
+    pub(crate) fn spill_above(mut self, threshold: usize) -> Self {
+        self.spill.threshold = threshold;
+        self
+    }
+
+    /// Run the slow actions even if fast actions failed, instead of skipping
+    /// them
+    pub(crate) fn keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Report the output of each invocation of actions streaming it, as it
+    /// comes in
+    pub(crate) fn stream_output(mut self, stream_output: bool) -> Self {
+        self.stream_output = stream_output;
+        self
+    }
+
+    /// Run the actions with their `isolated_environment`
+    pub(crate) fn isolated(mut self, isolate: bool) -> Self {
+        self.isolate = isolate;
+        self
+    }
+
+    /// Run the commands of the actions with `executor` instead of spawning
+    /// processes
+    #[cfg(test)]
+    pub(crate) fn with_executor(mut self, executor: Arc<dyn executor::CommandExecutor>) -> Self {
+        self.executor = executor;
+        self
+    }
+}
+
+/// Report a detail on running an action, if details are of interest
+async fn report_detail(
+    sender: &ActionUpdateSender,
+    settings: &RunSettings,
+    action_id: &str,
+    message: impl FnOnce() -> String,
+) {
+    if settings.verbosity > 0 {
+        report(
+            sender,
+            ActionUpdate::Detail {
+                action_id: action_id.to_string(),
+                message: message(),
+            },
+        )
+        .await;
+    }
+}
+
+/// Report how many files the filters of `action` selected for each input
+async fn report_input_details(
+    sender: &ActionUpdateSender,
+    settings: &RunSettings,
+    action: &ActionDefinition,
+    inputs: &inputs::InputQuery,
+    root_directory: &Path,
+) {
+    if settings.verbosity == 0 {
+        return;
+    }
+
+    let mut names = action.input_filters.inputs().collect::<Vec<_>>();
+    names.sort();
+    for k in names {
+        let candidates = inputs.inputs(k.clone()).await.map_or(0, |f| f.len());
+        let selected = action
+            .input_filters
+            .filtered(k, inputs, root_directory)
+            .await
+            .map_or(0, |f| f.len());
+        let mut filters = action
+            .input_filters
+            .patterns(k)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        if k == inputs::FILES_INPUTS {
+            filters.extend(action.input_filters.kind().map(|k| format!("kind {k}")));
+            filters.extend(
+                action
+                    .input_filters
+                    .content_patterns()
+                    .iter()
+                    .map(|c| format!("content {c}")),
+            );
+        }
+        let filters = if filters.is_empty() {
+            "no filters".to_string()
+        } else {
+            filters.join(", ")
+        };
+        report_detail(sender, settings, &action.instance_id(), || {
+            format!("{k}: {selected} of {candidates} selected by {filters}")
+        })
+        .await;
+    }
+}
+
+/// Find an input without any files left after filtering
+async fn find_empty_input(
     inputs: &inputs::InputQuery,
     input_filters: &inputs::InputFilters,
+    extra_input: Option<&String>,
     root_directory: &Path,
-) -> bool {
-    for k in input_filters.inputs() {
+) -> Option<NotApplicableReason> {
+    let mut names = input_filters
+        .inputs()
+        .chain(extra_input)
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+
+    for k in names {
         if input_filters
             .filtered(k, inputs, root_directory)
             .await
-            .map(|v| v.is_empty())
-            .unwrap_or(true)
+            .map_or(true, |v| v.is_empty())
+        {
+            let candidates = inputs
+                .inputs(k.clone())
+                .await
+                .map_or(0, |files| files.len());
+            return Some(NotApplicableReason::NoInputFiles {
+                input: k.clone(),
+                candidates,
+                filters: input_filters
+                    .patterns(k)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+            });
+        }
+    }
+    None
+}
+
+/// The `hint` of `action` with its placeholders expanded, if it has one
+async fn expand_hint(
+    action: &ActionDefinition,
+    inputs: &inputs::InputQuery,
+    current_directory: &Path,
+    placeholders: &args::Placeholders,
+) -> Option<crate::SendableResult<String>> {
+    let hint = action.hint.as_ref()?;
+    Some(
+        args::expand_text(
+            hint,
+            inputs.clone(),
+            current_directory,
+            &action.input_filters,
+            placeholders,
+        )
+        .await,
+    )
+}
+
+async fn run_diff_command(
+    current_directory: &Path,
+    environment: &[(OsString, OsString)],
+    action: &ActionDefinition,
+    inputs: inputs::InputQuery,
+    placeholders: &args::Placeholders,
+    executor: &dyn executor::CommandExecutor,
+    diff: &mut output::OutputBuffer,
+) -> crate::Result<()> {
+    let Some(command) = action.diff_command.first() else {
+        return Err(anyhow::anyhow!("No diff command defined"));
+    };
+
+    let mut args = args::parse_args(
+        &action.diff_command,
+        inputs,
+        current_directory,
+        &action.input_filters,
+        placeholders,
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!(format!("Argument parsing failed: {e}")))?;
+
+    loop {
+        let output = executor
+            .execute(
+                Path::new(command),
+                &args
+                    .args_iter()
+                    .map(std::ffi::OsStr::to_os_string)
+                    .collect::<Vec<_>>(),
+                current_directory,
+                environment,
+                diff.spill(),
+            )
+            .await?;
+
+        output.stderr.discard().await;
+        diff.append(output.stdout).await;
+
+        if args.increment() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// The prefix of the placeholders holding `matrix` values
+const MATRIX_PLACEHOLDER_PREFIX: &str = "matrix.";
+
+fn uses_placeholder(command: &[String], name: &str) -> bool {
+    let plain = format!("{{{{{name}}}}}");
+    let array = format!("{{{{{name}...}}}}");
+    command
+        .iter()
+        .any(|a| a.contains(&plain) || a.contains(&array))
+}
+
+/// The placeholders of `action` and the list file backing one of them
+async fn action_placeholders(
+    action: &ActionDefinition,
+    inputs: &inputs::InputQuery,
+    root_directory: &Path,
+    settings: &RunSettings,
+) -> crate::Result<(args::Placeholders, Option<list_file::ListFile>)> {
+    let mut placeholders = args::Placeholders::new();
+    if let Some(directory) = &settings.temp_directory {
+        placeholders.insert(
+            temp_dir::TEMP_DIR_PLACEHOLDER.to_string(),
+            directory.clone(),
+        );
+    }
+    if let Some(diff_file) = &settings.diff_file {
+        placeholders.insert(
+            temp_dir::DIFF_FILE_PLACEHOLDER.to_string(),
+            diff_file.clone(),
+        );
+    }
+    for (name, values) in &action.matrix {
+        if let Some(value) = values.first() {
+            placeholders.insert(
+                format!("{MATRIX_PLACEHOLDER_PREFIX}{name}"),
+                PathBuf::from(value),
+            );
+        }
+    }
+
+    let environment = action
+        .environment
+        .iter()
+        .chain(action.isolated_environment.iter())
+        .map(|(_, v)| v.clone())
+        .collect::<Vec<_>>();
+    let instance_id = action.instance_id();
+    for (placeholder, action_id) in [
+        (cache_dir::CACHE_DIR_PLACEHOLDER, Some(instance_id.as_str())),
+        (cache_dir::PROJECT_CACHE_DIR_PLACEHOLDER, None),
+    ] {
+        if uses_placeholder(&action.command, placeholder)
+            || uses_placeholder(&action.diff_command, placeholder)
+            || uses_placeholder(&environment, placeholder)
+            || action
+                .script
+                .as_ref()
+                .is_some_and(|s| s.contains(&script::placeholder_variable(placeholder)))
         {
-            return false;
+            let directory = cache_dir::prepare(root_directory, action_id)
+                .await
+                .context(format!(
+                    "Failed to set up cache directory for '{instance_id}'"
+                ))?;
+            placeholders.insert(placeholder.to_string(), directory);
+        }
+    }
+
+    let list_file = if action.files_via == FilesVia::File {
+        let files = action
+            .input_filters
+            .filtered(inputs::FILES_INPUTS, inputs, root_directory)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+        let list_file =
+            list_file::ListFile::new(&files, settings.temp_directory.as_deref()).await?;
+        placeholders.insert(
+            list_file::LIST_FILE_PLACEHOLDER.to_string(),
+            list_file.path().to_path_buf(),
+        );
+        Some(list_file)
+    } else {
+        None
+    };
+
+    Ok((placeholders, list_file))
+}
+
+/// The environment variables passing all placeholders to a script
+async fn script_environment(
+    action: &ActionDefinition,
+    inputs: &inputs::InputQuery,
+    root_directory: &Path,
+    placeholders: &args::Placeholders,
+) -> crate::SendableResult<Vec<(String, std::ffi::OsString)>> {
+    let mut result = placeholders
+        .iter()
+        .map(|(k, v)| (script::placeholder_variable(k), v.clone().into_os_string()))
+        .collect::<Vec<_>>();
+
+    for input in action.input_filters.inputs() {
+        if input == inputs::FILES_INPUTS && action.files_via == FilesVia::File {
+            continue;
+        }
+        let files = action
+            .input_filters
+            .filtered(input, inputs, root_directory)
+            .await?;
+        let mut value = std::ffi::OsString::new();
+        for (index, f) in files.iter().enumerate() {
+            if index > 0 {
+                value.push("\n");
+            }
+            value.push(f);
+        }
+        result.push((script::placeholder_variable(input), value));
+    }
+
+    Ok(result)
+}
+
+/// The environment variables of `action` with all placeholders expanded,
+/// followed by `extra_environment`
+///
+/// The `isolated_environment` is included when running `isolated`. The
+/// `PATH` starts with the `path_prepend` directories of the action and the
+/// project tool directories found.
+async fn action_environment(
+    action: &ActionDefinition,
+    isolated: bool,
+    extra_environment: &HashMap<String, String>,
+    inputs: &inputs::InputQuery,
+    root_directory: &Path,
+    placeholders: &args::Placeholders,
+) -> crate::SendableResult<Vec<(OsString, OsString)>> {
+    let isolated_environment = if isolated {
+        action.isolated_environment.as_slice()
+    } else {
+        &[]
+    };
+    let mut result = Vec::with_capacity(
+        action.environment.len() + isolated_environment.len() + extra_environment.len(),
+    );
+    for (key, value) in action.environment.iter().chain(isolated_environment) {
+        let value = if value.contains("{{") {
+            args::expand_environment_value(
+                value,
+                inputs.clone(),
+                root_directory,
+                &action.input_filters,
+                placeholders,
+            )
+            .await
+            .map_err(|e| format!("{e} in {key}"))?
+        } else {
+            OsString::from(value)
+        };
+        result.push((OsString::from(key), value));
+    }
+    let mut prepend = action
+        .path_prepend
+        .iter()
+        .map(|d| root_directory.join(d))
+        .collect::<Vec<_>>();
+    if action.project_tools {
+        prepend.extend(
+            PROJECT_TOOL_DIRECTORIES
+                .iter()
+                .map(|d| root_directory.join(d))
+                .filter(|d| d.is_dir()),
+        );
+    }
+    if !prepend.is_empty() {
+        let path = result
+            .iter()
+            .rev()
+            .find(|(k, _)| k == "PATH")
+            .map(|(_, v)| v.clone())
+            .or_else(|| std::env::var_os("PATH"))
+            .unwrap_or_default();
+        let path = std::env::join_paths(prepend.into_iter().chain(std::env::split_paths(&path)))
+            .map_err(|e| format!("{e} in path-prepend"))?;
+        result.push((OsString::from("PATH"), path));
+    }
+    result.extend(
+        extra_environment
+            .iter()
+            .map(|(k, v)| (OsString::from(k), OsString::from(v))),
+    );
+    Ok(result)
+}
+
+/// The directories (relative to the root directory) package managers install
+/// project-local tools into
+#[cfg(not(windows))]
+pub const PROJECT_TOOL_DIRECTORIES: &[&str] = &["node_modules/.bin", ".venv/bin"];
+/// The directories (relative to the root directory) package managers install
+/// project-local tools into
+#[cfg(windows)]
+pub const PROJECT_TOOL_DIRECTORIES: &[&str] = &["node_modules/.bin", ".venv/Scripts"];
+
+/// The name of the environment variable holding the number of files an
+/// action works on
+const FILE_COUNT_VARIABLE: &str = "BEAUTYTIPS_FILE_COUNT";
+
+/// The directories to run `action` in, one for each value of `input`
+///
+/// Values that are no directories are replaced by the directory containing
+/// them.
+async fn target_directories(
+    action: &ActionDefinition,
+    input: &str,
+    inputs: &inputs::InputQuery,
+    root_directory: &Path,
+) -> crate::SendableResult<Vec<PathBuf>> {
+    let mut result = Vec::new();
+    for value in action
+        .input_filters
+        .filtered(input, inputs, root_directory)
+        .await?
+    {
+        let path = root_directory.join(value);
+        let directory = if path.is_dir() {
+            path
+        } else {
+            path.parent().unwrap_or(root_directory).to_path_buf()
+        };
+        if !result.contains(&directory) {
+            result.push(directory);
         }
     }
-    true
+    Ok(result)
+}
+
+/// One run of the command of an action
+struct Invocation {
+    directory: PathBuf,
+    args: Vec<OsString>,
+}
+
+/// Everything needed to run the command of an action, except its arguments
+struct Invoker {
+    command: PathBuf,
+    environment: Vec<(OsString, OsString)>,
+    /// The output goes into a file, so it is not reported
+    capture_stdout: bool,
+    /// The error output goes into a file, so it is not reported
+    capture_stderr: bool,
+    /// Report the output of each invocation as it comes in, see
+    /// `OutputCondition::streams`
+    stream_output: bool,
+    /// Where large outputs go
+    spill: output::Spill,
+}
+
+impl Invoker {
+    /// The command line and directory of `invocation`, for humans
+    fn describe(&self, invocation: &Invocation) -> String {
+        let command_line = std::iter::once(self.command.as_os_str())
+            .chain(invocation.args.iter().map(OsString::as_os_str))
+            .map(|a| a.to_string_lossy())
+            .collect::<Vec<_>>();
+        format!(
+            "ran `{}` in {}",
+            shell_words::join(command_line),
+            invocation.directory.display()
+        )
+    }
+
+    async fn invoke(
+        &self,
+        executor: &dyn executor::CommandExecutor,
+        invocation: &Invocation,
+    ) -> crate::Result<executor::ProcessOutput> {
+        let Invocation { directory, args } = invocation;
+        let output = executor
+            .execute(
+                &self.command,
+                args,
+                directory,
+                &self.environment,
+                &self.spill,
+            )
+            .await?;
+
+        tracing::trace!(
+            "result of running {} {args:?} in {}: {output:?}",
+            self.command.display(),
+            directory.display()
+        );
+        Ok(output)
+    }
+}
+
+/// Run the command of `invoker` with all `invocations` of arguments,
+/// reporting the output as it comes in
+///
+/// With `parallel` set, the invocations run in parallel as far as the
+/// invocation limit in `settings` allows. Each action waits for one slot at
+/// a time, so actions waiting for the limit take turns. The outputs are
+/// returned in the order of the `invocations`.
+async fn invoke_all(
+    invoker: Arc<Invoker>,
+    invocations: Vec<Invocation>,
+    parallel: bool,
+    executor: &Arc<dyn executor::CommandExecutor>,
+    settings: &RunSettings,
+    action_id: &str,
+    sender: &ActionUpdateSender,
+) -> crate::Result<Vec<executor::ProcessOutput>> {
+    let report_output = |description: String, output: &executor::ProcessOutput| {
+        let status = output.status;
+        let output = invoker.stream_output.then(|| ActionUpdate::Output {
+            action_id: action_id.to_string(),
+            stdout: if invoker.capture_stdout {
+                Vec::new()
+            } else {
+                output.stdout.bytes().into_owned()
+            },
+            stderr: if invoker.capture_stderr {
+                Vec::new()
+            } else {
+                output.stderr.bytes().into_owned()
+            },
+        });
+        async move {
+            report_detail(sender, settings, action_id, || {
+                format!("{description}: {status}")
+            })
+            .await;
+            if let Some(output) = output {
+                report(sender, output).await;
+            }
+        }
+    };
+
+    if !parallel {
+        let mut outputs = Vec::with_capacity(invocations.len());
+        for invocation in invocations {
+            let output = invoker.invoke(executor.as_ref(), &invocation).await?;
+            report_output(invoker.describe(&invocation), &output).await;
+            outputs.push(output);
+        }
+        return Ok(outputs);
+    }
+
+    let mut outputs = std::iter::repeat_with(|| None)
+        .take(invocations.len())
+        .collect::<Vec<_>>();
+    let mut invocations = invocations.into_iter().enumerate().peekable();
+    let mut join_set = tokio::task::JoinSet::new();
+    let limit = &settings.invocation_limit;
+    let mut next_permit = Box::pin(limit.clone().acquire_owned());
+    loop {
+        tokio::select! {
+            permit = &mut next_permit, if invocations.peek().is_some() => {
+                let permit = permit.expect("The invocation limit is never closed");
+                next_permit.set(limit.clone().acquire_owned());
+                let (index, invocation) = invocations.next().expect("An invocation is left");
+                let invoker = invoker.clone();
+                let executor = executor.clone();
+                join_set.spawn(async move {
+                    let _permit = permit;
+                    let output = invoker.invoke(executor.as_ref(), &invocation).await;
+                    (index, invoker.describe(&invocation), output)
+                });
+            }
+            Some(result) = join_set.join_next() => {
+                let (index, description, output) = result.expect("Join Error found");
+                let output = output?;
+                report_output(description, &output).await;
+                outputs[index] = Some(output);
+            }
+            else => break,
+        }
+    }
+    Ok(outputs.into_iter().flatten().collect())
+}
+
+/// The number of `files` the `action` works on
+async fn file_count(
+    action: &ActionDefinition,
+    inputs: &inputs::InputQuery,
+    root_directory: &Path,
+) -> crate::SendableResult<usize> {
+    Ok(action
+        .input_filters
+        .filtered(inputs::FILES_INPUTS, inputs, root_directory)
+        .await?
+        .len())
+}
+
+/// Write the inline script of `action` (if any) and set up its environment
+async fn setup_script(
+    action: &ActionDefinition,
+    inputs: &inputs::InputQuery,
+    root_directory: &Path,
+    placeholders: &args::Placeholders,
+    settings: &RunSettings,
+) -> crate::Result<Option<(script::Script, Vec<(String, std::ffi::OsString)>)>> {
+    let Some(text) = &action.script else {
+        return Ok(None);
+    };
+
+    let environment = script_environment(action, inputs, root_directory, placeholders)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    let script = script::Script::new(text, settings.temp_directory.as_deref()).await?;
+    Ok(Some((script, environment)))
 }
 
-#[tracing::instrument(skip(inputs))]
+/// Write the output of all invocations into the `stdout_file` and
+/// `stderr_file` of `action`, creating missing directories
+///
+/// Returns a note on where the output went, one line per file written.
+async fn capture_output(
+    action: &ActionDefinition,
+    root_directory: &Path,
+    outputs: &[executor::ProcessOutput],
+) -> crate::Result<String> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut note = String::new();
+    for (file, output) in [
+        (
+            &action.stdout_file,
+            outputs.iter().map(|o| &o.stdout).collect::<Vec<_>>(),
+        ),
+        (
+            &action.stderr_file,
+            outputs.iter().map(|o| &o.stderr).collect::<Vec<_>>(),
+        ),
+    ] {
+        let Some(file) = file else {
+            continue;
+        };
+        let path = root_directory.join(file);
+        if let Some(directory) = path.parent() {
+            tokio::fs::create_dir_all(directory)
+                .await
+                .context(format!("Failed to create {}", directory.display()))?;
+        }
+        let mut target = tokio::fs::File::create(&path)
+            .await
+            .context(format!("Failed to create {}", path.display()))?;
+        let mut size = 0;
+        for o in output {
+            size += o
+                .copy_to(&mut target)
+                .await
+                .context(format!("Failed to write {}", path.display()))?;
+        }
+        target
+            .flush()
+            .await
+            .context(format!("Failed to write {}", path.display()))?;
+        let _ = writeln!(note, "Output written to {} ({size} bytes)", file.display());
+    }
+    Ok(note)
+}
+
+/// Claim the files `action` uses, waiting for conflicting actions to finish
+///
+/// The files used are those of all its inputs, including the `files` passed
+/// to the command without any input filter.
+async fn claim_files(
+    action: &ActionDefinition,
+    inputs: &inputs::InputQuery,
+    root_directory: &Path,
+    claims: &Arc<claims::FileClaims>,
+) -> crate::SendableResult<claims::ClaimGuard> {
+    let mut used_inputs = action.input_filters.inputs().collect::<HashSet<_>>();
+    let files_input = inputs::FILES_INPUTS.to_string();
+    if action.script.is_some() || uses_placeholder(&action.command, inputs::FILES_INPUTS) {
+        used_inputs.insert(&files_input);
+    }
+
+    let mut reads = HashSet::new();
+    for input in used_inputs {
+        reads.extend(
+            action
+                .input_filters
+                .filtered(input, inputs, root_directory)
+                .await?,
+        );
+    }
+
+    let writes = match &action.writes {
+        Writes::Nothing => HashSet::new(),
+        Writes::InputFiles => reads.clone(),
+        Writes::Patterns(patterns) => inputs::InputFilters::from(HashMap::from([(
+            inputs::FILES_INPUTS.to_string(),
+            patterns.clone(),
+        )]))
+        .filtered(inputs::FILES_INPUTS, inputs, root_directory)
+        .await?
+        .into_iter()
+        .collect(),
+    };
+
+    Ok(claims.claim(reads, writes).await)
+}
+
+/// The executor to run the commands of `action` with
+///
+/// Restricted sandboxes may only write to the temporary directory of the
+/// run and the cache directory of the action.
+fn restricted_executor(
+    action: &ActionDefinition,
+    settings: &RunSettings,
+    placeholders: &args::Placeholders,
+) -> Arc<dyn executor::CommandExecutor> {
+    if action.sandbox != Some(SandboxMode::Restricted) {
+        return settings.executor.clone();
+    }
+    let writable = [
+        settings.temp_directory.as_ref(),
+        placeholders.get(cache_dir::CACHE_DIR_PLACEHOLDER),
+        placeholders.get(cache_dir::PROJECT_CACHE_DIR_PLACEHOLDER),
+    ]
+    .into_iter()
+    .flatten()
+    .cloned()
+    .collect();
+    Arc::new(executor::RestrictedExecutor::new(
+        settings.executor.clone(),
+        writable,
+    ))
+}
+
+async fn setup_sandbox(
+    mode: &SandboxMode,
+    root_directory: &Path,
+    args: &mut args::Args,
+    settings: &RunSettings,
+) -> crate::Result<sandbox::Sandbox> {
+    let mut sandbox =
+        sandbox::Sandbox::new(mode, root_directory, settings.executor.clone()).await?;
+    let files = args.rebase(root_directory, sandbox.directory());
+    sandbox.copy_in(files).await?;
+    Ok(sandbox)
+}
+
+#[tracing::instrument(skip(inputs, claims))]
+#[allow(clippy::too_many_lines)]
 async fn run_single_action(
     current_directory: PathBuf,
     extra_environment: Arc<HashMap<String, String>>,
     sender: ActionUpdateSender,
-    action: &'static ActionDefinition,
+    action: Arc<ActionDefinition>,
     inputs: inputs::InputQuery,
+    claims: Arc<claims::FileClaims>,
+    settings: Arc<RunSettings>,
 ) -> crate::Result<()> {
-    tracing::debug!("running action '{}': {:?}", action.id, action.command);
-    let action_id = action.id.to_string();
+    let action = action.as_ref();
+    let action_id = action.instance_id();
+    tracing::debug!("running action '{action_id}': {:?}", action.command);
 
     sender
         .send(ActionUpdate::Started {
@@ -142,35 +1781,29 @@ async fn run_single_action(
         .await
         .expect("Failed to send start message to reporter");
 
-    if !has_unfiltered_input(&inputs, &action.input_filters, &current_directory).await {
+    let not_applicable = if action.enabled_when.is_met(&current_directory) {
+        find_empty_input(
+            &inputs,
+            &action.input_filters,
+            action.cwd_per_target.as_ref(),
+            &current_directory,
+        )
+        .await
+    } else {
+        Some(NotApplicableReason::ConditionNotMet)
+    };
+    if let Some(reason) = not_applicable {
         sender
             .send(ActionUpdate::Done {
                 action_id: action_id.clone(),
-                result: ActionResult::NotApplicable,
+                result: ActionResult::NotApplicable { reason },
             })
             .await
             .expect("Failed to send message to reporter");
         return Ok(());
     }
 
-    if std::env::var("SKIP")
-        .unwrap_or_default()
-        .split(',')
-        .any(|s| s == action_id)
-    {
-        tracing::trace!("Skipping '{}'", action_id);
-        report(
-            &sender,
-            ActionUpdate::Done {
-                action_id: action_id.clone(),
-                result: ActionResult::Skipped,
-            },
-        )
-        .await;
-        return Ok(());
-    }
-
-    let Some(command) = action.command.first() else {
+    if action.script.is_none() && action.command.is_empty() {
         tracing::error!("No command in action '{}'", action_id);
         let message = format!("No command defined in action '{action_id}'");
         sender
@@ -178,20 +1811,189 @@ async fn run_single_action(
                 action_id: action_id.clone(),
                 result: ActionResult::Error {
                     message: message.clone(),
+                    hint: None,
                 },
             })
             .await
             .expect("Failed to send message to reporter");
         return Err(anyhow::anyhow!(format!("Invalid configuration: {message}")));
+    }
+
+    report_input_details(&sender, &settings, action, &inputs, &current_directory).await;
+
+    let _claim = match claim_files(action, &inputs, &current_directory, &claims).await {
+        Ok(claim) => claim,
+        Err(e) => {
+            report(
+                &sender,
+                ActionUpdate::Done {
+                    action_id: action_id.clone(),
+                    result: ActionResult::Error {
+                        message: format!("Failed to claim files: {e}"),
+                        hint: None,
+                    },
+                },
+            )
+            .await;
+            return Ok(());
+        }
     };
 
+    let file_count = match file_count(action, &inputs, &current_directory).await {
+        Ok(count) => count,
+        Err(e) => {
+            report(
+                &sender,
+                ActionUpdate::Done {
+                    action_id: action_id.clone(),
+                    result: ActionResult::Error {
+                        message: format!("Failed to count files: {e}"),
+                        hint: None,
+                    },
+                },
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    let (placeholders, _list_file) =
+        match action_placeholders(action, &inputs, &current_directory, &settings).await {
+            Ok(placeholders) => placeholders,
+            Err(e) => {
+                report(
+                    &sender,
+                    ActionUpdate::Done {
+                        action_id: action_id.clone(),
+                        result: ActionResult::Error {
+                            message: format!("{e:#}"),
+                            hint: None,
+                        },
+                    },
+                )
+                .await;
+                return Ok(());
+            }
+        };
+
+    let full_run = action.full_run_threshold.is_some_and(|t| file_count > t);
+    if let Some(max_files) = action.max_files.filter(|m| !full_run && file_count > *m) {
+        report(
+            &sender,
+            ActionUpdate::Done {
+                action_id: action_id.clone(),
+                result: ActionResult::Error {
+                    message: format!(
+                        "Refusing to run on {file_count} files, more than the maximum of \
+                         {max_files}. Run on fewer files, narrow the `inputs` or raise the \
+                         `max-files` of the action."
+                    ),
+                    hint: None,
+                },
+            },
+        )
+        .await;
+        return Ok(());
+    }
+
+    let command_line = if full_run {
+        tracing::debug!("{file_count} files, running the full command of '{action_id}'");
+        report_detail(&sender, &settings, &action_id, || {
+            format!("{file_count} files, running the full command")
+        })
+        .await;
+        &action.full_command
+    } else {
+        &action.command
+    };
+    if action.script.is_none() && command_line.is_empty() {
+        report(
+            &sender,
+            ActionUpdate::Done {
+                action_id: action_id.clone(),
+                result: ActionResult::Error {
+                    message: if full_run {
+                        format!("{action_id} has no full command to run on {file_count} files")
+                    } else {
+                        format!("{action_id} has no command to run")
+                    },
+                    hint: None,
+                },
+            },
+        )
+        .await;
+        return Ok(());
+    }
+    let file_count = file_count.to_string();
+
     let args = args::parse_args(
-        &action.command,
-        inputs,
+        command_line,
+        inputs.clone(),
+        &current_directory,
+        &action.input_filters,
+        &placeholders,
+    )
+    .await;
+
+    let environment = match action_environment(
+        action,
+        settings.isolate,
+        &extra_environment,
+        &inputs,
+        &current_directory,
+        &placeholders,
+    )
+    .await
+    {
+        Ok(environment) => environment,
+        Err(e) => {
+            report(
+                &sender,
+                ActionUpdate::Done {
+                    action_id: action_id.clone(),
+                    result: ActionResult::Error {
+                        message: format!("Failed to expand environment: {e}"),
+                        hint: None,
+                    },
+                },
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    let script = match setup_script(
+        action,
+        &inputs,
         &current_directory,
-        &action.input_filters,
+        &placeholders,
+        &settings,
     )
-    .await;
+    .await
+    {
+        Ok(script) => script,
+        Err(e) => {
+            report(
+                &sender,
+                ActionUpdate::Done {
+                    action_id: action_id.clone(),
+                    result: ActionResult::Error {
+                        message: format!("Failed to set up script: {e:#}"),
+                        hint: None,
+                    },
+                },
+            )
+            .await;
+            return Ok(());
+        }
+    };
+    let (command, script_args, script_environment) = match &script {
+        Some((script, environment)) => {
+            let (command, args) = script.command();
+            (command, args, environment.as_slice())
+        }
+        None => (PathBuf::from(&command_line[0]), vec![], [].as_slice()),
+    };
 
     let mut args = match args {
         Ok(args) => args,
@@ -201,6 +2003,7 @@ async fn run_single_action(
                     action_id: action_id.clone(),
                     result: ActionResult::Error {
                         message: format!("Argument parsing failed: {e}"),
+                        hint: None,
                     },
                 })
                 .await
@@ -209,82 +2012,268 @@ async fn run_single_action(
         }
     };
 
-    let mut stdout = Vec::new();
-    let mut stderr = Vec::new();
-    let mut invalid_exit_code = false;
+    let sandbox = if let Some(mode) = action
+        .sandbox
+        .as_ref()
+        .filter(|m| **m != SandboxMode::Restricted)
+    {
+        match setup_sandbox(mode, &current_directory, &mut args, &settings).await {
+            Ok(sandbox) => Some(sandbox),
+            Err(e) => {
+                report(
+                    &sender,
+                    ActionUpdate::Done {
+                        action_id: action_id.clone(),
+                        result: ActionResult::Error {
+                            message: format!("Failed to set up sandbox: {e}"),
+                            hint: None,
+                        },
+                    },
+                )
+                .await;
+                return Ok(());
+            }
+        }
+    } else {
+        None
+    };
+    let command_directory = sandbox
+        .as_ref()
+        .map_or(current_directory.clone(), |s| s.directory().to_path_buf());
 
-    loop {
-        let output = tokio::process::Command::new(command)
-            .current_dir(current_directory.clone())
-            .args(args.args_iter())
-            .envs(
-                action
-                    .environment
+    let directories = match &action.cwd_per_target {
+        Some(input) => match target_directories(action, input, &inputs, &current_directory).await {
+            Ok(directories) => directories,
+            Err(e) => {
+                report(
+                    &sender,
+                    ActionUpdate::Done {
+                        action_id: action_id.clone(),
+                        result: ActionResult::Error {
+                            message: format!("Failed to find target directories: {e}"),
+                            hint: None,
+                        },
+                    },
+                )
+                .await;
+                return Ok(());
+            }
+        },
+        None => vec![current_directory.clone()],
+    };
+    // Target directories move into the sandbox as well
+    let directories = directories
+        .iter()
+        .map(|d| match d.strip_prefix(&current_directory) {
+            Ok(relative) if relative.as_os_str().is_empty() => command_directory.clone(),
+            Ok(relative) => command_directory.join(relative),
+            Err(_) => d.clone(),
+        })
+        .collect::<Vec<_>>();
+    let invocations = args
+        .invocations()
+        .into_iter()
+        .flat_map(|args| {
+            let args = script_args.iter().cloned().chain(args).collect::<Vec<_>>();
+            directories.iter().map(move |directory| Invocation {
+                directory: directory.clone(),
+                args: args.clone(),
+            })
+        })
+        .collect();
+
+    let spill = settings.spill.for_action(&action_id);
+    let invoker = Arc::new(Invoker {
+        command,
+        environment: environment
+            .iter()
+            .cloned()
+            .chain(
+                script_environment
                     .iter()
-                    .map(|(k, v)| (k, v))
-                    .chain(extra_environment.iter()),
+                    .map(|(k, v)| (k.into(), v.clone())),
             )
-            .output()
+            .chain(std::iter::once((
+                FILE_COUNT_VARIABLE.into(),
+                file_count.into(),
+            )))
+            .collect(),
+        capture_stdout: action.stdout_file.is_some(),
+        capture_stderr: action.stderr_file.is_some(),
+        stream_output: settings.stream_output && action.show_output.streams(),
+        spill: spill.clone(),
+    });
+    let executor = restricted_executor(action, &settings, &placeholders);
+    let invoked = invoke_all(
+        invoker,
+        invocations,
+        action.parallel_invocations,
+        &executor,
+        &settings,
+        &action_id,
+        &sender,
+    );
+    // Dropping the invocations kills the processes still running
+    let invoked = match action.timeout {
+        Some(timeout) => tokio::time::timeout(timeout, invoked)
             .await
-            .context(format!("Could not start '{command}'"))?;
+            .unwrap_or_else(|_| {
+                Err(anyhow::anyhow!(format!(
+                    "Timed out after {}s",
+                    timeout.as_secs_f32()
+                )))
+            }),
+        None => invoked.await,
+    };
+    let outputs = match invoked {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            report(
+                &sender,
+                ActionUpdate::Done {
+                    action_id: action_id.clone(),
+                    result: ActionResult::Error {
+                        message: format!("{e:#}"),
+                        hint: expand_hint(action, &inputs, &current_directory, &placeholders)
+                            .await
+                            .and_then(Result::ok)
+                            .or_else(|| action.hint.clone()),
+                    },
+                },
+            )
+            .await;
+            return Ok(());
+        }
+    };
 
-        tracing::trace!(
-            "result of running action '{}' ({} {}): {output:?}",
-            action_id,
-            command,
-            args.print()
-        );
+    let mut stdout = spill.buffer("stdout");
+    let mut stderr = spill.buffer("stderr");
+    let mut invalid_exit_code = false;
+
+    let capture_note = match capture_output(action, &current_directory, &outputs).await {
+        Ok(note) => note,
+        Err(e) => {
+            invalid_exit_code = true;
+            format!("Failed to capture output: {e:#}\n")
+        }
+    };
 
+    for output in outputs {
         if output.status.code() != Some(action.expected_exit_code) {
             tracing::debug!("Unexpected return code for action '{}'", action_id);
             invalid_exit_code = true;
         }
 
-        stdout.extend_from_slice(&output.stdout);
-        if !stdout.ends_with(b"\n") {
-            stdout.push(b'\n');
+        if action.stdout_file.is_none() {
+            stdout.append(output.stdout).await;
+            if !stdout.ends_with(b'\n') {
+                stdout.write(b"\n").await;
+            }
+        } else {
+            output.stdout.discard().await;
         }
-        stderr.extend_from_slice(&output.stderr);
-        if !stderr.ends_with(b"\n") {
-            stderr.push(b'\n');
+        if action.stderr_file.is_none() {
+            stderr.append(output.stderr).await;
+            if !stderr.ends_with(b'\n') {
+                stderr.write(b"\n").await;
+            }
+        } else {
+            output.stderr.discard().await;
         }
+    }
+    let stdout = stdout.finish().await;
+    let mut stderr = stderr.finish().await;
 
-        if args.increment() {
-            break;
+    let mut sandbox_diff = Vec::new();
+    let mut sandbox_error = None;
+    if let Some(sandbox) = sandbox {
+        let apply = action.sandbox_apply && !invalid_exit_code;
+        match sandbox.finish(apply).await {
+            Ok(diff) => {
+                if !apply && !diff.is_empty() {
+                    invalid_exit_code = true;
+                    sandbox_diff = diff;
+                }
+            }
+            Err(e) => {
+                invalid_exit_code = true;
+                sandbox_error = Some(format!("Failed to compare sandbox: {e}\n"));
+            }
         }
+        sandbox.close().await;
+    }
+    if let Some(error) = sandbox_error {
+        let mut buffer = spill.resume("stderr", stderr).await;
+        buffer.write(error.as_bytes()).await;
+        stderr = buffer.finish().await;
     }
 
     if invalid_exit_code {
         tracing::trace!("Failure running '{}'", action_id);
-        if action.show_output == OutputCondition::Never
-            || action.show_output == OutputCondition::Success
-        {
-            stdout = Vec::new();
-            stderr = Vec::new();
+        let stdout = action.show_output.apply(false, stdout).await;
+        let stderr = action.show_output.apply(false, stderr).await;
+        let mut stdout = spill.resume("stdout", stdout).await;
+        let mut stderr = spill.resume("stderr", stderr).await;
+
+        stdout.write(capture_note.as_bytes()).await;
+        stdout.write(&sandbox_diff).await;
+
+        let hint = match expand_hint(action, &inputs, &current_directory, &placeholders).await {
+            Some(Ok(hint)) => Some(hint),
+            Some(Err(e)) => {
+                stderr
+                    .write(format!("Failed to expand hint: {e}\n").as_bytes())
+                    .await;
+                action.hint.clone()
+            }
+            None => None,
+        };
+
+        if action.diff_on_failure {
+            if let Err(e) = run_diff_command(
+                &current_directory,
+                &environment,
+                action,
+                inputs,
+                &placeholders,
+                executor.as_ref(),
+                &mut stdout,
+            )
+            .await
+            {
+                stderr
+                    .write(format!("Failed to generate diff: {e}\n").as_bytes())
+                    .await;
+            }
         }
 
         report(
             &sender,
             ActionUpdate::Done {
                 action_id: action_id.clone(),
-                result: ActionResult::Warn { stdout, stderr },
+                result: ActionResult::Warn {
+                    stdout: stdout.finish().await,
+                    stderr: stderr.finish().await,
+                    hint,
+                },
             },
         )
         .await;
     } else {
         tracing::trace!("Success running '{}'", action_id);
-        if action.show_output == OutputCondition::Never
-            || action.show_output == OutputCondition::Failure
-        {
-            stdout = Vec::new();
-            stderr = Vec::new();
-        }
+        let stdout = action.show_output.apply(true, stdout).await;
+        let stderr = action.show_output.apply(true, stderr).await;
+        let mut stdout = spill.resume("stdout", stdout).await;
+        stdout.write(capture_note.as_bytes()).await;
 
         report(
             &sender,
             ActionUpdate::Done {
                 action_id: action_id.clone(),
-                result: ActionResult::Ok { stdout, stderr },
+                result: ActionResult::Ok {
+                    stdout: stdout.finish().await,
+                    stderr,
+                },
             },
         )
         .await;
@@ -292,6 +2281,113 @@ async fn run_single_action(
     Ok(())
 }
 
+/// Run the `actions` of one phase: Those running in parallel first, then
+/// those running sequentially
+async fn run_phase(
+    root_directory: &Path,
+    extra_environment: &Arc<HashMap<String, String>>,
+    sender: &ActionUpdateSender,
+    actions: &ActionSet,
+    cache_handle: &inputs::InputCacheHandle,
+    claims: &Arc<claims::FileClaims>,
+    settings: &Arc<RunSettings>,
+) -> crate::Result<()> {
+    let mut join_set = tokio::task::JoinSet::new();
+
+    // parallel phase:
+    tracing::trace!("Entering parallel run phase");
+    for a in actions.filter(|ad| !ad.run_sequentially) {
+        tracing::trace!("Spawning task for action {}", a.id);
+
+        join_set.spawn(run_single_action(
+            root_directory.to_path_buf(),
+            extra_environment.clone(),
+            sender.clone(),
+            a,
+            cache_handle.query(),
+            claims.clone(),
+            settings.clone(),
+        ));
+    }
+
+    tracing::trace!("Joining actions: {}", join_set.len());
+
+    while let Some(r) = join_set.join_next().await {
+        tracing::debug!("joined => in JS: {}", join_set.len());
+        r.expect("Join Error found")?;
+    }
+
+    // sequential phase:
+    tracing::trace!("Entering sequential run phase");
+    for a in actions.filter(|ad| ad.run_sequentially) {
+        tracing::trace!("Spawning task for action {}", a.id);
+
+        run_single_action(
+            root_directory.to_path_buf(),
+            extra_environment.clone(),
+            sender.clone(),
+            a,
+            cache_handle.query(),
+            claims.clone(),
+            settings.clone(),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Run the `actions` of one phase like `run_phase` does, returning the ids
+/// (without variants) of those failing the run
+async fn run_watched_phase(
+    root_directory: &Path,
+    extra_environment: &Arc<HashMap<String, String>>,
+    sender: &ActionUpdateSender,
+    actions: &ActionSet,
+    cache_handle: &inputs::InputCacheHandle,
+    claims: &Arc<claims::FileClaims>,
+    settings: &Arc<RunSettings>,
+) -> crate::Result<HashSet<String>> {
+    // Watch the results of the actions on their way to the reporter
+    let (phase_sender, mut phase_receiver) =
+        channel(DEFAULT_REPORT_CAPACITY, ReportOverflow::Block);
+    let forwarder = tokio::task::spawn({
+        let sender = sender.clone();
+        let actions = actions.clone();
+        async move {
+            let mut failed = HashSet::new();
+            while let Some(update) = phase_receiver.recv().await {
+                if let ActionUpdate::Done { action_id, result } = &update {
+                    if let Some(a) = actions
+                        .iter()
+                        .find(|a| a.instance_id() == *action_id && a.fails_run(result))
+                    {
+                        // Any failing instance fails the gate of the action
+                        failed.insert(a.id.to_string());
+                    }
+                }
+                if sender.send(update).await.is_err() {
+                    break;
+                }
+            }
+            failed
+        }
+    });
+    let result = run_phase(
+        root_directory,
+        extra_environment,
+        &phase_sender,
+        actions,
+        cache_handle,
+        claims,
+        settings,
+    )
+    .await;
+    drop(phase_sender);
+    let failed = forwarder.await.expect("Join Error found");
+    result?;
+    Ok(failed)
+}
+
 /// Run actions on `files`
 ///
 /// # Errors
@@ -301,54 +2397,742 @@ async fn run_single_action(
 pub async fn run(
     mut context: crate::ExecutionContext,
     sender: ActionUpdateSender,
-    actions: ActionDefinitionIterator<'static>,
+    actions: ActionSet,
+    mut settings: RunSettings,
 ) -> crate::Result<()> {
     tracing::trace!("Starting actions");
+    // Removed once all actions are done, even if running them failed
+    let temp_dir = temp_dir::TempDir::new().await?;
+    settings.temp_directory = Some(temp_dir.path().to_path_buf());
+    context.extra_environment.insert(
+        temp_dir::TEMP_DIR_VARIABLE.to_string(),
+        temp_dir.path().to_string_lossy().to_string(),
+    );
+    // Empty unless the files come from a version control system
+    let diff_file = temp_dir
+        .write_diff(&context.diff.take().unwrap_or_default())
+        .await?;
+    context.extra_environment.insert(
+        temp_dir::DIFF_FILE_VARIABLE.to_string(),
+        diff_file.to_string_lossy().to_string(),
+    );
+    settings.diff_file = Some(diff_file);
+
+    // The actions may run in the snapshot, so keep it until they are done
+    let snapshot = context.snapshot.take();
     let cache_handle = inputs::setup_input_cache(
         context.root_directory.clone(),
         std::mem::take(&mut context.files_to_process),
     );
-    let mut join_set = tokio::task::JoinSet::new();
 
     let extra_environment = Arc::new(context.extra_environment);
+    let claims = Arc::new(claims::FileClaims::default());
+    let settings = Arc::new(settings);
 
-    // parallel phase:
-    tracing::trace!("Entering parallel run phase");
-    for a in actions.clone().filter(|ad| !ad.run_sequentially) {
-        let cd = context.root_directory.clone();
-        let ee = extra_environment.clone();
-        let tx = sender.clone();
+    let gates = actions.filter(|ad| ad.gate);
+    let failed_gates = if gates.is_empty() {
+        HashSet::new()
+    } else {
+        run_watched_phase(
+            &context.root_directory,
+            &extra_environment,
+            &sender,
+            &gates,
+            &cache_handle,
+            &claims,
+            &settings,
+        )
+        .await?
+    };
+    let (gated_actions, actions) = actions
+        .filter(|ad| !ad.gate)
+        .partition(|ad| !settings.keep_going && ad.failed_gate(&failed_gates).is_some());
+    for a in gated_actions.iter() {
+        let gate = a.failed_gate(&failed_gates).expect("Partitioned above");
+        tracing::debug!("Gate {gate} failed, skipping {}", a.id);
+        report(
+            &sender,
+            ActionUpdate::Detail {
+                action_id: a.instance_id(),
+                message: format!("skipped due to the failed gate {gate}"),
+            },
+        )
+        .await;
+        report(
+            &sender,
+            ActionUpdate::Done {
+                action_id: a.instance_id(),
+                result: ActionResult::Skipped,
+            },
+        )
+        .await;
+    }
 
-        tracing::trace!("Spawning task for action {}", a.id);
+    let fast_actions = actions.filter(|ad| ad.phase == Phase::Fast);
+    let slow_actions = actions.filter(|ad| ad.phase == Phase::Slow);
+    let fast_failed = if fast_actions.is_empty() {
+        false
+    } else if slow_actions.is_empty() || settings.keep_going {
+        run_phase(
+            &context.root_directory,
+            &extra_environment,
+            &sender,
+            &fast_actions,
+            &cache_handle,
+            &claims,
+            &settings,
+        )
+        .await?;
+        false
+    } else {
+        !run_watched_phase(
+            &context.root_directory,
+            &extra_environment,
+            &sender,
+            &fast_actions,
+            &cache_handle,
+            &claims,
+            &settings,
+        )
+        .await?
+        .is_empty()
+    };
+
+    if fast_failed {
+        tracing::debug!("Fast actions failed, skipping the slow actions");
+        for a in slow_actions.iter() {
+            report(
+                &sender,
+                ActionUpdate::Done {
+                    action_id: a.instance_id(),
+                    result: ActionResult::Skipped,
+                },
+            )
+            .await;
+        }
+    } else {
+        run_phase(
+            &context.root_directory,
+            &extra_environment,
+            &sender,
+            &slow_actions,
+            &cache_handle,
+            &claims,
+            &settings,
+        )
+        .await?;
+    }
+
+    tracing::trace!("All actions started");
+
+    drop(sender);
+
+    cache_handle.finish().await;
 
-        join_set.spawn(run_single_action(cd, ee, tx, a, cache_handle.query()));
+    if let Some(cache_root) = crate::actions_cache_directory() {
+        let _ = tokio::task::spawn_blocking(move || {
+            cache_dir::prune(&cache_root, cache_dir::MAX_UNUSED);
+        })
+        .await;
     }
+    let spill_directory = settings.spill.directory.clone();
+    let _ = tokio::task::spawn_blocking(move || {
+        if let Some(spill_directory) = spill_directory {
+            output::prune(&spill_directory, output::MAX_AGE);
+        }
+        // Removing the directories blocks as well
+        drop(snapshot);
+        drop(temp_dir);
+    })
+    .await;
 
-    tracing::trace!("Joining actions: {}", join_set.len());
+    tracing::trace!("Done running actions");
+    Ok(())
+}
 
-    while let Some(r) = join_set.join_next().await {
-        tracing::debug!("joined => in JS: {}", join_set.len());
-        r.expect("Join Error found")?;
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use executor::testing::{ScriptedExecutor, ScriptedOutput};
+
+    pub(crate) fn action(command: &[&str]) -> ActionDefinition {
+        ActionDefinition {
+            id: "test/action".parse().unwrap(),
+            description: "A test action".to_string(),
+            tags: vec![],
+            run_sequentially: false,
+            command: command.iter().map(ToString::to_string).collect(),
+            script: None,
+            environment: vec![("TEST_VARIABLE".to_string(), "set".to_string())],
+            isolated_environment: vec![],
+            path_prepend: vec![],
+            project_tools: false,
+            show_output: OutputCondition::Always,
+            expected_exit_code: 0,
+            input_filters: inputs::InputFilters::default(),
+            diff_on_failure: false,
+            diff_command: vec![],
+            sandbox: None,
+            sandbox_apply: false,
+            writes: Writes::Nothing,
+            enabled_when: Condition::default(),
+            full_run_threshold: None,
+            full_command: vec![],
+            max_files: None,
+            files_via: FilesVia::Arguments,
+            parallel_invocations: false,
+            cwd_per_target: None,
+            hint: None,
+            stdout_file: None,
+            stderr_file: None,
+            matrix: vec![],
+            variant: None,
+            severity: Severity::Warning,
+            allow_failure: false,
+            phase: Phase::Slow,
+            gate: false,
+            needs: vec![],
+            timeout: None,
+        }
     }
 
-    // sequential phase:
-    tracing::trace!("Entering sequential run phase");
-    for a in actions.filter(|ad| ad.run_sequentially) {
-        let cd = context.root_directory.clone();
-        let ee = extra_environment.clone();
-        let tx = sender.clone();
+    /// Run `action` on `files` with `executor`, returning all its updates
+    async fn run_action(
+        action: ActionDefinition,
+        executor: Arc<ScriptedExecutor>,
+        files: &[&str],
+    ) -> Vec<ActionUpdate> {
+        run_action_with(
+            action,
+            RunSettings::new(2, 0).stream_output(true),
+            executor,
+            files,
+        )
+        .await
+    }
 
-        tracing::trace!("Spawning task for action {}", a.id);
+    async fn run_action_with(
+        action: ActionDefinition,
+        settings: RunSettings,
+        executor: Arc<ScriptedExecutor>,
+        files: &[&str],
+    ) -> Vec<ActionUpdate> {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let cache =
+            inputs::setup_input_cache(root.clone(), files.iter().map(PathBuf::from).collect());
+        let (tx, mut rx) = channel(DEFAULT_REPORT_CAPACITY, ReportOverflow::Unbounded);
 
-        run_single_action(cd, ee, tx, a, cache_handle.query()).await?;
+        run_single_action(
+            root,
+            Arc::new(HashMap::new()),
+            tx,
+            Arc::new(action),
+            cache.query(),
+            Arc::new(claims::FileClaims::default()),
+            Arc::new(settings.with_executor(executor)),
+        )
+        .await
+        .unwrap();
+        cache.finish().await;
+
+        let mut updates = vec![];
+        while let Some(update) = rx.recv().await {
+            updates.push(update);
+        }
+        updates
     }
 
-    tracing::trace!("All actions started");
+    fn result(updates: &[ActionUpdate]) -> &ActionResult {
+        match updates.last() {
+            Some(ActionUpdate::Done { result, .. }) => result,
+            u => panic!("Action is not done: {u:?}"),
+        }
+    }
 
-    drop(sender);
+    #[tokio::test]
+    async fn test_run_action_per_file() {
+        let executor = Arc::new(ScriptedExecutor::new([
+            ScriptedOutput::new(0, "first", ""),
+            ScriptedOutput::new(0, "second", "oops"),
+        ]));
+        let updates = run_action(
+            action(&["tool", "--check", "{{files}}"]),
+            executor.clone(),
+            &["a.rs", "b.rs"],
+        )
+        .await;
 
-    cache_handle.finish().await;
+        let executed = executor.executed();
+        assert_eq!(
+            executed.iter().map(|e| e.args.clone()).collect::<Vec<_>>(),
+            [["--check", "a.rs"], ["--check", "b.rs"]]
+        );
+        assert!(executed.iter().all(|e| e.command == Path::new("tool")
+            && e.directory == Path::new(env!("CARGO_MANIFEST_DIR"))));
+        assert!(executed[0]
+            .environment
+            .contains(&("TEST_VARIABLE".to_string(), "set".to_string())));
+        assert!(executed[0]
+            .environment
+            .contains(&(FILE_COUNT_VARIABLE.to_string(), "2".to_string())));
 
-    tracing::trace!("Done running actions");
-    Ok(())
+        assert!(matches!(updates[0], ActionUpdate::Started { .. }));
+        assert_eq!(
+            updates
+                .iter()
+                .filter(|u| matches!(u, ActionUpdate::Output { .. }))
+                .count(),
+            2
+        );
+        let ActionResult::Ok { stdout, stderr } = result(&updates) else {
+            panic!("Action did not succeed: {updates:?}");
+        };
+        assert_eq!(stdout.bytes().as_ref(), b"first\nsecond\n");
+        assert_eq!(stderr.bytes().as_ref(), b"\noops\n");
+
+        // Nobody wants the output as it comes in
+        let executor = Arc::new(ScriptedExecutor::new([ScriptedOutput::new(0, "first", "")]));
+        let updates = run_action_with(
+            action(&["tool", "{{files}}"]),
+            RunSettings::new(2, 0),
+            executor,
+            &["a.rs"],
+        )
+        .await;
+        assert!(!updates
+            .iter()
+            .any(|u| matches!(u, ActionUpdate::Output { .. })));
+        let ActionResult::Ok { stdout, .. } = result(&updates) else {
+            panic!("Action did not succeed: {updates:?}");
+        };
+        assert_eq!(stdout.bytes().as_ref(), b"first\n");
+    }
+
+    #[tokio::test]
+    async fn test_run_action_parallel_invocations() {
+        let mut parallel = action(&["tool", "{{files}}"]);
+        parallel.parallel_invocations = true;
+        let executor = Arc::new(ScriptedExecutor::new([
+            ScriptedOutput::new(0, "first", ""),
+            ScriptedOutput::new(0, "second", ""),
+            ScriptedOutput::new(0, "third", ""),
+        ]));
+        let updates = run_action(parallel, executor.clone(), &["a.rs", "b.rs", "c.rs"]).await;
+
+        assert_eq!(executor.executed().len(), 3);
+        let ActionResult::Ok { stdout, .. } = result(&updates) else {
+            panic!("Action did not succeed: {updates:?}");
+        };
+        assert_eq!(stdout.bytes().as_ref(), b"first\nsecond\nthird\n");
+    }
+
+    #[tokio::test]
+    async fn test_run_action_failure() {
+        let mut failing = action(&["tool", "{{files...}}"]);
+        failing.show_output = OutputCondition::Success;
+        failing.diff_on_failure = true;
+        failing.diff_command = vec!["tool".to_string(), "--diff".to_string()];
+        failing.hint = Some("Run `tool --fix {{files...}}`".to_string());
+
+        let executor = Arc::new(ScriptedExecutor::new([
+            ScriptedOutput::new(1, "hidden", ""),
+            ScriptedOutput::new(0, "-old\n+new\n", ""),
+        ]));
+        let updates = run_action(failing, executor.clone(), &["a.rs", "b.rs"]).await;
+
+        assert_eq!(
+            executor
+                .executed()
+                .iter()
+                .map(|e| e.args.clone())
+                .collect::<Vec<_>>(),
+            [vec!["a.rs", "b.rs"], vec!["--diff"]]
+        );
+        let ActionResult::Warn {
+            stdout,
+            stderr,
+            hint,
+        } = result(&updates)
+        else {
+            panic!("Action did not fail: {updates:?}");
+        };
+        assert_eq!(stdout.bytes().as_ref(), b"-old\n+new\n");
+        assert!(stderr.is_empty());
+        assert_eq!(hint.as_deref(), Some("Run `tool --fix a.rs b.rs`"));
+    }
+
+    #[tokio::test]
+    async fn test_run_action_environment_placeholders() {
+        let mut with_environment = action(&["tool"]);
+        with_environment.environment = vec![
+            ("STUBS".to_string(), "{{top:directory}}/stubs".to_string()),
+            ("COUNT".to_string(), "{{files:count}} files".to_string()),
+        ];
+
+        let executor = Arc::new(ScriptedExecutor::default());
+        run_action(with_environment, executor.clone(), &["a.rs", "b.rs"]).await;
+
+        let environment = &executor.executed()[0].environment;
+        assert!(environment.contains(&(
+            "STUBS".to_string(),
+            format!("{}/stubs", env!("CARGO_MANIFEST_DIR"))
+        )));
+        assert!(environment.contains(&("COUNT".to_string(), "2 files".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_run_action_capture_output() {
+        let directory =
+            PathBuf::from("target").join(format!("beautytips-capture-test-{}", std::process::id()));
+        let mut capturing = action(&["tool", "{{files}}"]);
+        capturing.stdout_file = Some(directory.join("report.txt"));
+
+        let executor = Arc::new(ScriptedExecutor::new([
+            ScriptedOutput::new(0, "a is fine\n", "a warning\n"),
+            ScriptedOutput::new(0, "b is fine\n", ""),
+        ]));
+        let updates = run_action(capturing, executor, &["a.rs", "b.rs"]).await;
+
+        assert!(updates.iter().all(|u| match u {
+            ActionUpdate::Output { stdout, .. } => stdout.is_empty(),
+            _ => true,
+        }));
+        let ActionResult::Ok { stdout, stderr } = result(&updates) else {
+            panic!("Action did not succeed: {updates:?}");
+        };
+        let report = directory.join("report.txt");
+        assert_eq!(
+            String::from_utf8_lossy(&stdout.bytes()),
+            format!("Output written to {} (20 bytes)\n", report.display())
+        );
+        assert_eq!(stderr.bytes().as_ref(), b"a warning\n");
+
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        assert_eq!(
+            std::fs::read_to_string(root.join(&report)).unwrap(),
+            "a is fine\nb is fine\n"
+        );
+        std::fs::remove_dir_all(root.join(directory)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_action_isolated_environment() {
+        let mut isolated = action(&["tool"]);
+        isolated.isolated_environment = vec![
+            (
+                "TARGET_DIR".to_string(),
+                "{{top:directory}}/target".to_string(),
+            ),
+            ("TEST_VARIABLE".to_string(), "isolated".to_string()),
+        ];
+        let root = env!("CARGO_MANIFEST_DIR");
+
+        let executor = Arc::new(ScriptedExecutor::default());
+        run_action(isolated.clone(), executor.clone(), &["a.rs"]).await;
+        let environment = &executor.executed()[0].environment;
+        assert!(!environment.iter().any(|(k, _)| k == "TARGET_DIR"));
+        assert!(environment.contains(&("TEST_VARIABLE".to_string(), "set".to_string())));
+
+        let executor = Arc::new(ScriptedExecutor::default());
+        let settings = RunSettings::new(2, 0).isolated(true);
+        run_action_with(isolated, settings, executor.clone(), &["a.rs"]).await;
+        let environment = &executor.executed()[0].environment;
+        assert!(environment.contains(&("TARGET_DIR".to_string(), format!("{root}/target"))));
+        // The isolated environment wins
+        assert_eq!(
+            environment
+                .iter()
+                .rev()
+                .find(|(k, _)| k == "TEST_VARIABLE")
+                .map(|(_, v)| v.as_str()),
+            Some("isolated")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_action_temp_dir() {
+        let mut with_temp_dir = action(&["tool", "--scratch={{temp_dir}}/out"]);
+        with_temp_dir.environment = vec![("SCRATCH".to_string(), "{{temp_dir}}".to_string())];
+        assert!(with_temp_dir.validate().is_ok());
+
+        let executor = Arc::new(ScriptedExecutor::default());
+        let mut settings = RunSettings::new(2, 0);
+        settings.temp_directory = Some(PathBuf::from("/tmp/beautytips-run-1"));
+        run_action_with(with_temp_dir, settings, executor.clone(), &["a.rs"]).await;
+        let executed = &executor.executed()[0];
+        assert_eq!(executed.args, ["--scratch=/tmp/beautytips-run-1/out"]);
+        assert!(executed
+            .environment
+            .contains(&("SCRATCH".to_string(), "/tmp/beautytips-run-1".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_run_action_matrix() {
+        let mut with_matrix = action(&["tool", "--python={{matrix.python}}", "{{files}}"]);
+        with_matrix.matrix = vec![
+            (
+                "python".to_string(),
+                vec!["3.10".to_string(), "3.12".to_string()],
+            ),
+            ("os".to_string(), vec!["linux".to_string()]),
+        ];
+        with_matrix.environment = vec![("OS".to_string(), "{{matrix.os}}".to_string())];
+        assert!(with_matrix.validate().is_ok());
+
+        let instances = ActionSet::from_iter([with_matrix]).expand_matrix();
+        assert_eq!(
+            instances.ids().collect::<Vec<_>>(),
+            ["test/action[3.10,linux]", "test/action[3.12,linux]"]
+        );
+
+        let executor = Arc::new(ScriptedExecutor::default());
+        let instance = instances.iter().last().unwrap().clone();
+        let updates = run_action(instance, executor.clone(), &["a.py"]).await;
+
+        let ActionUpdate::Done { action_id, .. } = updates.last().unwrap() else {
+            panic!("Action is not done: {updates:?}");
+        };
+        assert_eq!(action_id, "test/action[3.12,linux]");
+        let executed = &executor.executed()[0];
+        assert_eq!(executed.args, ["--python=3.12", "a.py"]);
+        assert!(executed
+            .environment
+            .contains(&("OS".to_string(), "linux".to_string())));
+    }
+
+    #[test]
+    fn test_validate_executables() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+        let mut missing = action(&["beautytips-no-such-tool", "{{files}}"]);
+        missing.diff_command = vec!["./Cargo.toml".to_string()];
+        assert!(missing.validate().is_ok());
+        let error = missing.validate_with(Some(root)).unwrap_err().to_string();
+        assert!(error.contains("the executable 'beautytips-no-such-tool' of the command"));
+        assert!(!error.contains("diff-command"));
+
+        missing.path_prepend = vec![PathBuf::from("src")];
+        missing.command[0] = "main.rs".to_string();
+        assert!(missing.validate_with(Some(root)).is_ok());
+
+        missing.command[0] = "beautytips-no-such-tool".to_string();
+        missing.enabled_when.os = Some("plan9".to_string());
+        assert!(missing.validate_with(Some(root)).is_ok());
+    }
+
+    #[test]
+    fn test_validate_matrix() {
+        let mut invalid = action(&["tool", "{{matrix.python}}", "{{matrix.node}}"]);
+        invalid.matrix = vec![
+            ("python".to_string(), vec![]),
+            ("no-dash".to_string(), vec!["a,b".to_string()]),
+            (
+                "version".to_string(),
+                vec![String::new(), "2\n".to_string()],
+            ),
+        ];
+        let error = invalid.validate().unwrap_err().to_string();
+        assert!(error.contains("the matrix has no values for 'python'"));
+        assert!(error.contains("'no-dash' is not a valid name"));
+        assert!(error.contains("the matrix values of 'no-dash' can not contain"));
+        assert!(error.contains("the matrix has an empty value for 'version'"));
+        assert!(error.contains("the matrix values of 'version' can not contain"));
+        assert!(error.contains("'{{matrix.node}}' in the command"));
+        assert!(!error.contains("'{{matrix.python}}'"));
+    }
+
+    #[test]
+    fn test_action_handle() {
+        let handle = ActionHandle::from(&action(&["true"]));
+        assert_eq!(handle.id, "test/action");
+        assert_eq!(handle.source, "test");
+        assert_eq!(handle.description, "A test action");
+
+        let handle = ActionHandle::from_id("mypy/check[3.10]");
+        assert_eq!(handle.source, "mypy");
+        assert!(handle.description.is_empty());
+        assert_eq!(ActionHandle::from_id("no_source").source, "");
+    }
+
+    #[test]
+    fn test_severity() {
+        let warn = || ActionResult::Warn {
+            stdout: b"bad style\n".to_vec().into(),
+            stderr: ActionOutput::default(),
+            hint: Some("run `tool --fix`".to_string()),
+        };
+        let error = || ActionResult::Error {
+            message: "tool crashed".to_string(),
+            hint: None,
+        };
+
+        assert_eq!(Severity::Warning.apply(warn()), warn());
+        assert_eq!(Severity::Warning.apply(error()), error());
+        assert_eq!(
+            Severity::Error.apply(warn()),
+            ActionResult::Error {
+                message: "bad style".to_string(),
+                hint: Some("run `tool --fix`".to_string()),
+            }
+        );
+        assert_eq!(Severity::Error.apply(error()), error());
+        assert_eq!(
+            Severity::Info.apply(warn()),
+            ActionResult::Ok {
+                stdout: b"bad style\n".to_vec().into(),
+                stderr: ActionOutput::default()
+            }
+        );
+        assert_eq!(
+            Severity::Info.apply(error()),
+            ActionResult::Ok {
+                stdout: ActionOutput::default(),
+                stderr: b"tool crashed".to_vec().into()
+            }
+        );
+        assert_eq!(
+            Severity::Info.apply(ActionResult::Skipped),
+            ActionResult::Skipped
+        );
+    }
+
+    #[test]
+    fn test_fails_run() {
+        let warn = ActionResult::Warn {
+            stdout: ActionOutput::default(),
+            stderr: ActionOutput::default(),
+            hint: None,
+        };
+        let ok = ActionResult::Ok {
+            stdout: ActionOutput::default(),
+            stderr: ActionOutput::default(),
+        };
+
+        let mut a = action(&["tool"]);
+        assert!(a.fails_run(&warn));
+        assert!(!a.fails_run(&ok));
+        assert!(!a.fails_run(&ActionResult::Skipped));
+
+        a.severity = Severity::Info;
+        assert!(!a.fails_run(&warn));
+
+        a.severity = Severity::Error;
+        a.allow_failure = true;
+        assert!(!a.fails_run(&warn));
+    }
+
+    #[test]
+    fn test_failed_gate() {
+        let mut a = action(&["tool"]);
+        let failed = HashSet::from(["cargo/check".to_string()]);
+        assert_eq!(a.failed_gate(&failed), None);
+
+        a.needs = vec!["rust/check".parse().unwrap()];
+        assert_eq!(a.failed_gate(&failed), None);
+
+        a.needs.push("cargo/check".parse().unwrap());
+        assert_eq!(
+            a.failed_gate(&failed).map(crate::ActionId::as_str),
+            Some("cargo/check")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_action_not_applicable() {
+        let mut rust_only = action(&["tool", "{{files}}"]);
+        rust_only.input_filters = inputs::InputFilters::try_from(HashMap::from([(
+            inputs::FILES_INPUTS.to_string(),
+            vec!["**/*.rs".to_string()],
+        )]))
+        .unwrap();
+
+        let executor = Arc::new(ScriptedExecutor::default());
+        let updates = run_action(rust_only, executor.clone(), &["README.md"]).await;
+
+        assert!(executor.executed().is_empty());
+        assert!(matches!(
+            result(&updates),
+            ActionResult::NotApplicable {
+                reason: NotApplicableReason::NoInputFiles { candidates: 1, .. }
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_run_action_max_files() {
+        let mut limited = action(&["tool", "{{files...}}"]);
+        limited.max_files = Some(1);
+
+        let executor = Arc::new(ScriptedExecutor::default());
+        let updates = run_action(limited.clone(), executor.clone(), &["a.rs", "b.rs"]).await;
+        assert!(executor.executed().is_empty());
+        assert!(matches!(result(&updates), ActionResult::Error { .. }));
+
+        // The full command runs on all files anyway
+        limited.full_run_threshold = Some(1);
+        limited.full_command = vec!["tool".to_string(), "--all".to_string()];
+        let executor = Arc::new(ScriptedExecutor::new([ScriptedOutput::new(0, "", "")]));
+        let updates = run_action(limited.clone(), executor.clone(), &["a.rs", "b.rs"]).await;
+        assert_eq!(executor.executed()[0].args, ["--all"]);
+        assert!(matches!(result(&updates), ActionResult::Ok { .. }));
+
+        // Unvalidated actions without full command fail instead of panicking
+        limited.full_command = vec![];
+        let executor = Arc::new(ScriptedExecutor::default());
+        let updates = run_action(limited, executor.clone(), &["a.rs", "b.rs"]).await;
+        assert!(executor.executed().is_empty());
+        assert!(matches!(result(&updates), ActionResult::Error { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_run_action_output_condition() {
+        let long = (1..=30).map(|i| i.to_string() + "\n").collect::<String>();
+        let mut summarized = action(&["tool"]);
+        summarized.show_output = OutputCondition::Summary;
+
+        let executor = Arc::new(ScriptedExecutor::new([ScriptedOutput::new(1, &long, "")]));
+        let updates = run_action(summarized, executor, &[]).await;
+        assert!(!updates
+            .iter()
+            .any(|u| matches!(u, ActionUpdate::Output { .. })));
+        let ActionResult::Warn { stdout, .. } = result(&updates) else {
+            panic!("Action did not fail: {updates:?}");
+        };
+        let stdout = String::from_utf8_lossy(&stdout.bytes()).to_string();
+        assert!(stdout.starts_with("1\n2\n"));
+        assert!(stdout.contains("10\n[... 10 lines omitted ...]\n21\n"));
+        assert!(stdout.ends_with("30\n"));
+
+        let mut on_failure = action(&["tool"]);
+        on_failure.show_output = OutputCondition::Failure;
+        let executor = Arc::new(ScriptedExecutor::new([ScriptedOutput::new(0, "noise", "")]));
+        let updates = run_action(on_failure, executor, &[]).await;
+        assert!(!updates
+            .iter()
+            .any(|u| matches!(u, ActionUpdate::Output { .. })));
+        let ActionResult::Ok { stdout, stderr } = result(&updates) else {
+            panic!("Action did not succeed: {updates:?}");
+        };
+        assert!(stdout.is_empty() && stderr.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_run_action_path_prepend() {
+        let mut local = action(&["eslint", "{{files...}}"]);
+        local.path_prepend = vec![PathBuf::from("node_modules/.bin")];
+
+        let executor = Arc::new(ScriptedExecutor::default());
+        run_action(local, executor.clone(), &["a.js"]).await;
+        let executed = executor.executed();
+        let (_, path) = executed[0]
+            .environment
+            .iter()
+            .find(|(k, _)| k == "PATH")
+            .expect("PATH is set");
+        assert_eq!(
+            std::env::split_paths(path).next(),
+            Some(Path::new(env!("CARGO_MANIFEST_DIR")).join("node_modules/.bin"))
+        );
+    }
 }
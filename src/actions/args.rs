@@ -4,6 +4,7 @@
 // cSpell: ignore concatcp dotdotdot starstar
 
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
 
@@ -62,12 +63,32 @@ impl Args {
         self.0.iter().map(Arg::current)
     }
 
-    pub(crate) fn print(&self) -> String {
-        self.0
-            .iter()
-            .map(|a| shell_words::quote(&a.current().to_string_lossy()).to_string())
-            .collect::<Vec<_>>()
-            .join(" ")
+    /// Redirect all arguments that are paths inside `from` into `to`
+    ///
+    /// Returns the redirected paths, relative to `from`.
+    pub(crate) fn rebase(&mut self, from: &Path, to: &Path) -> Vec<PathBuf> {
+        let mut result = Vec::new();
+        for a in &mut self.0 {
+            for v in &mut a.values {
+                if let Ok(relative) = Path::new(v.as_os_str()).strip_prefix(from) {
+                    let relative = relative.to_path_buf();
+                    *v = to.join(&relative).into_os_string();
+                    result.push(relative);
+                }
+            }
+        }
+        result
+    }
+
+    /// The arguments of all invocations, one after the other
+    pub(crate) fn invocations(&mut self) -> Vec<Vec<OsString>> {
+        let mut result = Vec::new();
+        loop {
+            result.push(self.args_iter().map(OsStr::to_os_string).collect());
+            if self.increment() {
+                return result;
+            }
+        }
     }
 }
 
@@ -142,12 +163,47 @@ fn split_arg(arg: &str) -> Vec<String> {
     result
 }
 
+/// The names of the placeholders used in `arg`, without any `...` suffix
+///
+/// Reports the first malformed placeholder, like an unterminated one.
+pub(crate) fn placeholder_names(arg: &str) -> Result<Vec<String>, String> {
+    let mut result = Vec::new();
+    for part in split_arg(arg) {
+        let Some(name) = part.strip_prefix("{{") else {
+            continue;
+        };
+        let Some(name) = name.strip_suffix("}}") else {
+            return Err(format!("the placeholder '{part}' is not terminated"));
+        };
+        let name = name.strip_suffix("...").unwrap_or(name);
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '{' || c == '}') {
+            return Err(format!("'{part}' is not a valid placeholder"));
+        }
+        result.push(name.to_string());
+    }
+    Ok(result)
+}
+
+/// Placeholders with a fixed value, checked before inputs
+pub(crate) type Placeholders = HashMap<String, PathBuf>;
+
+/// Suffix of placeholders resolving to the number of files of an input
+const COUNT_SUFFIX: &str = ":count";
+
+/// The input a placeholder `name` refers to, if any
+///
+/// `{{files:count}}` refers to the `files` input, just like `{{files}}`.
+pub(crate) fn placeholder_input(name: &str) -> &str {
+    name.strip_suffix(COUNT_SUFFIX).unwrap_or(name)
+}
+
 #[tracing::instrument(skip(inputs))]
 async fn input_arg(
     arg: &str,
     inputs: inputs::InputQuery,
     root_directory: &Path,
     input_filters: &inputs::InputFilters,
+    placeholders: &Placeholders,
 ) -> crate::SendableResult<Option<(Vec<PathBuf>, bool)>> {
     if arg.starts_with("{{") && arg.ends_with("}}") {
         let input_name = &arg[2..(arg.len() - 2)];
@@ -157,6 +213,18 @@ async fn input_arg(
             (input_name, false)
         };
 
+        if let Some(value) = placeholders.get(input_name) {
+            return Ok(Some((vec![value.clone()], is_array)));
+        }
+
+        if let Some(input_name) = input_name.strip_suffix(COUNT_SUFFIX) {
+            let count = input_filters
+                .filtered(input_name, &inputs, root_directory)
+                .await?
+                .len();
+            return Ok(Some((vec![PathBuf::from(count.to_string())], false)));
+        }
+
         let paths = input_filters
             .filtered(input_name, &inputs, root_directory)
             .await?;
@@ -172,6 +240,7 @@ pub(crate) async fn parse_arg(
     inputs: inputs::InputQuery,
     root_directory: &Path,
     input_filters: &inputs::InputFilters,
+    placeholders: &Placeholders,
 ) -> crate::SendableResult<Vec<Arg>> {
     let argument_parts = split_arg(arg);
 
@@ -180,7 +249,7 @@ pub(crate) async fn parse_arg(
     if argument_parts.len() == 1 {
         let arg = &argument_parts[0];
         if let Some((paths, is_array)) =
-            input_arg(arg, inputs, root_directory, input_filters).await?
+            input_arg(arg, inputs, root_directory, input_filters, placeholders).await?
         {
             if is_array {
                 result.extend(
@@ -200,8 +269,14 @@ pub(crate) async fn parse_arg(
         let mut extended_arg = vec![String::new()];
 
         for p in &argument_parts {
-            if let Some((paths, is_array)) =
-                input_arg(p, inputs.clone(), root_directory, input_filters).await?
+            if let Some((paths, is_array)) = input_arg(
+                p,
+                inputs.clone(),
+                root_directory,
+                input_filters,
+                placeholders,
+            )
+            .await?
             {
                 if is_array {
                     let total = paths
@@ -237,17 +312,102 @@ pub(crate) async fn parse_arg(
     Ok(result)
 }
 
+/// Replace all placeholders in the free-form `text`, e.g. a hint
+///
+/// Paths are shell-quoted and relative to `root_directory`, several values
+/// are separated by spaces.
+#[tracing::instrument(skip(inputs))]
+pub(crate) async fn expand_text(
+    text: &str,
+    inputs: inputs::InputQuery,
+    root_directory: &Path,
+    input_filters: &inputs::InputFilters,
+    placeholders: &Placeholders,
+) -> crate::SendableResult<String> {
+    let mut result = String::with_capacity(text.len());
+    for part in split_arg(text) {
+        let Some((paths, _)) = input_arg(
+            &part,
+            inputs.clone(),
+            root_directory,
+            input_filters,
+            placeholders,
+        )
+        .await?
+        else {
+            result.push_str(&part);
+            continue;
+        };
+        let values = paths
+            .iter()
+            .map(|p| {
+                let p = p.strip_prefix(root_directory).unwrap_or(p);
+                shell_words::quote(&p.to_string_lossy()).to_string()
+            })
+            .collect::<Vec<_>>();
+        result.push_str(&values.join(" "));
+    }
+    Ok(result)
+}
+
+/// Separates several values in an environment variable, just like in `PATH`
+const ENVIRONMENT_SEPARATOR: &str = if cfg!(windows) { ";" } else { ":" };
+
+/// Replace all placeholders in the value of an environment variable
+///
+/// Paths are used as they are, without any quoting. Several values are
+/// separated like in `PATH`, so `{{files}}` works for variables listing paths.
+#[tracing::instrument(skip(inputs))]
+pub(crate) async fn expand_environment_value(
+    value: &str,
+    inputs: inputs::InputQuery,
+    root_directory: &Path,
+    input_filters: &inputs::InputFilters,
+    placeholders: &Placeholders,
+) -> crate::SendableResult<OsString> {
+    let mut result = OsString::with_capacity(value.len());
+    for part in split_arg(value) {
+        let Some((paths, _)) = input_arg(
+            &part,
+            inputs.clone(),
+            root_directory,
+            input_filters,
+            placeholders,
+        )
+        .await?
+        else {
+            result.push(&part);
+            continue;
+        };
+        for (index, p) in paths.iter().enumerate() {
+            if index > 0 {
+                result.push(ENVIRONMENT_SEPARATOR);
+            }
+            result.push(p);
+        }
+    }
+    Ok(result)
+}
+
 #[tracing::instrument(skip(inputs))]
 pub(crate) async fn parse_args(
     args: &[String],
     inputs: inputs::InputQuery,
     root_directory: &Path,
     input_filters: &inputs::InputFilters,
+    placeholders: &Placeholders,
 ) -> crate::SendableResult<Args> {
-    let mut parsed_args = Vec::with_capacity(args.len() - 1);
+    let mut parsed_args = Vec::with_capacity(args.len().saturating_sub(1));
 
     for a in args.iter().skip(1) {
-        let filtered_args = parse_arg(a, inputs.clone(), root_directory, input_filters).await?;
+        let filtered_args = parse_arg(
+            a,
+            inputs.clone(),
+            root_directory,
+            input_filters,
+            placeholders,
+        )
+        .await?;
         parsed_args.extend_from_slice(&filtered_args);
     }
 
@@ -284,6 +444,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_placeholder_names() {
+        assert_eq!(
+            placeholder_names("--out={{cache_dir}}/{{files...}}").unwrap(),
+            ["cache_dir", "files"]
+        );
+        assert!(placeholder_names("{}").unwrap().is_empty());
+        assert!(placeholder_names("{{files}").is_err());
+        assert!(placeholder_names("{{files").is_err());
+        assert!(placeholder_names("{{}}").is_err());
+        assert!(placeholder_names("{{ files }}").is_err());
+    }
+
+    #[test]
+    fn test_invocations() {
+        let arg = |values: &[&str]| Arg::new(values.iter().map(OsString::from).collect());
+        let mut args = Args(vec![arg(&["-v"]), arg(&["a", "b"]), arg(&["1", "2"])]);
+
+        assert_eq!(
+            args.invocations(),
+            [
+                ["-v", "a", "1"],
+                ["-v", "b", "1"],
+                ["-v", "a", "2"],
+                ["-v", "b", "2"]
+            ]
+        );
+        // All arguments are back at their start
+        assert_eq!(args.invocations().len(), 4);
+    }
+
     const ROOT_DIR: &str = if cfg!(windows) {
         "C:\\51bb3d94"
     } else {
@@ -335,7 +526,82 @@ mod tests {
 
         let root_directory = PathBuf::from(ROOT_DIR);
 
-        input_arg(arg, input_cache.query(), &root_directory, &filter).await
+        let placeholders =
+            Placeholders::from([("cache_dir".to_string(), PathBuf::from("/tmp/cache"))]);
+
+        input_arg(
+            arg,
+            input_cache.query(),
+            &root_directory,
+            &filter,
+            &placeholders,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_expand_text() {
+        let input_cache = inputs::setup_input_cache(
+            PathBuf::from(ROOT_DIR),
+            vec![PathBuf::from(PATH_0), PathBuf::from(PATH_3)],
+        );
+        let filter = crate::InputFilters::from(HashMap::from([(
+            "files".to_string(),
+            vec![glob::Pattern::new("**/*.md").unwrap()],
+        )]));
+
+        let text = expand_text(
+            "run `fix {{files...}}` ({{files:count}} files)",
+            input_cache.query(),
+            Path::new(ROOT_DIR),
+            &filter,
+            &Placeholders::new(),
+        )
+        .await
+        .unwrap();
+        let doc = Path::new("docs").join("doc.md");
+        assert_eq!(
+            text,
+            format!("run `fix README.md {}` (2 files)", doc.display())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expand_environment_value() {
+        let input_cache = inputs::setup_input_cache(
+            PathBuf::from(ROOT_DIR),
+            vec![PathBuf::from(PATH_0), PathBuf::from(PATH_3)],
+        );
+        let filter = crate::InputFilters::from(HashMap::from([(
+            "files".to_string(),
+            vec![glob::Pattern::new("**/*.md").unwrap()],
+        )]));
+        let expand = |value: &'static str| {
+            let query = input_cache.query();
+            let filter = filter.clone();
+            async move {
+                expand_environment_value(
+                    value,
+                    query,
+                    Path::new(ROOT_DIR),
+                    &filter,
+                    &Placeholders::new(),
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        assert_eq!(
+            expand("{{top:directory}}/stubs").await,
+            OsString::from(format!("{ROOT_DIR}/stubs"))
+        );
+        assert_eq!(
+            expand("{{files}}").await,
+            OsString::from(format!("{PATH_0}{ENVIRONMENT_SEPARATOR}{PATH_3}"))
+        );
+        assert_eq!(expand("{{files:count}}").await, OsString::from("2"));
+        assert_eq!(expand("plain").await, OsString::from("plain"));
     }
 
     #[tokio::test]
@@ -345,6 +611,36 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[tokio::test]
+    async fn test_input_arg_placeholder() {
+        let (paths, is_array) = test_input_arg("{{cache_dir}}", &[]).await.unwrap().unwrap();
+
+        assert!(!is_array);
+        assert_eq!(paths, [PathBuf::from("/tmp/cache")]);
+    }
+
+    #[tokio::test]
+    async fn test_input_arg_count() {
+        let (paths, is_array) = test_input_arg("{{files:count}}", &[])
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!is_array);
+        assert_eq!(paths, [PathBuf::from("5")]);
+
+        let (paths, _) = test_input_arg("{{files:count}}", &["**/*.md"])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(paths, [PathBuf::from("3")]);
+
+        let (paths, _) = test_input_arg("{{files:count}}", &["*.none"])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(paths, [PathBuf::from("0")]);
+    }
+
     #[tokio::test]
     async fn test_input_arg_files() {
         let (paths, is_array) = test_input_arg("{{files}}", &[]).await.unwrap().unwrap();
@@ -0,0 +1,160 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+
+/// The placeholder resolving to the cache directory of an action
+pub(crate) const CACHE_DIR_PLACEHOLDER: &str = "cache_dir";
+
+/// The placeholder resolving to the cache directory all actions of a project
+/// share
+pub(crate) const PROJECT_CACHE_DIR_PLACEHOLDER: &str = "project_cache_dir";
+
+/// The name of the cache directory shared by all actions of a project, which
+/// no action group can have
+const SHARED_DIRECTORY: &str = "shared-cache";
+
+/// Cache directories not used for this long get removed
+pub(crate) const MAX_UNUSED: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const STAMP_FILE: &str = ".beautytips-last-used";
+
+pub(crate) fn project_key(root_directory: &Path) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    root_directory.hash(&mut hasher);
+    let name = root_directory
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    format!("{name}-{:016x}", hasher.finish())
+}
+
+/// The cache directory of `action_id` when run in `root_directory`
+fn cache_directory(cache_root: &Path, root_directory: &Path, action_id: &str) -> PathBuf {
+    cache_root
+        .join(project_key(root_directory))
+        .join(action_id.replace(['/', '\\'], std::path::MAIN_SEPARATOR_STR))
+}
+
+/// Create the cache directory of `action_id`, or the one shared by all
+/// actions of the project without it, and mark it as used
+pub(crate) async fn prepare(
+    root_directory: &Path,
+    action_id: Option<&str>,
+) -> crate::Result<PathBuf> {
+    let cache_root =
+        crate::actions_cache_directory().ok_or(anyhow::anyhow!("Cache directory not found"))?;
+    let directory = cache_directory(
+        &cache_root,
+        root_directory,
+        action_id.unwrap_or(SHARED_DIRECTORY),
+    );
+
+    tokio::fs::create_dir_all(&directory)
+        .await
+        .context(format!("Failed to create {}", directory.display()))?;
+    tokio::fs::write(directory.join(STAMP_FILE), [])
+        .await
+        .context(format!("Failed to mark {} as used", directory.display()))?;
+
+    Ok(directory)
+}
+
+fn last_used(directory: &Path) -> Option<SystemTime> {
+    std::fs::metadata(directory.join(STAMP_FILE))
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+/// Remove all cache directories in `directory` that were not used
+/// within `max_unused`
+///
+/// Returns `true` if `directory` was removed.
+fn prune_directory(directory: &Path, now: SystemTime, max_unused: Duration) -> bool {
+    if let Some(last_used) = last_used(directory) {
+        if now.duration_since(last_used).unwrap_or_default() <= max_unused {
+            return false;
+        }
+        if let Err(e) = std::fs::remove_dir_all(directory) {
+            tracing::warn!("Failed to remove {}: {e}", directory.display());
+            return false;
+        }
+        return true;
+    }
+
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return false;
+    };
+
+    let mut all_removed = true;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() || !prune_directory(&path, now, max_unused) {
+            all_removed = false;
+        }
+    }
+
+    all_removed && std::fs::remove_dir(directory).is_ok()
+}
+
+/// Remove cache directories that were not used within `max_unused`
+pub(crate) fn prune(cache_root: &Path, max_unused: Duration) {
+    let now = SystemTime::now();
+    let Ok(entries) = std::fs::read_dir(cache_root) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            prune_directory(&path, now, max_unused);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prune() {
+        let temp = tempfile::tempdir().unwrap();
+        let cache_root = temp.path().join("cache");
+        let root_directory = Path::new("/tmp/project");
+
+        let used = cache_directory(&cache_root, root_directory, "rust/check");
+        let unused = cache_directory(&cache_root, root_directory, "rust/fix");
+        let shared = cache_directory(&cache_root, root_directory, SHARED_DIRECTORY);
+        let other_project = cache_directory(&cache_root, Path::new("/tmp/other"), "rust/fix");
+        for d in [&used, &unused, &shared, &other_project] {
+            std::fs::create_dir_all(d).unwrap();
+            std::fs::write(d.join("data"), "cached").unwrap();
+            std::fs::write(d.join(STAMP_FILE), []).unwrap();
+        }
+        assert_ne!(used.parent(), other_project.parent().and_then(Path::parent));
+
+        let old = SystemTime::now() - 2 * MAX_UNUSED;
+        for d in [&unused, &other_project] {
+            std::fs::File::options()
+                .write(true)
+                .open(d.join(STAMP_FILE))
+                .unwrap()
+                .set_modified(old)
+                .unwrap();
+        }
+
+        prune(&cache_root, MAX_UNUSED);
+
+        assert!(used.join("data").exists());
+        assert!(shared.join("data").exists());
+        assert!(!unused.exists());
+        assert!(!other_project.exists());
+        // The directory of the other project is empty now:
+        assert!(!other_project.parent().unwrap().parent().unwrap().exists());
+    }
+}
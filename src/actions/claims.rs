@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// The files an action may change
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum Writes {
+    /// The action does not change any files
+    #[default]
+    Nothing,
+    /// The action may change its input files
+    InputFiles,
+    /// The action may change all files matching any of these patterns
+    Patterns(Vec<glob::Pattern>),
+}
+
+/// The files used by one running action
+struct Claim {
+    id: usize,
+    reads: HashSet<PathBuf>,
+    writes: HashSet<PathBuf>,
+}
+
+impl Claim {
+    fn conflicts_with(&self, other: &Claim) -> bool {
+        !self.writes.is_disjoint(&other.writes)
+            || !self.writes.is_disjoint(&other.reads)
+            || !self.reads.is_disjoint(&other.writes)
+    }
+}
+
+/// Keeps actions from running at the same time when one of them changes
+/// files the other one uses
+#[derive(Default)]
+pub(crate) struct FileClaims {
+    active: Mutex<Vec<Claim>>,
+    released: tokio::sync::Notify,
+    next_id: AtomicUsize,
+}
+
+impl FileClaims {
+    /// Wait till no running action writes any of `reads` or uses any of
+    /// `writes`, then claim those files
+    ///
+    /// The files are released again when the returned guard is dropped.
+    pub(crate) async fn claim(
+        self: &Arc<Self>,
+        reads: HashSet<PathBuf>,
+        writes: HashSet<PathBuf>,
+    ) -> ClaimGuard {
+        let claim = Claim {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            reads,
+            writes,
+        };
+
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            {
+                let mut active = self.active.lock().expect("File claims lock poisoned");
+                if !active.iter().any(|c| c.conflicts_with(&claim)) {
+                    let id = claim.id;
+                    active.push(claim);
+                    return ClaimGuard {
+                        claims: self.clone(),
+                        id,
+                    };
+                }
+            }
+
+            released.await;
+        }
+    }
+}
+
+/// Files claimed by a running action
+pub(crate) struct ClaimGuard {
+    claims: Arc<FileClaims>,
+    id: usize,
+}
+
+impl Drop for ClaimGuard {
+    fn drop(&mut self) {
+        self.claims
+            .active
+            .lock()
+            .expect("File claims lock poisoned")
+            .retain(|c| c.id != self.id);
+        self.claims.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(names: &[&str]) -> HashSet<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[tokio::test]
+    async fn test_file_claims() {
+        let claims = Arc::new(FileClaims::default());
+        let timeout = std::time::Duration::from_millis(50);
+
+        let writer = claims.claim(files(&["a.rs"]), files(&["a.rs"])).await;
+
+        // Readers of other files and other readers of the same files are fine
+        let reader = claims.claim(files(&["b.rs"]), files(&[])).await;
+        let other_writer = claims.claim(files(&["c.rs"]), files(&["c.rs"])).await;
+        drop(other_writer);
+
+        // Using files that get written needs to wait
+        assert!(
+            tokio::time::timeout(timeout, claims.claim(files(&["a.rs"]), files(&[])))
+                .await
+                .is_err()
+        );
+        assert!(
+            tokio::time::timeout(timeout, claims.claim(files(&[]), files(&["b.rs"])))
+                .await
+                .is_err()
+        );
+
+        let waiting = tokio::spawn({
+            let claims = claims.clone();
+            async move {
+                let _guard = claims
+                    .claim(files(&["a.rs", "b.rs"]), files(&["b.rs"]))
+                    .await;
+            }
+        });
+        drop(writer);
+        assert!(!waiting.is_finished());
+        drop(reader);
+        tokio::time::timeout(timeout, waiting)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
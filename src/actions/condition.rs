@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::path::{Path, PathBuf};
+
+/// Conditions an action needs to meet to run
+///
+/// All conditions that are set must be met. Actions not meeting them are
+/// reported as not applicable.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Condition {
+    /// An environment variable that must be set and not empty, or a
+    /// `NAME=value` pair that must match exactly
+    pub env: Option<String>,
+    /// A file that must exist, relative to the root directory
+    pub file_exists: Option<PathBuf>,
+    /// The operating systems (like `linux`, `macos` or `windows`) or families
+    /// (`unix` or `windows`) the action runs on, separated by commas
+    pub os: Option<String>,
+}
+
+impl Condition {
+    /// `true` if no condition is set
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    fn env_is_met(env: &str) -> bool {
+        match env.split_once('=') {
+            Some((name, value)) => std::env::var_os(name).is_some_and(|v| v == value),
+            None => std::env::var_os(env).is_some_and(|v| !v.is_empty()),
+        }
+    }
+
+    fn os_is_met(os: &str) -> bool {
+        os.split(',')
+            .map(str::trim)
+            .any(|os| os == std::env::consts::OS || os == std::env::consts::FAMILY)
+    }
+
+    /// Check whether all conditions are met when running in `root_directory`
+    #[must_use]
+    pub fn is_met(&self, root_directory: &Path) -> bool {
+        self.env.as_deref().map_or(true, Self::env_is_met)
+            && self
+                .file_exists
+                .as_ref()
+                .map_or(true, |f| root_directory.join(f).exists())
+            && self.os.as_deref().map_or(true, Self::os_is_met)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+        assert!(Condition::default().is_empty());
+        assert!(Condition::default().is_met(root));
+
+        let condition = |env: Option<&str>, file: Option<&str>, os: Option<&str>| Condition {
+            env: env.map(ToString::to_string),
+            file_exists: file.map(PathBuf::from),
+            os: os.map(ToString::to_string),
+        };
+
+        assert!(condition(Some("CARGO_MANIFEST_DIR"), None, None).is_met(root));
+        assert!(!condition(Some("BEAUTYTIPS_TEST_UNSET_VARIABLE"), None, None).is_met(root));
+        assert!(condition(
+            Some(&format!("CARGO_PKG_NAME={}", env!("CARGO_PKG_NAME"))),
+            None,
+            None
+        )
+        .is_met(root));
+        assert!(!condition(Some("CARGO_PKG_NAME=something-else"), None, None).is_met(root));
+
+        assert!(condition(None, Some("Cargo.toml"), None).is_met(root));
+        assert!(!condition(None, Some("does-not-exist.toml"), None).is_met(root));
+
+        assert!(condition(None, None, Some(std::env::consts::OS)).is_met(root));
+        assert!(condition(None, None, Some(std::env::consts::FAMILY)).is_met(root));
+        assert!(!condition(None, None, Some("plan9")).is_met(root));
+        assert!(condition(
+            None,
+            None,
+            Some(&format!("plan9, {}", std::env::consts::OS))
+        )
+        .is_met(root));
+
+        assert!(!condition(
+            Some("CARGO_MANIFEST_DIR"),
+            Some("Cargo.toml"),
+            Some("plan9")
+        )
+        .is_met(root));
+    }
+}
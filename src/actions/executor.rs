@@ -0,0 +1,531 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+
+use super::output::{ActionOutput, Spill};
+
+/// The exit status and the outputs of a finished process
+#[derive(Debug)]
+pub(crate) struct ProcessOutput {
+    pub status: std::process::ExitStatus,
+    pub stdout: ActionOutput,
+    pub stderr: ActionOutput,
+}
+
+/// Runs the processes of actions
+///
+/// The runner never spawns processes itself, so that tests can replace the
+/// real processes with scripted ones.
+#[async_trait::async_trait]
+pub(crate) trait CommandExecutor: std::fmt::Debug + Send + Sync {
+    /// Run `command` with `args` in `directory` and wait for it to finish
+    ///
+    /// `environment` is added to the environment of the current process.
+    /// The output is collected as it comes in, moving into files as `spill`
+    /// says once it grows too large.
+    ///
+    /// # Errors
+    ///
+    /// Reports an error if the command could not get started.
+    async fn execute(
+        &self,
+        command: &Path,
+        args: &[OsString],
+        directory: &Path,
+        environment: &[(OsString, OsString)],
+        spill: &Spill,
+    ) -> crate::Result<ProcessOutput>;
+}
+
+/// Runs commands as tokio processes
+#[derive(Debug, Default)]
+pub(crate) struct ProcessExecutor;
+
+#[async_trait::async_trait]
+impl CommandExecutor for ProcessExecutor {
+    async fn execute(
+        &self,
+        command: &Path,
+        args: &[OsString],
+        directory: &Path,
+        environment: &[(OsString, OsString)],
+        spill: &Spill,
+    ) -> crate::Result<ProcessOutput> {
+        let mut child = match tokio::process::Command::new(command)
+            .current_dir(directory)
+            .args(args)
+            .envs(environment.iter().map(|(k, v)| (k, v)))
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            // Cancelled actions must not leave their processes behind
+            .kill_on_drop(true)
+            .spawn()
+        {
+            Ok(child) => child,
+            // A missing working directory is reported as not found as well
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !directory.is_dir() => {
+                return Err(anyhow::anyhow!(format!(
+                    "Could not start '{}': the working directory {} does not exist",
+                    command.display(),
+                    directory.display()
+                )))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(anyhow::anyhow!(format!(
+                    "Could not start '{}': {e}\n{}",
+                    command.display(),
+                    not_found_hint(command, directory, environment)
+                )))
+            }
+            Err(e) => return Err(e).context(format!("Could not start '{}'", command.display())),
+        };
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let (status, stdout, stderr) = tokio::try_join!(
+            child.wait(),
+            spill.collect("stdout", stdout),
+            spill.collect("stderr", stderr)
+        )
+        .context(format!("Failed to run '{}'", command.display()))?;
+        Ok(ProcessOutput {
+            status,
+            stdout,
+            stderr,
+        })
+    }
+}
+
+/// The program setting up restricted sandboxes
+const BWRAP: &str = "bwrap";
+
+/// The `bwrap` arguments running `command` with `args` in `directory`, with
+/// the file system read-only except for the `writable` directories and an
+/// empty `/tmp`
+///
+/// The command runs in user and process namespaces of its own, so it can
+/// neither see nor signal other processes.
+fn bwrap_args(
+    command: &Path,
+    args: &[OsString],
+    directory: &Path,
+    writable: &[PathBuf],
+) -> Vec<OsString> {
+    let mut result: Vec<OsString> = [
+        "--die-with-parent",
+        "--unshare-user",
+        "--unshare-pid",
+        "--ro-bind",
+        "/",
+        "/",
+    ]
+    .into_iter()
+    .map(OsString::from)
+    .collect();
+    // Tools need to write to `/dev/null` and friends
+    result.extend(["--dev-bind", "/dev", "/dev"].map(OsString::from));
+    result.extend(["--proc", "/proc"].map(OsString::from));
+    // Tools write temporary files, without seeing those of others
+    result.extend(["--tmpfs", "/tmp", "--setenv", "TMPDIR", "/tmp"].map(OsString::from));
+    for w in writable {
+        result.extend([OsString::from("--bind"), w.into(), w.into()]);
+    }
+    result.extend([OsString::from("--chdir"), directory.into()]);
+    result.push("--".into());
+    result.push(command.into());
+    result.extend(args.iter().cloned());
+    result
+}
+
+/// Runs commands in a bubblewrap sandbox, with read access to the entire
+/// file system but write access only to some directories
+///
+/// The sandboxed commands are run with the wrapped executor. This needs
+/// Linux and `bwrap` to be installed.
+#[derive(Debug)]
+pub(crate) struct RestrictedExecutor {
+    inner: Arc<dyn CommandExecutor>,
+    writable: Vec<PathBuf>,
+}
+
+impl RestrictedExecutor {
+    pub(crate) fn new(inner: Arc<dyn CommandExecutor>, writable: Vec<PathBuf>) -> Self {
+        Self { inner, writable }
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandExecutor for RestrictedExecutor {
+    async fn execute(
+        &self,
+        command: &Path,
+        args: &[OsString],
+        directory: &Path,
+        environment: &[(OsString, OsString)],
+        spill: &Spill,
+    ) -> crate::Result<ProcessOutput> {
+        if !cfg!(target_os = "linux") {
+            return Err(anyhow::anyhow!(
+                "Restricted sandboxes are only supported on Linux"
+            ));
+        }
+        self.inner
+            .execute(
+                Path::new(BWRAP),
+                &bwrap_args(command, args, directory, &self.writable),
+                directory,
+                environment,
+                spill,
+            )
+            .await
+    }
+}
+
+/// The `PATH` commands started with `environment` are looked up in
+fn search_path(environment: &[(OsString, OsString)]) -> OsString {
+    environment
+        .iter()
+        .rev()
+        .find(|(k, _)| k == "PATH")
+        .map(|(_, v)| v.clone())
+        .or_else(|| std::env::var_os("PATH"))
+        .unwrap_or_default()
+}
+
+/// The files starting `path` may run, trying the `PATHEXT` extensions on
+/// Windows
+fn executable_candidates(path: &Path) -> Vec<PathBuf> {
+    let mut result = vec![path.to_path_buf()];
+    if cfg!(windows) && path.extension().is_none() {
+        let extensions =
+            std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+        result.extend(
+            extensions
+                .split(';')
+                .filter(|e| !e.is_empty())
+                .map(|e| path.with_extension(e.trim_start_matches('.'))),
+        );
+    }
+    result
+}
+
+/// Find the file starting `command` in `directory` with `environment` runs
+///
+/// Commands with a directory part are relative to `directory`, all others
+/// are looked up in the `PATH`.
+pub(crate) fn find_executable(
+    command: &Path,
+    directory: &Path,
+    environment: &[(OsString, OsString)],
+) -> Option<PathBuf> {
+    let candidates = if command.components().count() > 1 {
+        executable_candidates(&directory.join(command))
+    } else {
+        std::env::split_paths(&search_path(environment))
+            .flat_map(|d| executable_candidates(&d.join(command)))
+            .collect()
+    };
+    candidates.into_iter().find(|c| c.is_file())
+}
+
+/// Explain why `command` was not found when starting it in `directory` with
+/// `environment`
+fn not_found_hint(
+    command: &Path,
+    directory: &Path,
+    environment: &[(OsString, OsString)],
+) -> String {
+    let local = directory.join(command);
+    if command.components().count() > 1 {
+        return if local.exists() {
+            format!(
+                "'{}' exists, so the interpreter in its `#!` line was probably not found",
+                command.display()
+            )
+        } else {
+            format!(
+                "'{}' does not exist in {}",
+                command.display(),
+                directory.display()
+            )
+        };
+    }
+
+    let path = search_path(environment);
+    let fix = if local.is_file() {
+        format!(
+            "{} exists, use './{}' to run that",
+            local.display(),
+            command.display()
+        )
+    } else {
+        "Install it, or add the directory holding it to the `path-prepend` of the action"
+            .to_string()
+    };
+    format!(
+        "'{}' is in none of the directories of the PATH: {}\n{fix}",
+        command.display(),
+        path.to_string_lossy()
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::{
+        collections::VecDeque,
+        ffi::OsString,
+        path::{Path, PathBuf},
+        sync::Mutex,
+    };
+
+    use super::{CommandExecutor, ProcessOutput, Spill};
+
+    /// A command the `ScriptedExecutor` was asked to run
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub(crate) struct ExecutedCommand {
+        pub command: PathBuf,
+        pub args: Vec<String>,
+        pub directory: PathBuf,
+        pub environment: Vec<(String, String)>,
+    }
+
+    /// The outcome of a scripted command
+    #[derive(Clone, Debug)]
+    pub(crate) struct ScriptedOutput {
+        pub exit_code: i32,
+        pub stdout: Vec<u8>,
+        pub stderr: Vec<u8>,
+    }
+
+    impl ScriptedOutput {
+        pub(crate) fn new(exit_code: i32, stdout: &str, stderr: &str) -> Self {
+            Self {
+                exit_code,
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: stderr.as_bytes().to_vec(),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn exit_status(code: i32) -> std::process::ExitStatus {
+        use std::os::unix::process::ExitStatusExt;
+        std::process::ExitStatus::from_raw(code << 8)
+    }
+
+    #[cfg(windows)]
+    #[allow(clippy::cast_sign_loss)]
+    fn exit_status(code: i32) -> std::process::ExitStatus {
+        use std::os::windows::process::ExitStatusExt;
+        std::process::ExitStatus::from_raw(code as u32)
+    }
+
+    /// Answers commands with scripted outputs and records what got run
+    ///
+    /// Outputs are handed out in the order they were queued in. Commands run
+    /// after all outputs are used up succeed without any output.
+    #[derive(Debug, Default)]
+    pub(crate) struct ScriptedExecutor {
+        outputs: Mutex<VecDeque<ScriptedOutput>>,
+        executed: Mutex<Vec<ExecutedCommand>>,
+    }
+
+    impl ScriptedExecutor {
+        pub(crate) fn new(outputs: impl IntoIterator<Item = ScriptedOutput>) -> Self {
+            Self {
+                outputs: Mutex::new(outputs.into_iter().collect()),
+                executed: Mutex::new(Vec::new()),
+            }
+        }
+
+        /// The commands run so far, in the order they got started
+        pub(crate) fn executed(&self) -> Vec<ExecutedCommand> {
+            self.executed
+                .lock()
+                .expect("Executor lock poisoned")
+                .clone()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl CommandExecutor for ScriptedExecutor {
+        async fn execute(
+            &self,
+            command: &Path,
+            args: &[OsString],
+            directory: &Path,
+            environment: &[(OsString, OsString)],
+            spill: &Spill,
+        ) -> crate::Result<ProcessOutput> {
+            self.executed
+                .lock()
+                .expect("Executor lock poisoned")
+                .push(ExecutedCommand {
+                    command: command.to_path_buf(),
+                    args: args
+                        .iter()
+                        .map(|a| a.to_string_lossy().to_string())
+                        .collect(),
+                    directory: directory.to_path_buf(),
+                    environment: environment
+                        .iter()
+                        .map(|(k, v)| {
+                            (
+                                k.to_string_lossy().to_string(),
+                                v.to_string_lossy().to_string(),
+                            )
+                        })
+                        .collect(),
+                });
+
+            let output = self
+                .outputs
+                .lock()
+                .expect("Executor lock poisoned")
+                .pop_front()
+                .unwrap_or_else(|| ScriptedOutput::new(0, "", ""));
+            Ok(ProcessOutput {
+                status: exit_status(output.exit_code),
+                stdout: spill.collect("stdout", &output.stdout[..]).await?,
+                stderr: spill.collect("stderr", &output.stderr[..]).await?,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_not_found_hint() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let path = [(OsString::from("PATH"), OsString::from("/nowhere"))];
+
+        let hint = not_found_hint(Path::new("no-such-tool"), directory, &path);
+        assert!(hint.contains("none of the directories of the PATH: /nowhere"));
+        assert!(hint.contains("`path-prepend`"));
+
+        let hint = not_found_hint(Path::new("Cargo.toml"), directory, &path);
+        assert!(hint.contains("use './Cargo.toml' to run that"));
+
+        let hint = not_found_hint(Path::new("tools/no-such-tool"), directory, &path);
+        assert!(hint.contains("does not exist in"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_directory() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("no-such-directory");
+
+        let error = ProcessExecutor
+            .execute(Path::new("git"), &[], &directory, &[], &Spill::never())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("the working directory"));
+        assert!(!error.contains("PATH"));
+    }
+
+    #[test]
+    fn test_find_executable() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let path = [(OsString::from("PATH"), directory.join("src").into())];
+
+        assert_eq!(
+            find_executable(Path::new("main.rs"), Path::new("/"), &path),
+            Some(directory.join("src/main.rs"))
+        );
+        assert_eq!(
+            find_executable(Path::new("./Cargo.toml"), directory, &path),
+            Some(directory.join("./Cargo.toml"))
+        );
+        assert_eq!(
+            find_executable(Path::new("Cargo.toml"), directory, &path),
+            None
+        );
+        assert_eq!(find_executable(Path::new("src"), directory, &path), None);
+    }
+
+    #[tokio::test]
+    async fn test_restricted_executor() {
+        let inner = Arc::new(testing::ScriptedExecutor::default());
+        let executor =
+            RestrictedExecutor::new(inner.clone(), vec![PathBuf::from("/tmp/beautytips-run-1")]);
+        let result = executor
+            .execute(
+                Path::new("cargo"),
+                &[OsString::from("fmt")],
+                Path::new("/src/project"),
+                &[],
+                &Spill::never(),
+            )
+            .await;
+        if !cfg!(target_os = "linux") {
+            assert!(result.is_err());
+            return;
+        }
+
+        let executed = inner.executed();
+        assert_eq!(executed[0].command, Path::new("bwrap"));
+        assert_eq!(
+            executed[0].args.join(" "),
+            "--die-with-parent --unshare-user --unshare-pid --ro-bind / / \
+             --dev-bind /dev /dev --proc /proc --tmpfs /tmp --setenv TMPDIR /tmp \
+             --bind /tmp/beautytips-run-1 /tmp/beautytips-run-1 \
+             --chdir /src/project -- cargo fmt"
+        );
+        assert_eq!(executed[0].directory, Path::new("/src/project"));
+    }
+
+    /// Runs `bwrap` for real, where it is installed and works
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_restricted_executor_bwrap() {
+        let works = std::process::Command::new(BWRAP)
+            .args(["--unshare-user", "--ro-bind", "/", "/", "true"])
+            .status()
+            .is_ok_and(|s| s.success());
+        if !works {
+            eprintln!("Skipping test: bwrap is not available");
+            return;
+        }
+
+        let writable = tempfile::tempdir().unwrap();
+        let read_only = tempfile::tempdir().unwrap();
+        let executor = RestrictedExecutor::new(
+            Arc::new(ProcessExecutor),
+            vec![writable.path().to_path_buf()],
+        );
+        let script = "touch \"$TMPDIR/scratch\" && touch written && ! touch \"$1/denied\"";
+        let output = executor
+            .execute(
+                Path::new("sh"),
+                &[
+                    "-c".into(),
+                    script.into(),
+                    "sh".into(),
+                    read_only.path().into(),
+                ],
+                writable.path(),
+                &[],
+                &Spill::never(),
+            )
+            .await
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr.bytes())
+        );
+        assert!(writable.path().join("written").exists());
+        assert!(!read_only.path().join("denied").exists());
+    }
+}
@@ -4,26 +4,238 @@
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
 };
 
 use anyhow::Context;
 
 mod cargo;
+mod content;
+mod gitattributes;
+mod probe;
+
+pub use content::ContentPattern;
+pub use probe::{FileKind, IsBinary, PROBE_SIZE};
+
+/// Trait used to provide inputs that are generated on demand
+#[async_trait::async_trait]
+pub trait InputGenerator {
+    /// The name of the input generated, as used in `{{name}}` placeholders
+    /// and input filters
+    fn name(&self) -> &str;
+
+    /// Generate the values of the input
+    ///
+    /// `files` are the files to process, as collected before running any
+    /// actions.
+    ///
+    /// # Errors
+    ///
+    /// Reports an error if the data could not get generated.
+    async fn generate(
+        &self,
+        root_directory: &Path,
+        files: &[PathBuf],
+    ) -> crate::Result<Vec<PathBuf>>;
+
+    /// How long generating the values may take before giving up
+    fn timeout(&self) -> std::time::Duration {
+        DEFAULT_GENERATOR_TIMEOUT
+    }
+}
+
+/// How long input generators may take by default
+pub const DEFAULT_GENERATOR_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+#[allow(clippy::module_name_repetitions)]
+pub type BoxedInputGenerator = Box<dyn InputGenerator + Sync + Send>;
+type DynInputGenerator = &'static (dyn InputGenerator + Sync + Send);
+
+static KNOWN_INPUT_GENERATORS: OnceLock<Vec<BoxedInputGenerator>> = OnceLock::new();
+static REGISTERED_INPUT_GENERATORS: Mutex<Vec<DynInputGenerator>> = Mutex::new(Vec::new());
+
+/// Register an additional input generator
+///
+/// Registered generators are tried in the order they were registered in, but
+/// before any of the builtin ones.
+///
+/// # Panics
+///
+/// Panics if the registry lock got poisoned.
+pub fn register_input_generator(generator: BoxedInputGenerator) {
+    let generator: DynInputGenerator = Box::leak(generator);
+    REGISTERED_INPUT_GENERATORS
+        .lock()
+        .expect("Input generator registry lock poisoned")
+        .push(generator);
+}
+
+fn known_input_generators() -> &'static [BoxedInputGenerator] {
+    KNOWN_INPUT_GENERATORS.get_or_init(|| {
+        vec![
+            Box::new(cargo::CargoTargets {}),
+            Box::new(gitattributes::NonGeneratedFiles {}),
+        ]
+    })
+}
+
+fn input_generator_by_name(name: &str) -> Option<DynInputGenerator> {
+    let registered = REGISTERED_INPUT_GENERATORS
+        .lock()
+        .expect("Input generator registry lock poisoned")
+        .clone();
+
+    registered
+        .into_iter()
+        .chain(known_input_generators().iter().map(Box::as_ref))
+        .find(|g| g.name() == name)
+}
+
+/// The names of all inputs available, builtin ones first
+pub(crate) fn known_inputs() -> Vec<String> {
+    let registered = REGISTERED_INPUT_GENERATORS
+        .lock()
+        .expect("Input generator registry lock poisoned")
+        .clone();
+
+    [FILES_INPUTS, TOP_DIRECTORY_INPUTS]
+        .into_iter()
+        .chain(known_input_generators().iter().map(|g| g.name()))
+        .chain(registered.iter().map(|g| g.name()))
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Paths are matched relative to the root directory, and `*` does not match
+/// a `/`. Use `**` to match any number of directories.
+const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// A glob pattern selecting files of an input
+///
+/// Patterns starting with `!` exclude the files they match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputPattern {
+    glob: glob::Pattern,
+    negated: bool,
+}
+
+impl InputPattern {
+    /// # Errors
+    ///
+    /// Errors out when `pattern` is not a valid glob pattern
+    pub fn new(pattern: &str) -> crate::Result<Self> {
+        let (glob, negated) = match pattern.strip_prefix('!') {
+            Some(glob) => (glob, true),
+            None => (pattern, false),
+        };
+        Ok(Self {
+            glob: glob::Pattern::new(glob)?,
+            negated,
+        })
+    }
+
+    /// `true` if the pattern excludes the files it matches
+    #[must_use]
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+}
+
+impl From<glob::Pattern> for InputPattern {
+    fn from(glob: glob::Pattern) -> Self {
+        Self {
+            glob,
+            negated: false,
+        }
+    }
+}
+
+impl std::fmt::Display for InputPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.negated {
+            write!(f, "!")?;
+        }
+        write!(f, "{}", self.glob)
+    }
+}
+
+/// Check whether `path` (relative to the root directory) is selected by
+/// `patterns`
+///
+/// The patterns are evaluated in order, the last one matching decides. Paths
+/// matching no pattern at all are only selected when there are no positive
+/// patterns.
+fn is_selected(patterns: &[InputPattern], path: &Path) -> bool {
+    patterns
+        .iter()
+        .fold(patterns.iter().all(InputPattern::is_negated), |acc, p| {
+            if p.glob.matches_path_with(path, MATCH_OPTIONS) {
+                !p.negated
+            } else {
+                acc
+            }
+        })
+}
+
+fn parse_patterns(input_name: &str, patterns: &[String]) -> crate::Result<Vec<InputPattern>> {
+    patterns
+        .iter()
+        .map(|p| {
+            InputPattern::new(p).context(format!(
+                "Failed to parse glob pattern '{p}' for '{input_name}'"
+            ))
+        })
+        .collect()
+}
+
+/// The key of content patterns in the input filters
+const CONTENT_KEY: &str = "content";
+/// The key of the file kind in the input filters
+const KIND_KEY: &str = "kind";
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
-pub struct InputFilters(HashMap<String, Vec<glob::Pattern>>);
+pub struct InputFilters {
+    patterns: HashMap<String, Vec<InputPattern>>,
+    /// Only `files` starting with content matching one of these are used
+    content: Vec<ContentPattern>,
+    /// Only `files` of this kind are used
+    kind: Option<FileKind>,
+}
 
 impl From<HashMap<String, Vec<glob::Pattern>>> for InputFilters {
     fn from(value: HashMap<String, Vec<glob::Pattern>>) -> Self {
-        Self(value)
+        Self {
+            patterns: value
+                .into_iter()
+                .map(|(k, v)| (k, v.into_iter().map(InputPattern::from).collect()))
+                .collect(),
+            content: vec![],
+            kind: None,
+        }
     }
 }
 
 impl TryFrom<HashMap<String, Vec<String>>> for InputFilters {
     type Error = anyhow::Error;
 
-    fn try_from(value: HashMap<String, Vec<String>>) -> Result<Self, Self::Error> {
-        let inner = value
+    fn try_from(mut value: HashMap<String, Vec<String>>) -> Result<Self, Self::Error> {
+        let content = value
+            .remove(CONTENT_KEY)
+            .unwrap_or_default()
+            .iter()
+            .map(|p| ContentPattern::new(p))
+            .collect::<crate::Result<Vec<_>>>()?;
+        let kind = match value.remove(KIND_KEY).as_deref() {
+            None | Some([]) => None,
+            Some([kind]) => Some(kind.parse()?),
+            Some(_) => return Err(anyhow::anyhow!("Only one kind of input files is allowed")),
+        };
+
+        let patterns = value
             .iter()
             .try_fold(HashMap::new(), |mut acc, (k, v)| {
                 let entry = acc.entry(k.clone());
@@ -32,19 +244,18 @@ impl TryFrom<HashMap<String, Vec<String>>> for InputFilters {
                         "Redefinition of input filters for '{k}'"
                     )));
                 }
-                let globs = v
-                    .iter()
-                    .map(|p| {
-                        glob::Pattern::new(p)
-                            .context(format!("Failed to parse glob pattern '{p}' for '{k}'"))
-                    })
-                    .collect::<Result<_, _>>()?;
-                entry.or_insert(globs);
+                entry.or_insert(parse_patterns(k, v)?);
                 Ok(acc)
             })
             .context("Parsing input filters for action '{id}'")?;
 
-        Ok(Self(inner))
+        let mut result = Self {
+            patterns,
+            content,
+            kind,
+        };
+        result.declare_probed_files();
+        Ok(result)
     }
 }
 
@@ -55,48 +266,191 @@ impl InputFilters {
         inputs: &InputQuery,
         root_directory: &Path,
     ) -> crate::SendableResult<Vec<PathBuf>> {
-        static EMPTY: Vec<glob::Pattern> = vec![];
-
-        let current_filters = self.0.get(input_name).unwrap_or(&EMPTY);
-        let match_options = {
-            let mut opt = glob::MatchOptions::new();
-            opt.require_literal_separator = true;
-            opt
-        };
+        let current_filters = self.patterns(input_name);
 
-        Ok(inputs
+        let files = inputs
             .inputs(input_name.to_string())
             .await
             .map_err(|e| format!("Failed to get inputs for {input_name:?}: {e}"))?
             .into_iter()
             .filter(|p| {
                 let rel_path = p.strip_prefix(root_directory).unwrap_or(p);
-                current_filters.is_empty()
-                    || current_filters
-                        .iter()
-                        .any(|f| f.matches_path_with(rel_path, match_options))
+                is_selected(current_filters, rel_path)
             })
-            .collect())
+            .collect();
+
+        if input_name == FILES_INPUTS && self.is_probing() {
+            Ok(inputs
+                .probes
+                .filter(files, |probe| {
+                    self.kind.map_or(true, |k| probe.is_kind(k))
+                        && content::matches_any(&self.content, &probe.head)
+                })
+                .await)
+        } else {
+            Ok(files)
+        }
+    }
+
+    /// `true` if the `files` are filtered by looking into them
+    fn is_probing(&self) -> bool {
+        self.kind.is_some() || !self.content.is_empty()
+    }
+
+    /// Probing filters the `files`, so those are an input then
+    fn declare_probed_files(&mut self) {
+        if self.is_probing() {
+            self.patterns.entry(FILES_INPUTS.to_string()).or_default();
+        }
     }
 
     pub fn inputs(&self) -> impl Iterator<Item = &String> {
-        self.0.keys()
+        self.patterns.keys()
+    }
+
+    /// The glob patterns filtering `input_name`
+    #[must_use]
+    pub fn patterns(&self, input_name: &str) -> &[InputPattern] {
+        self.patterns.get(input_name).map_or(&[], Vec::as_slice)
+    }
+
+    /// The last pattern of `input_name` matching `path` (relative to the
+    /// root directory), which decides whether `path` is selected
+    #[must_use]
+    pub fn deciding_pattern(&self, input_name: &str, path: &Path) -> Option<&InputPattern> {
+        self.patterns(input_name)
+            .iter()
+            .rev()
+            .find(|p| p.glob.matches_path_with(path, MATCH_OPTIONS))
+    }
+
+    /// `true` if `path` (relative to the root directory) passes the patterns
+    /// of `input_name`
+    #[must_use]
+    pub fn selects(&self, input_name: &str, path: &Path) -> bool {
+        is_selected(self.patterns(input_name), path)
+    }
+
+    /// The patterns the start of the `files` must match
+    #[must_use]
+    pub fn content_patterns(&self) -> &[ContentPattern] {
+        &self.content
+    }
+
+    /// The kind the `files` must be of
+    #[must_use]
+    pub fn kind(&self) -> Option<FileKind> {
+        self.kind
     }
 
     /// # Errors
     ///
     /// Errors out when trying to remove some input that does not exist
-    pub fn update_from(&mut self, value: HashMap<String, Vec<String>>) -> crate::Result<()> {
+    pub fn update_from(&mut self, mut value: HashMap<String, Vec<String>>) -> crate::Result<()> {
+        let probe_filters = [CONTENT_KEY, KIND_KEY]
+            .into_iter()
+            .filter_map(|k| value.remove_entry(k))
+            .collect::<HashMap<_, _>>();
         let mut inputs = InputFilters::try_from(value)?;
-        for (k, v) in inputs.0.drain() {
+        for (k, v) in inputs.patterns.drain() {
             if v.is_empty() {
-                if self.0.remove(&k).is_none() {
+                if self.patterns.remove(&k).is_none() {
                     return Err(anyhow::anyhow!(format!(
                         "{k} does not exist when trying to remove it from inputs"
                     )));
                 }
             } else {
-                self.0.insert(k, v);
+                self.patterns.insert(k, v);
+            }
+        }
+
+        // An empty list removes the content patterns or kind
+        let replace_content = probe_filters.contains_key(CONTENT_KEY);
+        let replace_kind = probe_filters.contains_key(KIND_KEY);
+        let probe_filters = InputFilters::try_from(probe_filters)?;
+        if replace_content {
+            self.content = probe_filters.content;
+        }
+        if replace_kind {
+            self.kind = probe_filters.kind;
+        }
+        self.declare_probed_files();
+        Ok(())
+    }
+
+    /// Add the patterns in `value` to the patterns of their inputs
+    ///
+    /// # Errors
+    ///
+    /// Errors out when a pattern is invalid or when adding a `kind`
+    pub fn add_patterns(&mut self, mut value: HashMap<String, Vec<String>>) -> crate::Result<()> {
+        if value.contains_key(KIND_KEY) {
+            return Err(anyhow::anyhow!(format!(
+                "{KIND_KEY} can not be added to, set it instead"
+            )));
+        }
+        if let Some(content) = value.remove(CONTENT_KEY) {
+            for p in content {
+                self.content.push(ContentPattern::new(&p)?);
+            }
+        }
+        for (k, v) in value {
+            let patterns = parse_patterns(&k, &v)?;
+            self.patterns.entry(k).or_default().extend(patterns);
+        }
+        self.declare_probed_files();
+        Ok(())
+    }
+
+    /// Remove the patterns in `value` from the patterns of their inputs
+    ///
+    /// # Errors
+    ///
+    /// Errors out when a pattern is not used, when removing a `kind` or when
+    /// no pattern selecting files is left, as that would select all files
+    pub fn remove_patterns(
+        &mut self,
+        mut value: HashMap<String, Vec<String>>,
+    ) -> crate::Result<()> {
+        if value.contains_key(KIND_KEY) {
+            return Err(anyhow::anyhow!(format!(
+                "{KIND_KEY} can not be removed from, set it to `[]` instead"
+            )));
+        }
+        if let Some(content) = value.remove(CONTENT_KEY) {
+            for p in content {
+                let pattern = ContentPattern::new(&p)?;
+                let Some(index) = self.content.iter().position(|c| *c == pattern) else {
+                    return Err(anyhow::anyhow!(format!(
+                        "{CONTENT_KEY} does not contain '{p}' when trying to remove it"
+                    )));
+                };
+                self.content.remove(index);
+            }
+            if self.content.is_empty() {
+                return Err(anyhow::anyhow!(format!(
+                    "Removing all {CONTENT_KEY} patterns would select all files, set it to `[]` instead"
+                )));
+            }
+        }
+        for (k, v) in value {
+            let Some(patterns) = self.patterns.get_mut(&k) else {
+                return Err(anyhow::anyhow!(format!(
+                    "{k} does not exist when trying to remove patterns from it"
+                )));
+            };
+            for (p, pattern) in v.iter().zip(parse_patterns(&k, &v)?) {
+                let Some(index) = patterns.iter().position(|i| *i == pattern) else {
+                    return Err(anyhow::anyhow!(format!(
+                        "{k} does not contain '{p}' when trying to remove it"
+                    )));
+                };
+                patterns.remove(index);
+            }
+            if patterns.iter().all(InputPattern::is_negated) {
+                return Err(anyhow::anyhow!(format!(
+                    "Removing these patterns leaves nothing selected in {k}, which would select all files"
+                )));
             }
         }
         Ok(())
@@ -109,14 +463,18 @@ pub(crate) struct InputQueryMessage {
 }
 
 #[derive(Clone)]
-pub(crate) struct InputQuery(InputQueryTx);
+pub(crate) struct InputQuery {
+    tx: InputQueryTx,
+    /// The probed files, shared by all actions of a run
+    probes: probe::ProbeCache,
+}
 
 impl InputQuery {
     #[tracing::instrument(skip(self))]
     pub(crate) async fn inputs(&self, input: String) -> InputQueryReplyMessage {
         tracing::trace!("Querying values for input \"{input}\"");
         let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
-        self.0
+        self.tx
             .send(InputQueryMessage {
                 input,
                 tx: reply_tx,
@@ -126,12 +484,13 @@ impl InputQuery {
 
         reply_rx
             .await
-            .expect("Internal communication should not fail")
+            .unwrap_or_else(|_| Err("The input cache stopped before answering".to_string()))
     }
 }
 
 pub(crate) struct InputCacheHandle {
     tx: InputQueryTx,
+    probes: probe::ProbeCache,
     handle: tokio::task::JoinHandle<Result<(), String>>,
 }
 
@@ -150,7 +509,10 @@ impl InputCacheHandle {
 
     #[tracing::instrument(skip(self))]
     pub(crate) fn query(&self) -> InputQuery {
-        InputQuery(self.tx.clone())
+        InputQuery {
+            tx: self.tx.clone(),
+            probes: self.probes.clone(),
+        }
     }
 }
 
@@ -182,7 +544,6 @@ struct InputCache {
 }
 
 pub(crate) const FILES_INPUTS: &str = "files";
-pub(crate) const CARGO_TARGETS_INPUTS: &str = "cargo_targets";
 pub(crate) const TOP_DIRECTORY_INPUTS: &str = "top:directory";
 
 impl InputCache {
@@ -228,62 +589,51 @@ impl InputCache {
         let sender = query.tx;
         match self.inputs.get_mut(&query.input) {
             Some(InputMapEntry::Cached(data)) => {
-                sender
-                    .send(data.clone())
-                    .expect("Failed to send internal message");
+                // The action asking might be gone already
+                let _ = sender.send(data.clone());
             }
             Some(InputMapEntry::Generating(data)) => {
                 data.push(sender);
             }
             None => {
+                let query_name = query.input;
+
+                let Some(generator) = input_generator_by_name(&query_name) else {
+                    let _ = sender.send(Err(format!("Input '{query_name}' is not supported")));
+                    return Ok(true);
+                };
+
                 let generator_tx = self.generator_channel.0.clone();
-                let query_name = query.input.clone();
                 let qn = query_name.clone();
 
-                match query_name.as_str() {
-                    FILES_INPUTS => unreachable!("Set from the start"),
-                    TOP_DIRECTORY_INPUTS => unreachable!("Set at the start"),
-                    CARGO_TARGETS_INPUTS => {
-                        let files = {
-                            let Some(InputMapEntry::Cached(Ok(tmp))) =
-                                self.inputs.get(FILES_INPUTS)
-                            else {
-                                unreachable!("Set at the start");
-                            };
-                            tmp.clone()
-                        };
-                        let top_directory = {
-                            let Some(InputMapEntry::Cached(Ok(tmp))) =
-                                self.inputs.get(TOP_DIRECTORY_INPUTS)
-                            else {
-                                unreachable!("Set at the start");
-                            };
-                            tmp.first().unwrap().clone()
-                        };
-
-                        tokio::spawn(async move {
-                            let targets = cargo::find_cargo_targets(top_directory, &files).await;
-
-                            generator_tx
-                                .send(GeneratorReply {
-                                    input: qn,
-                                    data: Ok(targets),
-                                })
-                                .await
-                                .expect("Failed to send internal message");
-                        });
-
-                        self.inputs
-                            .insert(query_name, InputMapEntry::Generating(vec![sender]));
-                    }
-                    _ => {
-                        sender
-                            .send(Err(format!("Input '{query_name}' is not supported")))
-                            .expect("Failed to send internal message");
-                    }
+                let files = {
+                    let Some(InputMapEntry::Cached(Ok(tmp))) = self.inputs.get(FILES_INPUTS) else {
+                        unreachable!("Set at the start");
+                    };
+                    tmp.clone()
+                };
+                let top_directory = {
+                    let Some(InputMapEntry::Cached(Ok(tmp))) =
+                        self.inputs.get(TOP_DIRECTORY_INPUTS)
+                    else {
+                        unreachable!("Set at the start");
+                    };
+                    tmp.first().unwrap().clone()
                 };
+
+                tokio::spawn(async move {
+                    let data = run_generator(generator, &qn, top_directory, files).await;
+
+                    generator_tx
+                        .send(GeneratorReply { input: qn, data })
+                        .await
+                        .expect("Failed to send internal message");
+                });
+
+                self.inputs
+                    .insert(query_name, InputMapEntry::Generating(vec![sender]));
             }
-        };
+        }
 
         Ok(true)
     }
@@ -306,14 +656,41 @@ impl InputCache {
             unreachable!("Unexpected content in cache hashmap");
         };
         for tx in to_notify {
-            tx.send(reply.data.clone())
-                .expect("Internal communication should not fail");
+            let _ = tx.send(reply.data.clone());
         }
 
         Ok(true)
     }
 }
 
+/// Run `generator` in a task of its own
+///
+/// Panics and timeouts of the generator are reported as errors, so that
+/// nobody waits for its values forever.
+async fn run_generator(
+    generator: DynInputGenerator,
+    input: &str,
+    top_directory: PathBuf,
+    files: Vec<PathBuf>,
+) -> InputQueryReplyMessage {
+    let timeout = generator.timeout();
+    let task = tokio::spawn(async move { generator.generate(&top_directory, &files).await });
+    let abort_handle = task.abort_handle();
+
+    match tokio::time::timeout(timeout, task).await {
+        Ok(Ok(data)) => data.map_err(|e| format!("Failed to generate input '{input}': {e}")),
+        Ok(Err(e)) if e.is_panic() => Err(format!("Generating input '{input}' panicked")),
+        Ok(Err(e)) => Err(format!("Generating input '{input}' failed: {e}")),
+        Err(_) => {
+            abort_handle.abort();
+            Err(format!(
+                "Generating input '{input}' timed out after {}s",
+                timeout.as_secs_f32()
+            ))
+        }
+    }
+}
+
 #[tracing::instrument]
 pub(crate) fn setup_input_cache(
     current_directory: PathBuf,
@@ -334,5 +711,215 @@ pub(crate) fn setup_input_cache(
         Ok(())
     });
 
-    InputCacheHandle { tx, handle }
+    InputCacheHandle {
+        tx,
+        probes: probe::ProbeCache::default(),
+        handle,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestGenerator {}
+
+    #[async_trait::async_trait]
+    impl InputGenerator for TestGenerator {
+        fn name(&self) -> &'static str {
+            "test_inputs"
+        }
+
+        async fn generate(
+            &self,
+            root_directory: &Path,
+            files: &[PathBuf],
+        ) -> crate::Result<Vec<PathBuf>> {
+            Ok(files
+                .iter()
+                .map(|f| root_directory.join("generated").join(f))
+                .collect())
+        }
+    }
+
+    struct PanickingGenerator {}
+
+    #[async_trait::async_trait]
+    impl InputGenerator for PanickingGenerator {
+        fn name(&self) -> &'static str {
+            "panicking_inputs"
+        }
+
+        async fn generate(
+            &self,
+            _root_directory: &Path,
+            _files: &[PathBuf],
+        ) -> crate::Result<Vec<PathBuf>> {
+            panic!("Generator blew up");
+        }
+    }
+
+    struct SlowGenerator {}
+
+    #[async_trait::async_trait]
+    impl InputGenerator for SlowGenerator {
+        fn name(&self) -> &'static str {
+            "slow_inputs"
+        }
+
+        async fn generate(
+            &self,
+            _root_directory: &Path,
+            _files: &[PathBuf],
+        ) -> crate::Result<Vec<PathBuf>> {
+            tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+            Ok(vec![])
+        }
+
+        fn timeout(&self) -> std::time::Duration {
+            std::time::Duration::from_millis(10)
+        }
+    }
+
+    fn selected(patterns: &[&str], path: &str) -> bool {
+        let patterns = patterns
+            .iter()
+            .map(|p| InputPattern::new(p).unwrap())
+            .collect::<Vec<_>>();
+        is_selected(&patterns, Path::new(path))
+    }
+
+    #[test]
+    fn test_is_selected_separators() {
+        assert!(selected(&[], "src/main.rs"));
+
+        assert!(selected(&["*.rs"], "main.rs"));
+        assert!(!selected(&["*.rs"], "src/main.rs"));
+        assert!(selected(&["src/*.rs"], "src/main.rs"));
+        assert!(!selected(&["src/*.rs"], "src/bin/main.rs"));
+        assert!(!selected(&["src*"], "src/main.rs"));
+
+        assert!(selected(&["**/*.rs"], "main.rs"));
+        assert!(selected(&["**/*.rs"], "src/bin/main.rs"));
+        assert!(selected(&["src/**/*.rs"], "src/main.rs"));
+        assert!(selected(&["src/**/*.rs"], "src/bin/main.rs"));
+        assert!(!selected(&["src/**/*.rs"], "tests/main.rs"));
+        assert!(selected(&["**/generated/**"], "generated/a.rs"));
+        assert!(selected(&["**/generated/**"], "src/generated/sub/a.rs"));
+        assert!(!selected(&["**/generated/**"], "src/generated.rs"));
+
+        assert!(selected(&[".github/**/*.yml"], ".github/workflows/ci.yml"));
+        assert!(selected(&["*.toml"], ".taplo.toml"));
+        assert!(!selected(&["*.RS"], "main.rs"));
+    }
+
+    #[test]
+    fn test_is_selected_negated() {
+        let rust_but_generated = ["**/*.rs", "!**/generated/**"];
+        assert!(selected(&rust_but_generated, "src/main.rs"));
+        assert!(!selected(&rust_but_generated, "src/generated/a.rs"));
+        assert!(!selected(&rust_but_generated, "README.md"));
+
+        // The last matching pattern decides
+        let keep_one = ["**/*.rs", "!**/generated/**", "**/generated/keep.rs"];
+        assert!(selected(&keep_one, "generated/keep.rs"));
+        assert!(!selected(&keep_one, "generated/drop.rs"));
+        let exclude_all = ["**/generated/keep.rs", "!**/generated/**"];
+        assert!(!selected(&exclude_all, "generated/keep.rs"));
+
+        // Only negated patterns: Everything else is selected
+        let not_vendored = ["!vendor/**", "!third_party/**"];
+        assert!(selected(&not_vendored, "src/main.rs"));
+        assert!(!selected(&not_vendored, "vendor/lib.rs"));
+        assert!(!selected(&not_vendored, "third_party/x/y.c"));
+
+        assert_eq!(
+            InputPattern::new("!**/generated/**").unwrap().to_string(),
+            "!**/generated/**"
+        );
+        assert!(InputPattern::new("!**a").is_err());
+    }
+
+    #[test]
+    fn test_deciding_pattern() {
+        let filters = InputFilters::try_from(HashMap::from([(
+            FILES_INPUTS.to_string(),
+            vec!["**/*.rs".to_string(), "!**/generated/**".to_string()],
+        )]))
+        .unwrap();
+        let deciding = |path: &str| {
+            filters
+                .deciding_pattern(FILES_INPUTS, Path::new(path))
+                .map(ToString::to_string)
+        };
+
+        assert_eq!(deciding("src/main.rs"), Some("**/*.rs".to_string()));
+        assert_eq!(
+            deciding("src/generated/a.rs"),
+            Some("!**/generated/**".to_string())
+        );
+        assert_eq!(deciding("README.md"), None);
+        assert!(filters.selects(FILES_INPUTS, Path::new("src/main.rs")));
+        assert!(!filters.selects(FILES_INPUTS, Path::new("src/generated/a.rs")));
+        assert!(filters.selects("other", Path::new("README.md")));
+    }
+
+    #[tokio::test]
+    async fn test_registered_input_generator() {
+        register_input_generator(Box::new(TestGenerator {}));
+
+        let cache = setup_input_cache(
+            PathBuf::from("/tmp/root"),
+            vec![PathBuf::from("a"), PathBuf::from("b")],
+        );
+        let query = cache.query();
+
+        assert_eq!(
+            query.inputs("test_inputs".to_string()).await.unwrap(),
+            [
+                PathBuf::from("/tmp/root/generated/a"),
+                PathBuf::from("/tmp/root/generated/b")
+            ]
+        );
+        // cached now:
+        assert_eq!(
+            query.inputs("test_inputs".to_string()).await.unwrap().len(),
+            2
+        );
+        assert!(query.inputs("unknown_inputs".to_string()).await.is_err());
+
+        drop(query);
+        cache.finish().await;
+    }
+
+    #[tokio::test]
+    async fn test_failing_input_generators() {
+        register_input_generator(Box::new(PanickingGenerator {}));
+        register_input_generator(Box::new(SlowGenerator {}));
+
+        let cache = setup_input_cache(PathBuf::from("/tmp/root"), vec![PathBuf::from("a")]);
+        let query = cache.query();
+
+        // All queries waiting for the generator get the error
+        let (first, second) = tokio::join!(
+            query.inputs("panicking_inputs".to_string()),
+            query.inputs("panicking_inputs".to_string())
+        );
+        assert!(first.unwrap_err().contains("panicked"));
+        assert!(second.unwrap_err().contains("panicked"));
+        assert!(query
+            .inputs("slow_inputs".to_string())
+            .await
+            .unwrap_err()
+            .contains("timed out"));
+
+        // The cache keeps working
+        assert_eq!(
+            query.inputs(FILES_INPUTS.to_string()).await.unwrap().len(),
+            1
+        );
+
+        drop(query);
+        cache.finish().await;
+    }
 }
@@ -40,11 +40,11 @@ async fn find_cargo_target(top_directory: &Path, path: &Path) -> Option<String>
     }
 }
 
-pub(crate) async fn find_cargo_targets(top_directory: PathBuf, files: &[PathBuf]) -> Vec<PathBuf> {
+pub(crate) async fn find_cargo_targets(top_directory: &Path, files: &[PathBuf]) -> Vec<PathBuf> {
     let mut targets = HashSet::new();
 
     for f in files {
-        if let Some(target) = find_cargo_target(&top_directory, f).await {
+        if let Some(target) = find_cargo_target(top_directory, f).await {
             targets.insert(target);
         }
     }
@@ -54,3 +54,21 @@ pub(crate) async fn find_cargo_targets(top_directory: PathBuf, files: &[PathBuf]
 
     targets
 }
+
+/// The `cargo_targets` input: All cargo packages containing any of the files
+pub(crate) struct CargoTargets {}
+
+#[async_trait::async_trait]
+impl super::InputGenerator for CargoTargets {
+    fn name(&self) -> &'static str {
+        "cargo_targets"
+    }
+
+    async fn generate(
+        &self,
+        root_directory: &Path,
+        files: &[PathBuf],
+    ) -> crate::Result<Vec<PathBuf>> {
+        Ok(find_cargo_targets(root_directory, files).await)
+    }
+}
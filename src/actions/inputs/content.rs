@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use anyhow::Context;
+
+/// A pattern matching the start of a file
+///
+/// Patterns enclosed in `/` are regular expressions, all others are searched
+/// for literally.
+#[derive(Clone, Debug)]
+pub enum ContentPattern {
+    Literal(String),
+    Regex(regex::Regex),
+}
+
+impl ContentPattern {
+    /// # Errors
+    ///
+    /// Errors out when `pattern` is an invalid regular expression
+    pub fn new(pattern: &str) -> crate::Result<Self> {
+        match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+            Some(regex) => Ok(Self::Regex(
+                regex::Regex::new(regex)
+                    .context(format!("Failed to parse regular expression '{regex}'"))?,
+            )),
+            None => Ok(Self::Literal(pattern.to_string())),
+        }
+    }
+
+    /// `true` if `head`, the start of a file, matches
+    #[must_use]
+    pub fn matches(&self, head: &str) -> bool {
+        match self {
+            Self::Literal(literal) => head.contains(literal.as_str()),
+            Self::Regex(regex) => regex.is_match(head),
+        }
+    }
+}
+
+impl PartialEq for ContentPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for ContentPattern {}
+
+impl std::fmt::Display for ContentPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Literal(literal) => write!(f, "{literal}"),
+            Self::Regex(regex) => write!(f, "/{regex}/"),
+        }
+    }
+}
+
+/// `true` if `head` matches any of the `patterns`, or there are no patterns
+pub(crate) fn matches_any(patterns: &[ContentPattern], head: &str) -> bool {
+    patterns.is_empty() || patterns.iter().any(|p| p.matches(head))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::actions::inputs::probe::ProbeCache;
+
+    async fn filter(
+        files: Vec<std::path::PathBuf>,
+        patterns: &[ContentPattern],
+        cache: &ProbeCache,
+    ) -> Vec<std::path::PathBuf> {
+        cache
+            .filter(files, |probe| matches_any(patterns, &probe.head))
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_filter() {
+        let directory =
+            std::env::temp_dir().join(format!("beautytips-content-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let file = |name: &str, content: &str| {
+            let path = directory.join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+        let python = file("script", "#!/usr/bin/env python3\nprint('hi')\n");
+        let shell = file("run", "#!/bin/sh\necho hi\n");
+        let late = file(
+            "late",
+            &format!("{}#!/usr/bin/env python", " ".repeat(2000)),
+        );
+        let missing = directory.join("missing");
+        let files = vec![python.clone(), shell.clone(), late, missing];
+
+        let cache = ProbeCache::default();
+        let literal = [ContentPattern::new("#!/usr/bin/env python").unwrap()];
+        assert_eq!(
+            filter(files.clone(), &literal, &cache).await,
+            std::slice::from_ref(&python)
+        );
+
+        let regex = [ContentPattern::new(r"/^#!.*\b(sh|bash)\n/").unwrap()];
+        assert_eq!(
+            filter(files.clone(), &regex, &cache).await,
+            std::slice::from_ref(&shell)
+        );
+
+        let both = [literal[0].clone(), regex[0].clone()];
+        assert_eq!(filter(files.clone(), &both, &cache).await, [python, shell]);
+        assert_eq!(filter(files.clone(), &[], &cache).await, files);
+
+        assert_eq!(regex[0].to_string(), r"/^#!.*\b(sh|bash)\n/");
+        assert!(ContentPattern::new("/(/").is_err());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Attributes marking files as not written by hand
+const GENERATED_ATTRIBUTES: [&str; 2] = ["linguist-generated", "export-ignore"];
+
+/// The state of an attribute on a path, see `man gitattributes`
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum State {
+    Set,
+    Unset,
+    Unspecified,
+    Value(String),
+}
+
+impl State {
+    fn is_set(&self) -> bool {
+        match self {
+            Self::Set => true,
+            Self::Value(v) => v != "false",
+            Self::Unset | Self::Unspecified => false,
+        }
+    }
+}
+
+/// One line of a `.gitattributes` file
+#[derive(Clone, Debug)]
+struct Rule {
+    pattern: glob::Pattern,
+    /// Patterns without a `/` match the file name in any directory
+    match_file_name: bool,
+    attributes: Vec<(String, State)>,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let mut parts = line.split_whitespace();
+        let pattern = parts.next()?;
+        // Patterns for directories never match files, negated patterns are
+        // not allowed
+        if pattern.ends_with('/') || pattern.starts_with('!') {
+            return None;
+        }
+        let match_file_name = !pattern.contains('/');
+        let pattern = glob::Pattern::new(pattern.strip_prefix('/').unwrap_or(pattern)).ok()?;
+
+        let attributes = parts
+            .map(|a| {
+                if let Some(name) = a.strip_prefix('-') {
+                    (name.to_string(), State::Unset)
+                } else if let Some(name) = a.strip_prefix('!') {
+                    (name.to_string(), State::Unspecified)
+                } else if let Some((name, value)) = a.split_once('=') {
+                    (name.to_string(), State::Value(value.to_string()))
+                } else {
+                    (a.to_string(), State::Set)
+                }
+            })
+            .collect();
+
+        Some(Self {
+            pattern,
+            match_file_name,
+            attributes,
+        })
+    }
+
+    /// `path` is relative to the directory of the `.gitattributes` file
+    fn matches(&self, path: &Path) -> bool {
+        if self.match_file_name {
+            path.file_name().is_some_and(|n| {
+                self.pattern
+                    .matches_with(&n.to_string_lossy(), MATCH_OPTIONS)
+            })
+        } else {
+            self.pattern.matches_path_with(path, MATCH_OPTIONS)
+        }
+    }
+}
+
+const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+fn parse(contents: &str) -> Vec<Rule> {
+    contents.lines().filter_map(Rule::parse).collect()
+}
+
+/// The `.gitattributes` files of a repository, parsed when first needed
+#[derive(Debug, Default)]
+struct Attributes {
+    /// The rules by the directory (relative to the root) containing them
+    rules: HashMap<PathBuf, Vec<Rule>>,
+}
+
+impl Attributes {
+    async fn rules(&mut self, root_directory: &Path, directory: &Path) -> &[Rule] {
+        if !self.rules.contains_key(directory) {
+            let file = root_directory.join(directory).join(".gitattributes");
+            let rules = tokio::fs::read_to_string(&file)
+                .await
+                .map(|c| parse(&c))
+                .unwrap_or_default();
+            self.rules.insert(directory.to_path_buf(), rules);
+        }
+        &self.rules[directory]
+    }
+
+    /// `true` if any of the `GENERATED_ATTRIBUTES` is set for `path`
+    ///
+    /// `path` is relative to the root. Files in deeper directories and later
+    /// lines take precedence, just like in git.
+    async fn is_generated(&mut self, root_directory: &Path, path: &Path) -> bool {
+        let mut directories = path.ancestors().skip(1).collect::<Vec<_>>();
+        directories.reverse();
+
+        let mut states: HashMap<&str, State> = HashMap::new();
+        for directory in directories {
+            let relative = path.strip_prefix(directory).unwrap_or(path);
+            for rule in self.rules(root_directory, directory).await {
+                if !rule.matches(relative) {
+                    continue;
+                }
+                for (name, state) in &rule.attributes {
+                    if let Some(name) = GENERATED_ATTRIBUTES.iter().find(|a| *a == name) {
+                        states.insert(name, state.clone());
+                    }
+                }
+            }
+        }
+        states.values().any(State::is_set)
+    }
+}
+
+/// The `files:nongenerated` input: The `files`, without those marked as
+/// `linguist-generated` or `export-ignore` in `.gitattributes`
+pub(crate) struct NonGeneratedFiles {}
+
+#[async_trait::async_trait]
+impl super::InputGenerator for NonGeneratedFiles {
+    fn name(&self) -> &'static str {
+        "files:nongenerated"
+    }
+
+    async fn generate(
+        &self,
+        root_directory: &Path,
+        files: &[PathBuf],
+    ) -> crate::Result<Vec<PathBuf>> {
+        let mut attributes = Attributes::default();
+        let mut result = Vec::new();
+        for f in files {
+            let relative = f.strip_prefix(root_directory).unwrap_or(f);
+            if !attributes.is_generated(root_directory, relative).await {
+                result.push(f.clone());
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generated(rules: &[(&str, &str)], path: &str) -> bool {
+        let mut attributes = Attributes {
+            rules: rules
+                .iter()
+                .map(|(d, c)| (PathBuf::from(d), parse(c)))
+                .collect(),
+        };
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(attributes.is_generated(Path::new("/nonexistent"), Path::new(path)))
+    }
+
+    #[test]
+    fn test_is_generated() {
+        let root = (
+            "",
+            r"
+# Generated code
+*.pb.go linguist-generated
+/bindings/** linguist-generated=true
+docs/** export-ignore
+*.md text
+",
+        );
+
+        assert!(generated(&[root], "api.pb.go"));
+        assert!(generated(&[root], "src/api/api.pb.go"));
+        assert!(generated(&[root], "bindings/c/lib.h"));
+        assert!(!generated(&[root], "src/bindings/lib.h"));
+        assert!(generated(&[root], "docs/index.html"));
+        assert!(!generated(&[root], "README.md"));
+        assert!(!generated(&[root], "src/main.go"));
+
+        // Later lines and deeper files win
+        let keep = (
+            "",
+            "*.pb.go linguist-generated\nkeep.pb.go -linguist-generated",
+        );
+        assert!(!generated(&[keep], "keep.pb.go"));
+        assert!(generated(&[keep], "drop.pb.go"));
+        let nested = ("src", "*.pb.go linguist-generated=false\n");
+        assert!(!generated(&[root, nested], "src/api/api.pb.go"));
+        assert!(generated(&[root, nested], "api.pb.go"));
+
+        // Patterns with a `/` are relative to the directory of their file
+        let nested = ("src", "gen/* linguist-generated\n");
+        assert!(generated(&[nested], "src/gen/a.rs"));
+        assert!(!generated(&[nested], "gen/a.rs"));
+        assert!(!generated(&[nested], "src/gen/sub/a.rs"));
+
+        // Directory patterns match no files
+        assert!(!generated(&[("", "gen/ linguist-generated")], "gen/a.rs"));
+    }
+}
@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use futures::StreamExt;
+use tokio::io::AsyncReadExt;
+
+/// The number of bytes at the start of a file that gets probed
+pub const PROBE_SIZE: u64 = 1024;
+
+/// The number of files read at the same time
+const PARALLEL_PROBES: usize = 32;
+
+/// Heuristic to decide whether some data is binary, fed one byte at a time
+#[derive(Clone, Debug, Default)]
+pub struct IsBinary {
+    total_bytes: usize,
+    odd_bytes: usize,
+    early_decision: bool,
+    expected_utf8_bytes: usize,
+}
+
+impl IsBinary {
+    /// `true` if `data` is binary
+    #[must_use]
+    pub fn check(data: &[u8]) -> bool {
+        let mut checker = Self::default();
+        for b in data {
+            if checker.is_binary(*b) {
+                break;
+            }
+        }
+        checker.final_verdict()
+    }
+
+    /// Feed the next byte, returns `true` once the data is known to be binary
+    pub fn is_binary(&mut self, b: u8) -> bool {
+        self.total_bytes += 1;
+
+        if self.early_decision {
+            return self.early_decision;
+        }
+
+        if b == b'\0' {
+            self.early_decision = true;
+            return true;
+        }
+
+        if self.expected_utf8_bytes > 0 {
+            self.expected_utf8_bytes -= 1;
+            if b & 0b1100_0000 != 0b1000_0000 {
+                self.odd_bytes += 1;
+            }
+        } else {
+            match b {
+                b if b & 0b1111_1000 == 0b1111_0000 => {
+                    self.expected_utf8_bytes = 3;
+                }
+                b if b & 0b1111_0000 == 0b1110_0000 => {
+                    self.expected_utf8_bytes = 2;
+                }
+                b if b & 0b1110_0000 == 0b1100_0000 => {
+                    self.expected_utf8_bytes = 1;
+                }
+                b if b >= 32 || [b'\n', b'\r', b'\t', 7, 12].contains(&b) => { /* do nothing */ }
+                _ => {
+                    self.odd_bytes += 1;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// `true` if all the bytes fed in look like binary data
+    #[must_use]
+    pub fn final_verdict(self) -> bool {
+        self.early_decision || ((self.total_bytes / 10) * 3 < self.odd_bytes) // 30% odd bytes might happen in text;-)
+    }
+}
+
+/// The kind of files to use as inputs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    Text,
+    Binary,
+}
+
+impl std::str::FromStr for FileKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "binary" => Ok(Self::Binary),
+            _ => Err(anyhow::anyhow!(
+                "Unknown file kind '{s}', expected 'text' or 'binary'"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for FileKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+/// What is known about the start of a file
+#[derive(Debug, Default)]
+pub(crate) struct Probe {
+    /// The start of the file, invalid UTF-8 replaced
+    pub(crate) head: String,
+    pub(crate) is_binary: bool,
+}
+
+impl Probe {
+    fn new(data: &[u8]) -> Self {
+        Self {
+            head: String::from_utf8_lossy(data).to_string(),
+            is_binary: IsBinary::check(data),
+        }
+    }
+
+    /// `true` if the file is of `kind`
+    pub(crate) fn is_kind(&self, kind: FileKind) -> bool {
+        self.is_binary == (kind == FileKind::Binary)
+    }
+}
+
+/// The probes of files, each file is read at most once per run
+#[derive(Clone, Default)]
+pub(crate) struct ProbeCache(Arc<Mutex<HashMap<PathBuf, Arc<Probe>>>>);
+
+impl ProbeCache {
+    async fn read_head(path: &Path) -> std::io::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        tokio::fs::File::open(path)
+            .await?
+            .take(PROBE_SIZE)
+            .read_to_end(&mut buffer)
+            .await?;
+        Ok(buffer)
+    }
+
+    /// Probe the file at `path`, an empty text file if it could not get read
+    async fn probe(&self, path: &Path) -> Arc<Probe> {
+        if let Some(probe) = self.0.lock().expect("Probe cache lock poisoned").get(path) {
+            return probe.clone();
+        }
+
+        let probe = Arc::new(match Self::read_head(path).await {
+            Ok(data) => Probe::new(&data),
+            Err(e) => {
+                tracing::debug!("Failed to read {path:?} to probe it: {e}");
+                Probe::default()
+            }
+        });
+        self.0
+            .lock()
+            .expect("Probe cache lock poisoned")
+            .insert(path.to_path_buf(), probe.clone());
+        probe
+    }
+
+    /// Keep the `files` whose probe passes `predicate`
+    pub(crate) async fn filter(
+        &self,
+        files: Vec<PathBuf>,
+        predicate: impl Fn(&Probe) -> bool,
+    ) -> Vec<PathBuf> {
+        let predicate = &predicate;
+        futures::stream::iter(files)
+            .map(|f| async move { predicate(&*self.probe(&f).await).then_some(f) })
+            .buffered(PARALLEL_PROBES)
+            .filter_map(|f| async move { f })
+            .collect()
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_binary(data: &[u8]) -> bool {
+        Probe::new(data).is_binary
+    }
+
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(b""));
+        assert!(!is_binary(b"fn main() {\n\tprintln!(\"Hello\");\r\n}\n"));
+        assert!(!is_binary("Grüße aus Köln, 日本語 und 🦀\n".as_bytes()));
+        assert!(is_binary(b"text\0with a zero byte"));
+        assert!(is_binary(&[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]));
+        assert!(is_binary(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]));
+
+        let probe = Probe::new(b"plain text");
+        assert!(probe.is_kind(FileKind::Text));
+        assert!(!probe.is_kind(FileKind::Binary));
+    }
+}
@@ -0,0 +1,72 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// The placeholder resolving to the path of the list file
+pub(crate) const LIST_FILE_PLACEHOLDER: &str = "files:listfile";
+
+/// A temporary file listing the files of an action, separated by NUL bytes
+///
+/// The file gets a random name in `directory` (the temporary directory of
+/// the run) and is removed again when the `ListFile` is dropped.
+pub(crate) struct ListFile {
+    path: tempfile::TempPath,
+}
+
+impl ListFile {
+    pub(crate) async fn new(files: &[PathBuf], directory: Option<&Path>) -> crate::Result<Self> {
+        let mut contents = Vec::new();
+        for f in files {
+            contents.extend_from_slice(f.as_os_str().as_encoded_bytes());
+            contents.push(b'\0');
+        }
+
+        let directory = directory.map(Path::to_path_buf);
+        tokio::task::spawn_blocking(move || {
+            let mut builder = tempfile::Builder::new();
+            builder.prefix("beautytips-files-");
+            // The file is created exclusively, never following links
+            let mut file = match &directory {
+                Some(directory) => builder.tempfile_in(directory),
+                None => builder.tempfile(),
+            }
+            .context("Failed to create the list file")?;
+            file.write_all(&contents)
+                .context(format!("Failed to write {}", file.path().display()))?;
+            Ok(Self {
+                path: file.into_temp_path(),
+            })
+        })
+        .await
+        .expect("Join Error")
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_list_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let files = [PathBuf::from("/tmp/a b.rs"), PathBuf::from("/tmp/c.rs")];
+        let list_file = ListFile::new(&files, Some(directory.path())).await.unwrap();
+        let path = list_file.path().to_path_buf();
+        assert!(path.starts_with(directory.path()));
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"/tmp/a b.rs\0/tmp/c.rs\0");
+
+        drop(list_file);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,473 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    borrow::Cow,
+    io::Read,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Outputs of actions larger than this many bytes are written to a file
+pub const DEFAULT_SPILL_THRESHOLD: usize = 1024 * 1024;
+
+/// How much of spilled output gets shown
+const PREVIEW_SIZE: u64 = 64 * 1024;
+
+/// The size of the chunks output is read in
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Files with spilled output older than this get removed
+pub(crate) const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The stdout or stderr of an action
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ActionOutput {
+    /// Kept in memory
+    Inline(Vec<u8>),
+    /// Written to a file, as it was too large to keep in memory
+    Spilled { path: PathBuf, size: u64 },
+}
+
+impl ActionOutput {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Inline(bytes) => bytes.is_empty(),
+            Self::Spilled { size, .. } => *size == 0,
+        }
+    }
+
+    /// The output to show
+    ///
+    /// Only the start of spilled output is read back, followed by a note on
+    /// the file holding all of it.
+    #[must_use]
+    pub fn bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            Self::Inline(bytes) => Cow::Borrowed(bytes),
+            Self::Spilled { path, size } => {
+                let mut preview = Vec::new();
+                if let Err(e) = std::fs::File::open(path)
+                    .and_then(|f| f.take(PREVIEW_SIZE).read_to_end(&mut preview))
+                {
+                    return Cow::Owned(
+                        format!("Failed to read output from {}: {e}\n", path.display())
+                            .into_bytes(),
+                    );
+                }
+                if *size > PREVIEW_SIZE {
+                    if !preview.ends_with(b"\n") {
+                        preview.push(b'\n');
+                    }
+                    preview.extend_from_slice(
+                        format!(
+                            "[{} more bytes of output in {}]\n",
+                            size - PREVIEW_SIZE,
+                            path.display()
+                        )
+                        .as_bytes(),
+                    );
+                }
+                Cow::Owned(preview)
+            }
+        }
+    }
+
+    /// Write all of the output to `target`, returning the number of bytes
+    /// written
+    pub(crate) async fn copy_to(
+        &self,
+        target: &mut (impl AsyncWrite + Unpin),
+    ) -> std::io::Result<u64> {
+        match self {
+            Self::Inline(bytes) => {
+                target.write_all(bytes).await?;
+                Ok(bytes.len() as u64)
+            }
+            Self::Spilled { path, .. } => {
+                let mut file = tokio::fs::File::open(path).await?;
+                tokio::io::copy(&mut file, target).await
+            }
+        }
+    }
+
+    /// Remove the file the output was spilled to, if any
+    pub(crate) async fn discard(self) {
+        if let Self::Spilled { path, .. } = self {
+            let _ = tokio::fs::remove_file(&path).await;
+        }
+    }
+}
+
+impl Default for ActionOutput {
+    fn default() -> Self {
+        Self::Inline(Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for ActionOutput {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::Inline(bytes)
+    }
+}
+
+/// The directory spilled output is written to: `output` in the log
+/// directory, or in the directory of temporary directories without state
+/// directory
+///
+/// Both belong to the user. Without either, output stays in memory.
+pub(crate) fn spill_directory() -> Option<PathBuf> {
+    crate::log_directory()
+        .map(|d| d.join("output"))
+        .or_else(|| super::temp_dir::base_directory().map(|d| d.join("output")))
+}
+
+/// Where output gets written to once it grows too large to keep in memory
+#[derive(Clone, Debug)]
+pub(crate) struct Spill {
+    /// Outputs larger than this many bytes are written to a file
+    pub(crate) threshold: usize,
+    /// The directory holding the files, output stays in memory without one
+    pub(crate) directory: Option<PathBuf>,
+    /// The action the output belongs to, used to name the files
+    action_id: String,
+}
+
+impl Default for Spill {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_SPILL_THRESHOLD,
+            directory: spill_directory(),
+            action_id: String::new(),
+        }
+    }
+}
+
+impl Spill {
+    /// Keep all output in memory
+    pub(crate) fn never() -> Self {
+        Self {
+            threshold: usize::MAX,
+            directory: None,
+            action_id: String::new(),
+        }
+    }
+
+    /// Spill the output of `action_id`
+    pub(crate) fn for_action(&self, action_id: &str) -> Self {
+        Self {
+            action_id: action_id.to_string(),
+            ..self.clone()
+        }
+    }
+
+    /// A buffer collecting the `stream` of the action
+    pub(crate) fn buffer(&self, stream: &'static str) -> OutputBuffer {
+        OutputBuffer {
+            spill: self.clone(),
+            stream,
+            memory: Vec::new(),
+            file: None,
+            spill_failed: false,
+            size: 0,
+            last: None,
+        }
+    }
+
+    /// A buffer continuing `output` (the `stream` of the action)
+    pub(crate) async fn resume(&self, stream: &'static str, output: ActionOutput) -> OutputBuffer {
+        let mut buffer = self.buffer(stream);
+        match output {
+            ActionOutput::Inline(bytes) => {
+                buffer.size = bytes.len() as u64;
+                buffer.last = bytes.last().copied();
+                buffer.memory = bytes;
+            }
+            ActionOutput::Spilled { path, size } => {
+                match tokio::fs::OpenOptions::new().append(true).open(&path).await {
+                    Ok(file) => {
+                        buffer.size = size;
+                        buffer.last = None;
+                        buffer.file = Some((file, path));
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to open {}: {e}", path.display());
+                        buffer
+                            .write(&ActionOutput::Spilled { path, size }.bytes())
+                            .await;
+                    }
+                }
+            }
+        }
+        buffer
+    }
+
+    /// Read `reader` (the `stream` of the action) to its end
+    pub(crate) async fn collect(
+        &self,
+        stream: &'static str,
+        mut reader: impl AsyncRead + Unpin,
+    ) -> std::io::Result<ActionOutput> {
+        let mut buffer = self.buffer(stream);
+        let mut chunk = vec![0; CHUNK_SIZE];
+        loop {
+            let count = reader.read(&mut chunk).await?;
+            if count == 0 {
+                return Ok(buffer.finish().await);
+            }
+            buffer.write(&chunk[..count]).await;
+        }
+    }
+}
+
+/// Create a file only the user may access in `directory`, named after
+/// `action_id` and `stream`
+fn create_spill_file(
+    directory: &Path,
+    action_id: &str,
+    stream: &str,
+) -> crate::Result<(std::fs::File, PathBuf)> {
+    let mut dir_builder = std::fs::DirBuilder::new();
+    dir_builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        dir_builder.mode(0o700);
+    }
+    dir_builder
+        .create(directory)
+        .context(format!("Failed to create {}", directory.display()))?;
+
+    let name = action_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    tempfile::Builder::new()
+        .prefix(&format!("{name}-"))
+        .suffix(&format!(".{stream}"))
+        .tempfile_in(directory)
+        .context(format!(
+            "Failed to create a file in {}",
+            directory.display()
+        ))?
+        .keep()
+        .context(format!("Failed to keep a file in {}", directory.display()))
+}
+
+/// Collects output in memory, moving it into a file once it grows beyond the
+/// threshold of its `Spill`
+///
+/// The output stays in memory if writing the file fails.
+#[derive(Debug)]
+pub(crate) struct OutputBuffer {
+    spill: Spill,
+    stream: &'static str,
+    memory: Vec<u8>,
+    file: Option<(tokio::fs::File, PathBuf)>,
+    spill_failed: bool,
+    size: u64,
+    last: Option<u8>,
+}
+
+impl OutputBuffer {
+    pub(crate) fn spill(&self) -> &Spill {
+        &self.spill
+    }
+
+    /// Whether the output written so far ends with `byte`
+    pub(crate) fn ends_with(&self, byte: u8) -> bool {
+        self.last == Some(byte)
+    }
+
+    async fn start_file(&mut self) -> crate::Result<()> {
+        let Some(directory) = self.spill.directory.clone() else {
+            return Err(anyhow::anyhow!("There is no directory to write output to"));
+        };
+        let action_id = self.spill.action_id.clone();
+        let stream = self.stream;
+        let (file, path) =
+            tokio::task::spawn_blocking(move || create_spill_file(&directory, &action_id, stream))
+                .await
+                .expect("Join Error")?;
+
+        let mut file = tokio::fs::File::from_std(file);
+        file.write_all(&self.memory)
+            .await
+            .context(format!("Failed to write {}", path.display()))?;
+        self.memory = Vec::new();
+        self.file = Some((file, path));
+        Ok(())
+    }
+
+    pub(crate) async fn write(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        self.size += bytes.len() as u64;
+        self.last = bytes.last().copied();
+
+        if self.file.is_none()
+            && !self.spill_failed
+            && self.memory.len() + bytes.len() > self.spill.threshold
+        {
+            if let Err(e) = self.start_file().await {
+                tracing::warn!("Failed to spill output of {}: {e:#}", self.spill.action_id);
+                self.spill_failed = true;
+            }
+        }
+        match &mut self.file {
+            Some((file, path)) => {
+                if let Err(e) = file.write_all(bytes).await {
+                    tracing::warn!("Failed to write {}: {e}", path.display());
+                }
+            }
+            None => self.memory.extend_from_slice(bytes),
+        }
+    }
+
+    /// Append `output`, removing the file it was spilled to
+    pub(crate) async fn append(&mut self, output: ActionOutput) {
+        match output {
+            ActionOutput::Inline(bytes) => self.write(&bytes).await,
+            ActionOutput::Spilled { path, .. } => {
+                let result = async {
+                    let mut file = tokio::fs::File::open(&path).await?;
+                    let mut chunk = vec![0; CHUNK_SIZE];
+                    loop {
+                        let count = file.read(&mut chunk).await?;
+                        if count == 0 {
+                            return Ok::<_, std::io::Error>(());
+                        }
+                        self.write(&chunk[..count]).await;
+                    }
+                }
+                .await;
+                if let Err(e) = result {
+                    self.write(
+                        format!("Failed to read output from {}: {e}\n", path.display()).as_bytes(),
+                    )
+                    .await;
+                }
+                let _ = tokio::fs::remove_file(&path).await;
+            }
+        }
+    }
+
+    pub(crate) async fn finish(self) -> ActionOutput {
+        match self.file {
+            Some((mut file, path)) => {
+                if let Err(e) = file.flush().await {
+                    tracing::warn!("Failed to write {}: {e}", path.display());
+                }
+                ActionOutput::Spilled {
+                    path,
+                    size: self.size,
+                }
+            }
+            None => ActionOutput::Inline(self.memory),
+        }
+    }
+}
+
+/// Remove the files in `directory` older than `max_age`
+pub(crate) fn prune(directory: &Path, max_age: Duration) {
+    let now = SystemTime::now();
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let modified = entry.metadata().and_then(|m| m.modified());
+        if modified.is_ok_and(|m| now.duration_since(m).unwrap_or_default() >= max_age) {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_spill() {
+        let temp = tempfile::tempdir().unwrap();
+        let directory = temp.path().join("output");
+        let spill = Spill {
+            threshold: 4,
+            directory: Some(directory.clone()),
+            action_id: String::new(),
+        }
+        .for_action("rust/check");
+
+        let small = spill.collect("stdout", &b"1234"[..]).await.unwrap();
+        assert_eq!(small, ActionOutput::Inline(b"1234".to_vec()));
+        assert_eq!(small.bytes().as_ref(), b"1234");
+        assert!(!directory.exists());
+
+        let mut buffer = spill.buffer("stdout");
+        buffer.write(b"123").await;
+        buffer.write(b"45").await;
+        assert!(buffer.ends_with(b'5'));
+        let large = buffer.finish().await;
+        let ActionOutput::Spilled { path, size } = &large else {
+            panic!("Output not spilled: {large:?}");
+        };
+        assert_eq!(*size, 5);
+        assert!(path.starts_with(&directory));
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("rust_check-"));
+        assert_eq!(large.bytes().as_ref(), b"12345");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |p: &Path| p.metadata().unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&directory), 0o700);
+            assert_eq!(mode(path), 0o600);
+        }
+
+        let mut resumed = spill.resume("stdout", large.clone()).await;
+        resumed.write(b"6").await;
+        assert_eq!(resumed.finish().await.bytes().as_ref(), b"123456");
+
+        let mut appended = spill.buffer("stdout");
+        appended.append(b"0".to_vec().into()).await;
+        appended.append(large.clone()).await;
+        let appended = appended.finish().await;
+        assert_eq!(appended.bytes().as_ref(), b"0123456");
+        assert!(!path.exists());
+
+        let preview_size = usize::try_from(PREVIEW_SIZE).unwrap();
+        let huge = spill
+            .collect("stderr", &vec![b'x'; preview_size + 10][..])
+            .await
+            .unwrap();
+        let shown = huge.bytes();
+        assert!(shown.starts_with(&vec![b'x'; preview_size]));
+        assert!(String::from_utf8_lossy(&shown[preview_size..]).starts_with("\n[10 more bytes"));
+
+        let ActionOutput::Spilled { path, .. } = &appended else {
+            panic!("Output not spilled: {appended:?}");
+        };
+        prune(&directory, Duration::from_secs(3600));
+        assert!(path.exists());
+        prune(&directory, Duration::ZERO);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_spill_without_directory() {
+        let spill = Spill {
+            threshold: 4,
+            directory: None,
+            action_id: String::new(),
+        };
+        let output = spill.collect("stdout", &b"12345"[..]).await.unwrap();
+        assert_eq!(output, ActionOutput::Inline(b"12345".to_vec()));
+    }
+}
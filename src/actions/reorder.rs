@@ -0,0 +1,157 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::collections::{HashMap, VecDeque};
+
+use super::ActionUpdate;
+
+/// Holds back updates of actions until all actions sorted before them are
+/// done
+///
+/// This makes the reported results independent of the order the actions
+/// finish in.
+pub(crate) struct ReorderBuffer {
+    /// The actions that are not done yet, in reporting order
+    pending: VecDeque<String>,
+    /// Updates of actions that are not at the front of `pending` yet
+    buffered: HashMap<String, Vec<ActionUpdate>>,
+}
+
+impl ReorderBuffer {
+    pub(crate) fn new(mut action_ids: Vec<String>) -> Self {
+        action_ids.sort();
+        action_ids.dedup();
+        Self {
+            pending: action_ids.into(),
+            buffered: HashMap::new(),
+        }
+    }
+
+    /// Add `update`, returning all updates that can be reported now
+    pub(crate) fn push(&mut self, update: ActionUpdate) -> Vec<ActionUpdate> {
+        let action_id = update.action_id();
+        if self.pending.front().map(String::as_str) != Some(action_id) {
+            if self.pending.iter().any(|p| p == action_id) {
+                self.buffered
+                    .entry(action_id.to_string())
+                    .or_default()
+                    .push(update);
+                return vec![];
+            }
+            // Not an action we know about: Nothing to wait for
+            return vec![update];
+        }
+
+        let is_done = matches!(update, ActionUpdate::Done { .. });
+        let mut ready = vec![update];
+        if is_done {
+            self.advance(&mut ready);
+        }
+        ready
+    }
+
+    /// Return all updates still held back, in reporting order
+    pub(crate) fn finish(mut self) -> Vec<ActionUpdate> {
+        self.pending
+            .drain(..)
+            .flat_map(|id| self.buffered.remove(&id).unwrap_or_default())
+            .collect()
+    }
+
+    /// The front action is done: Move on to the next action that is not done
+    /// yet, releasing all updates of actions done in the meantime
+    fn advance(&mut self, ready: &mut Vec<ActionUpdate>) {
+        self.pending.pop_front();
+        while let Some(next) = self.pending.front() {
+            let updates = self.buffered.remove(next).unwrap_or_default();
+            let is_done = updates
+                .iter()
+                .any(|u| matches!(u, ActionUpdate::Done { .. }));
+            ready.extend(updates);
+            if !is_done {
+                break;
+            }
+            self.pending.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn started(id: &str) -> ActionUpdate {
+        ActionUpdate::Started {
+            action_id: id.to_string(),
+        }
+    }
+
+    fn done(id: &str) -> ActionUpdate {
+        ActionUpdate::Done {
+            action_id: id.to_string(),
+            result: crate::ActionResult::Ok {
+                stdout: crate::ActionOutput::default(),
+                stderr: crate::ActionOutput::default(),
+            },
+        }
+    }
+
+    fn describe(updates: &[ActionUpdate]) -> Vec<String> {
+        updates
+            .iter()
+            .map(|u| match u {
+                ActionUpdate::Started { action_id } => format!("start {action_id}"),
+                ActionUpdate::Output { action_id, .. } => format!("output {action_id}"),
+                ActionUpdate::Detail { action_id, .. } => format!("detail {action_id}"),
+                ActionUpdate::Done { action_id, .. } => format!("done {action_id}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reorder_buffer() {
+        let mut buffer = ReorderBuffer::new(vec![
+            "test/c".to_string(),
+            "test/a".to_string(),
+            "test/b".to_string(),
+        ]);
+
+        assert_eq!(
+            describe(&buffer.push(started("test/c"))),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            describe(&buffer.push(started("test/a"))),
+            vec!["start test/a"]
+        );
+        assert_eq!(describe(&buffer.push(done("test/c"))), Vec::<String>::new());
+        assert_eq!(
+            describe(&buffer.push(started("other/x"))),
+            vec!["start other/x"]
+        );
+        assert_eq!(
+            describe(&buffer.push(started("test/b"))),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            describe(&buffer.push(done("test/a"))),
+            vec!["done test/a", "start test/b"]
+        );
+        assert_eq!(
+            describe(&buffer.push(done("test/b"))),
+            vec!["done test/b", "start test/c", "done test/c"]
+        );
+        assert!(buffer.finish().is_empty());
+    }
+
+    #[test]
+    fn test_reorder_buffer_finish() {
+        let mut buffer = ReorderBuffer::new(vec!["test/a".to_string(), "test/b".to_string()]);
+
+        assert_eq!(
+            describe(&buffer.push(started("test/b"))),
+            Vec::<String>::new()
+        );
+        assert_eq!(describe(&buffer.finish()), vec!["start test/b"]);
+    }
+}
@@ -0,0 +1,328 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use super::ActionUpdate;
+
+/// The number of reports queued up for the reporter by default
+pub const DEFAULT_REPORT_CAPACITY: usize = 10;
+
+/// What to do when actions report faster than the reporter can handle
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ReportOverflow {
+    /// Make the actions wait for the reporter to catch up
+    #[default]
+    Block,
+    /// Queue up all reports, no matter how much memory that takes
+    Unbounded,
+    /// Drop the oldest output and detail reports to make room
+    ///
+    /// Actions starting and finishing are always reported, actions wait for
+    /// the reporter when nothing else can be dropped.
+    DropOldest,
+}
+
+/// The reporter is gone, so reports can not be delivered anymore
+#[derive(Debug)]
+pub(crate) struct ReporterGone;
+
+impl ActionUpdate {
+    /// `true` if the report can get lost without breaking the results
+    fn is_droppable(&self) -> bool {
+        matches!(self, Self::Output { .. } | Self::Detail { .. })
+    }
+}
+
+#[derive(Debug, Default)]
+struct DropOldestState {
+    queue: VecDeque<ActionUpdate>,
+    senders: usize,
+    receiver_gone: bool,
+    dropped: usize,
+}
+
+/// A queue dropping the oldest droppable reports when full
+#[derive(Debug)]
+struct DropOldestQueue {
+    capacity: usize,
+    state: Mutex<DropOldestState>,
+    readable: tokio::sync::Notify,
+    writable: tokio::sync::Notify,
+}
+
+impl DropOldestQueue {
+    fn state(&self) -> std::sync::MutexGuard<'_, DropOldestState> {
+        self.state.lock().expect("Report queue lock poisoned")
+    }
+
+    /// Queue `update`, returning it again if the queue is full
+    fn try_push(&self, update: ActionUpdate) -> Result<Result<(), ActionUpdate>, ReporterGone> {
+        let mut state = self.state();
+        if state.receiver_gone {
+            return Err(ReporterGone);
+        }
+        if state.queue.len() >= self.capacity {
+            let Some(oldest) = state.queue.iter().position(ActionUpdate::is_droppable) else {
+                return Ok(Err(update));
+            };
+            state.queue.remove(oldest);
+            state.dropped += 1;
+            tracing::debug!(
+                dropped = state.dropped,
+                "Reporter is behind, dropped the oldest report"
+            );
+        }
+        state.queue.push_back(update);
+        drop(state);
+        self.readable.notify_one();
+        Ok(Ok(()))
+    }
+
+    async fn push(&self, mut update: ActionUpdate) -> Result<(), ReporterGone> {
+        loop {
+            // Register before trying, so the reporter going away in between
+            // still wakes this sender up
+            let writable = self.writable.notified();
+            tokio::pin!(writable);
+            writable.as_mut().enable();
+
+            // This fails once the reporter is gone
+            match self.try_push(update)? {
+                Ok(()) => return Ok(()),
+                Err(u) => update = u,
+            }
+            // Only reports that must not get lost are queued, so wait
+            let start = std::time::Instant::now();
+            writable.await;
+            tracing::debug!(
+                waited_ms = start.elapsed().as_millis(),
+                "Reporter is behind, waited for it"
+            );
+        }
+    }
+
+    async fn pop(&self) -> Option<ActionUpdate> {
+        loop {
+            {
+                let mut state = self.state();
+                if let Some(update) = state.queue.pop_front() {
+                    drop(state);
+                    self.writable.notify_one();
+                    return Some(update);
+                }
+                if state.senders == 0 {
+                    return None;
+                }
+            }
+            self.readable.notified().await;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct DropOldestSender(Arc<DropOldestQueue>);
+
+impl Clone for DropOldestSender {
+    fn clone(&self) -> Self {
+        self.0.state().senders += 1;
+        Self(self.0.clone())
+    }
+}
+
+impl Drop for DropOldestSender {
+    fn drop(&mut self) {
+        let mut state = self.0.state();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.0.readable.notify_one();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct DropOldestReceiver(Arc<DropOldestQueue>);
+
+impl Drop for DropOldestReceiver {
+    fn drop(&mut self) {
+        let mut state = self.0.state();
+        state.receiver_gone = true;
+        if state.dropped > 0 {
+            tracing::debug!(
+                dropped = state.dropped,
+                "Reporter dropped reports to keep up"
+            );
+        }
+        drop(state);
+        // Senders waiting for room need to learn that there is no reporter
+        self.0.writable.notify_waiters();
+    }
+}
+
+/// Sends the updates of actions to the reporter
+#[derive(Clone, Debug)]
+pub(crate) enum ActionUpdateSender {
+    Block(tokio::sync::mpsc::Sender<ActionUpdate>),
+    Unbounded(tokio::sync::mpsc::UnboundedSender<ActionUpdate>),
+    DropOldest(DropOldestSender),
+}
+
+impl ActionUpdateSender {
+    pub(crate) async fn send(&self, update: ActionUpdate) -> Result<(), ReporterGone> {
+        match self {
+            Self::Block(sender) => match sender.try_send(update) {
+                Ok(()) => Ok(()),
+                Err(tokio::sync::mpsc::error::TrySendError::Full(update)) => {
+                    let start = std::time::Instant::now();
+                    let result = sender.send(update).await.map_err(|_| ReporterGone);
+                    tracing::debug!(
+                        waited_ms = start.elapsed().as_millis(),
+                        "Reporter is behind, waited for it"
+                    );
+                    result
+                }
+                Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => Err(ReporterGone),
+            },
+            Self::Unbounded(sender) => sender.send(update).map_err(|_| ReporterGone),
+            Self::DropOldest(sender) => sender.0.push(update).await,
+        }
+    }
+}
+
+/// Receives the updates of actions in the reporter
+#[derive(Debug)]
+pub(crate) enum ActionUpdateReceiver {
+    Block(tokio::sync::mpsc::Receiver<ActionUpdate>),
+    Unbounded(tokio::sync::mpsc::UnboundedReceiver<ActionUpdate>),
+    DropOldest(DropOldestReceiver),
+}
+
+impl ActionUpdateReceiver {
+    /// The next update, `None` once all senders are gone
+    pub(crate) async fn recv(&mut self) -> Option<ActionUpdate> {
+        match self {
+            Self::Block(receiver) => receiver.recv().await,
+            Self::Unbounded(receiver) => receiver.recv().await,
+            Self::DropOldest(receiver) => receiver.0.pop().await,
+        }
+    }
+}
+
+/// A channel from the actions to the reporter, queueing up to `capacity`
+/// reports and handling more as `overflow` says
+pub(crate) fn channel(
+    capacity: usize,
+    overflow: ReportOverflow,
+) -> (ActionUpdateSender, ActionUpdateReceiver) {
+    let capacity = capacity.max(1);
+    match overflow {
+        ReportOverflow::Block => {
+            let (tx, rx) = tokio::sync::mpsc::channel(capacity);
+            (
+                ActionUpdateSender::Block(tx),
+                ActionUpdateReceiver::Block(rx),
+            )
+        }
+        ReportOverflow::Unbounded => {
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+            (
+                ActionUpdateSender::Unbounded(tx),
+                ActionUpdateReceiver::Unbounded(rx),
+            )
+        }
+        ReportOverflow::DropOldest => {
+            let queue = Arc::new(DropOldestQueue {
+                capacity,
+                state: Mutex::new(DropOldestState {
+                    senders: 1,
+                    ..DropOldestState::default()
+                }),
+                readable: tokio::sync::Notify::new(),
+                writable: tokio::sync::Notify::new(),
+            });
+            (
+                ActionUpdateSender::DropOldest(DropOldestSender(queue.clone())),
+                ActionUpdateReceiver::DropOldest(DropOldestReceiver(queue)),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(action_id: &str) -> ActionUpdate {
+        ActionUpdate::Output {
+            action_id: action_id.to_string(),
+            stdout: vec![],
+            stderr: vec![],
+        }
+    }
+
+    fn started(action_id: &str) -> ActionUpdate {
+        ActionUpdate::Started {
+            action_id: action_id.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let (tx, mut rx) = channel(2, ReportOverflow::DropOldest);
+
+        tx.send(started("a")).await.unwrap();
+        tx.send(output("a")).await.unwrap();
+        tx.send(output("b")).await.unwrap();
+        tx.send(started("c")).await.unwrap();
+
+        // Nothing left to drop, so sending waits for the reporter
+        let second = tx.clone();
+        let waiting = tokio::spawn(async move { second.send(started("d")).await.unwrap() });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        let mut ids = vec![];
+        ids.push(rx.recv().await.unwrap().action_id().to_string());
+        waiting.await.unwrap();
+        drop(tx);
+        while let Some(update) = rx.recv().await {
+            ids.push(update.action_id().to_string());
+        }
+        assert_eq!(ids, ["a", "c", "d"]);
+
+        let (tx, rx) = channel(2, ReportOverflow::DropOldest);
+        drop(rx);
+        assert!(tx.send(started("a")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_reporter_gone_while_waiting() {
+        let (tx, rx) = channel(1, ReportOverflow::DropOldest);
+        tx.send(started("a")).await.unwrap();
+
+        let waiting = tokio::spawn(async move { tx.send(started("b")).await });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        drop(rx);
+        assert!(waiting.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unbounded() {
+        let (tx, mut rx) = channel(1, ReportOverflow::Unbounded);
+        for id in ["a", "b", "c"] {
+            tx.send(output(id)).await.unwrap();
+        }
+        drop(tx);
+        let mut count = 0;
+        while rx.recv().await.is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 3);
+    }
+}
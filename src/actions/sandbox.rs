@@ -0,0 +1,448 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+
+use super::{
+    executor::{CommandExecutor, ProcessOutput},
+    output::Spill,
+};
+
+/// Where to run an action instead of the working tree
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SandboxMode {
+    /// A temporary directory holding copies of the input files
+    Copy,
+    /// A temporary git worktree with the input files copied over
+    Worktree,
+    /// The working tree itself, with the file system read-only except for
+    /// the temporary directory of the run and an empty `/tmp` (Linux only,
+    /// needs `bwrap`)
+    Restricted,
+}
+
+/// Extensions of files configuring tools, like `rustfmt.toml`
+const CONFIG_EXTENSIONS: [&str; 8] = ["cfg", "conf", "ini", "json", "json5", "toml", "yaml", "yml"];
+
+/// Is the file `name` likely configuring some tool, like `.editorconfig` or
+/// `pyproject.toml`?
+fn is_config_file(name: &std::ffi::OsStr) -> bool {
+    let name = Path::new(name);
+    name.to_string_lossy().starts_with('.')
+        || name
+            .extension()
+            .is_some_and(|e| CONFIG_EXTENSIONS.iter().any(|c| e == *c))
+}
+
+async fn sandbox_directory() -> crate::Result<tempfile::TempDir> {
+    tokio::task::spawn_blocking(|| super::temp_dir::create_private("beautytips-sandbox-"))
+        .await
+        .expect("Join Error")
+}
+
+/// Run git with `args` in `directory`
+async fn git(
+    executor: &dyn CommandExecutor,
+    directory: &Path,
+    args: &[OsString],
+) -> crate::Result<ProcessOutput> {
+    executor
+        .execute(Path::new("git"), args, directory, &[], &Spill::never())
+        .await
+}
+
+/// Remove the git worktree in `directory`
+async fn remove_worktree(executor: &dyn CommandExecutor, root_directory: &Path, directory: &Path) {
+    let output = git(
+        executor,
+        root_directory,
+        &[
+            "worktree".into(),
+            "remove".into(),
+            "--force".into(),
+            directory.into(),
+        ],
+    )
+    .await;
+    if !output.is_ok_and(|o| o.status.success()) {
+        tracing::warn!("Failed to remove git worktree {}", directory.display());
+    }
+}
+
+async fn file_diff(
+    executor: &dyn CommandExecutor,
+    root_directory: &Path,
+    relative: &Path,
+    sandboxed: &Path,
+) -> Vec<u8> {
+    let output = git(
+        executor,
+        root_directory,
+        &[
+            "diff".into(),
+            "--no-index".into(),
+            "--no-color".into(),
+            "--".into(),
+            relative.into(),
+            sandboxed.into(),
+        ],
+    )
+    .await;
+
+    match output {
+        Ok(output) if !output.stdout.is_empty() => output.stdout.bytes().into_owned(),
+        _ => format!("{} would change\n", relative.display()).into_bytes(),
+    }
+}
+
+/// A copy of (parts of) the working tree to run an action in
+///
+/// The copy is removed again by `close`, or in the background when the
+/// `Sandbox` is dropped without being closed.
+pub(crate) struct Sandbox {
+    mode: SandboxMode,
+    root_directory: PathBuf,
+    directory: Option<tempfile::TempDir>,
+    files: Vec<PathBuf>,
+    /// Runs git
+    executor: Arc<dyn CommandExecutor>,
+}
+
+impl Sandbox {
+    pub(crate) async fn new(
+        mode: &SandboxMode,
+        root_directory: &Path,
+        executor: Arc<dyn CommandExecutor>,
+    ) -> crate::Result<Self> {
+        let directory = sandbox_directory().await?;
+
+        match mode {
+            SandboxMode::Copy => {}
+            SandboxMode::Worktree => {
+                let output = git(
+                    executor.as_ref(),
+                    root_directory,
+                    &[
+                        "worktree".into(),
+                        "add".into(),
+                        "--detach".into(),
+                        "--quiet".into(),
+                        directory.path().into(),
+                        "HEAD".into(),
+                    ],
+                )
+                .await
+                .context("Failed to run git")?;
+                if !output.status.success() {
+                    return Err(anyhow::anyhow!(format!(
+                        "Failed to create git worktree: {}",
+                        String::from_utf8_lossy(&output.stderr.bytes()).trim()
+                    )));
+                }
+            }
+            SandboxMode::Restricted => {
+                return Err(anyhow::anyhow!(
+                    "Restricted sandboxes run in the working tree"
+                ));
+            }
+        }
+
+        Ok(Self {
+            mode: mode.clone(),
+            root_directory: root_directory.to_path_buf(),
+            directory: Some(directory),
+            files: Vec::new(),
+            executor,
+        })
+    }
+
+    /// The directory to run the action in
+    pub(crate) fn directory(&self) -> &Path {
+        self.directory
+            .as_ref()
+            .expect("The sandbox is only closed when consumed")
+            .path()
+    }
+
+    /// Copy the `files` (relative to the root directory) into the sandbox
+    ///
+    /// A copy gets the configuration files next to the `files` and in all
+    /// directories above them as well, so that tools find their settings.
+    /// These are never written back.
+    pub(crate) async fn copy_in(&mut self, files: Vec<PathBuf>) -> crate::Result<()> {
+        let mut directories = std::collections::BTreeSet::new();
+        for relative in files {
+            let source = self.root_directory.join(&relative);
+            if !source.is_file() || self.files.contains(&relative) {
+                continue;
+            }
+
+            self.copy_file(&relative).await?;
+            directories.extend(relative.ancestors().skip(1).map(Path::to_path_buf));
+            self.files.push(relative);
+        }
+
+        if self.mode == SandboxMode::Copy {
+            for directory in directories {
+                self.copy_config_files(&directory).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn copy_file(&self, relative: &Path) -> crate::Result<()> {
+        let source = self.root_directory.join(relative);
+        let target = self.directory().join(relative);
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .context(format!("Failed to create {}", parent.display()))?;
+        }
+        tokio::fs::copy(&source, &target)
+            .await
+            .context(format!("Failed to copy {} into sandbox", source.display()))?;
+        Ok(())
+    }
+
+    /// Copy the configuration files in `directory` (relative to the root
+    /// directory) not in the sandbox yet
+    async fn copy_config_files(&self, directory: &Path) -> crate::Result<()> {
+        let source = self.root_directory.join(directory);
+        let mut entries = tokio::fs::read_dir(&source)
+            .await
+            .context(format!("Failed to read {}", source.display()))?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .context(format!("Failed to read {}", source.display()))?
+        {
+            let relative = directory.join(entry.file_name());
+            if !is_config_file(&entry.file_name())
+                || !entry.file_type().await.is_ok_and(|t| t.is_file())
+                || self.directory().join(&relative).exists()
+            {
+                continue;
+            }
+            self.copy_file(&relative).await?;
+        }
+        Ok(())
+    }
+
+    /// Compare the files in the sandbox to the originals
+    ///
+    /// Returns a diff of all changes done in the sandbox. The changes are
+    /// written back into the working tree if `apply` is set.
+    pub(crate) async fn finish(&self, apply: bool) -> crate::Result<Vec<u8>> {
+        let mut diff = Vec::new();
+
+        for relative in &self.files {
+            let original = self.root_directory.join(relative);
+            let sandboxed = self.directory().join(relative);
+
+            let before = tokio::fs::read(&original)
+                .await
+                .context(format!("Failed to read {}", original.display()))?;
+            let after = tokio::fs::read(&sandboxed)
+                .await
+                .context(format!("Failed to read {}", sandboxed.display()))?;
+            if before == after {
+                continue;
+            }
+
+            diff.extend_from_slice(
+                &file_diff(
+                    self.executor.as_ref(),
+                    &self.root_directory,
+                    relative,
+                    &sandboxed,
+                )
+                .await,
+            );
+
+            if apply {
+                tokio::task::spawn_blocking(move || crate::safe_rewrite(&original, &after))
+                    .await??;
+            }
+        }
+
+        Ok(diff)
+    }
+
+    /// Remove the sandbox
+    pub(crate) async fn close(mut self) {
+        let Some(directory) = self.directory.take() else {
+            return;
+        };
+        if self.mode == SandboxMode::Worktree {
+            remove_worktree(
+                self.executor.as_ref(),
+                &self.root_directory,
+                directory.path(),
+            )
+            .await;
+        }
+        tokio::task::spawn_blocking(move || remove_directory(directory))
+            .await
+            .expect("Join Error");
+    }
+}
+
+fn remove_directory(directory: tempfile::TempDir) {
+    let path = directory.path().to_path_buf();
+    match directory.close() {
+        // `git worktree remove` removed it already
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => tracing::warn!("Failed to remove sandbox {}: {e}", path.display()),
+        Ok(()) => {}
+    }
+}
+
+impl Drop for Sandbox {
+    /// Clean up sandboxes not closed, e.g. as their action got cancelled
+    fn drop(&mut self) {
+        let Some(directory) = self.directory.take() else {
+            return;
+        };
+        let worktree = (self.mode == SandboxMode::Worktree)
+            .then(|| (self.root_directory.clone(), self.executor.clone()));
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let handle = runtime.clone();
+                runtime.spawn_blocking(move || {
+                    if let Some((root_directory, executor)) = worktree {
+                        handle.block_on(remove_worktree(
+                            executor.as_ref(),
+                            &root_directory,
+                            directory.path(),
+                        ));
+                    }
+                    remove_directory(directory);
+                });
+            }
+            // Git prunes worktrees once their directory is gone
+            Err(_) => remove_directory(directory),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn executor() -> Arc<dyn CommandExecutor> {
+        Arc::new(crate::actions::executor::ProcessExecutor)
+    }
+
+    #[tokio::test]
+    async fn test_copy_sandbox() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::create_dir_all(root.join("other")).unwrap();
+        std::fs::write(root.join("sub/a.txt"), "before\n").unwrap();
+        std::fs::write(root.join("b.txt"), "unchanged\n").unwrap();
+        for config in [
+            "rustfmt.toml",
+            ".editorconfig",
+            "sub/.prettierrc",
+            "other/x.toml",
+        ] {
+            std::fs::write(root.join(config), "").unwrap();
+        }
+
+        let mut sandbox = Sandbox::new(&SandboxMode::Copy, root, executor())
+            .await
+            .unwrap();
+        sandbox
+            .copy_in(vec![
+                PathBuf::from("sub/a.txt"),
+                PathBuf::from("b.txt"),
+                PathBuf::from("missing.txt"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(sandbox.files.len(), 2);
+        for (config, copied) in [
+            ("rustfmt.toml", true),
+            (".editorconfig", true),
+            ("sub/.prettierrc", true),
+            ("other/x.toml", false),
+        ] {
+            assert_eq!(
+                sandbox.directory().join(config).exists(),
+                copied,
+                "{config}"
+            );
+        }
+
+        std::fs::write(sandbox.directory().join("sub/a.txt"), "after\n").unwrap();
+        std::fs::write(sandbox.directory().join("rustfmt.toml"), "changed").unwrap();
+
+        let diff = sandbox.finish(false).await.unwrap();
+        assert!(!diff.is_empty());
+        assert_eq!(
+            std::fs::read_to_string(root.join("sub/a.txt")).unwrap(),
+            "before\n"
+        );
+
+        let diff = sandbox.finish(true).await.unwrap();
+        assert!(!diff.is_empty());
+        assert_eq!(
+            std::fs::read_to_string(root.join("sub/a.txt")).unwrap(),
+            "after\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("rustfmt.toml")).unwrap(),
+            ""
+        );
+        assert!(sandbox.finish(false).await.unwrap().is_empty());
+
+        let directory = sandbox.directory().to_path_buf();
+        sandbox.close().await;
+        assert!(!directory.exists());
+    }
+
+    #[tokio::test]
+    async fn test_worktree_sandbox() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let git = |args: &[&str]| {
+            std::process::Command::new("git")
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(root)
+                .output()
+                .unwrap()
+        };
+        if !git(&["init", "--quiet"]).status.success() {
+            return; // No git to test with
+        }
+        std::fs::write(root.join("rustfmt.toml"), "").unwrap();
+        git(&["add", "rustfmt.toml"]);
+        assert!(git(&["commit", "--quiet", "-m", "Initial"])
+            .status
+            .success());
+
+        let sandbox = Sandbox::new(&SandboxMode::Worktree, root, executor())
+            .await
+            .unwrap();
+        let directory = sandbox.directory().to_path_buf();
+        assert!(directory.join("rustfmt.toml").is_file());
+
+        sandbox.close().await;
+        assert!(!directory.exists());
+        let worktrees = git(&["worktree", "list", "--porcelain"]).stdout;
+        assert_eq!(
+            String::from_utf8_lossy(&worktrees)
+                .matches("worktree ")
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,161 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// The interpreter and its arguments named in the `#!` line of `text`
+///
+/// The interpreter is reduced to its file name (`/bin/sh` to `sh`) and
+/// `env` is skipped, so the result can be looked up in the `PATH` on systems
+/// that can not run scripts themselves.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn shebang_command(text: &str) -> Option<Vec<String>> {
+    let line = text.lines().next()?.strip_prefix("#!")?;
+    let mut parts = line.split_whitespace();
+    let interpreter = parts.next()?;
+    let interpreter = interpreter.rsplit(['/', '\\']).next()?;
+
+    let mut command = vec![];
+    if interpreter != "env" {
+        command.push(interpreter.to_string());
+    }
+    command.extend(parts.map(ToString::to_string));
+    (!command.is_empty()).then_some(command)
+}
+
+/// The name of the environment variable holding the value of the
+/// `placeholder` in scripts
+pub(crate) fn placeholder_variable(placeholder: &str) -> String {
+    format!(
+        "BEAUTYTIPS_{}",
+        placeholder.to_uppercase().replace([':', '.'], "_")
+    )
+}
+
+/// An inline script written into an executable file
+///
+/// The file gets a random name in `directory` (the temporary directory of
+/// the run) and is removed again when the `Script` is dropped.
+pub(crate) struct Script {
+    path: tempfile::TempPath,
+    /// The interpreter to run the script with, on systems that can not run
+    /// scripts themselves
+    #[cfg_attr(not(windows), allow(dead_code))]
+    interpreter: Vec<String>,
+}
+
+impl Script {
+    pub(crate) async fn new(text: &str, directory: Option<&Path>) -> crate::Result<Self> {
+        let text = text.to_string();
+        let directory = directory.map(Path::to_path_buf);
+        tokio::task::spawn_blocking(move || Self::create(&text, directory.as_deref()))
+            .await
+            .expect("Join Error")
+    }
+
+    fn create(text: &str, directory: Option<&Path>) -> crate::Result<Self> {
+        let interpreter = if cfg!(windows) {
+            shebang_command(text).ok_or_else(|| {
+                anyhow::anyhow!("The script needs a #! line naming its interpreter")
+            })?
+        } else {
+            vec![]
+        };
+
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("beautytips-script-");
+        // The file is created exclusively, never following links
+        let mut file = match directory {
+            Some(directory) => builder.tempfile_in(directory),
+            None => builder.tempfile(),
+        }
+        .context("Failed to create the script file")?;
+        file.write_all(text.as_bytes())
+            .context(format!("Failed to write {}", file.path().display()))?;
+        // The file must be closed to be executed
+        let path = file.into_temp_path();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o700))
+                .context(format!("Failed to make {} executable", path.display()))?;
+        }
+
+        Ok(Self { path, interpreter })
+    }
+
+    /// The command to run the script and the arguments to pass before those
+    /// of the action
+    pub(crate) fn command(&self) -> (PathBuf, Vec<OsString>) {
+        #[cfg(windows)]
+        {
+            let mut interpreter = self.interpreter.iter().map(OsString::from);
+            let command = PathBuf::from(interpreter.next().expect("Interpreter is set"));
+            let args = interpreter
+                .chain(std::iter::once(self.path.as_os_str().to_os_string()))
+                .collect();
+            (command, args)
+        }
+        #[cfg(not(windows))]
+        {
+            (self.path.to_path_buf(), vec![])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shebang_command() {
+        assert_eq!(
+            shebang_command("#!/bin/sh\necho"),
+            Some(vec!["sh".to_string()])
+        );
+        assert_eq!(
+            shebang_command("#!/usr/bin/env python3 -u\n"),
+            Some(vec!["python3".to_string(), "-u".to_string()])
+        );
+        assert_eq!(
+            shebang_command("#! /bin/bash -e"),
+            Some(vec!["bash".to_string(), "-e".to_string()])
+        );
+        assert_eq!(shebang_command("echo hello\n"), None);
+        assert_eq!(shebang_command("#!/usr/bin/env\n"), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_script() {
+        let directory = tempfile::tempdir().unwrap();
+        let script = Script::new(
+            "#!/bin/sh\necho \"hello $BEAUTYTIPS_FILES\"\n",
+            Some(directory.path()),
+        )
+        .await
+        .unwrap();
+        let (path, args) = script.command();
+        assert!(path.starts_with(directory.path()));
+        assert!(args.is_empty());
+
+        let output = tokio::process::Command::new(&path)
+            .env(placeholder_variable("files"), "a.txt")
+            .output()
+            .await
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hello a.txt\n");
+
+        drop(script);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+
+/// The placeholder resolving to the temporary directory of a run
+pub(crate) const TEMP_DIR_PLACEHOLDER: &str = "temp_dir";
+
+/// The environment variable holding the temporary directory of a run
+pub(crate) const TEMP_DIR_VARIABLE: &str = "BEAUTYTIPS_TMPDIR";
+
+/// The placeholder resolving to the file holding the diff of a run
+pub(crate) const DIFF_FILE_PLACEHOLDER: &str = "diff_file";
+
+/// The environment variable holding the file with the diff of a run
+pub(crate) const DIFF_FILE_VARIABLE: &str = "BEAUTYTIPS_DIFF_FILE";
+
+/// The name of the file holding the diff, in the temporary directory
+const DIFF_FILE_NAME: &str = "changes.diff";
+
+const PREFIX: &str = "beautytips-run-";
+
+/// Temporary directories of runs older than this are removed by later runs
+const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The directory holding temporary directories: `tmp` in the cache directory
+///
+/// The cache directory belongs to the user, unlike the system temporary
+/// directory shared with everybody else.
+pub(crate) fn base_directory() -> Option<PathBuf> {
+    crate::cache_directory().map(|d| d.join("tmp"))
+}
+
+/// Create a new directory in `base` that only the user may access, named
+/// `prefix` followed by a random suffix
+fn create_private_in(base: Option<&Path>, prefix: &str) -> crate::Result<tempfile::TempDir> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(prefix);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(std::fs::Permissions::from_mode(0o700));
+    }
+
+    match base {
+        Some(base) => {
+            let mut dir_builder = std::fs::DirBuilder::new();
+            dir_builder.recursive(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::DirBuilderExt;
+                dir_builder.mode(0o700);
+            }
+            dir_builder
+                .create(base)
+                .context(format!("Failed to create {}", base.display()))?;
+            builder.tempdir_in(base).context(format!(
+                "Failed to create a directory in {}",
+                base.display()
+            ))
+        }
+        None => builder
+            .tempdir()
+            .context("Failed to create a temporary directory"),
+    }
+}
+
+/// Create a new directory that only the user may access, named `prefix`
+/// followed by a random suffix
+///
+/// The directory is removed when the returned `tempfile::TempDir` gets
+/// dropped.
+pub(crate) fn create_private(prefix: &str) -> crate::Result<tempfile::TempDir> {
+    create_private_in(base_directory().as_deref(), prefix)
+}
+
+/// A directory all actions of a run can use as scratch space
+///
+/// The directory is removed again when the `TempDir` is dropped. Directories
+/// left behind by runs that got killed are removed by later runs once they
+/// are older than a day.
+#[derive(Debug)]
+pub(crate) struct TempDir {
+    directory: tempfile::TempDir,
+}
+
+impl TempDir {
+    pub(crate) async fn new() -> crate::Result<Self> {
+        tokio::task::spawn_blocking(|| Self::new_in(base_directory().as_deref()))
+            .await
+            .expect("Join Error")
+    }
+
+    fn new_in(base: Option<&Path>) -> crate::Result<Self> {
+        let directory = create_private_in(base, PREFIX)?;
+        if let Some(base) = base {
+            prune(base, MAX_AGE);
+        }
+        Ok(Self { directory })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.directory.path()
+    }
+
+    /// Write the unified `diff` of the run into the directory, returning the
+    /// path of the file
+    pub(crate) async fn write_diff(&self, diff: &[u8]) -> crate::Result<PathBuf> {
+        let path = self.path().join(DIFF_FILE_NAME);
+        tokio::fs::write(&path, diff)
+            .await
+            .context(format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Remove the temporary directories (of runs, snapshots, ...) in `base`
+/// older than `max_age`
+fn prune(base: &Path, max_age: Duration) {
+    let now = SystemTime::now();
+    let Ok(entries) = std::fs::read_dir(base) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with("beautytips-")
+        {
+            continue;
+        }
+        let modified = entry.metadata().and_then(|m| m.modified());
+        if modified.is_ok_and(|m| now.duration_since(m).unwrap_or_default() >= max_age) {
+            tracing::debug!("Removing {} left behind", entry.path().display());
+            let _ = std::fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_dir() {
+        let base =
+            std::env::temp_dir().join(format!("beautytips-prune-test-{}", std::process::id()));
+        let other = base.join("other-directory");
+        std::fs::create_dir_all(&other).unwrap();
+
+        let first = TempDir::new_in(Some(&base)).unwrap();
+        let second = TempDir::new_in(Some(&base)).unwrap();
+        assert_ne!(first.path(), second.path());
+        assert!(first.path().starts_with(&base));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = first.path().metadata().unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        prune(&base, MAX_AGE);
+        assert!(first.path().exists());
+        prune(&base, Duration::ZERO);
+        assert!(!first.path().exists());
+        assert!(!second.path().exists());
+        assert!(other.exists());
+
+        let path = TempDir::new_in(Some(&base)).unwrap().path().to_path_buf();
+        assert!(!path.exists());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -1,11 +1,15 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
 
-use clap::{Args, Parser, Subcommand};
+use anyhow::Context;
+use clap::{builder::TypedValueParser, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
 
-use std::{ffi::OsString, path::PathBuf};
+use std::path::PathBuf;
 
-use crate::config::{ActionSelector, ActionSelectors};
+use crate::builtin_commands::BuiltinCommand;
+use crate::config::{ActionId, ActionSelector, ActionSelectors, DumpFormat};
+use crate::hook::HookCommand;
+use crate::reporter::OutputFormat;
 
 /// Where to get files to look at from
 #[derive(Clone, Debug, Args)]
@@ -26,32 +30,281 @@ struct CliVcsExtra {
     from_revision: Option<String>,
     #[arg(long = "to-rev", requires = "vcs-input")]
     to_revision: Option<String>,
+    /// Look at all files changed since this revision or date (e.g.
+    /// `main` or `"1 week ago"`), including uncommitted changes
+    #[arg(
+        long = "changed-since",
+        value_name = "REV_OR_DATE",
+        requires = "vcs-input",
+        conflicts_with_all = ["from_revision", "to_revision"]
+    )]
+    changed_since: Option<String>,
+    /// Check the files changed in this revision exactly as recorded there,
+    /// ignoring local modifications
+    #[arg(
+        long = "at-rev",
+        value_name = "REV",
+        requires = "vcs-input",
+        conflicts_with_all = ["from_revision", "to_revision", "changed_since"]
+    )]
+    at_revision: Option<String>,
+    /// Ignore changed files below this path, e.g. vendored code (can be
+    /// repeated)
+    #[arg(long = "exclude-path", value_name = "PATH", requires = "vcs-input")]
+    excluded_paths: Vec<PathBuf>,
 }
 
 /// Where to get files to look at from
 #[derive(Clone, Debug, Subcommand)]
 #[command(rename_all = "kebab-case")]
+#[allow(clippy::large_enum_variant)]
 enum CliCommand {
+    /// Run a command built into beautytips, used by the builtin actions
     Builtin {
-        action: String,
-        arguments: Vec<OsString>,
+        #[command(subcommand)]
+        command: BuiltinCommand,
     },
     ListActions,
-    /// Doc comment
+    /// Check that the actions running builtin commands pass options these
+    /// accept, and optionally that all executables are found
+    CheckConfig {
+        /// Also check that the executables of all actions enabled in the
+        /// current directory are found
+        #[arg(long = "executables")]
+        executables: bool,
+    },
+    /// Print the JSON Schema describing the configuration file format
+    ConfigSchema,
+    /// Remove the caches, the state and the logs of beautytips
+    Clean,
+    /// Print the configuration merged from the builtin and the user
+    /// configuration, with the file each action and group comes from
+    DumpConfig {
+        /// The format to print the configuration in
+        #[arg(long = "format", value_enum, default_value = "toml")]
+        format: DumpFormat,
+    },
+    /// Show detailed information on actions
+    HelpAction {
+        #[arg(value_name = "SELECTOR", required = true)]
+        actions: Vec<ActionSelector>,
+    },
+    /// Run the actions for a git hook, on the files git passes to it
+    ///
+    /// The actions are those of the action groups named like the hook,
+    /// e.g. `my/pre_push` or `my/commit_msg`.
+    Hook {
+        #[command(subcommand)]
+        hook: HookCommand,
+    },
+    /// Explain whether actions look at a file, and why not
+    Why {
+        #[command(flatten)]
+        source: CliInputFiles,
+        #[command(flatten)]
+        vcs_input_extra: CliVcsExtra,
+        /// The file to explain
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+        /// The actions to look at
+        #[arg(value_name = "SELECTOR", required = true)]
+        actions: Vec<ActionSelector>,
+    },
+    /// Update beautytips to the latest release
+    SelfUpdate {
+        /// Only check whether a newer release is available
+        #[arg(long = "check")]
+        check: bool,
+    },
+    /// Add a new action to a configuration file
+    AddAction {
+        /// The id of the new action, e.g. `mytool/check`
+        #[arg(long = "name")]
+        name: ActionId,
+        /// A short description of the action
+        #[arg(long = "description")]
+        description: Option<String>,
+        /// The command to run, e.g. `mytool --check {{files...}}`
+        #[arg(long = "command")]
+        command: String,
+        /// Glob pattern selecting the files for the action (can be repeated)
+        #[arg(long = "filter", value_name = "GLOB")]
+        filters: Vec<String>,
+        /// The configuration file to add the action to. Defaults to the user
+        /// configuration file.
+        #[arg(long = "config", value_name = "FILE")]
+        config_file: Option<PathBuf>,
+    },
+    /// List the files actions would look at
     ListFiles {
         #[command(flatten)]
         source: CliInputFiles,
         #[command(flatten)]
         vcs_input_extra: CliVcsExtra,
+        /// Print the number of files only
+        #[arg(long = "count", conflicts_with_all = ["relative", "null"])]
+        count: bool,
+        /// Print paths relative to the root directory, which `-v` prints to
+        /// stderr
+        #[arg(long = "relative")]
+        relative: bool,
+        /// Separate paths by NUL characters instead of newlines, e.g. for
+        /// `xargs -0`
+        #[arg(long = "null", short = '0')]
+        null: bool,
     },
     Run {
         #[command(flatten)]
         source: CliInputFiles,
         #[command(flatten)]
         vcs_input_extra: CliVcsExtra,
+        /// Run the actions with this tag as well, like the selector
+        /// `tag:<TAG>` (can be repeated)
+        #[arg(long = "tags", value_name = "TAG", value_delimiter = ',', value_parser = ActionSelector::from_tag)]
+        tags: Vec<ActionSelector>,
+        /// Only run actions matching this selector (can be repeated)
+        #[arg(long = "only", value_name = "SELECTOR", value_delimiter = ',')]
+        only: Vec<ActionSelector>,
+        /// Skip actions matching this selector (can be repeated)
+        ///
+        /// Selectors in the comma separated `SKIP` environment variable are
+        /// skipped as well.
+        #[arg(long = "skip", value_name = "SELECTOR", value_delimiter = ',')]
+        skip: Vec<ActionSelector>,
+        /// Run in all of these repositories (one after the other) and merge
+        /// the results into one report
+        #[arg(long = "repos", value_name = "DIR", value_delimiter = ',')]
+        repositories: Vec<PathBuf>,
+        /// Run in all repositories found below this directory, like with
+        /// `--repos`. Hidden directories are not searched.
+        #[arg(
+            long = "workspace",
+            value_name = "DIR",
+            conflicts_with = "repositories"
+        )]
+        workspace: Option<PathBuf>,
+        /// Start the actions that failed in the last run first
+        #[arg(long = "failed-first")]
+        failed_first: bool,
+        /// The format to report results in, instead of the `reporters` of the
+        /// configuration. Without both, it is detected from the environment
+        /// when running in `TeamCity`, `Buildkite` or `GitHub Actions` and
+        /// `terminal` otherwise.
+        #[arg(long = "output", value_enum)]
+        output: Option<OutputFormat>,
+        /// Report the actions sorted by their id, no matter in which order
+        /// they finish. This makes logs of different runs comparable.
+        #[arg(long = "stable-order")]
+        stable_order: bool,
+        /// Report all skipped and all not applicable actions in one line
+        /// each. Use `--verbose` to list all of them.
+        #[arg(long = "collapse")]
+        collapse: bool,
+        /// Print the output of actions as is. By default escape sequences
+        /// are removed and other control characters escaped, so the output
+        /// of tools can not take over the terminal.
+        #[arg(long = "raw-output")]
+        raw_output: bool,
+        /// Append all reports to this JSON Lines file as they happen
+        #[arg(long = "report-file", value_name = "FILE")]
+        report_file: Option<PathBuf>,
+        /// Load environment variables for the actions from `.env` in the
+        /// repository root, next to those from `.beautytips.env`
+        #[arg(long = "dotenv")]
+        dotenv: bool,
+        /// Set an environment variable for all actions (can be repeated)
+        #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_environment)]
+        environment: Vec<(String, String)>,
+        /// Set the `isolated-environment` of the actions, e.g. to use build
+        /// directories of their own. Useful for hooks, so they do not
+        /// invalidate the build directories you use.
+        #[arg(long = "isolate")]
+        isolate: bool,
+        /// Run the slow actions even if fast actions failed, instead of
+        /// skipping them
+        #[arg(long = "keep-going")]
+        keep_going: bool,
+        /// Refuse to run when there are more files to look at than this,
+        /// e.g. to not run linters on a huge vendored import by accident
+        #[arg(long = "max-files", value_name = "N")]
+        max_files: Option<usize>,
+        /// Cancel the actions still running after this long (like `90s`,
+        /// `10m` or `1h`), report them as errors and exit with code 124
+        #[arg(long = "timeout", value_name = "DURATION", value_parser = parse_timeout)]
+        timeout: Option<std::time::Duration>,
+        /// The number of invocations of actions with `parallel-invocations`
+        /// to run at the same time. Defaults to the number of CPUs.
+        #[arg(long = "jobs", short = 'j', value_name = "N")]
+        jobs: Option<usize>,
+        /// The number of reports to queue up when the output can not keep
+        /// up with the actions. Defaults to 10.
+        #[arg(long = "report-buffer", value_name = "N")]
+        report_capacity: Option<usize>,
+        /// What to do when more reports are waiting: `block` the actions,
+        /// queue `unbounded` or `drop-oldest` output of actions
+        #[arg(
+            long = "report-overflow",
+            value_name = "POLICY",
+            value_parser = clap::builder::PossibleValuesParser::new(["block", "unbounded", "drop-oldest"])
+                .map(|p| match p.as_str() {
+                    "unbounded" => beautytips::ReportOverflow::Unbounded,
+                    "drop-oldest" => beautytips::ReportOverflow::DropOldest,
+                    _ => beautytips::ReportOverflow::Block,
+                }),
+        )]
+        report_overflow: Option<beautytips::ReportOverflow>,
+        /// Write outputs of actions larger than this (like `512k` or `4M`)
+        /// into files in the log directory instead of keeping them in
+        /// memory. Only their start gets reported. Defaults to 1M.
+        #[arg(long = "spill-output-above", value_name = "SIZE", value_parser = parse_size)]
+        spill_threshold: Option<usize>,
+        /// Wait for other runs in the same repository to finish instead of
+        /// failing
+        #[arg(long = "wait-for-lock", conflicts_with = "no_lock")]
+        wait_for_lock: bool,
+        /// Run even when other runs are working in the same repository.
+        /// Fixing actions of both runs might overwrite each other's changes!
+        #[arg(long = "no-lock")]
+        no_lock: bool,
+        /// The actions to run. Without any, the checks of the builtin rule
+        /// packs applying to the files run.
+        #[arg(value_name = "ACTIONS")]
+        actions: Vec<ActionSelector>,
+    },
+    /// Run the actions that failed in the last run again, on the same files
+    RerunFailed,
+    /// Run actions on the files changed below the current directory, each
+    /// time files change
+    ///
+    /// Changes to the same file are coalesced, and the temporary files of
+    /// editors are ignored, just like the files git ignores.
+    Watch {
+        /// How long no file may change before running the actions (like
+        /// `500ms` or `2s`)
+        #[arg(
+            long = "debounce",
+            value_name = "DURATION",
+            default_value = "500ms",
+            value_parser = parse_duration
+        )]
+        debounce: std::time::Duration,
+        /// The actions to run. Without any, the checks of the builtin rule
+        /// packs applying to the changed files run.
         #[arg(value_name = "ACTIONS")]
         actions: Vec<ActionSelector>,
     },
+    /// Run the builtin actions on sample files and compare the results to
+    /// the golden files
+    #[command(hide = true)]
+    SelfTest {
+        /// Only test this rule pack
+        #[arg(long = "pack")]
+        pack: Option<String>,
+        /// Print the results in the format of the golden files instead of
+        /// comparing them
+        #[arg(long = "print")]
+        print: bool,
+    },
 }
 
 #[derive(Clone, Debug, Parser)]
@@ -61,34 +314,197 @@ struct Cli {
     debug_level: u8,
     #[arg(long = "verbose", action = clap::ArgAction::Count)]
     verbosity_level: u8,
+    /// Keep caches (like the cache directories of actions) in this directory
+    #[arg(long = "cache-dir", value_name = "DIR", env = beautytips::CACHE_DIR_VARIABLE)]
+    cache_directory: Option<PathBuf>,
+    /// Keep state (like the results of the last run) and logs in this
+    /// directory
+    #[arg(long = "state-dir", value_name = "DIR", env = beautytips::STATE_DIR_VARIABLE)]
+    state_directory: Option<PathBuf>,
+    /// Print a man page
+    #[arg(long = "man", exclusive = true)]
+    man: bool,
 
     #[command(subcommand)]
-    action: CliCommand,
+    action: Option<CliCommand>,
+}
+
+/// The command line interface, with `builtin --describe` added by hand
+///
+/// That option must work without the required options of the builtin
+/// commands, so it can not be part of the derived `Cli`.
+fn cli_command() -> clap::Command {
+    Cli::command().mut_subcommand("builtin", |builtin| {
+        builtin.arg(
+            clap::Arg::new("describe")
+                .long("describe")
+                .help("Print the options the command accepts as JSON instead of running it")
+                .action(clap::ArgAction::SetTrue)
+                .global(true)
+                .exclusive(true),
+        )
+    })
+}
+
+/// Render the man page of the command line interface
+pub fn man_page() -> anyhow::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    clap_mangen::Man::new(cli_command())
+        .render(&mut buffer)
+        .context("Failed to render man page")?;
+    Ok(buffer)
 }
 
 #[derive(Clone, Debug)]
 pub enum Command {
     Builtin {
-        action: String,
-        arguments: Vec<OsString>,
+        command: BuiltinCommand,
+    },
+    DescribeBuiltin {
+        name: String,
     },
     ListFiles {
         source: beautytips::InputFiles,
+        count: bool,
+        relative: bool,
+        null: bool,
     },
     ListActions {},
-    RunActions {
+    CheckConfig {
+        executables: bool,
+    },
+    ConfigSchema {},
+    Clean {},
+    DumpConfig {
+        format: DumpFormat,
+    },
+    HelpAction {
+        actions: ActionSelectors,
+    },
+    Why {
         source: beautytips::InputFiles,
+        path: PathBuf,
         actions: ActionSelectors,
     },
+    Man {},
+    SelfUpdate {
+        check_only: bool,
+    },
+    AddAction {
+        name: ActionId,
+        description: Option<String>,
+        command: String,
+        filters: Vec<String>,
+        config_file: Option<PathBuf>,
+    },
+    RunActions(Box<RunActions>),
+    RerunFailed {},
+    Watch {
+        debounce: std::time::Duration,
+        run: Box<RunActions>,
+    },
+    SelfTest {
+        pack: Option<String>,
+        print: bool,
+    },
+}
+
+/// The `run` command
+#[derive(Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct RunActions {
+    pub source: beautytips::InputFiles,
+    pub actions: ActionSelectors,
+    pub only: ActionSelectors,
+    pub skip: ActionSelectors,
+    pub repositories: Vec<PathBuf>,
+    pub workspace: Option<PathBuf>,
+    pub failed_first: bool,
+    pub output: Option<OutputFormat>,
+    pub stable_order: bool,
+    pub collapse: bool,
+    pub raw_output: bool,
+    pub report_file: Option<PathBuf>,
+    pub dotenv: bool,
+    pub environment: Vec<(String, String)>,
+    pub isolate: bool,
+    pub keep_going: bool,
+    pub max_files: Option<usize>,
+    pub timeout: Option<std::time::Duration>,
+    pub jobs: Option<usize>,
+    pub report_capacity: Option<usize>,
+    pub report_overflow: beautytips::ReportOverflow,
+    pub spill_threshold: Option<usize>,
+    pub lock: beautytips::RunLock,
 }
 
 #[derive(Clone, Debug)]
 pub struct CommandlineConfiguration {
     pub debug_level: u8,
     pub verbosity_level: u8,
+    pub cache_directory: Option<PathBuf>,
+    pub state_directory: Option<PathBuf>,
     pub command: Command,
 }
 
+fn parse_environment(input: &str) -> anyhow::Result<(String, String)> {
+    match input.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(anyhow::anyhow!(format!(
+            "'{input}' is not of the form KEY=VALUE"
+        ))),
+    }
+}
+
+/// Parse a duration in seconds, with an optional `ms`, `s`, `m` or `h` suffix
+fn parse_duration(input: &str) -> anyhow::Result<std::time::Duration> {
+    if let Some(number) = input.strip_suffix("ms") {
+        let milliseconds = number.parse::<u64>().context(format!(
+            "'{input}' is not a duration like `500ms`, `90s`, `10m` or `1h`"
+        ))?;
+        return Ok(std::time::Duration::from_millis(milliseconds));
+    }
+    let (number, factor) = match input.char_indices().last() {
+        Some((i, 's')) => (&input[..i], 1),
+        Some((i, 'm')) => (&input[..i], 60),
+        Some((i, 'h')) => (&input[..i], 60 * 60),
+        _ => (input, 1),
+    };
+    let seconds = number.parse::<u64>().context(format!(
+        "'{input}' is not a duration like `500ms`, `90s`, `10m` or `1h`"
+    ))?;
+    seconds
+        .checked_mul(factor)
+        .map(std::time::Duration::from_secs)
+        .ok_or(anyhow::anyhow!(format!("'{input}' is too long")))
+}
+
+/// Parse a duration like `parse_duration` does, which must not be zero
+pub(crate) fn parse_timeout(input: &str) -> anyhow::Result<std::time::Duration> {
+    let timeout = parse_duration(input)?;
+    if timeout.is_zero() {
+        return Err(anyhow::anyhow!(format!(
+            "'{input}' would time out right away"
+        )));
+    }
+    Ok(timeout)
+}
+
+/// Parse a size in bytes, with an optional `k`, `M` or `G` suffix
+fn parse_size(input: &str) -> anyhow::Result<usize> {
+    let (number, factor) = match input.char_indices().last() {
+        Some((i, 'k')) => (&input[..i], 1024),
+        Some((i, 'M')) => (&input[..i], 1024 * 1024),
+        Some((i, 'G')) => (&input[..i], 1024 * 1024 * 1024),
+        _ => (input, 1),
+    };
+    let size = number
+        .parse::<usize>()
+        .context(format!("'{input}' is not a size like `512k`, `4M` or `1G`"))?;
+    size.checked_mul(factor)
+        .ok_or(anyhow::anyhow!(format!("'{input}' is too large")))
+}
+
 fn generate_input_files(
     inputs: &CliInputFiles,
     vcs_input_extra: &CliVcsExtra,
@@ -98,6 +514,9 @@ fn generate_input_files(
             tool: vcs.clone(),
             from_revision: vcs_input_extra.from_revision.clone(),
             to_revision: vcs_input_extra.to_revision.clone(),
+            changed_since: vcs_input_extra.changed_since.clone(),
+            at_revision: vcs_input_extra.at_revision.clone(),
+            excluded_paths: vcs_input_extra.excluded_paths.clone(),
         }))
     } else if let Some(files) = &inputs.files {
         Ok(beautytips::InputFiles::FileList(files.clone()))
@@ -111,30 +530,245 @@ fn generate_input_files(
 }
 
 pub fn command() -> anyhow::Result<CommandlineConfiguration> {
-    let cli = Cli::parse();
+    let matches = cli_command().get_matches();
+    if let Some(name) = matches
+        .subcommand_matches("builtin")
+        .filter(|m| m.get_flag("describe"))
+        .and_then(clap::ArgMatches::subcommand_name)
+    {
+        // The derived parser insists on the required options of the builtin
+        // command, and describing it needs no directories
+        return Ok(CommandlineConfiguration {
+            debug_level: matches.get_count("debug_level"),
+            verbosity_level: matches.get_count("verbosity_level"),
+            cache_directory: None,
+            state_directory: None,
+            command: Command::DescribeBuiltin {
+                name: name.to_string(),
+            },
+        });
+    }
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
     let command = match cli.action {
-        CliCommand::Builtin { action, arguments } => Command::Builtin { action, arguments },
+        _ if cli.man => Command::Man {},
+        Some(action) => command_from_cli(action)?,
+        None => Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                "A subcommand is required",
+            )
+            .exit(),
+    };
+
+    Ok(CommandlineConfiguration {
+        debug_level: cli.debug_level,
+        verbosity_level: cli.verbosity_level,
+        cache_directory: cli.cache_directory,
+        state_directory: cli.state_directory,
+        command,
+    })
+}
+
+/// The selectors in the comma separated `SKIP` environment variable
+///
+/// `SKIP` is often set for other tools as well, so invalid entries are
+/// reported and ignored instead of stopping the run.
+fn env_skip() -> ActionSelectors {
+    parse_skip(&std::env::var("SKIP").unwrap_or_default())
+}
+
+fn parse_skip(value: &str) -> ActionSelectors {
+    value
+        .split(',')
+        .filter_map(|entry| match entry.parse::<ActionSelectors>() {
+            Ok(selectors) => Some(selectors),
+            Err(e) => {
+                eprintln!(
+                    "Ignoring `{}` in the SKIP environment variable: {e}",
+                    entry.trim()
+                );
+                None
+            }
+        })
+        .flat_map(|selectors| selectors.into_iter().cloned().collect::<Vec<_>>())
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Run `actions` on the files of `source` like `run` does with its default
+/// options
+fn default_run(
+    source: beautytips::InputFiles,
+    actions: ActionSelectors,
+) -> anyhow::Result<RunActions> {
+    let matches = cli_command()
+        .try_get_matches_from(["beautytips", "run", "--from-vcs"])
+        .context("Failed to parse the default options of run")?;
+    let cli = Cli::from_arg_matches(&matches)?;
+    let Some(Command::RunActions(run)) = cli.action.map(command_from_cli).transpose()? else {
+        unreachable!("`run` is parsed into `RunActions`");
+    };
+    Ok(RunActions {
+        source,
+        actions,
+        ..*run
+    })
+}
+
+/// Run the actions of `hook` like `run` does with its default options
+fn hook_run(hook: &HookCommand) -> anyhow::Result<RunActions> {
+    let (source, actions) = crate::hook::hook_inputs(hook)?;
+    default_run(source, actions)
+}
+
+#[allow(clippy::too_many_lines)]
+fn command_from_cli(action: CliCommand) -> anyhow::Result<Command> {
+    let command = match action {
+        CliCommand::Builtin { command } => Command::Builtin { command },
         CliCommand::ListActions => Command::ListActions {},
+        CliCommand::CheckConfig { executables } => Command::CheckConfig { executables },
+        CliCommand::ConfigSchema => Command::ConfigSchema {},
+        CliCommand::Clean => Command::Clean {},
+        CliCommand::DumpConfig { format } => Command::DumpConfig { format },
+        CliCommand::HelpAction { actions } => Command::HelpAction {
+            actions: actions.into(),
+        },
+        CliCommand::Why {
+            source,
+            vcs_input_extra,
+            path,
+            actions,
+        } => Command::Why {
+            source: generate_input_files(&source, &vcs_input_extra)?,
+            path,
+            actions: actions.into(),
+        },
+        CliCommand::SelfUpdate { check } => Command::SelfUpdate { check_only: check },
+        CliCommand::AddAction {
+            name,
+            description,
+            command,
+            filters,
+            config_file,
+        } => Command::AddAction {
+            name,
+            description,
+            command,
+            filters,
+            config_file,
+        },
         CliCommand::ListFiles {
             source,
             vcs_input_extra,
+            count,
+            relative,
+            null,
         } => Command::ListFiles {
             source: generate_input_files(&source, &vcs_input_extra)?,
+            count,
+            relative,
+            null,
         },
         CliCommand::Run {
             source,
             actions,
+            tags,
+            only,
+            skip,
+            repositories,
+            workspace,
+            failed_first,
+            output,
+            stable_order,
+            collapse,
+            raw_output,
+            report_file,
+            dotenv,
+            environment,
+            isolate,
+            keep_going,
+            max_files,
+            timeout,
+            jobs,
+            report_capacity,
+            report_overflow,
+            spill_threshold,
+            wait_for_lock,
+            no_lock,
             vcs_input_extra,
-        } => Command::RunActions {
+        } => Command::RunActions(Box::new(RunActions {
             source: generate_input_files(&source, &vcs_input_extra)?,
-            actions: actions.into(),
+            actions: actions.into_iter().chain(tags).collect::<Vec<_>>().into(),
+            only: only.into(),
+            skip: {
+                let mut skip: ActionSelectors = skip.into();
+                skip.extend(env_skip().into_iter().cloned());
+                skip
+            },
+            repositories,
+            workspace,
+            failed_first,
+            output,
+            stable_order,
+            collapse,
+            raw_output,
+            report_file,
+            dotenv,
+            environment,
+            isolate,
+            keep_going,
+            max_files,
+            timeout,
+            jobs,
+            report_capacity,
+            report_overflow: report_overflow.unwrap_or_default(),
+            spill_threshold,
+            lock: if no_lock {
+                beautytips::RunLock::Off
+            } else if wait_for_lock {
+                beautytips::RunLock::Wait
+            } else {
+                beautytips::RunLock::Fail
+            },
+        })),
+        CliCommand::Hook { hook } => Command::RunActions(Box::new(hook_run(&hook)?)),
+        CliCommand::RerunFailed => Command::RerunFailed {},
+        CliCommand::Watch { debounce, actions } => Command::Watch {
+            debounce,
+            run: Box::new(RunActions {
+                // Runs wait for each other, as a run of their own is started
+                // whenever files change
+                lock: beautytips::RunLock::Wait,
+                ..default_run(beautytips::InputFiles::FileList(vec![]), actions.into())?
+            }),
         },
+        CliCommand::SelfTest { pack, print } => Command::SelfTest { pack, print },
     };
 
-    Ok(CommandlineConfiguration {
-        debug_level: cli.debug_level,
-        verbosity_level: cli.verbosity_level,
-        command,
-    })
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+        assert_eq!(parse_duration("0s").unwrap(), Duration::ZERO);
+        assert!(parse_duration("1d").is_err());
+        assert!(parse_duration("-1s").is_err());
+        assert!(parse_duration(&format!("{}h", u64::MAX / 60)).is_err());
+
+        assert_eq!(parse_timeout("10m").unwrap(), Duration::from_secs(600));
+        assert!(parse_timeout("0s").is_err());
+        assert!(parse_timeout("0ms").is_err());
+    }
 }
@@ -4,63 +4,191 @@
 // spell-checker:ignore boms
 
 use std::{
-    ffi::OsString,
-    io::{Read, Write},
+    io::Read,
     path::{Path, PathBuf},
 };
 
 use anyhow::Context;
 
-type Args = Vec<(String, String)>;
-type Inputs = Vec<PathBuf>;
-
-fn parse_arguments(arguments: &[OsString]) -> anyhow::Result<(Args, Inputs)> {
-    let mut parse_args = true;
-    let mut key: Option<String> = None;
-    let mut args = vec![];
-    let mut inputs = vec![];
+pub mod describe;
+mod executables;
+mod fail_on_output;
+mod filenames;
+mod indentation;
+mod lfs;
+mod license_header;
+mod syntax;
+
+/// A command built into beautytips
+#[derive(Clone, Debug, clap::Subcommand)]
+#[command(rename_all = "kebab-case")]
+pub enum BuiltinCommand {
+    /// Report files larger than a size limit
+    LargeFiles {
+        /// The largest acceptable file size in bytes, with an optional `k`,
+        /// `m`, `g` or `t` suffix
+        #[arg(long = "size", value_parser = parse_size, default_value = "50k")]
+        size: u64,
+        /// The files to look at
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Report files starting with an UTF-8 byte order mark
+    Bom {
+        /// Remove the byte order marks
+        #[arg(long = "fix")]
+        fix: bool,
+        /// Print a diff of the changes `--fix` would do instead of changing
+        /// the files
+        #[arg(long = "diff", requires = "fix")]
+        diff: bool,
+        /// The files to look at
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Report files using more than one kind of line endings
+    MixedLineEndings {
+        /// Change all line endings to this one, `auto` picks the one used
+        /// most in each file
+        #[arg(long = "fix", value_enum, value_name = "LINE_ENDING")]
+        fix: Option<LineEnding>,
+        /// Print a diff of the changes `--fix` would do instead of changing
+        /// the files
+        #[arg(long = "diff", requires = "fix")]
+        diff: bool,
+        /// The files to look at
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Report JSON files with syntax errors
+    CheckJson {
+        /// The files to look at
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Report TOML files with syntax errors
+    CheckToml {
+        /// The files to look at
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Report YAML files with syntax errors
+    CheckYaml {
+        /// The files to look at
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Report scripts that are not executable and executable text files
+    /// without shebang
+    CheckExecutables {
+        /// Make scripts executable and other text files not executable
+        #[arg(long = "fix")]
+        fix: bool,
+        /// The files to look at
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Report files breaking naming conventions or with forbidden characters
+    /// in their path
+    CheckFilenames {
+        /// A naming convention for the names of files matching a glob
+        /// pattern, like `**/*.py=snake_case`. Conventions are `snake_case`,
+        /// `kebab-case`, `camelCase`, `PascalCase` and `lowercase`. Can be
+        /// repeated.
+        #[arg(long = "rule", value_name = "GLOB=CONVENTION")]
+        rules: Vec<filenames::NamingRule>,
+        /// The characters not allowed anywhere in a path
+        #[arg(long = "forbidden", value_name = "CHARACTERS", default_value = filenames::DEFAULT_FORBIDDEN)]
+        forbidden: String,
+        /// The files to look at
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Report files not starting with a license header
+    LicenseHeader {
+        /// The file holding the license header, without comment markers.
+        /// `{year}` and `{author}` in it match any year and author.
+        #[arg(long = "template", value_name = "FILE")]
+        template: PathBuf,
+        /// The copyright holder to put into new headers
+        #[arg(long = "author")]
+        author: Option<String>,
+        /// The year to put into new headers, defaults to the current year
+        #[arg(long = "year")]
+        year: Option<i64>,
+        /// Insert missing headers and replace outdated ones
+        #[arg(long = "fix")]
+        fix: bool,
+        /// The files to look at
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Report files indented with the wrong mix of tabs and spaces
+    Indentation {
+        /// The indentation policy for files with an extension or name, like
+        /// `go=tabs` or `Makefile=tabs`. Can be repeated.
+        #[arg(long = "policy", value_name = "EXTENSION=POLICY")]
+        policies: Vec<indentation::FilePolicy>,
+        /// The indentation policy for all other files
+        #[arg(long = "default-policy", value_enum, default_value = "consistent")]
+        default_policy: indentation::Policy,
+        /// The number of columns a tab moves to
+        #[arg(long = "tab-width", default_value = "4")]
+        tab_width: usize,
+        /// Re-indent the offending lines
+        #[arg(long = "fix")]
+        fix: bool,
+        /// The files to look at
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Report files assigned to git-lfs in `.gitattributes` but stored in
+    /// git directly, and git-lfs pointers of files not assigned to git-lfs
+    LfsPointers {
+        /// The files to look at
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Run a command and fail if it prints anything, for tools like
+    /// `gofmt -l` that list problems but always succeed
+    FailOnOutput {
+        /// The command to run, with its arguments
+        #[arg(value_name = "COMMAND", required = true, trailing_var_arg = true)]
+        command: Vec<String>,
+    },
+    /// Print the environment the command runs in
+    PrintEnvironment {
+        /// The files to look at
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+}
 
-    let separator = OsString::from(&"--");
+/// The line ending to fix files to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LineEnding {
+    Cr,
+    Crlf,
+    Lf,
+    Auto,
+}
 
-    for a in arguments {
-        if a == &separator {
-            if let Some(key) = key {
-                return Err(anyhow::anyhow!(format!("Incomplete argument \"{key}\"")));
-            }
-            parse_args = false;
-            continue;
-        }
-        if parse_args {
-            let a = a
-                .clone()
-                .into_string()
-                .map_err(|_| anyhow::anyhow!("Failed to convert an argument"))?;
-
-            if let Some(k) = &key {
-                args.push((k.clone(), a));
-            } else {
-                if !a.starts_with("--") {
-                    return Err(anyhow::anyhow!(format!(
-                        "Argument {a} does not start with \"--\""
-                    )));
-                }
-                if let Some(equal_sign) = a.find('=') {
-                    let k = &a[2..equal_sign];
-                    let v = &a[(equal_sign + 1)..];
-                    args.push((k.to_string(), v.to_string()));
-                } else {
-                    key = Some(a[2..].to_string());
-                }
-            }
-        } else {
-            inputs.push(PathBuf::from(a));
+impl LineEnding {
+    /// The index into `LINE_ENDING_NAMES`
+    fn index(self) -> usize {
+        match self {
+            Self::Cr => 0,
+            Self::Crlf => 1,
+            Self::Lf => 2,
+            Self::Auto => 3,
         }
     }
-    Ok((args, inputs))
 }
 
 fn parse_size(input: &str) -> anyhow::Result<u64> {
-    let last_char = input.as_bytes()[input.len() - 1];
+    let Some(&last_char) = input.as_bytes().last() else {
+        return Err(anyhow::anyhow!("No size given"));
+    };
     let factor = match last_char {
         b'k' | b'K' => 1024,
         b'm' | b'M' => 1024 * 1024,
@@ -76,46 +204,34 @@ fn parse_size(input: &str) -> anyhow::Result<u64> {
     };
     let base = to_parse.parse::<u64>().context("Failed to parse size")?;
 
-    Ok(base * factor)
-}
-
-fn is_true(input: &str) -> bool {
-    let input = input.to_lowercase();
-    (&input == "true") || (&input == "1") || (&input == "on")
+    base.checked_mul(factor)
+        .ok_or(anyhow::anyhow!("The size {input} is too large"))
 }
 
-fn check_large_files(
-    args: &[(String, String)],
-    inputs: &[PathBuf],
-    verbosity: u8,
-) -> anyhow::Result<i32> {
-    let mut size = 0;
-    for (k, v) in args {
-        match k.as_str() {
-            "size" => {
-                size = parse_size(v)?;
-            }
-            _ => {
-                return Err(anyhow::anyhow!(format!("Unexpected argument {k}={v}")));
-            }
-        }
-    }
-
+fn check_large_files(size: u64, inputs: &[PathBuf], verbosity: u8) -> anyhow::Result<i32> {
     let mut large_files = 0;
     for p in inputs {
         let meta = p.metadata()?;
         let actual_size = meta.len();
 
         if actual_size > size {
-            eprintln!("{p:?}: {} bytes too big", actual_size - size);
+            eprintln!("{}: {} bytes too big", p.display(), actual_size - size);
             large_files += 1;
         } else if verbosity > 0 {
-            eprintln!("{p:?}: {actual_size} bytes, OK");
+            eprintln!("{}: {actual_size} bytes, OK", p.display());
         }
     }
     Ok(large_files)
 }
 
+/// `true` if `contents` start with a shebang line
+///
+/// Only `#!/` and `#! /` count, so Rust inner attributes (`#![...]`) are not
+/// taken for one.
+fn has_shebang(contents: &[u8]) -> bool {
+    contents.starts_with(b"#!/") || contents.starts_with(b"#! /")
+}
+
 fn open_for_check(path: &Path) -> anyhow::Result<std::io::BufReader<std::fs::File>> {
     let file = std::fs::OpenOptions::new()
         .read(true)
@@ -128,19 +244,36 @@ fn open_for_check(path: &Path) -> anyhow::Result<std::io::BufReader<std::fs::Fil
     Ok(std::io::BufReader::new(file))
 }
 
-fn handle_bom(args: &[(String, String)], inputs: &[PathBuf], verbosity: u8) -> anyhow::Result<i32> {
-    let fix = {
-        let mut fix = false;
-        for (k, v) in args {
-            match k.as_str() {
-                "fix" => fix = is_true(v),
-                _ => {
-                    return Err(anyhow::anyhow!(format!("Unexpected argument {k}={v}")));
-                }
-            }
-        }
-        fix
-    };
+/// A unified diff from `old` to `new` contents of the file `name`
+fn unified_diff(name: &str, old: &str, new: &str) -> String {
+    similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&format!("a/{name}"), &format!("b/{name}"))
+        .to_string()
+}
+
+/// A unified diff from the file at `path` to `contents`
+fn contents_diff(path: &Path, contents: &[u8]) -> anyhow::Result<String> {
+    let old = std::fs::read(path).context(format!("Failed to read {}", path.display()))?;
+    Ok(unified_diff(
+        &path.display().to_string().replace('\\', "/"),
+        &String::from_utf8_lossy(&old),
+        &String::from_utf8_lossy(contents),
+    ))
+}
+
+/// Write the `contents` fixing the file at `path`, or print a diff of them
+/// with `diff` set
+fn apply_fix(path: &Path, contents: &[u8], diff: bool) -> anyhow::Result<()> {
+    if diff {
+        print!("{}", contents_diff(path, contents)?);
+        Ok(())
+    } else {
+        beautytips::safe_rewrite(path, contents)
+    }
+}
+
+fn handle_bom(fix: bool, diff: bool, inputs: &[PathBuf], verbosity: u8) -> anyhow::Result<i32> {
     if verbosity > 1 {
         eprintln!("Fixing mode {}", if fix { "enabled" } else { "disabled" });
     }
@@ -158,30 +291,25 @@ fn handle_bom(args: &[(String, String)], inputs: &[PathBuf], verbosity: u8) -> a
                         if buf.read_to_end(&mut contents).is_ok() {
                             drop(buf);
 
-                            let file = std::fs::OpenOptions::new()
-                                .read(true)
-                                .write(true)
-                                .create(false)
-                                .append(false)
-                                .truncate(true)
-                                .open(p)
-                                .context("Failed to write file {p:?}")?;
-                            let mut buf = std::io::BufWriter::new(file);
-                            buf.write_all(&contents).context("Failed to write data")?;
-                            eprintln!("{p:?}: byte order mark removed");
-                            continue;
+                            apply_fix(p, &contents, diff)?;
+                            if diff {
+                                eprintln!("{}: byte order mark found", p.display());
+                            } else {
+                                eprintln!("{}: byte order mark removed", p.display());
+                                continue;
+                            }
                         }
                     } else {
-                        eprintln!("{p:?}: byte order mark found");
+                        eprintln!("{}: byte order mark found", p.display());
                     }
                     unfixed_boms += 1;
                 } else if verbosity > 0 {
-                    eprintln!("{p:?}: no byte order mark, OK");
+                    eprintln!("{}: no byte order mark, OK", p.display());
                 }
             }
             Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
                 if verbosity > 0 {
-                    eprintln!("{p:?}: too short for a byte order mark");
+                    eprintln!("{}: too short for a byte order mark", p.display());
                 }
             }
             Err(e) => return Err(e).context("Failed to read byte oder mark"),
@@ -190,58 +318,6 @@ fn handle_bom(args: &[(String, String)], inputs: &[PathBuf], verbosity: u8) -> a
     Ok(unfixed_boms)
 }
 
-#[derive(Clone, Debug, Default)]
-struct IsBinary {
-    total_bytes: usize,
-    odd_bytes: usize,
-    early_decision: bool,
-    expected_utf8_bytes: usize,
-}
-
-impl IsBinary {
-    pub fn is_binary(&mut self, b: u8) -> bool {
-        self.total_bytes += 1;
-
-        if self.early_decision {
-            return self.early_decision;
-        }
-
-        if b == b'\0' {
-            self.early_decision = true;
-            return true;
-        }
-
-        if self.expected_utf8_bytes > 0 {
-            self.expected_utf8_bytes -= 1;
-            if b & 0b1100_0000 == 0b1000_0000 {
-                self.odd_bytes += 1;
-            }
-        } else {
-            match b {
-                b if b & 0b1111_0000 == 0b1110_0000 => {
-                    self.expected_utf8_bytes = 3;
-                }
-                b if b & 0b1110_0000 == 0b1100_0000 => {
-                    self.expected_utf8_bytes = 2;
-                }
-                b if b & 0b1110_0000 == 0b1100_0000 => {
-                    self.expected_utf8_bytes = 1;
-                }
-                b if b >= 32 || [b'\n', b'\r', b'\t', 7, 12].contains(&b) => { /* do nothing */ }
-                _ => {
-                    self.odd_bytes += 1;
-                }
-            }
-        }
-
-        false
-    }
-
-    pub fn final_verdict(self) -> bool {
-        self.early_decision || ((self.total_bytes / 10) * 3 > self.odd_bytes) // 30% odd bytes might happen in text;-)
-    }
-}
-
 const LINE_ENDING_NAMES: [&str; 4] = ["cr", "crlf", "lf", "auto"];
 const LINE_ENDING_STRINGS: [&str; 4] = ["\r", "\r\n", "\n", "auto"];
 const LF: u8 = b'\n';
@@ -268,7 +344,6 @@ impl IsMixedLineEnding {
 
     pub fn final_verdict(mut self) -> (bool, usize) {
         self.count_line_endings(b'\0');
-        eprintln!("Final counts: {:?}", self.end_counts);
         let is_mixed = self.end_counts.into_iter().filter(|c| *c > 0).count() > 1;
         let majority_index = self
             .end_counts
@@ -276,16 +351,12 @@ impl IsMixedLineEnding {
             .enumerate()
             .max_by(|(_, a), (_, b)| a.cmp(b))
             .map_or(0, |(i, _)| i);
-        eprintln!(
-            "Final counts: {:?} => {}",
-            self.end_counts, LINE_ENDING_NAMES[majority_index]
-        );
         (is_mixed, majority_index)
     }
 }
 
 fn detect_mixed_line_endings(contents: &[u8]) -> (bool, bool, usize) {
-    let mut binary_checker = IsBinary::default();
+    let mut binary_checker = beautytips::IsBinary::default();
     let mut mixed_line_end_checker = IsMixedLineEnding::default();
 
     for b in contents {
@@ -331,31 +402,13 @@ fn fix_mixed_line_endings(contents: &[u8], fix_index: usize) -> Vec<u8> {
 }
 
 fn handle_mixed_line_endings(
-    args: &[(String, String)],
+    fix: Option<LineEnding>,
+    diff: bool,
     inputs: &[PathBuf],
     verbosity: u8,
 ) -> anyhow::Result<i32> {
-    let (fix, expected_index) = {
-        let mut fix = false;
-        let mut expected_index = 0;
-
-        for (k, v) in args {
-            match k.as_str() {
-                "fix" => {
-                    if let Some(pos) = LINE_ENDING_NAMES.iter().position(|r| r == v) {
-                        fix = true;
-                        expected_index = pos;
-                    } else {
-                        return Err(anyhow::anyhow!(format!("Unknown fix mode {v}")));
-                    }
-                }
-                _ => {
-                    return Err(anyhow::anyhow!(format!("Unexpected argument {k}={v}")));
-                }
-            }
-        }
-        (fix, expected_index)
-    };
+    let expected_index = fix.map_or(0, LineEnding::index);
+    let fix = fix.is_some();
 
     let mut mixed_line_endings = 0;
     for p in inputs {
@@ -369,14 +422,18 @@ fn handle_mixed_line_endings(
 
         if is_binary {
             if verbosity > 0 {
-                eprintln!("{p:?}: binary file, SKIPPING");
+                eprintln!("{}: binary file, SKIPPING", p.display());
             }
             continue;
         }
 
         if !is_mixed {
             if verbosity > 0 {
-                eprintln!("{p:?}: {} only, OK", LINE_ENDING_NAMES[majority_index]);
+                eprintln!(
+                    "{}: {} only, OK",
+                    p.display(),
+                    LINE_ENDING_NAMES[majority_index]
+                );
             }
             continue;
         }
@@ -390,24 +447,17 @@ fn handle_mixed_line_endings(
 
             let new_contents = fix_mixed_line_endings(&contents, fix_index);
 
-            let file = std::fs::OpenOptions::new()
-                .read(false)
-                .write(true)
-                .create(false)
-                .append(false)
-                .truncate(true)
-                .open(p)
-                .context("Failed to write file {p:?}")?;
-            let mut buf = std::io::BufWriter::new(file);
-            buf.write_all(&new_contents)
-                .context("Failed to write data")?;
-            eprintln!("{p:?}: FIXED to {}", LINE_ENDING_NAMES[fix_index]);
-            continue;
+            apply_fix(p, &new_contents, diff)?;
+            if !diff {
+                eprintln!("{}: FIXED to {}", p.display(), LINE_ENDING_NAMES[fix_index]);
+                continue;
+            }
         }
 
         mixed_line_endings += 1;
         eprintln!(
-            "{p:?}: mixed with {} being the majority FAIL",
+            "{}: mixed with {} being the majority FAIL",
+            p.display(),
             LINE_ENDING_NAMES[majority_index]
         );
     }
@@ -415,15 +465,11 @@ fn handle_mixed_line_endings(
     Ok(mixed_line_endings)
 }
 
-fn print_environment(args: &[(String, String)], inputs: &[PathBuf], verbosity: u8) {
+fn print_environment(inputs: &[PathBuf], verbosity: u8) {
     println!("Verbosity: {verbosity}");
-    println!("Arguments:");
-    for (k, v) in args {
-        println!("    {k}={v}");
-    }
     println!("Inputs");
     for p in inputs {
-        println!("    {p:?}");
+        println!("    {}", p.display());
     }
     println!("Environment:");
     for (k, v) in std::env::vars() {
@@ -431,24 +477,70 @@ fn print_environment(args: &[(String, String)], inputs: &[PathBuf], verbosity: u
     }
 }
 
-pub fn run_builtin_command(
-    action: &str,
-    arguments: &[OsString],
-    verbosity: u8,
-) -> anyhow::Result<i32> {
-    let (args, inputs) = parse_arguments(arguments)?;
-
-    match action {
-        "large-files" => check_large_files(&args, &inputs, verbosity),
-        "bom" => handle_bom(&args, &inputs, verbosity),
-        "mixed-line-endings" => handle_mixed_line_endings(&args, &inputs, verbosity),
-        "print-environment" => {
-            print_environment(&args, &inputs, verbosity);
+pub fn run_builtin_command(command: &BuiltinCommand, verbosity: u8) -> anyhow::Result<i32> {
+    match command {
+        BuiltinCommand::LargeFiles { size, files } => check_large_files(*size, files, verbosity),
+        BuiltinCommand::Bom { fix, diff, files } => handle_bom(*fix, *diff, files, verbosity),
+        BuiltinCommand::MixedLineEndings { fix, diff, files } => {
+            handle_mixed_line_endings(*fix, *diff, files, verbosity)
+        }
+        BuiltinCommand::CheckJson { files } => {
+            syntax::check_syntax(syntax::Format::Json, files, verbosity)
+        }
+        BuiltinCommand::CheckToml { files } => {
+            syntax::check_syntax(syntax::Format::Toml, files, verbosity)
+        }
+        BuiltinCommand::CheckYaml { files } => {
+            syntax::check_syntax(syntax::Format::Yaml, files, verbosity)
+        }
+        BuiltinCommand::CheckExecutables { fix, files } => {
+            executables::check_executables(*fix, files, verbosity)
+        }
+        BuiltinCommand::CheckFilenames {
+            rules,
+            forbidden,
+            files,
+        } => Ok(filenames::check_filenames(
+            rules, forbidden, files, verbosity,
+        )),
+        BuiltinCommand::LicenseHeader {
+            template,
+            author,
+            year,
+            fix,
+            files,
+        } => {
+            let text = std::fs::read_to_string(template)
+                .context(format!("Failed to read {}", template.display()))?;
+            let template = license_header::HeaderTemplate::new(
+                &text,
+                author.clone(),
+                year.unwrap_or_else(license_header::current_year),
+            )?;
+            license_header::check_license_header(&template, *fix, files, verbosity)
+        }
+        BuiltinCommand::Indentation {
+            policies,
+            default_policy,
+            tab_width,
+            fix,
+            files,
+        } => {
+            let settings = indentation::Settings {
+                policies: policies.clone(),
+                default_policy: *default_policy,
+                tab_width: *tab_width,
+            };
+            indentation::check_indentation(&settings, *fix, files, verbosity)
+        }
+        BuiltinCommand::LfsPointers { files } => lfs::check_lfs_pointers(files, verbosity),
+        BuiltinCommand::FailOnOutput { command } => {
+            fail_on_output::fail_on_output(command, verbosity)
+        }
+        BuiltinCommand::PrintEnvironment { files } => {
+            print_environment(files, verbosity);
             Ok(0)
         }
-        _ => Err(anyhow::anyhow!(format!(
-            "{action} is not a builtin command"
-        ))),
     }
 }
 
@@ -456,6 +548,76 @@ pub fn run_builtin_command(
 mod tests {
     use super::*;
 
+    use clap::Parser;
+
+    #[derive(Debug, Parser)]
+    struct TestCli {
+        #[command(subcommand)]
+        command: BuiltinCommand,
+    }
+
+    fn parse(args: &[&str]) -> Result<BuiltinCommand, clap::Error> {
+        TestCli::try_parse_from(std::iter::once("builtin").chain(args.iter().copied()))
+            .map(|cli| cli.command)
+    }
+
+    #[test]
+    fn test_parse_builtin_commands() {
+        let Ok(BuiltinCommand::LargeFiles { size, files }) =
+            parse(&["large-files", "--size=2k", "--", "a", "--b"])
+        else {
+            panic!("large-files not parsed");
+        };
+        assert_eq!(size, 2048);
+        assert_eq!(files, [PathBuf::from("a"), PathBuf::from("--b")]);
+
+        let Ok(BuiltinCommand::Bom { fix, diff, files }) = parse(&["bom", "--fix", "--", "a"])
+        else {
+            panic!("bom not parsed");
+        };
+        assert!(fix);
+        assert!(!diff);
+        assert_eq!(files, [PathBuf::from("a")]);
+
+        let Ok(BuiltinCommand::MixedLineEndings { fix, diff, .. }) =
+            parse(&["mixed-line-endings", "--fix=crlf", "--diff", "--"])
+        else {
+            panic!("mixed-line-endings not parsed");
+        };
+        assert_eq!(fix, Some(LineEnding::Crlf));
+        assert!(diff);
+
+        let Ok(BuiltinCommand::FailOnOutput { command }) =
+            parse(&["fail-on-output", "--", "gofmt", "-l", "a.go"])
+        else {
+            panic!("fail-on-output not parsed");
+        };
+        assert_eq!(command, ["gofmt", "-l", "a.go"]);
+
+        assert!(parse(&["large-files", "--size=big", "--", "a"]).is_err());
+        assert!(parse(&["large-files", "--size=", "--", "a"]).is_err());
+        assert!(parse(&["large-files", "--size=k", "--", "a"]).is_err());
+        assert!(parse(&["large-files", "--size=99999999t", "--", "a"]).is_err());
+        assert!(parse(&["fail-on-output", "--"]).is_err());
+        assert!(parse(&["mixed-line-endings", "--fix=unix", "--", "a"]).is_err());
+        assert!(parse(&["bom", "--fox", "--", "a"]).is_err());
+        assert!(parse(&["bom", "--diff", "--", "a"]).is_err());
+        assert!(parse(&["unknown", "--", "a"]).is_err());
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        assert_eq!(
+            unified_diff("x.txt", "a\r\nb\n", "a\nb\n"),
+            "--- a/x.txt\n+++ b/x.txt\n@@ -1,2 +1,2 @@\n-a\r\n+a\n b\n"
+        );
+        assert_eq!(
+            unified_diff("x.txt", "\u{feff}a\n", "a\n"),
+            "--- a/x.txt\n+++ b/x.txt\n@@ -1 +1 @@\n-\u{feff}a\n+a\n"
+        );
+        assert_eq!(unified_diff("x.txt", "a\n", "a\n"), "");
+    }
+
     #[test]
     fn test_detect_line_endings_empty_file() {
         let input = vec![];
@@ -0,0 +1,262 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use clap::Subcommand;
+
+use super::BuiltinCommand;
+
+/// How an option of a builtin command takes a value
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueKind {
+    /// A flag like `--fix`
+    None,
+    /// `--size=50k` or `--size 50k`
+    Required,
+    /// `--name` or `--name=value`, the value needs the `=`
+    Optional,
+}
+
+/// An option of a builtin command
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct OptionDescription {
+    /// The name of the option, without the leading `--`
+    pub long: String,
+    pub value: ValueKind,
+    /// The values accepted, any value is accepted if this is empty
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub possible_values: Vec<String>,
+    pub required: bool,
+    pub help: String,
+}
+
+/// The arguments a builtin command accepts, as printed by
+/// `beautytips builtin <name> --describe`
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct BuiltinDescription {
+    pub name: String,
+    pub about: String,
+    pub options: Vec<OptionDescription>,
+    /// The command takes the files to look at as positional arguments
+    pub files: bool,
+}
+
+fn builtin_commands() -> clap::Command {
+    let mut command = BuiltinCommand::augment_subcommands(clap::Command::new("builtin"));
+    command.build();
+    command
+}
+
+fn describe_option(arg: &clap::Arg, long: &str) -> OptionDescription {
+    let value = if !arg.get_action().takes_values() {
+        ValueKind::None
+    } else if arg.get_num_args().is_some_and(|n| n.min_values() == 0) {
+        ValueKind::Optional
+    } else {
+        ValueKind::Required
+    };
+    OptionDescription {
+        long: long.to_string(),
+        value,
+        possible_values: arg
+            .get_possible_values()
+            .iter()
+            .map(|v| v.get_name().to_string())
+            .collect(),
+        required: arg.is_required_set(),
+        help: arg.get_help().map(ToString::to_string).unwrap_or_default(),
+    }
+}
+
+/// The arguments the builtin command `name` accepts
+pub fn describe(name: &str) -> Option<BuiltinDescription> {
+    let commands = builtin_commands();
+    let command = commands.find_subcommand(name)?;
+    let options = command
+        .get_arguments()
+        .filter(|a| a.get_id() != "help")
+        .filter_map(|a| Some(describe_option(a, a.get_long()?)))
+        .collect();
+    let files = command.get_positionals().next().is_some();
+    Some(BuiltinDescription {
+        name: command.get_name().to_string(),
+        about: command
+            .get_about()
+            .map(ToString::to_string)
+            .unwrap_or_default(),
+        options,
+        files,
+    })
+}
+
+/// The arguments following `builtin`
+#[derive(clap::Parser)]
+#[command(name = "builtin")]
+struct Invocation {
+    #[command(subcommand)]
+    _command: BuiltinCommand,
+}
+
+/// A value of `arg` that gets accepted, standing in for a placeholder
+fn stand_in(arg: Option<&clap::Arg>) -> String {
+    arg.and_then(|a| {
+        a.get_possible_values()
+            .first()
+            .map(|v| v.get_name().to_string())
+            .or_else(|| {
+                a.get_default_values()
+                    .first()
+                    .map(|v| v.to_string_lossy().to_string())
+            })
+    })
+    .unwrap_or_else(|| "placeholder".to_string())
+}
+
+/// `args` with all values using placeholders replaced by values `command`
+/// accepts
+fn substitute_placeholders(command: &clap::Command, args: &[String]) -> Vec<String> {
+    let find = |long: &str| command.get_arguments().find(|a| a.get_long() == Some(long));
+
+    let mut result = Vec::with_capacity(args.len());
+    let mut value_of = None;
+    for arg in args {
+        let option = arg.strip_prefix("--").filter(|o| !o.is_empty());
+        let substituted = match option.and_then(|o| o.split_once('=')) {
+            Some((long, value)) if value.contains("{{") => {
+                format!("--{long}={}", stand_in(find(long)))
+            }
+            _ if arg.contains("{{") => stand_in(value_of),
+            _ => arg.clone(),
+        };
+        result.push(substituted);
+
+        // Options without `=` take the next argument as value
+        value_of = option
+            .filter(|o| !o.contains('='))
+            .and_then(find)
+            .filter(|a| a.get_num_args().is_some_and(|n| n.min_values() > 0));
+    }
+    result
+}
+
+/// The problems with running a builtin command with `args`, the arguments
+/// following `builtin`
+///
+/// Placeholders get replaced with values the command accepts, so their
+/// values are not checked.
+pub fn check_invocation(args: &[String]) -> Vec<String> {
+    let Some(name) = args.first() else {
+        return vec!["no builtin command given".to_string()];
+    };
+    let commands = builtin_commands();
+    let Some(command) = commands.find_subcommand(name) else {
+        return vec![format!("'{name}' is no builtin command")];
+    };
+
+    let args = substitute_placeholders(command, &args[1..]);
+    match <Invocation as clap::Parser>::try_parse_from(
+        ["builtin", name.as_str()]
+            .into_iter()
+            .chain(args.iter().map(String::as_str)),
+    ) {
+        Ok(_) => vec![],
+        Err(e) => {
+            // Only the first paragraph, without usage and tips
+            let message = e.to_string();
+            let message = message
+                .split("\n\n")
+                .next()
+                .unwrap_or_default()
+                .lines()
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join(" ");
+            vec![format!(
+                "{name}: {}",
+                message.strip_prefix("error: ").unwrap_or(&message)
+            )]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command_line: &str) -> Vec<String> {
+        shell_words::split(command_line).unwrap()
+    }
+
+    #[test]
+    fn test_describe() {
+        let description = describe("license-header").unwrap();
+        assert!(description.files);
+        let template = description
+            .options
+            .iter()
+            .find(|o| o.long == "template")
+            .unwrap();
+        assert!(template.required);
+        assert_eq!(template.value, ValueKind::Required);
+        let fix = description
+            .options
+            .iter()
+            .find(|o| o.long == "fix")
+            .unwrap();
+        assert_eq!(fix.value, ValueKind::None);
+
+        let description = describe("mixed-line-endings").unwrap();
+        let fix = description
+            .options
+            .iter()
+            .find(|o| o.long == "fix")
+            .unwrap();
+        assert_eq!(fix.possible_values, ["cr", "crlf", "lf", "auto"]);
+
+        assert!(describe("no-such-command").is_none());
+    }
+
+    #[test]
+    fn test_check_invocation() {
+        assert!(check_invocation(&args("large-files --size=50k -- {{files...}}")).is_empty());
+        assert!(check_invocation(&args("large-files --size 50k a.txt")).is_empty());
+        assert!(check_invocation(&args("large-files --size {{limit}} {{files}}")).is_empty());
+        assert!(check_invocation(&args("license-header --template=x --fix -- a")).is_empty());
+        assert!(check_invocation(&args("mixed-line-endings --fix={{mode}} a")).is_empty());
+
+        assert_eq!(
+            check_invocation(&args("large-files --sizes=50k")),
+            ["large-files: unexpected argument '--sizes' found"]
+        );
+        assert_eq!(
+            check_invocation(&args("large-files --size")),
+            ["large-files: a value is required for '--size <SIZE>' but none was supplied"]
+        );
+        assert_eq!(
+            check_invocation(&args("large-files --size=huge")),
+            ["large-files: invalid value 'huge' for '--size <SIZE>': Failed to parse size"]
+        );
+        assert_eq!(
+            check_invocation(&args("bom --fix=yes")),
+            ["bom: unexpected value 'yes' for '--fix' found; no more were expected"]
+        );
+        assert_eq!(
+            check_invocation(&args("mixed-line-endings --fix=unix")),
+            [
+                "mixed-line-endings: invalid value 'unix' for '--fix <LINE_ENDING>' \
+              [possible values: cr, crlf, lf, auto]"
+            ]
+        );
+        assert_eq!(
+            check_invocation(&args("license-header --fix")),
+            [
+                "license-header: the following required arguments were not provided: \
+              --template <FILE>"
+            ]
+        );
+        assert_eq!(
+            check_invocation(&args("lint-everything")),
+            ["'lint-everything' is no builtin command"]
+        );
+    }
+}
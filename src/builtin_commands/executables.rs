@@ -0,0 +1,163 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{io::Read, path::Path, path::PathBuf};
+
+use anyhow::Context;
+
+/// How the executable bit of a file relates to its content
+#[derive(Debug, PartialEq, Eq)]
+enum Problem {
+    /// A script starting with a shebang, but not executable
+    NotExecutable,
+    /// An executable text file without shebang
+    NoShebang,
+}
+
+/// The first bytes of the file at `path`
+#[cfg_attr(not(unix), allow(dead_code))]
+fn read_head(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut head = Vec::new();
+    std::fs::File::open(path)
+        .context(format!("Failed to open {}", path.display()))?
+        .take(1024)
+        .read_to_end(&mut head)
+        .context(format!("Failed to read {}", path.display()))?;
+    Ok(head)
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+fn find_problem(head: &[u8], is_executable: bool) -> Option<Problem> {
+    let has_shebang = crate::builtin_commands::has_shebang(head);
+    if has_shebang && !is_executable {
+        Some(Problem::NotExecutable)
+    } else if !has_shebang && is_executable && !beautytips::IsBinary::check(head) {
+        // Compiled programs are executable without shebang
+        Some(Problem::NoShebang)
+    } else {
+        None
+    }
+}
+
+#[cfg(unix)]
+fn check_file(path: &Path, fix: bool) -> anyhow::Result<Option<(Problem, bool)>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = path
+        .metadata()
+        .context(format!("Failed to look up {}", path.display()))?
+        .permissions()
+        .mode();
+    let Some(problem) = find_problem(&read_head(path)?, mode & 0o111 != 0) else {
+        return Ok(None);
+    };
+    if !fix {
+        return Ok(Some((problem, false)));
+    }
+
+    let new_mode = match problem {
+        // Everybody who may read the script may execute it
+        Problem::NotExecutable => mode | ((mode & 0o444) >> 2),
+        Problem::NoShebang => mode & !0o111,
+    };
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(new_mode)).context(format!(
+        "Failed to change permissions of {}",
+        path.display()
+    ))?;
+    Ok(Some((problem, true)))
+}
+
+#[cfg(not(unix))]
+fn check_file(_path: &Path, _fix: bool) -> anyhow::Result<Option<(Problem, bool)>> {
+    // There is no executable bit to check
+    Ok(None)
+}
+
+/// Report scripts that are not executable and executable text files that
+/// are no scripts, fixing their permissions if `fix` is set
+///
+/// Returns the number of files with unfixed problems. Nothing is reported on
+/// systems without executable bits.
+pub fn check_executables(fix: bool, inputs: &[PathBuf], verbosity: u8) -> anyhow::Result<i32> {
+    if !cfg!(unix) && verbosity > 0 {
+        eprintln!("There are no executable bits to check on this system");
+    }
+
+    let mut problems = 0;
+    for p in inputs {
+        match check_file(p, fix)? {
+            None => {
+                if verbosity > 0 {
+                    eprintln!("{}: OK", p.display());
+                }
+            }
+            Some((Problem::NotExecutable, true)) => {
+                eprintln!("{}: made executable", p.display());
+            }
+            Some((Problem::NoShebang, true)) => {
+                eprintln!("{}: not executable anymore", p.display());
+            }
+            Some((Problem::NotExecutable, false)) => {
+                eprintln!("{}: has a shebang, but is not executable", p.display());
+                problems += 1;
+            }
+            Some((Problem::NoShebang, false)) => {
+                eprintln!("{}: is executable, but has no shebang", p.display());
+                problems += 1;
+            }
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_problem() {
+        assert_eq!(find_problem(b"#!/bin/sh\n", true), None);
+        assert_eq!(
+            find_problem(b"#!/bin/sh\n", false),
+            Some(Problem::NotExecutable)
+        );
+        assert_eq!(find_problem(b"text\n", false), None);
+        assert_eq!(find_problem(b"text\n", true), Some(Problem::NoShebang));
+        assert_eq!(find_problem(b"\x7fELF\x02\x01\x01\0\0\0", true), None);
+        assert_eq!(find_problem(b"#! /usr/bin/env python3\n", true), None);
+        // Rust inner attributes are no shebang
+        assert_eq!(find_problem(b"#![deny(unsafe_code)]\n", false), None);
+        assert_eq!(
+            find_problem(b"#![deny(unsafe_code)]\n", true),
+            Some(Problem::NoShebang)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_executables() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory =
+            std::env::temp_dir().join(format!("beautytips-executables-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let script = directory.join("script");
+        std::fs::write(&script, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let text = directory.join("text");
+        std::fs::write(&text, "text\n").unwrap();
+        std::fs::set_permissions(&text, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let files = [script.clone(), text.clone()];
+        let mode = |p: &Path| p.metadata().unwrap().permissions().mode() & 0o777;
+
+        assert_eq!(check_executables(false, &files, 0).unwrap(), 2);
+        assert_eq!(mode(&script), 0o644);
+
+        assert_eq!(check_executables(true, &files, 0).unwrap(), 0);
+        assert_eq!(mode(&script), 0o755);
+        assert_eq!(mode(&text), 0o644);
+        assert_eq!(check_executables(false, &files, 0).unwrap(), 0);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::io::Write;
+
+use anyhow::Context;
+
+/// The exit code to report for a command exiting with `status` after
+/// printing `stdout`
+fn exit_code(status: Option<i32>, stdout: &[u8]) -> i32 {
+    match status {
+        Some(0) if stdout.iter().all(u8::is_ascii_whitespace) => 0,
+        Some(code) if code != 0 => code,
+        // Printed something or killed by a signal
+        _ => 1,
+    }
+}
+
+/// Run `command` and fail if it fails or prints anything to stdout
+///
+/// This turns tools like `gofmt -l`, that list the problems they find but
+/// always succeed, into checks. The output of the command is passed on.
+pub fn fail_on_output(command: &[String], verbosity: u8) -> anyhow::Result<i32> {
+    let (program, args) = command
+        .split_first()
+        .ok_or(anyhow::anyhow!("No command to run"))?;
+    if verbosity > 0 {
+        eprintln!("Running {}", command.join(" "));
+    }
+
+    let output = std::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .context(format!("Failed to run {program}"))?;
+    std::io::stdout()
+        .write_all(&output.stdout)
+        .context("Failed to pass on the output")?;
+
+    Ok(exit_code(output.status.code(), &output.stdout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(Some(0), b""), 0);
+        assert_eq!(exit_code(Some(0), b"\n"), 0);
+        assert_eq!(exit_code(Some(0), b"main.go\n"), 1);
+        assert_eq!(exit_code(Some(2), b""), 2);
+        assert_eq!(exit_code(Some(2), b"main.go\n"), 2);
+        assert_eq!(exit_code(None, b""), 1);
+    }
+}
@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::path::{Path, PathBuf};
+
+/// The characters not allowed in paths by default
+pub const DEFAULT_FORBIDDEN: &str = " \t:*?\"<>|\\";
+
+/// A naming convention for file names
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Convention {
+    /// `like_this`
+    Snake,
+    /// `like-this`
+    Kebab,
+    /// `likeThis`
+    Camel,
+    /// `LikeThis`
+    Pascal,
+    /// `likethis`, with any separators
+    Lower,
+}
+
+impl Convention {
+    fn is_followed_by(self, name: &str) -> bool {
+        let mut chars = name.chars();
+        let Some(first) = chars.next() else {
+            return true;
+        };
+        match self {
+            Self::Snake => name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+            Self::Kebab => name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'),
+            Self::Camel => first.is_ascii_lowercase() && chars.all(|c| c.is_ascii_alphanumeric()),
+            Self::Pascal => first.is_ascii_uppercase() && chars.all(|c| c.is_ascii_alphanumeric()),
+            Self::Lower => !name.chars().any(char::is_uppercase),
+        }
+    }
+}
+
+impl std::str::FromStr for Convention {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "snake_case" => Ok(Self::Snake),
+            "kebab-case" => Ok(Self::Kebab),
+            "camelCase" => Ok(Self::Camel),
+            "PascalCase" => Ok(Self::Pascal),
+            "lowercase" => Ok(Self::Lower),
+            _ => Err(anyhow::anyhow!(format!(
+                "Unknown naming convention '{s}', expected one of snake_case, kebab-case, camelCase, PascalCase or lowercase"
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for Convention {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Snake => write!(f, "snake_case"),
+            Self::Kebab => write!(f, "kebab-case"),
+            Self::Camel => write!(f, "camelCase"),
+            Self::Pascal => write!(f, "PascalCase"),
+            Self::Lower => write!(f, "lowercase"),
+        }
+    }
+}
+
+/// A naming convention for files matching a glob pattern
+#[derive(Clone, Debug)]
+pub struct NamingRule {
+    glob: glob::Pattern,
+    convention: Convention,
+}
+
+impl std::str::FromStr for NamingRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((glob, convention)) = s.rsplit_once('=') else {
+            return Err(anyhow::anyhow!(format!(
+                "Rule '{s}' is not of the form GLOB=CONVENTION"
+            )));
+        };
+        Ok(Self {
+            glob: glob::Pattern::new(glob)?,
+            convention: convention.parse()?,
+        })
+    }
+}
+
+/// The name of `path` without any extensions
+fn base_name(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = name.strip_prefix('.').unwrap_or(&name);
+    name.split('.').next().unwrap_or_default().to_string()
+}
+
+/// All violations of the naming `rules` and `forbidden` characters in `path`
+fn violations(path: &Path, rules: &[NamingRule], forbidden: &str) -> Vec<String> {
+    let mut result = Vec::new();
+
+    // Look at the components only, so separators are never forbidden
+    let mut found = path
+        .components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .flat_map(|name| name.chars().collect::<Vec<_>>())
+        .filter(|c| forbidden.contains(*c))
+        .collect::<Vec<_>>();
+    found.sort_unstable();
+    found.dedup();
+    if !found.is_empty() {
+        result.push(format!("contains forbidden characters {found:?}"));
+    }
+
+    let options = glob::MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    let name = base_name(path);
+    for rule in rules {
+        if rule.glob.matches_path_with(path, options) && !rule.convention.is_followed_by(&name) {
+            result.push(format!("'{name}' is not {}", rule.convention));
+        }
+    }
+
+    result
+}
+
+/// Report files breaking the naming `rules` or containing `forbidden`
+/// characters in their path
+///
+/// Paths are checked relative to the current directory. Returns the number of
+/// violations.
+pub fn check_filenames(
+    rules: &[NamingRule],
+    forbidden: &str,
+    inputs: &[PathBuf],
+    verbosity: u8,
+) -> i32 {
+    let current_directory = std::env::current_dir().unwrap_or_default();
+
+    let mut count = 0;
+    for p in inputs {
+        let relative = p.strip_prefix(&current_directory).unwrap_or(p);
+        let found = violations(relative, rules, forbidden);
+        if found.is_empty() && verbosity > 0 {
+            eprintln!("{}: OK", relative.display());
+        }
+        for v in &found {
+            eprintln!("{}: {v}", relative.display());
+        }
+        count += i32::try_from(found.len()).unwrap_or(i32::MAX);
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conventions() {
+        let check = |c: &str, name: &str| c.parse::<Convention>().unwrap().is_followed_by(name);
+
+        assert!(check("snake_case", "my_module2"));
+        assert!(!check("snake_case", "my-module"));
+        assert!(check("kebab-case", "my-doc"));
+        assert!(!check("kebab-case", "My-doc"));
+        assert!(check("camelCase", "myComponent"));
+        assert!(!check("camelCase", "MyComponent"));
+        assert!(check("PascalCase", "MyComponent"));
+        assert!(!check("PascalCase", "my_component"));
+        assert!(check("lowercase", "readme-now_2"));
+        assert!(!check("lowercase", "README"));
+        assert!("Snake".parse::<Convention>().is_err());
+    }
+
+    #[test]
+    fn test_violations() {
+        let rules = [
+            "**/*.py=snake_case".parse::<NamingRule>().unwrap(),
+            "docs/**/*.md=kebab-case".parse::<NamingRule>().unwrap(),
+        ];
+        let check = |p: &str| violations(Path::new(p), &rules, DEFAULT_FORBIDDEN).len();
+
+        assert_eq!(check("src/my_module.py"), 0);
+        assert_eq!(check("src/.hidden_file.py"), 0);
+        assert_eq!(check("src/myModule.py"), 1);
+        assert_eq!(check("docs/guide/getting-started.md"), 0);
+        assert_eq!(check("docs/guide/Getting Started.md"), 2);
+        assert_eq!(check("README.md"), 0);
+        assert_eq!(check("notes/what?.txt"), 1);
+        assert!("**/*.py".parse::<NamingRule>().is_err());
+    }
+}
@@ -0,0 +1,235 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+/// How files need to be indented
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Policy {
+    /// Tabs, optionally followed by less than a tab width of spaces for
+    /// alignment
+    Tabs,
+    /// Spaces only
+    Spaces,
+    /// Whatever most lines of the file use
+    Consistent,
+}
+
+/// The indentation policy for files with an extension or name
+#[derive(Clone, Debug)]
+pub struct FilePolicy {
+    name: String,
+    policy: Policy,
+}
+
+impl std::str::FromStr for FilePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, policy)) = s.rsplit_once('=') else {
+            return Err(anyhow::anyhow!(format!(
+                "Policy '{s}' is not of the form EXTENSION=POLICY"
+            )));
+        };
+        let policy =
+            <Policy as clap::ValueEnum>::from_str(policy, false).map_err(|e| anyhow::anyhow!(e))?;
+        Ok(Self {
+            name: name.to_string(),
+            policy,
+        })
+    }
+}
+
+/// The indentation settings
+pub struct Settings {
+    pub policies: Vec<FilePolicy>,
+    pub default_policy: Policy,
+    pub tab_width: usize,
+}
+
+impl Settings {
+    /// The policy for `path`, looked up by file name first and extension second
+    fn policy(&self, path: &Path) -> Policy {
+        let file_name = path.file_name().map(|n| n.to_string_lossy());
+        let extension = path.extension().map(|e| e.to_string_lossy());
+        self.policies
+            .iter()
+            .find(|p| file_name.as_deref() == Some(p.name.as_str()))
+            .or_else(|| {
+                self.policies
+                    .iter()
+                    .find(|p| extension.as_deref() == Some(p.name.as_str()))
+            })
+            .map_or(self.default_policy, |p| p.policy)
+    }
+}
+
+/// The leading whitespace of `line`
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+fn follows(indent: &str, policy: Policy, tab_width: usize) -> bool {
+    match policy {
+        Policy::Spaces => !indent.contains('\t'),
+        Policy::Tabs => {
+            let alignment = indent.trim_start_matches('\t');
+            !alignment.contains('\t') && alignment.len() < tab_width
+        }
+        Policy::Consistent => unreachable!("Consistent is resolved per file"),
+    }
+}
+
+/// Pick tabs or spaces for `policy`, based on what most lines in `text` use
+fn resolve(policy: Policy, text: &str) -> Policy {
+    if policy != Policy::Consistent {
+        return policy;
+    }
+    let (tabs, spaces) = text.lines().fold((0, 0), |(tabs, spaces), l| {
+        match indentation(l).chars().next() {
+            Some('\t') => (tabs + 1, spaces),
+            Some(_) => (tabs, spaces + 1),
+            None => (tabs, spaces),
+        }
+    });
+    if tabs > spaces {
+        Policy::Tabs
+    } else {
+        Policy::Spaces
+    }
+}
+
+/// `indent` re-done following `policy`
+fn fix_indentation(indent: &str, policy: Policy, tab_width: usize) -> String {
+    let width = indent.chars().fold(0, |width, c| {
+        if c == '\t' {
+            (width / tab_width + 1) * tab_width
+        } else {
+            width + 1
+        }
+    });
+    match policy {
+        Policy::Tabs => "\t".repeat(width / tab_width) + &" ".repeat(width % tab_width),
+        _ => " ".repeat(width),
+    }
+}
+
+/// The 1-based numbers of the lines of `text` not following `policy`, and the
+/// fixed `text`
+fn check_text(text: &str, policy: Policy, tab_width: usize) -> (Vec<usize>, String) {
+    let mut bad_lines = Vec::new();
+    let mut fixed = String::with_capacity(text.len());
+    for (number, line) in text.split_inclusive('\n').enumerate() {
+        let indent = indentation(line);
+        // Whitespace-only lines are not indented
+        if line[indent.len()..].trim().is_empty() || follows(indent, policy, tab_width) {
+            fixed.push_str(line);
+        } else {
+            bad_lines.push(number + 1);
+            fixed.push_str(&fix_indentation(indent, policy, tab_width));
+            fixed.push_str(&line[indent.len()..]);
+        }
+    }
+    (bad_lines, fixed)
+}
+
+/// Report files indented with the wrong mix of tabs and spaces, fixing them
+/// when `fix` is set
+///
+/// Returns the number of files with unfixed problems.
+pub fn check_indentation(
+    settings: &Settings,
+    fix: bool,
+    inputs: &[PathBuf],
+    verbosity: u8,
+) -> anyhow::Result<i32> {
+    let tab_width = settings.tab_width.max(1);
+
+    let mut bad_files = 0;
+    for p in inputs {
+        let contents = std::fs::read(p).context(format!("Failed to read {}", p.display()))?;
+        let text = match String::from_utf8(contents) {
+            Ok(text) if !beautytips::IsBinary::check(text.as_bytes()) => text,
+            _ => {
+                if verbosity > 0 {
+                    eprintln!("{}: binary file, SKIPPING", p.display());
+                }
+                continue;
+            }
+        };
+
+        let policy = resolve(settings.policy(p), &text);
+        let (bad_lines, fixed) = check_text(&text, policy, tab_width);
+        if bad_lines.is_empty() {
+            if verbosity > 0 {
+                eprintln!("{}: OK", p.display());
+            }
+            continue;
+        }
+
+        let policy = if policy == Policy::Tabs {
+            "tabs"
+        } else {
+            "spaces"
+        };
+        if fix {
+            beautytips::safe_rewrite(p, fixed.as_bytes())?;
+            eprintln!("{}: FIXED to {policy}", p.display());
+        } else {
+            let lines = bad_lines
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            eprintln!(
+                "{}: not indented with {policy} in lines {lines}",
+                p.display()
+            );
+            bad_files += 1;
+        }
+    }
+    Ok(bad_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_text() {
+        let text = "fn main() {\n\tlet a = 1;\n    let b = 2;\n\t  // aligned\n\n}\n";
+
+        let (bad, fixed) = check_text(text, Policy::Spaces, 4);
+        assert_eq!(bad, [2, 4]);
+        assert_eq!(
+            fixed,
+            "fn main() {\n    let a = 1;\n    let b = 2;\n      // aligned\n\n}\n"
+        );
+
+        let (bad, fixed) = check_text(text, Policy::Tabs, 4);
+        assert_eq!(bad, [3]);
+        assert_eq!(
+            fixed,
+            "fn main() {\n\tlet a = 1;\n\tlet b = 2;\n\t  // aligned\n\n}\n"
+        );
+
+        assert_eq!(resolve(Policy::Consistent, text), Policy::Tabs);
+        assert_eq!(resolve(Policy::Consistent, "a\n  b\n"), Policy::Spaces);
+        assert_eq!(resolve(Policy::Tabs, "a\n  b\n"), Policy::Tabs);
+    }
+
+    #[test]
+    fn test_policy() {
+        let settings = Settings {
+            policies: vec!["go=tabs".parse().unwrap(), "Makefile=tabs".parse().unwrap()],
+            default_policy: Policy::Spaces,
+            tab_width: 4,
+        };
+        assert_eq!(settings.policy(Path::new("src/main.go")), Policy::Tabs);
+        assert_eq!(settings.policy(Path::new("Makefile")), Policy::Tabs);
+        assert_eq!(settings.policy(Path::new("src/main.rs")), Policy::Spaces);
+        assert!("go=wide".parse::<FilePolicy>().is_err());
+    }
+}
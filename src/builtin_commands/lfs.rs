@@ -0,0 +1,268 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write as _,
+    path::PathBuf,
+    process::Stdio,
+};
+
+use anyhow::Context;
+
+/// Pointer files are never larger than this
+const MAX_POINTER_SIZE: u64 = 1024;
+
+/// The first line of a pointer file, for the current and the pre-release
+/// spec
+const POINTER_VERSIONS: [&[u8]; 2] = [
+    b"version https://git-lfs.github.com/spec/v1\n",
+    b"version https://hawser.github.com/spec/v1\n",
+];
+
+/// The gitlink mode of submodules in `git ls-files -s`
+const GITLINK_MODE: &str = "160000";
+
+/// How the content git stores for a file relates to its `filter` attribute
+#[derive(Debug, PartialEq, Eq)]
+enum Problem {
+    /// Assigned to git-lfs, but stored in git directly
+    NotAPointer,
+    /// A pointer, but not assigned to git-lfs
+    UnexpectedPointer,
+}
+
+fn is_lfs_pointer(content: &[u8]) -> bool {
+    content.len() as u64 <= MAX_POINTER_SIZE
+        && POINTER_VERSIONS.iter().any(|v| content.starts_with(v))
+        && content
+            .split(|b| *b == b'\n')
+            .any(|l| l.starts_with(b"oid sha256:"))
+}
+
+fn find_problem(has_lfs_filter: bool, is_pointer: bool) -> Option<Problem> {
+    match (has_lfs_filter, is_pointer) {
+        (true, false) => Some(Problem::NotAPointer),
+        (false, true) => Some(Problem::UnexpectedPointer),
+        _ => None,
+    }
+}
+
+fn git(args: &[&str], files: &[PathBuf]) -> anyhow::Result<Vec<u8>> {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .arg("--")
+        .args(files)
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// The paths with the `filter=lfs` attribute in the output of
+/// `git check-attr -z filter`
+fn parse_check_attr(output: &[u8]) -> HashSet<String> {
+    let fields = output.split(|b| *b == 0).collect::<Vec<_>>();
+    fields
+        .chunks_exact(3)
+        .filter(|c| c[2] == b"lfs")
+        .map(|c| String::from_utf8_lossy(c[0]).to_string())
+        .collect()
+}
+
+/// The blob ids of the files in the output of `git ls-files -s -z`, by path
+///
+/// Submodules have no blob and are left out.
+fn parse_ls_files(output: &[u8]) -> HashMap<String, String> {
+    output
+        .split(|b| *b == 0)
+        .filter_map(|entry| {
+            let entry = String::from_utf8_lossy(entry);
+            let (info, path) = entry.split_once('\t')?;
+            let mut info = info.split(' ');
+            let mode = info.next()?;
+            let blob = info.next()?;
+            (mode != GITLINK_MODE).then(|| (path.to_string(), blob.to_string()))
+        })
+        .collect()
+}
+
+/// Run `git cat-file` with `batch` (like `--batch`) on the objects `ids`
+fn cat_file(batch: &str, ids: &[&str]) -> anyhow::Result<Vec<u8>> {
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut child = std::process::Command::new("git")
+        .args(["cat-file", batch])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run git")?;
+
+    // Write from a thread of its own, git blocks when its output is not read
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut input = ids.join("\n");
+    input.push('\n');
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let output = child.wait_with_output().context("Failed to run git")?;
+    writer
+        .join()
+        .expect("Writer thread panicked")
+        .context("Failed to pass object ids to git")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(format!("git cat-file {batch} failed")));
+    }
+    Ok(output.stdout)
+}
+
+/// The ids of the objects no larger than `MAX_POINTER_SIZE` in the output of
+/// `git cat-file --batch-check='%(objectname) %(objectsize)'`
+fn parse_small_objects(output: &[u8]) -> HashSet<String> {
+    String::from_utf8_lossy(output)
+        .lines()
+        .filter_map(|l| {
+            let (id, size) = l.split_once(' ')?;
+            (size.parse::<u64>().ok()? <= MAX_POINTER_SIZE).then(|| id.to_string())
+        })
+        .collect()
+}
+
+/// The content of the objects in the output of `git cat-file --batch`, by id
+fn parse_objects(output: &[u8]) -> anyhow::Result<HashMap<String, Vec<u8>>> {
+    // Each object is `<id> <type> <size>\n<content>\n`
+    let mut result = HashMap::new();
+    let mut rest = output;
+    while let Some(end) = rest.iter().position(|b| *b == b'\n') {
+        let header = String::from_utf8_lossy(&rest[..end]).to_string();
+        rest = &rest[end + 1..];
+        let mut header = header.split(' ');
+        let (Some(id), Some(_), Some(size)) = (header.next(), header.next(), header.next()) else {
+            // `<id> missing`
+            continue;
+        };
+        let size = size.parse::<usize>().context("Unexpected output of git")?;
+        let content = rest.get(..size).context("Unexpected output of git")?;
+        result.insert(id.to_string(), content.to_vec());
+        rest = rest.get(size + 1..).unwrap_or_default();
+    }
+    Ok(result)
+}
+
+/// Report files that `.gitattributes` assigns to git-lfs but that are stored
+/// in git directly, and git-lfs pointers of files not assigned to git-lfs
+///
+/// The content git stores for the files (in the index) is checked, as the
+/// working tree holds the real content of files in git-lfs. Files unknown
+/// to git are left alone. Returns the number of files with problems.
+pub fn check_lfs_pointers(inputs: &[PathBuf], verbosity: u8) -> anyhow::Result<i32> {
+    if inputs.is_empty() {
+        return Ok(0);
+    }
+
+    let lfs_files = parse_check_attr(&git(&["check-attr", "-z", "filter"], inputs)?);
+    let blobs = parse_ls_files(&git(&["ls-files", "-s", "-z"], inputs)?);
+
+    // Large blobs are no pointers, so only read small ones
+    let ids = blobs.values().map(String::as_str).collect::<Vec<_>>();
+    let small_ids = parse_small_objects(&cat_file(
+        "--batch-check=%(objectname) %(objectsize)",
+        &ids,
+    )?);
+    let ids = ids
+        .into_iter()
+        .filter(|id| small_ids.contains(*id))
+        .collect::<Vec<_>>();
+    let small = parse_objects(&cat_file("--batch", &ids)?)?;
+
+    let mut problems = 0;
+    for p in inputs {
+        let path = p.to_string_lossy().to_string();
+        let Some(blob) = blobs.get(&path) else {
+            if verbosity > 0 {
+                eprintln!("{path}: not known to git");
+            }
+            continue;
+        };
+        let is_pointer = small.get(blob).is_some_and(|c| is_lfs_pointer(c));
+        match find_problem(lfs_files.contains(&path), is_pointer) {
+            None => {
+                if verbosity > 0 {
+                    eprintln!("{path}: OK");
+                }
+            }
+            Some(Problem::NotAPointer) => {
+                eprintln!("{path}: is assigned to git-lfs, but stored in git directly");
+                problems += 1;
+            }
+            Some(Problem::UnexpectedPointer) => {
+                eprintln!("{path}: is a git-lfs pointer, but not assigned to git-lfs");
+                problems += 1;
+            }
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINTER: &[u8] = b"version https://git-lfs.github.com/spec/v1
+oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393
+size 12345
+";
+
+    #[test]
+    fn test_is_lfs_pointer() {
+        assert!(is_lfs_pointer(POINTER));
+        assert!(!is_lfs_pointer(&POINTER[..44]));
+        assert!(!is_lfs_pointer(b"\x89PNG\r\n\x1a\n"));
+        assert!(!is_lfs_pointer(&[POINTER, &[b' '; 1024]].concat()));
+    }
+
+    #[test]
+    fn test_find_problem() {
+        assert_eq!(find_problem(true, true), None);
+        assert_eq!(find_problem(false, false), None);
+        assert_eq!(find_problem(true, false), Some(Problem::NotAPointer));
+        assert_eq!(find_problem(false, true), Some(Problem::UnexpectedPointer));
+    }
+
+    #[test]
+    fn test_parse_git_output() {
+        assert_eq!(
+            parse_check_attr(b"a.bin\0filter\0lfs\0b.txt\0filter\0unspecified\0"),
+            HashSet::from(["a.bin".to_string()])
+        );
+        assert_eq!(
+            parse_ls_files(
+                b"100644 4d7a2146 0\ta.bin\x00160000 9f2c1a3b 0\tthird_party/foo\x00100755 c0ffee12 0\tdir/run me.sh\0"
+            ),
+            HashMap::from([
+                ("a.bin".to_string(), "4d7a2146".to_string()),
+                ("dir/run me.sh".to_string(), "c0ffee12".to_string()),
+            ])
+        );
+        assert_eq!(
+            parse_small_objects(b"4d7a2146 132\nc0ffee12 52428800\n"),
+            HashSet::from(["4d7a2146".to_string()])
+        );
+        assert_eq!(
+            parse_objects(b"4d7a2146 blob 5\nhello\n9f2c1a3b missing\nc0ffee12 blob 0\n\n")
+                .unwrap(),
+            HashMap::from([
+                ("4d7a2146".to_string(), b"hello".to_vec()),
+                ("c0ffee12".to_string(), vec![]),
+            ])
+        );
+    }
+}
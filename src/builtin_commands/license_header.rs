@@ -0,0 +1,296 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+/// The placeholder for the copyright year in header templates
+const YEAR_PLACEHOLDER: &str = "{year}";
+/// The placeholder for the copyright holder in header templates
+const AUTHOR_PLACEHOLDER: &str = "{author}";
+
+/// How to turn a line into a comment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct CommentStyle {
+    prefix: &'static str,
+    suffix: &'static str,
+}
+
+impl CommentStyle {
+    const fn new(prefix: &'static str, suffix: &'static str) -> Self {
+        Self { prefix, suffix }
+    }
+
+    fn for_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "c" | "cc" | "cpp" | "cxx" | "h" | "hh" | "hpp" | "cs" | "dart" | "go" | "java"
+            | "js" | "jsx" | "kt" | "mjs" | "rs" | "scala" | "slint" | "swift" | "ts" | "tsx" => {
+                Some(Self::new("// ", ""))
+            }
+            "bash" | "cmake" | "nix" | "pl" | "ps1" | "py" | "r" | "rb" | "sh" | "toml"
+            | "yaml" | "yml" | "zsh" => Some(Self::new("# ", "")),
+            "hs" | "lua" | "sql" => Some(Self::new("-- ", "")),
+            "css" | "scss" => Some(Self::new("/* ", " */")),
+            "html" | "md" | "svg" | "xml" => Some(Self::new("<!-- ", " -->")),
+            _ => None,
+        }
+    }
+
+    fn comment(self, line: &str) -> String {
+        format!("{}{line}{}", self.prefix, self.suffix)
+            .trim_end()
+            .to_string()
+    }
+
+    fn is_comment(self, line: &str) -> bool {
+        line.starts_with(self.prefix.trim_end()) && line.trim_end().ends_with(self.suffix.trim())
+    }
+}
+
+/// A license header to look for at the start of files
+pub struct HeaderTemplate {
+    lines: Vec<String>,
+    author: Option<String>,
+    year: i64,
+}
+
+impl HeaderTemplate {
+    pub fn new(template: &str, author: Option<String>, year: i64) -> anyhow::Result<Self> {
+        let lines = template
+            .trim_end()
+            .lines()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return Err(anyhow::anyhow!("The license header template is empty"));
+        }
+        Ok(Self {
+            lines,
+            author,
+            year,
+        })
+    }
+
+    /// A regular expression matching the header in `style`, accepting any
+    /// year or range of years
+    fn pattern(&self, style: CommentStyle) -> regex::Regex {
+        let author = self
+            .author
+            .as_deref()
+            .map_or_else(|| ".+".to_string(), regex::escape);
+        let lines = self
+            .lines
+            .iter()
+            .map(|l| {
+                regex::escape(&style.comment(l))
+                    .replace(
+                        &regex::escape(YEAR_PLACEHOLDER),
+                        r"\d{4}(?:\s*[-,]\s*\d{4})?",
+                    )
+                    .replace(&regex::escape(AUTHOR_PLACEHOLDER), &author)
+            })
+            .collect::<Vec<_>>();
+        regex::Regex::new(&format!(r"\A{}(?:\r?\n|\z)", lines.join(r"\r?\n")))
+            .expect("Escaped header is a valid regular expression")
+    }
+
+    /// The header in `style`, ready to be inserted
+    fn render(&self, style: CommentStyle) -> anyhow::Result<String> {
+        let mut result = String::new();
+        for l in &self.lines {
+            let mut line = l.replace(YEAR_PLACEHOLDER, &self.year.to_string());
+            if line.contains(AUTHOR_PLACEHOLDER) {
+                let Some(author) = &self.author else {
+                    return Err(anyhow::anyhow!(
+                        "The license header needs an author, set one with --author"
+                    ));
+                };
+                line = line.replace(AUTHOR_PLACEHOLDER, author);
+            }
+            result.push_str(&style.comment(&line));
+            result.push('\n');
+        }
+        Ok(result)
+    }
+}
+
+/// The current year, in UTC
+pub fn current_year() -> i64 {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let days = i64::try_from(seconds / 86_400).unwrap_or_default();
+
+    // Convert days since 1970-01-01 into a civil year
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    year_of_era + era * 400 + i64::from(month_index >= 10)
+}
+
+/// The length of the shebang line at the start of `contents`, if any
+fn shebang_length(contents: &str) -> usize {
+    if crate::builtin_commands::has_shebang(contents.as_bytes()) {
+        contents.find('\n').map_or(contents.len(), |p| p + 1)
+    } else {
+        0
+    }
+}
+
+/// The length of the comment block at the start of `contents`, if it looks
+/// like a license header
+fn old_header_length(contents: &str, style: CommentStyle) -> usize {
+    let mut length = 0;
+    for line in contents.split_inclusive('\n') {
+        if !style.is_comment(line) {
+            break;
+        }
+        length += line.len();
+    }
+
+    let block = contents[..length].to_lowercase();
+    if block.contains("copyright") || block.contains("license") {
+        length
+    } else {
+        0
+    }
+}
+
+/// The fixed `contents`, with `header` inserted or replacing an old one
+fn fix_header(contents: &str, header: &str, style: CommentStyle) -> String {
+    let shebang = shebang_length(contents);
+    let rest = &contents[shebang..];
+    let old_header = old_header_length(rest, style);
+    let rest = &rest[old_header..];
+
+    let separator = if old_header > 0 || rest.is_empty() || rest.starts_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    format!("{}{header}{separator}{rest}", &contents[..shebang])
+}
+
+/// Check that all `inputs` start with the license `template`, inserting or
+/// updating the header when `fix` is set
+///
+/// Returns the number of files without the header.
+pub fn check_license_header(
+    template: &HeaderTemplate,
+    fix: bool,
+    inputs: &[PathBuf],
+    verbosity: u8,
+) -> anyhow::Result<i32> {
+    let mut missing = 0;
+    for p in inputs {
+        let Some(style) = CommentStyle::for_path(p) else {
+            if verbosity > 0 {
+                eprintln!("{}: unknown comment syntax, SKIPPING", p.display());
+            }
+            continue;
+        };
+        let contents =
+            std::fs::read_to_string(p).context(format!("Failed to read {}", p.display()))?;
+
+        if template
+            .pattern(style)
+            .is_match(&contents[shebang_length(&contents)..])
+        {
+            if verbosity > 0 {
+                eprintln!("{}: OK", p.display());
+            }
+            continue;
+        }
+
+        if fix {
+            let fixed = fix_header(&contents, &template.render(style)?, style);
+            beautytips::safe_rewrite(p, fixed.as_bytes())?;
+            eprintln!("{}: license header FIXED", p.display());
+        } else {
+            eprintln!("{}: license header missing or outdated", p.display());
+            missing += 1;
+        }
+    }
+    Ok(missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATE: &str = "SPDX-License-Identifier: MIT\nCopyright (C) {year} {author}\n";
+
+    fn template() -> HeaderTemplate {
+        HeaderTemplate::new(TEMPLATE, Some("Jane Doe".to_string()), 2024).unwrap()
+    }
+
+    #[test]
+    fn test_header_pattern() {
+        let rust = CommentStyle::for_path(Path::new("src/main.rs")).unwrap();
+        let pattern = template().pattern(rust);
+
+        assert!(pattern
+            .is_match("// SPDX-License-Identifier: MIT\n// Copyright (C) 2019 Jane Doe\n\nfn"));
+        assert!(pattern
+            .is_match("// SPDX-License-Identifier: MIT\n// Copyright (C) 2019-2024 Jane Doe"));
+        assert!(
+            !pattern.is_match("// SPDX-License-Identifier: MIT\n// Copyright (C) 2019 John Doe\n")
+        );
+        assert!(!pattern.is_match("fn main() {}\n"));
+
+        let anybody = HeaderTemplate::new(TEMPLATE, None, 2024).unwrap();
+        assert!(anybody
+            .pattern(rust)
+            .is_match("// SPDX-License-Identifier: MIT\n// Copyright (C) 2019 John Doe\n"));
+        assert!(anybody.render(rust).is_err());
+
+        assert!(CommentStyle::for_path(Path::new("image.png")).is_none());
+    }
+
+    #[test]
+    fn test_fix_header() {
+        let python = CommentStyle::for_path(Path::new("x.py")).unwrap();
+        let header = template().render(python).unwrap();
+        assert_eq!(
+            header,
+            "# SPDX-License-Identifier: MIT\n# Copyright (C) 2024 Jane Doe\n"
+        );
+
+        assert_eq!(
+            fix_header("#!/usr/bin/env python\nprint()\n", &header, python),
+            format!("#!/usr/bin/env python\n{header}\nprint()\n")
+        );
+        assert_eq!(
+            fix_header("# Copyright 2001 Jane Doe\n\nprint()\n", &header, python),
+            format!("{header}\nprint()\n")
+        );
+        assert_eq!(
+            fix_header("# A comment\nprint()\n", &header, python),
+            format!("{header}\n# A comment\nprint()\n")
+        );
+
+        let rust = CommentStyle::for_path(Path::new("lib.rs")).unwrap();
+        let rust_header = template().render(rust).unwrap();
+        assert_eq!(
+            fix_header("#![deny(unsafe_code)]\nmod x;\n", &rust_header, rust),
+            format!("{rust_header}\n#![deny(unsafe_code)]\nmod x;\n")
+        );
+
+        let html = CommentStyle::for_path(Path::new("x.html")).unwrap();
+        assert_eq!(
+            template().render(html).unwrap(),
+            "<!-- SPDX-License-Identifier: MIT -->\n<!-- Copyright (C) 2024 Jane Doe -->\n"
+        );
+    }
+
+    #[test]
+    fn test_current_year() {
+        assert!(current_year() >= 2024);
+    }
+}
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+/// The file formats `check_syntax` knows about
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+/// A syntax error at a 1-based line and column
+#[derive(Debug, PartialEq, Eq)]
+struct SyntaxError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+/// The 1-based line and column of the byte at `offset` in `text`
+fn line_and_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |p| p + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+fn check_json(text: &str) -> Result<(), SyntaxError> {
+    serde_json::from_str::<serde::de::IgnoredAny>(text)
+        .map(|_| ())
+        .map_err(|e| SyntaxError {
+            line: e.line(),
+            column: e.column(),
+            message: e.to_string(),
+        })
+}
+
+fn check_toml(text: &str) -> Result<(), SyntaxError> {
+    text.parse::<toml::Table>().map(|_| ()).map_err(|e| {
+        let (line, column) = line_and_column(text, e.span().map_or(0, |s| s.start));
+        SyntaxError {
+            line,
+            column,
+            message: e.message().to_string(),
+        }
+    })
+}
+
+fn check_yaml(text: &str) -> Result<(), SyntaxError> {
+    for document in serde_norway::Deserializer::from_str(text) {
+        serde::de::IgnoredAny::deserialize(document).map_err(|e| {
+            let (line, column) = e.location().map_or((1, 1), |l| (l.line(), l.column()));
+            SyntaxError {
+                line,
+                column,
+                message: e.to_string(),
+            }
+        })?;
+    }
+    Ok(())
+}
+
+/// Report all `inputs` that are not valid in `format`
+///
+/// Returns the number of invalid files.
+pub fn check_syntax(format: Format, inputs: &[PathBuf], verbosity: u8) -> anyhow::Result<i32> {
+    let mut invalid_files = 0;
+    for p in inputs {
+        let contents = std::fs::read(p).context(format!("Failed to read {}", p.display()))?;
+        let Ok(text) = String::from_utf8(contents) else {
+            eprintln!("{}: not valid UTF-8", p.display());
+            invalid_files += 1;
+            continue;
+        };
+
+        let result = match format {
+            Format::Json => check_json(&text),
+            Format::Toml => check_toml(&text),
+            Format::Yaml => check_yaml(&text),
+        };
+        match result {
+            Ok(()) => {
+                if verbosity > 0 {
+                    eprintln!("{}: OK", p.display());
+                }
+            }
+            Err(e) => {
+                // The location is printed up front already
+                let message = e
+                    .message
+                    .replace(&format!(" at line {} column {}", e.line, e.column), "")
+                    .replace('\n', ", ");
+                eprintln!("{}:{}:{}: {message}", p.display(), e.line, e.column);
+                invalid_files += 1;
+            }
+        }
+    }
+    Ok(invalid_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(result: Result<(), SyntaxError>) -> (usize, usize) {
+        let e = result.unwrap_err();
+        (e.line, e.column)
+    }
+
+    #[test]
+    fn test_check_syntax() {
+        assert!(check_json("{\"a\": [1, 2]}").is_ok());
+        assert_eq!(location(check_json("{\n  \"a\": [1, 2,]\n}")), (2, 14));
+
+        assert!(check_toml("a = 1\n[b]\nc = \"d\"\n").is_ok());
+        assert_eq!(location(check_toml("a = 1\nb = = 2\n")), (2, 5));
+
+        assert!(check_yaml("a: 1\n---\nb: [1, 2]\n").is_ok());
+        assert_eq!(location(check_yaml("a: 1\nb: [1, 2\n")).0, 3);
+
+        assert_eq!(line_and_column("ab\nüc", 5), (2, 2));
+    }
+}
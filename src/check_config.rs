@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::path::Path;
+
+use crate::{builtin_commands, config};
+
+/// The number of builtin command invocations in `config` and the problems
+/// found in them
+///
+/// Builtin commands are those run via `executable builtin`, which is what
+/// `{BEAUTY_TIPS}` in the configuration expands to.
+fn builtin_problems(config: &config::Configuration, executable: &Path) -> (usize, Vec<String>) {
+    let mut checked = 0;
+    let mut problems = Vec::new();
+    for (id, action) in &config.action_map {
+        for command in [&action.command, &action.full_command, &action.diff_command] {
+            let Some((program, args)) = command.split_first() else {
+                continue;
+            };
+            let Some(("builtin", args)) = args.split_first().map(|(a, rest)| (a.as_str(), rest))
+            else {
+                continue;
+            };
+            if Path::new(program) != executable {
+                continue;
+            }
+            checked += 1;
+            problems.extend(
+                builtin_commands::describe::check_invocation(args)
+                    .into_iter()
+                    .map(|p| format!("{id}: {p}")),
+            );
+        }
+    }
+    (checked, problems)
+}
+
+/// The problems of the actions in `config` whose executables are not found
+/// when running in `root_directory`
+fn executable_problems(config: &config::Configuration, root_directory: &Path) -> Vec<String> {
+    config
+        .action_map
+        .values()
+        .filter_map(|action| action.validate_with(Some(root_directory)).err())
+        .map(|e| e.to_string())
+        .collect()
+}
+
+/// Check that all actions running builtin commands pass options these accept,
+/// and with `executables` set that the executables of all actions are found
+pub fn check_config(config: &config::Configuration, executables: bool) -> anyhow::Result<()> {
+    let executable = std::env::current_exe()?;
+    let (checked, mut problems) = builtin_problems(config, &executable);
+    if executables {
+        problems.extend(executable_problems(config, &std::env::current_dir()?));
+    }
+    for problem in &problems {
+        println!("{problem}");
+    }
+    let checked = if executables {
+        format!(
+            "{checked} builtin command invocation(s) and {} action(s)",
+            config.action_map.len()
+        )
+    } else {
+        format!("{checked} builtin command invocation(s)")
+    };
+    if problems.is_empty() {
+        println!("Checked {checked}, no problems found");
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(format!(
+            "Found {} problem(s) in {checked}",
+            problems.len()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_problems() {
+        let executable = std::env::current_exe().unwrap();
+        let (checked, problems) = builtin_problems(&config::builtin(), &executable);
+        assert!(checked > 0);
+        assert_eq!(problems, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_executable_problems() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let source = config::ConfigurationSource::from_string(
+            r#"[[actions]]
+name = "test/missing"
+command = "beautytips-no-such-tool {{files}}"
+"#,
+        )
+        .unwrap();
+        let config = config::Configuration::default().merge(source).unwrap();
+
+        let problems = executable_problems(&config, root);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("'beautytips-no-such-tool' of the command is not found"));
+    }
+}
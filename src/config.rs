@@ -3,44 +3,24 @@
 
 use std::collections::hash_set::Iter;
 use std::collections::{BTreeMap, HashMap, HashSet};
-use std::{convert::TryFrom, fmt::Display, path::Path};
+use std::{
+    convert::TryFrom,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow::Context;
 
 use beautytips::InputFilters;
 
-fn is_valid_id(id: &str) -> bool {
-    if id.is_empty() {
-        return false;
-    }
-
-    let mut first_part_valid = false;
-    let mut had_separator = false;
-    let mut second_part_valid = false;
+use crate::reporter::{self, theme};
 
-    for c in id.chars() {
-        match c {
-            '/' => {
-                if had_separator || !first_part_valid {
-                    return false;
-                }
-                had_separator = true;
-            }
-            '_' => { /* do nothing */ }
-            c if c.is_ascii_digit() => { /* do nothing */ }
-            c if c.is_ascii_lowercase() => {
-                if had_separator {
-                    second_part_valid = true;
-                } else {
-                    first_part_valid = true;
-                }
-            }
-            _ => return false,
-        }
-    }
+mod dump;
+mod error;
 
-    second_part_valid
-}
+pub use beautytips::{ActionId, ActionSelector};
+pub use dump::{dump, DumpFormat};
+pub use error::ConfigError;
 
 fn find_selectors(action_groups: &ActionGroups, selectors: &ActionSelectors) -> ActionSelectors {
     let mut result = ActionSelectors::default();
@@ -63,137 +43,13 @@ fn find_selectors(action_groups: &ActionGroups, selectors: &ActionSelectors) ->
 fn find_actions<'a>(
     actions: &'a ActionMap,
     selectors: &ActionSelectors,
-) -> Vec<&'a beautytips::ActionDefinition> {
+) -> Vec<&'a Arc<beautytips::ActionDefinition>> {
     actions
-        .iter()
-        .filter_map(move |(_, ad)| {
-            selectors
-                .0
-                .iter()
-                .find_map(|s| s.matches(&ad.id).then_some(ad))
-        })
+        .values()
+        .filter(|ad| selectors.matches_action(ad))
         .collect()
 }
 
-#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Deserialize)]
-#[serde(try_from = "String", expecting = "an action id")]
-pub struct ActionId(String);
-
-impl ActionId {
-    /// Create a new `ActionId`
-    ///
-    /// # Errors
-    ///
-    /// Complain when the `input` is not a valid Action Id
-    pub fn new(input: String) -> anyhow::Result<Self> {
-        if is_valid_id(&input) {
-            Ok(Self(input))
-        } else {
-            Err(anyhow::anyhow!("{input} is not a valid action id"))
-        }
-    }
-}
-
-impl std::ops::Deref for ActionId {
-    type Target = str;
-
-    fn deref(&self) -> &Self::Target {
-        self.0.as_str()
-    }
-}
-
-impl Display for ActionId {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
-impl TryFrom<&str> for ActionId {
-    type Error = anyhow::Error;
-
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        Self::new(value.to_string())
-    }
-}
-
-impl TryFrom<String> for ActionId {
-    type Error = anyhow::Error;
-
-    fn try_from(value: String) -> Result<Self, Self::Error> {
-        Self::new(value)
-    }
-}
-
-impl std::str::FromStr for ActionId {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Self::new(s.to_string())
-    }
-}
-
-#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Deserialize)]
-#[serde(try_from = "String", expecting = "an action id selector")]
-pub struct ActionSelector(glob::Pattern);
-
-impl ActionSelector {
-    /// Create a new `ActionId`
-    ///
-    /// # Errors
-    ///
-    /// Complain if the selector is not valid
-    pub fn new(input: &str) -> anyhow::Result<Self> {
-        let pattern = if input.contains('/') {
-            glob::Pattern::new(input).context("Failed to parse action selector")?
-        } else {
-            glob::Pattern::new(&format!("*/{input}")).context("Failed to parse action selector")?
-        };
-
-        Ok(Self(pattern))
-    }
-
-    pub fn matches(&self, input: &str) -> bool {
-        self.0.matches_with(
-            input,
-            glob::MatchOptions {
-                case_sensitive: true,
-                require_literal_separator: true,
-                require_literal_leading_dot: false,
-            },
-        )
-    }
-}
-
-impl Display for ActionSelector {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
-impl TryFrom<String> for ActionSelector {
-    type Error = anyhow::Error;
-
-    fn try_from(value: String) -> Result<Self, Self::Error> {
-        Self::new(&value)
-    }
-}
-
-impl TryFrom<&str> for ActionSelector {
-    type Error = anyhow::Error;
-
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        Self::new(value)
-    }
-}
-
-impl std::str::FromStr for ActionSelector {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Self::new(s)
-    }
-}
-
 #[derive(Clone, Debug, Default)]
 pub struct ActionSelectors(HashSet<ActionSelector>);
 
@@ -216,15 +72,40 @@ impl ActionSelectors {
         self.0.iter().any(|s| s.matches(input))
     }
 
+    /// `true` if any selector matches the id or a tag of `action`
+    pub fn matches_action(&self, action: &beautytips::ActionDefinition) -> bool {
+        self.0.iter().any(|s| s.matches_action(action))
+    }
+
     pub fn len(&self) -> usize {
         self.0.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     pub fn contains(&self, needle: &ActionSelector) -> bool {
         self.0.contains(needle)
     }
 }
 
+impl std::str::FromStr for ActionSelectors {
+    type Err = anyhow::Error;
+
+    /// Parse a comma separated list of selectors, as found in the `SKIP`
+    /// environment variable
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(
+            s.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(ActionSelector::new)
+                .collect::<anyhow::Result<_>>()?,
+        ))
+    }
+}
+
 impl From<Vec<ActionSelector>> for ActionSelectors {
     fn from(input: Vec<ActionSelector>) -> ActionSelectors {
         ActionSelectors(input.into_iter().collect())
@@ -248,16 +129,23 @@ impl<'a> IntoIterator for &'a ActionSelectors {
     }
 }
 
-#[derive(Debug, Default, serde::Deserialize)]
+/// How to merge an action into the actions defined so far
+#[derive(Debug, Default, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum MergeAction {
+    /// Remove an existing action
     Remove,
+    /// Change some properties of an existing action
     Change,
+    /// Replace an existing action completely
+    Replace,
+    /// Add a new action
     #[default]
     Add,
 }
 
-#[derive(Debug, Default, serde::Deserialize)]
+/// When to show the output of an action
+#[derive(Debug, Default, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum OutputCondition {
     Never,
@@ -265,65 +153,550 @@ pub enum OutputCondition {
     #[default]
     Failure,
     Always,
+    /// Always, but only the first and last 10 lines
+    Summary,
+}
+
+/// Where to run an action
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SandboxMode {
+    /// A temporary directory holding copies of the input files
+    Copy,
+    /// A temporary git worktree with the input files copied over
+    Worktree,
+    /// The working tree, but only the temporary directory of the run and the
+    /// cache directory of the action are writable (Linux only, needs `bwrap`)
+    Restricted,
+}
+
+/// The sandbox of an action
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+#[serde(untagged)]
+pub enum TomlSandbox {
+    /// `true` for the `restricted` sandbox, `false` for none
+    Enabled(bool),
+    Mode(SandboxMode),
+}
+
+/// How an action gets the list of its files
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FilesVia {
+    /// As command line arguments
+    Arguments,
+    /// In a temporary file with NUL separated paths, passed in via
+    /// `{{files:listfile}}`
+    File,
+}
+
+/// How much failures of an action matter
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Warnings fail the run like errors
+    Error,
+    /// Report results as they are
+    Warning,
+    /// Never fail the run, but still show the output
+    Info,
+}
+
+/// When an action runs, relative to the other actions
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Before all slow actions, e.g. quick formatting checks
+    Fast,
+    /// Once all fast actions passed, the default
+    Slow,
+}
+
+/// Which files an action may change
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+#[serde(untagged)]
+pub enum TomlWrites {
+    /// `true` if the action may change its input files
+    InputFiles(bool),
+    /// Glob patterns of the files the action may change
+    Patterns(Vec<String>),
+}
+
+/// Commands to run on specific operating systems
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TomlOsCommands {
+    /// The command to run on operating systems without a command of their own
+    #[serde(default)]
+    pub default: Option<String>,
+    /// The command to run on Linux
+    #[serde(default)]
+    pub linux: Option<String>,
+    /// The command to run on macOS
+    #[serde(default)]
+    pub macos: Option<String>,
+    /// The command to run on Windows
+    #[serde(default)]
+    pub windows: Option<String>,
+}
+
+impl TomlOsCommands {
+    fn for_os(&self, os: &str) -> Option<&String> {
+        match os {
+            "linux" => self.linux.as_ref(),
+            "macos" => self.macos.as_ref(),
+            "windows" => self.windows.as_ref(),
+            _ => None,
+        }
+        .or(self.default.as_ref())
+    }
+
+    fn operating_systems(&self) -> Vec<&'static str> {
+        [
+            ("linux", &self.linux),
+            ("macos", &self.macos),
+            ("windows", &self.windows),
+        ]
+        .into_iter()
+        .filter_map(|(os, command)| command.as_ref().map(|_| os))
+        .collect()
+    }
+}
+
+/// The command of an action
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+#[serde(untagged)]
+pub enum TomlCommand {
+    /// The command to run on all operating systems
+    Generic(String),
+    /// Commands to run on specific operating systems
+    PerOs(TomlOsCommands),
+}
+
+/// The filter of an input
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+#[serde(untagged)]
+pub enum TomlInputFilter {
+    /// A single value, like the `kind` of files
+    One(String),
+    /// A list of patterns
+    Many(Vec<String>),
+}
+
+/// Conditions an action needs to meet to run
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TomlCondition {
+    /// An environment variable that must be set and not empty, or `NAME=value`
+    #[serde(default)]
+    pub env: Option<String>,
+    /// A file that must exist in the repository
+    #[serde(default)]
+    pub file_exists: Option<PathBuf>,
+    /// The operating system (e.g. `linux`, `macos`, `windows`) or family
+    /// (`unix`, `windows`). Separate several by commas.
+    #[serde(default)]
+    pub os: Option<String>,
 }
 
-#[derive(Debug, serde::Deserialize)]
+/// An action to run
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct TomlActionDefinition {
+    /// The id of the action
     pub name: ActionId,
+    /// How to merge this action into the actions defined so far
     #[serde(default)]
     pub merge: MergeAction,
+    /// The id of an action defined before to copy all settings from. The
+    /// other keys of this action override the copied settings.
+    #[serde(default)]
+    pub extends: Option<ActionId>,
+    /// A short description of the action
     #[serde(default)]
     pub description: Option<String>,
+    /// Free-form tags like `["security", "slow"]`, select the actions with a
+    /// tag by `tag:security`
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    /// The command to run, `{{input}}` placeholders get replaced. Use
+    /// `command.linux`, `command.macos` and `command.windows` for operating
+    /// system specific commands and `command.default` for all others.
     #[serde(default)]
-    pub command: Option<String>,
+    pub command: Option<TomlCommand>,
+    /// Arguments to put in front of the command of the action changed or
+    /// extended, e.g. a wrapper like `nice`
+    #[serde(default)]
+    pub command_prepend: Option<String>,
+    /// Arguments to add to the end of the command of the action changed or
+    /// extended
+    #[serde(default)]
+    pub command_append: Option<String>,
+    /// An inline script to run instead of a command. Placeholders are passed
+    /// in `BEAUTYTIPS_<PLACEHOLDER>` environment variables.
+    #[serde(default)]
+    pub script: Option<String>,
+    /// Extra environment variables as `NAME=value`. Values may use the
+    /// placeholders of the command, e.g. `MYPYPATH={{top:directory}}/stubs`.
+    /// Several paths are separated like in `PATH`.
     #[serde(default)]
     pub environment: Option<Vec<String>>,
+    /// Extra environment variables as `NAME=value`, only set when running
+    /// with `--isolate`. Use these to keep tools run from hooks from sharing
+    /// state with those the developer runs, e.g.
+    /// `CARGO_TARGET_DIR={{project_cache_dir}}/target`.
+    #[serde(default)]
+    pub isolated_environment: Option<Vec<String>>,
+    /// Directories (relative to the repository root) to put in front of the
+    /// `PATH`, e.g. `["node_modules/.bin"]` to run project-local tools
+    #[serde(default)]
+    pub path_prepend: Option<Vec<PathBuf>>,
+    /// Find commands in the tool directories of the project first:
+    /// `node_modules/.bin` and `.venv/bin` (`.venv/Scripts` on Windows)
+    #[serde(default)]
+    pub project_tools: Option<bool>,
+    /// Do not run this action in parallel with other actions
     #[serde(default)]
     pub run_sequentially: Option<bool>,
+    /// The exit code the command returns on success
     #[serde(default)]
     pub exit_code: Option<i32>,
+    /// When to show the output of the command
     #[serde(default)]
     pub show_output: Option<OutputCondition>,
+    /// Glob patterns selecting the files for each input. `content` holds
+    /// literal strings or `/regex/` patterns the start of the `files` must
+    /// match, `kind` is `text` or `binary`
+    #[serde(default)]
+    pub inputs: Option<HashMap<String, TomlInputFilter>>,
+    /// Patterns to add to the `inputs` of the action changed or extended
+    #[serde(default)]
+    pub inputs_add: Option<HashMap<String, TomlInputFilter>>,
+    /// Patterns to remove from the `inputs` of the action changed or
+    /// extended
+    ///
+    /// Removing the last pattern selecting files of an input is an error.
+    #[serde(default)]
+    pub inputs_remove: Option<HashMap<String, TomlInputFilter>>,
+    /// Run the `diff-command` when the action fails and show its output
+    #[serde(default)]
+    pub diff_on_failure: Option<bool>,
+    /// A command printing the changes needed to make the action pass
+    #[serde(default)]
+    pub diff_command: Option<String>,
+    /// Run the action on a copy of the input files, or with a restricted
+    /// view of the file system (`true`)
+    #[serde(default)]
+    pub sandbox: Option<TomlSandbox>,
+    /// Apply changes done in the sandbox when the action succeeded (default),
+    /// or just report them
+    #[serde(default)]
+    pub sandbox_apply: Option<bool>,
+    /// The files the action may change: `true` for its input files or a list
+    /// of glob patterns. Actions using files another action changes do not
+    /// run at the same time.
+    #[serde(default)]
+    pub writes: Option<TomlWrites>,
+    /// Only run the action when all of these conditions are met, it is
+    /// reported as not applicable otherwise
+    #[serde(default)]
+    pub enabled_when: Option<TomlCondition>,
+    /// Run the `full-command` instead of the `command` when the action gets
+    /// more files than this
+    #[serde(default)]
+    pub full_run_threshold: Option<usize>,
+    /// A command working on the whole project, run instead of the `command`
+    /// above the `full-run-threshold`
+    #[serde(default)]
+    pub full_command: Option<String>,
+    /// Report an error instead of running the action on more files than
+    /// this, e.g. after importing vendored code. Does not apply when the
+    /// `full-command` runs.
+    #[serde(default)]
+    pub max_files: Option<usize>,
+    /// `file` to write the files into a temporary file passed in via
+    /// `{{files:listfile}}` instead of passing them as arguments, e.g. for
+    /// tools supporting `--files-from`
+    #[serde(default)]
+    pub files_via: Option<FilesVia>,
+    /// Run the invocations of the command (e.g. one per file for a
+    /// `{{files}}` placeholder) in parallel instead of one after the other
+    #[serde(default)]
+    pub parallel_invocations: Option<bool>,
+    /// Run the command once for each value of this input (e.g.
+    /// `cargo_targets`), in that directory or the directory containing it
+    #[serde(default)]
+    pub cwd_per_target: Option<String>,
+    /// A hint on how to fix failures of the action, e.g.
+    /// `run "cargo fmt" to fix`. Placeholders are expanded.
+    #[serde(default)]
+    pub hint: Option<String>,
+    /// Write the output of the command into this file (relative to the
+    /// repository root) instead of showing it, e.g. for CI artifacts
+    #[serde(default)]
+    pub stdout_file: Option<PathBuf>,
+    /// Write the error output of the command into this file (relative to the
+    /// repository root) instead of showing it
+    #[serde(default)]
+    pub stderr_file: Option<PathBuf>,
+    /// Run the action once for each combination of these values, e.g.
+    /// `matrix.python = ["3.10", "3.12"]`. The value is available as
+    /// `{{matrix.python}}` in the command, environment and hint. An empty
+    /// table removes the matrix.
+    #[serde(default)]
+    pub matrix: Option<BTreeMap<String, Vec<String>>>,
+    /// `error` to treat warnings of the action as errors, `info` to never
+    /// let the action fail while still showing its output
+    #[serde(default)]
+    pub severity: Option<Severity>,
+    /// Report warnings and errors of the action as allowed failures that
+    /// never fail the run, e.g. to introduce a new linter gradually
+    #[serde(default)]
+    pub allow_failure: Option<bool>,
+    /// `slow` by default, `fast` runs the action before all slow ones. The
+    /// slow actions are skipped if a fast action fails, unless running with
+    /// `--keep-going`.
     #[serde(default)]
-    pub inputs: Option<HashMap<String, Vec<String>>>,
+    pub phase: Option<Phase>,
+    /// Run the action before all others, e.g. to check that the code
+    /// compiles. The actions needing it, by `needs` or by being in a group
+    /// with it, are skipped when it fails, unless running with `--keep-going`.
+    #[serde(default)]
+    pub gate: Option<bool>,
+    /// The ids of the gates that need to pass for the action to run, e.g.
+    /// `["cargo/check"]`
+    #[serde(default)]
+    pub needs: Option<Vec<ActionId>>,
 }
 
 type ActionGroups = HashMap<ActionId, Vec<ActionSelector>>;
-type ActionMap = BTreeMap<ActionId, beautytips::ActionDefinition>;
+type ActionMap = BTreeMap<ActionId, Arc<beautytips::ActionDefinition>>;
+
+/// Settings for all actions run via a group
+#[derive(Debug, Default, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TomlGroupDefaults {
+    /// Extra environment variables as `NAME=value`. Variables set by the
+    /// action itself win.
+    #[serde(default)]
+    pub environment: Option<Vec<String>>,
+    /// When to show the output of the commands
+    #[serde(default)]
+    pub show_output: Option<OutputCondition>,
+    /// Do not run the actions in parallel with other actions
+    #[serde(default)]
+    pub run_sequentially: Option<bool>,
+    /// Cancel the commands of an action still running after this long, e.g.
+    /// `90s` or `10m`
+    #[serde(default)]
+    pub timeout: Option<String>,
+}
+
+/// Settings applied to the actions of a group, see `TomlGroupDefaults`
+#[derive(Clone, Debug, Default)]
+struct GroupDefaults {
+    environment: Vec<(String, String)>,
+    show_output: Option<beautytips::OutputCondition>,
+    run_sequentially: Option<bool>,
+    timeout: Option<std::time::Duration>,
+}
+
+impl GroupDefaults {
+    fn new(defaults: &TomlGroupDefaults) -> anyhow::Result<Self> {
+        Ok(Self {
+            environment: defaults
+                .environment
+                .as_deref()
+                .map(map_environment)
+                .unwrap_or_default(),
+            show_output: defaults.show_output.as_ref().map(match_output_condition),
+            run_sequentially: defaults.run_sequentially,
+            timeout: defaults
+                .timeout
+                .as_deref()
+                .map(crate::arg_parse::parse_timeout)
+                .transpose()
+                .context("Invalid timeout")?,
+        })
+    }
+
+    /// These defaults, with the settings they leave open taken from the
+    /// defaults of the `inner` group
+    ///
+    /// Environment variables of both are set, those of `self` win.
+    fn or(mut self, inner: &Self) -> Self {
+        self.environment = inner
+            .environment
+            .iter()
+            .chain(self.environment.iter())
+            .cloned()
+            .collect();
+        self.show_output = self.show_output.or_else(|| inner.show_output.clone());
+        self.run_sequentially = self.run_sequentially.or(inner.run_sequentially);
+        self.timeout = self.timeout.or(inner.timeout);
+        self
+    }
+
+    /// A copy of `action` with these defaults applied
+    fn apply(&self, action: &beautytips::ActionDefinition) -> beautytips::ActionDefinition {
+        let mut result = action.clone();
+        result.environment = self
+            .environment
+            .iter()
+            .chain(action.environment.iter())
+            .cloned()
+            .collect();
+        if let Some(show_output) = &self.show_output {
+            result.show_output = show_output.clone();
+        }
+        if let Some(run_sequentially) = self.run_sequentially {
+            result.run_sequentially = run_sequentially;
+        }
+        if self.timeout.is_some() {
+            result.timeout = self.timeout;
+        }
+        result
+    }
+}
 
-#[derive(Debug, serde::Deserialize)]
+/// A named group of actions
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct TomlActionGroup {
+    /// The id of the group
     pub name: ActionId,
+    /// How to merge this group into the groups defined so far. Only `add`
+    /// and `replace` are supported.
+    #[serde(default)]
+    pub merge: MergeAction,
+    /// Selectors for the actions (or other groups) in this group
     pub actions: Vec<ActionSelector>,
+    /// Settings for the actions when they are run via this group, without
+    /// changing the actions themselves
+    #[serde(default)]
+    pub defaults: Option<TomlGroupDefaults>,
+}
+
+/// Settings for the terminal output
+#[derive(Debug, Default, serde::Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TomlUi {
+    /// `colorblind` for colors that stay apart with the common color vision
+    /// deficiencies, `monochrome` for no colors and ASCII prefixes
+    #[serde(default)]
+    pub theme: Option<theme::ThemeName>,
+    /// Colors overriding those of the theme, by kind of line, e.g.
+    /// `error = "magenta"` or `ok = "#0072b2"`
+    #[serde(default)]
+    pub colors: BTreeMap<theme::Kind, String>,
+}
+
+/// The settings for the terminal output merged from all configuration files
+#[derive(Clone, Debug, Default)]
+pub struct Ui {
+    pub theme: theme::ThemeName,
+    pub colors: BTreeMap<theme::Kind, crossterm::style::Color>,
+}
+
+impl Ui {
+    /// The theme with the colors overridden
+    pub fn theme(&self) -> theme::Theme {
+        theme::Theme::new(self.theme).with_colors(&self.colors)
+    }
+
+    /// Apply the settings of `update` on top of `self`
+    fn merge(&mut self, update: TomlUi) -> anyhow::Result<()> {
+        if let Some(theme) = update.theme {
+            self.theme = theme;
+        }
+        for (kind, color) in update.colors {
+            self.colors.insert(kind, theme::parse_color(&color)?);
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, serde::Deserialize)]
+/// A beautytips configuration file
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 #[serde(deny_unknown_fields)]
 struct TomlConfiguration {
     #[serde(default)]
-    pub action_groups: Vec<TomlActionGroup>,
+    #[schemars(with = "Vec<TomlActionGroup>")]
+    pub action_groups: Vec<toml::Spanned<TomlActionGroup>>,
+    #[serde(default)]
+    #[schemars(with = "Vec<TomlActionDefinition>")]
+    pub actions: Vec<toml::Spanned<TomlActionDefinition>>,
+    #[serde(default)]
+    pub ui: Option<TomlUi>,
+    /// The reporters of `run` without `--output`, each getting all results,
+    /// e.g. `["github", "jsonl:results.jsonl"]`. Only one of them may print
+    /// to stdout.
     #[serde(default)]
-    pub actions: Vec<TomlActionDefinition>,
+    pub reporters: Option<Vec<String>>,
+}
+
+/// The JSON Schema describing the configuration file format
+pub fn json_schema() -> String {
+    let schema = schemars::schema_for!(TomlConfiguration);
+    serde_json::to_string_pretty(&schema).expect("The schema is serializable")
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct Configuration {
     pub action_groups: ActionGroups,
     pub action_map: ActionMap,
+    /// The defaults of all groups that have some
+    group_defaults: HashMap<ActionId, GroupDefaults>,
+    /// The ids of the actions in each group with defaults
+    group_members: HashMap<ActionId, HashSet<ActionId>>,
+    /// Where each action and group was defined
+    origins: HashMap<ActionId, String>,
+    /// Where each action was changed after its definition
+    changed_in: HashMap<ActionId, Vec<String>>,
+    /// The operating systems each action is limited to
+    os_limits: HashMap<ActionId, OsLimit>,
+    /// The settings for the terminal output
+    pub ui: Ui,
+    /// The reporters to use, the output format is detected when empty
+    pub reporters: Vec<reporter::ReporterSpec>,
+}
+
+/// The operating systems an action is enabled on
+#[derive(Clone, Debug, Default)]
+struct OsLimit {
+    /// Set in `enabled-when.os`
+    user: Option<String>,
+    /// Derived from a command without variant for this operating system
+    command: Option<String>,
+}
+
+impl OsLimit {
+    /// The `os` condition of the action: Users can override the derived one
+    fn condition(&self) -> Option<String> {
+        self.user.clone().or_else(|| self.command.clone())
+    }
 }
 
 #[derive(Debug)]
 pub struct ConfigurationSource {
-    pub action_groups: Vec<TomlActionGroup>,
-    pub actions: Vec<TomlActionDefinition>,
+    pub action_groups: Vec<toml::Spanned<TomlActionGroup>>,
+    pub actions: Vec<toml::Spanned<TomlActionDefinition>>,
+    pub ui: Option<TomlUi>,
+    pub reporters: Option<Vec<String>>,
+    /// The file the configuration was read from (if any)
+    file: Option<PathBuf>,
+    /// The TOML text, used to locate errors
+    source: String,
 }
 
 impl ConfigurationSource {
-    fn from_string(value: &str) -> anyhow::Result<Self> {
+    pub(crate) fn from_string(value: &str) -> Result<Self, ConfigError> {
+        Self::parse(value, None)
+    }
+
+    fn parse(value: &str, file: Option<&Path>) -> Result<Self, ConfigError> {
         let mut toml_config: TomlConfiguration =
-            toml::from_str(value).context("Failed to parse toml")?;
+            toml::from_str(value).map_err(|e| ConfigError::from_toml(value, file, &e))?;
 
         let actions = std::mem::take(&mut toml_config.actions);
         let action_groups = std::mem::take(&mut toml_config.action_groups);
@@ -331,25 +704,89 @@ impl ConfigurationSource {
         Ok(Self {
             action_groups,
             actions,
+            ui: toml_config.ui,
+            reporters: toml_config.reporters,
+            file: file.map(Path::to_path_buf),
+            source: value.to_string(),
         })
     }
 
     fn from_path(path: &Path) -> anyhow::Result<Self> {
-        let config_data =
-            std::fs::read_to_string(path).context(format!("Failed to read toml file {path:?}"))?;
+        let config_data = std::fs::read_to_string(path)
+            .context(format!("Failed to read toml file {}", path.display()))?;
+
+        Ok(Self::parse(config_data.as_str(), Some(path))?)
+    }
+
+    fn from_builtin(name: &str, value: &str) -> Result<Self, ConfigError> {
+        Self::parse(value, Some(&PathBuf::from(format!("<builtin {name}>"))))
+    }
+
+    /// A description of where this configuration comes from
+    fn origin(&self) -> String {
+        self.file.as_ref().map_or_else(
+            || "<configuration>".to_string(),
+            |f| f.display().to_string(),
+        )
+    }
 
-        Self::from_string(config_data.as_str()).context("Failed to parse toml string")
+    /// An error about the `kind` (action or group) `id` defined at `span`
+    fn error_at(
+        &self,
+        span: std::ops::Range<usize>,
+        kind: &str,
+        id: &ActionId,
+        error: &anyhow::Error,
+    ) -> ConfigError {
+        ConfigError::new(
+            &self.source,
+            self.file.as_deref(),
+            Some(span),
+            Some(format!("{kind} `{id}`")),
+            format!("{error:#}"),
+        )
     }
 }
 
 fn remove_action(action: &TomlActionDefinition, action_map: &mut ActionMap) -> anyhow::Result<()> {
     let id = action.name.clone();
-    if action.description.is_some()
+    if action.extends.is_some()
+        || action.description.is_some()
+        || action.tags.is_some()
         || action.show_output.is_some()
         || action.run_sequentially.is_some()
         || action.command.is_some()
+        || action.command_prepend.is_some()
+        || action.command_append.is_some()
+        || action.script.is_some()
+        || action.isolated_environment.is_some()
+        || action.path_prepend.is_some()
+        || action.project_tools.is_some()
         || action.exit_code.is_some()
         || action.inputs.is_some()
+        || action.inputs_add.is_some()
+        || action.inputs_remove.is_some()
+        || action.diff_on_failure.is_some()
+        || action.diff_command.is_some()
+        || action.sandbox.is_some()
+        || action.sandbox_apply.is_some()
+        || action.writes.is_some()
+        || action.parallel_invocations.is_some()
+        || action.cwd_per_target.is_some()
+        || action.hint.is_some()
+        || action.stdout_file.is_some()
+        || action.stderr_file.is_some()
+        || action.matrix.is_some()
+        || action.severity.is_some()
+        || action.allow_failure.is_some()
+        || action.phase.is_some()
+        || action.gate.is_some()
+        || action.needs.is_some()
+        || action.max_files.is_some()
+        || action.full_run_threshold.is_some()
+        || action.full_command.is_some()
+        || action.files_via.is_some()
+        || action.enabled_when.is_some()
     {
         return Err(anyhow::anyhow!(format!(
             "{id} is removing an action, but has extra keys set"
@@ -371,33 +808,146 @@ fn match_output_condition(output: &OutputCondition) -> beautytips::OutputConditi
         OutputCondition::Success => beautytips::OutputCondition::Success,
         OutputCondition::Failure => beautytips::OutputCondition::Failure,
         OutputCondition::Always => beautytips::OutputCondition::Always,
+        OutputCondition::Summary => beautytips::OutputCondition::Summary,
+    }
+}
+
+fn match_sandbox_mode(sandbox: &TomlSandbox) -> Option<beautytips::SandboxMode> {
+    match sandbox {
+        TomlSandbox::Enabled(false) => None,
+        TomlSandbox::Enabled(true) | TomlSandbox::Mode(SandboxMode::Restricted) => {
+            Some(beautytips::SandboxMode::Restricted)
+        }
+        TomlSandbox::Mode(SandboxMode::Copy) => Some(beautytips::SandboxMode::Copy),
+        TomlSandbox::Mode(SandboxMode::Worktree) => Some(beautytips::SandboxMode::Worktree),
+    }
+}
+
+fn match_files_via(files_via: &FilesVia) -> beautytips::FilesVia {
+    match files_via {
+        FilesVia::Arguments => beautytips::FilesVia::Arguments,
+        FilesVia::File => beautytips::FilesVia::File,
+    }
+}
+
+fn match_phase(phase: &Phase) -> beautytips::Phase {
+    match phase {
+        Phase::Fast => beautytips::Phase::Fast,
+        Phase::Slow => beautytips::Phase::Slow,
+    }
+}
+
+fn match_severity(severity: &Severity) -> beautytips::Severity {
+    match severity {
+        Severity::Error => beautytips::Severity::Error,
+        Severity::Warning => beautytips::Severity::Warning,
+        Severity::Info => beautytips::Severity::Info,
+    }
+}
+
+fn map_writes(writes: &TomlWrites) -> anyhow::Result<beautytips::Writes> {
+    Ok(match writes {
+        TomlWrites::InputFiles(false) => beautytips::Writes::Nothing,
+        TomlWrites::InputFiles(true) => beautytips::Writes::InputFiles,
+        TomlWrites::Patterns(patterns) => beautytips::Writes::Patterns(
+            patterns
+                .iter()
+                .map(|p| glob::Pattern::new(p).context(format!("Failed to parse glob '{p}'")))
+                .collect::<anyhow::Result<Vec<_>>>()?,
+        ),
+    })
+}
+
+fn map_condition(condition: TomlCondition) -> beautytips::Condition {
+    beautytips::Condition {
+        env: condition.env,
+        file_exists: condition.file_exists,
+        os: condition.os,
     }
 }
 
+fn map_inputs(inputs: HashMap<String, TomlInputFilter>) -> HashMap<String, Vec<String>> {
+    inputs
+        .into_iter()
+        .map(|(k, v)| match v {
+            TomlInputFilter::One(value) => (k, vec![value]),
+            TomlInputFilter::Many(values) => (k, values),
+        })
+        .collect()
+}
+
 fn map_environment(environment: &[String]) -> Vec<(String, String)> {
     environment
         .iter()
         .map(|k| {
-            k.split_once('=')
-                .map(|(k, v)| (k.to_string(), v.to_string()))
-                .unwrap_or_else(|| (k.to_string(), String::new()))
+            k.split_once('=').map_or_else(
+                || (k.clone(), String::new()),
+                |(k, v)| (k.to_string(), v.to_string()),
+            )
         })
         .collect()
 }
 
+/// Make sure at most one of `command` and `script` is set
+fn check_command_or_script(action: &TomlActionDefinition) -> anyhow::Result<()> {
+    if action.command.is_some() && action.script.is_some() {
+        return Err(anyhow::anyhow!(format!(
+            "{} has both a command and a script",
+            action.name
+        )));
+    }
+    Ok(())
+}
+
 fn change_action(
     update: &mut TomlActionDefinition,
     action_map: &mut ActionMap,
+    os_limits: &mut HashMap<ActionId, OsLimit>,
 ) -> anyhow::Result<()> {
     let id = update.name.clone();
 
-    if update.description.is_none()
+    if update.extends.is_some() {
+        return Err(anyhow::anyhow!(format!(
+            "{id} is changing an existing action, it can not extend another one"
+        )));
+    }
+    if update.description.is_none()
+        && update.tags.is_none()
         && update.show_output.is_none()
         && update.run_sequentially.is_none()
         && update.command.is_none()
+        && update.command_prepend.is_none()
+        && update.command_append.is_none()
+        && update.script.is_none()
         && update.environment.is_none()
+        && update.isolated_environment.is_none()
+        && update.path_prepend.is_none()
+        && update.project_tools.is_none()
         && update.exit_code.is_none()
         && update.inputs.is_none()
+        && update.inputs_add.is_none()
+        && update.inputs_remove.is_none()
+        && update.diff_on_failure.is_none()
+        && update.diff_command.is_none()
+        && update.sandbox.is_none()
+        && update.sandbox_apply.is_none()
+        && update.writes.is_none()
+        && update.enabled_when.is_none()
+        && update.full_run_threshold.is_none()
+        && update.max_files.is_none()
+        && update.full_command.is_none()
+        && update.files_via.is_none()
+        && update.parallel_invocations.is_none()
+        && update.cwd_per_target.is_none()
+        && update.hint.is_none()
+        && update.stdout_file.is_none()
+        && update.stderr_file.is_none()
+        && update.matrix.is_none()
+        && update.severity.is_none()
+        && update.allow_failure.is_none()
+        && update.phase.is_none()
+        && update.gate.is_none()
+        && update.needs.is_none()
     {
         return Err(anyhow::anyhow!(format!(
             "{id} is changing an existing action, but has no extra keys set"
@@ -409,111 +959,520 @@ fn change_action(
         )));
     };
 
+    apply_changes(update, Arc::make_mut(ad), os_limits.entry(id).or_default())
+}
+
+/// Put the `command-prepend` and `command-append` of `update` around the
+/// command of `ad`
+fn extend_command(
+    update: &TomlActionDefinition,
+    ad: &mut beautytips::ActionDefinition,
+) -> anyhow::Result<()> {
+    if update.command_prepend.is_none() && update.command_append.is_none() {
+        return Ok(());
+    }
+    if ad.script.is_some() {
+        return Err(anyhow::anyhow!(format!(
+            "{} has a script, there is no command to extend",
+            update.name
+        )));
+    }
+    // There is no command on operating systems the action is not enabled on
+    if ad.command.is_empty() {
+        return Ok(());
+    }
+
+    if let Some(prepend) = &update.command_prepend {
+        let mut command = shell_words::split(prepend)
+            .context(format!("Failed to parse command-prepend '{prepend}'"))?;
+        command.append(&mut ad.command);
+        ad.command = command;
+    }
+    if let Some(append) = &update.command_append {
+        ad.command.extend(
+            shell_words::split(append)
+                .context(format!("Failed to parse command-append '{append}'"))?,
+        );
+    }
+    Ok(())
+}
+
+/// Override the settings of `ad` on reporting results with the keys set in
+/// `update`
+fn apply_result_changes(update: &mut TomlActionDefinition, ad: &mut beautytips::ActionDefinition) {
+    if let Some(hint) = update.hint.take() {
+        // An empty hint removes the hint again
+        ad.hint = Some(hint).filter(|h| !h.is_empty());
+    }
+    // An empty path shows the output again
+    if update.stdout_file.is_some() {
+        ad.stdout_file = take_output_file(&mut update.stdout_file);
+    }
+    if update.stderr_file.is_some() {
+        ad.stderr_file = take_output_file(&mut update.stderr_file);
+    }
+    if let Some(severity) = &update.severity {
+        ad.severity = match_severity(severity);
+    }
+    if let Some(allow_failure) = update.allow_failure {
+        ad.allow_failure = allow_failure;
+    }
+}
+
+/// Override the settings of `ad` with all keys set in `update`
+#[allow(clippy::too_many_lines)]
+fn apply_changes(
+    update: &mut TomlActionDefinition,
+    ad: &mut beautytips::ActionDefinition,
+    os_limit: &mut OsLimit,
+) -> anyhow::Result<()> {
+    let id = update.name.clone();
+
     if let Some(description) = std::mem::take(&mut update.description) {
         ad.description = description;
     }
+    if let Some(tags) = update.tags.take() {
+        ad.tags = tags;
+    }
     if let Some(show_output) = std::mem::take(&mut update.show_output) {
         ad.show_output = match_output_condition(&show_output);
     }
     if let Some(run_sequential) = std::mem::take(&mut update.run_sequentially) {
         ad.run_sequentially = run_sequential;
     }
+    if let Some(phase) = &update.phase {
+        ad.phase = match_phase(phase);
+    }
+    if let Some(gate) = update.gate {
+        ad.gate = gate;
+    }
+    if let Some(needs) = update.needs.take() {
+        ad.needs = needs;
+    }
+    check_command_or_script(update)?;
     if let Some(command) = &update.command {
-        ad.command = map_command(command)?;
+        (ad.command, os_limit.command) = map_os_command(command, std::env::consts::OS)?;
+        ad.script = None;
+    }
+    if let Some(script) = update.script.take() {
+        ad.command = vec![];
+        ad.script = Some(script);
+        os_limit.command = None;
     }
+    extend_command(update, ad)?;
     if let Some(env) = update.environment.take() {
         ad.environment = map_environment(&env);
     }
+    if let Some(env) = update.isolated_environment.take() {
+        ad.isolated_environment = map_environment(&env);
+    }
+    if let Some(path_prepend) = update.path_prepend.take() {
+        ad.path_prepend = path_prepend;
+    }
+    if let Some(project_tools) = update.project_tools {
+        ad.project_tools = project_tools;
+    }
     if let Some(exit_code) = &update.exit_code {
         ad.expected_exit_code = *exit_code;
     }
     if let Some(inputs) = update.inputs.take() {
         ad.input_filters
-            .update_from(inputs)
+            .update_from(map_inputs(inputs))
             .context(format!("While changing {id}"))?;
     }
+    if let Some(inputs) = update.inputs_add.take() {
+        ad.input_filters
+            .add_patterns(map_inputs(inputs))
+            .context(format!("While adding inputs to {id}"))?;
+    }
+    if let Some(inputs) = update.inputs_remove.take() {
+        ad.input_filters
+            .remove_patterns(map_inputs(inputs))
+            .context(format!("While removing inputs from {id}"))?;
+    }
+    if let Some(diff_on_failure) = update.diff_on_failure {
+        ad.diff_on_failure = diff_on_failure;
+    }
+    if let Some(diff_command) = &update.diff_command {
+        ad.diff_command = map_command(diff_command)?;
+    }
+    if let Some(sandbox) = &update.sandbox {
+        ad.sandbox = match_sandbox_mode(sandbox);
+    }
+    if let Some(sandbox_apply) = update.sandbox_apply {
+        ad.sandbox_apply = sandbox_apply;
+    }
+    if let Some(writes) = &update.writes {
+        ad.writes = map_writes(writes).context(format!("While changing {id}"))?;
+    }
+    if let Some(enabled_when) = update.enabled_when.take() {
+        ad.enabled_when = map_condition(enabled_when);
+        os_limit.user = ad.enabled_when.os.take();
+    }
+    ad.enabled_when.os = os_limit.condition();
+    if let Some(full_run_threshold) = update.full_run_threshold {
+        ad.full_run_threshold = Some(full_run_threshold);
+    }
+    if let Some(max_files) = update.max_files {
+        ad.max_files = Some(max_files);
+    }
+    if let Some(full_command) = &update.full_command {
+        ad.full_command = map_command(full_command)?;
+    }
+    if let Some(files_via) = &update.files_via {
+        ad.files_via = match_files_via(files_via);
+    }
+    if let Some(parallel_invocations) = update.parallel_invocations {
+        ad.parallel_invocations = parallel_invocations;
+    }
+    if let Some(cwd_per_target) = update.cwd_per_target.take() {
+        // An empty input name runs the action in the root directory again
+        ad.cwd_per_target = Some(cwd_per_target).filter(|i| !i.is_empty());
+    }
+    if let Some(matrix) = update.matrix.take() {
+        ad.matrix = matrix.into_iter().collect();
+    }
+    apply_result_changes(update, ad);
+    check_full_run(ad)?;
+    ad.validate()?;
+
+    Ok(())
+}
+
+/// Add a new action copying all settings `update` does not override from
+/// the existing action `base`
+fn extend_action(
+    update: &mut TomlActionDefinition,
+    base: &ActionId,
+    action_map: &mut ActionMap,
+    os_limits: &mut HashMap<ActionId, OsLimit>,
+) -> anyhow::Result<()> {
+    let id = update.name.clone();
+    let Some(base_action) = action_map.get(base) else {
+        return Err(anyhow::anyhow!(format!(
+            "{id} extends {base}, which is not defined (yet)"
+        )));
+    };
+
+    let mut ad = beautytips::ActionDefinition::clone(base_action);
+    ad.id = id.clone();
+    let mut os_limit = os_limits.get(base).cloned().unwrap_or_default();
+    apply_changes(update, &mut ad, &mut os_limit)?;
+
+    action_map.insert(id.clone(), Arc::new(ad));
+    os_limits.insert(id, os_limit);
 
     Ok(())
 }
 
-fn add_action(update: &mut TomlActionDefinition, action_map: &mut ActionMap) -> anyhow::Result<()> {
+/// An empty output file path unsets the file
+fn take_output_file(file: &mut Option<PathBuf>) -> Option<PathBuf> {
+    file.take().filter(|f| !f.as_os_str().is_empty())
+}
+
+#[allow(clippy::too_many_lines)]
+fn add_action(
+    update: &mut TomlActionDefinition,
+    action_map: &mut ActionMap,
+    os_limits: &mut HashMap<ActionId, OsLimit>,
+) -> anyhow::Result<()> {
     let id = update.name.clone();
 
-    let Some(command) = &update.command else {
+    if let Some(base) = update.extends.take() {
+        return extend_action(update, &base, action_map, os_limits);
+    }
+
+    check_command_or_script(update)?;
+    if update.command_prepend.is_some()
+        || update.command_append.is_some()
+        || update.inputs_add.is_some()
+        || update.inputs_remove.is_some()
+    {
+        return Err(anyhow::anyhow!(format!(
+            "{id} is a new action, set its command and inputs directly or extend another action"
+        )));
+    }
+    if update.command.is_none() && update.script.is_none() {
         return Err(anyhow::anyhow!(format!(
-            "Can not add {}: No command",
+            "Can not add {}: No command or script",
             update.name
         )));
-    };
+    }
 
     let description = std::mem::take(&mut update.description).unwrap_or_default();
     let show_output =
         match_output_condition(&std::mem::take(&mut update.show_output).unwrap_or_default());
-    let command = map_command(command).context("Processing command of {qid}")?;
+    let (command, only_on) = match &update.command {
+        Some(command) => map_os_command(command, std::env::consts::OS)
+            .context(format!("Processing command of {id}"))?,
+        None => (vec![], None),
+    };
+    let script = update.script.take();
     let run_sequentially = std::mem::take(&mut update.run_sequentially).unwrap_or(true);
     let expected_exit_code = update.exit_code.unwrap_or(0);
     let input_filters = if let Some(inputs) = update.inputs.take() {
-        InputFilters::try_from(inputs)?
+        InputFilters::try_from(map_inputs(inputs))?
     } else {
         beautytips::InputFilters::default()
     };
     let environment = if let Some(env) = &update.environment {
-        map_environment(&env)
+        map_environment(env)
+    } else {
+        vec![]
+    };
+    let diff_on_failure = update.diff_on_failure.unwrap_or_default();
+    let sandbox = update.sandbox.as_ref().and_then(match_sandbox_mode);
+    let sandbox_apply = update.sandbox_apply.unwrap_or(true);
+    let writes = match &update.writes {
+        Some(writes) => map_writes(writes).context(format!("Processing writes of {id}"))?,
+        None => beautytips::Writes::Nothing,
+    };
+    let mut enabled_when = update
+        .enabled_when
+        .take()
+        .map(map_condition)
+        .unwrap_or_default();
+    // Actions without a command for this OS are not applicable here
+    let os_limit = OsLimit {
+        user: enabled_when.os.take(),
+        command: only_on,
+    };
+    enabled_when.os = os_limit.condition();
+    let diff_command = if let Some(diff_command) = &update.diff_command {
+        map_command(diff_command).context(format!("Processing diff command of {id}"))?
+    } else {
+        vec![]
+    };
+    let full_command = if let Some(full_command) = &update.full_command {
+        map_command(full_command).context(format!("Processing full command of {id}"))?
     } else {
         vec![]
     };
 
     let ad = beautytips::ActionDefinition {
-        id: update.name.to_string(),
+        id: update.name.clone(),
         show_output,
         run_sequentially,
         description,
+        tags: update.tags.take().unwrap_or_default(),
         command,
+        script,
         environment,
+        isolated_environment: update
+            .isolated_environment
+            .as_deref()
+            .map(map_environment)
+            .unwrap_or_default(),
+        path_prepend: update.path_prepend.take().unwrap_or_default(),
+        project_tools: update.project_tools.unwrap_or_default(),
         expected_exit_code,
         input_filters,
+        diff_on_failure,
+        diff_command,
+        sandbox,
+        sandbox_apply,
+        writes,
+        enabled_when,
+        full_run_threshold: update.full_run_threshold,
+        max_files: update.max_files,
+        full_command,
+        files_via: update
+            .files_via
+            .as_ref()
+            .map(match_files_via)
+            .unwrap_or_default(),
+        parallel_invocations: update.parallel_invocations.unwrap_or_default(),
+        cwd_per_target: update.cwd_per_target.take().filter(|i| !i.is_empty()),
+        hint: update.hint.take().filter(|h| !h.is_empty()),
+        stdout_file: take_output_file(&mut update.stdout_file),
+        stderr_file: take_output_file(&mut update.stderr_file),
+        matrix: update
+            .matrix
+            .take()
+            .map(|m| m.into_iter().collect())
+            .unwrap_or_default(),
+        variant: None,
+        severity: update
+            .severity
+            .as_ref()
+            .map(match_severity)
+            .unwrap_or_default(),
+        allow_failure: update.allow_failure.unwrap_or_default(),
+        phase: update.phase.as_ref().map(match_phase).unwrap_or_default(),
+        gate: update.gate.unwrap_or_default(),
+        needs: update.needs.take().unwrap_or_default(),
+        timeout: None,
     };
+    check_full_run(&ad)?;
+    ad.validate()?;
+
+    action_map.insert(id.clone(), Arc::new(ad));
+    os_limits.insert(id, os_limit);
 
-    let entry = action_map.entry(id);
-    if matches!(entry, std::collections::btree_map::Entry::Occupied(_)) {
+    Ok(())
+}
+
+/// Make sure an action with a full run threshold has a command to run then
+fn check_full_run(ad: &beautytips::ActionDefinition) -> anyhow::Result<()> {
+    if ad.full_run_threshold.is_some() && ad.full_command.is_empty() {
         return Err(anyhow::anyhow!(format!(
-            "{} already exists, can not add",
-            entry.key()
+            "{} sets a full-run-threshold, but has no full-command to run",
+            ad.id
         )));
-    };
+    }
+    Ok(())
+}
 
-    entry.or_insert(ad);
+/// Make sure `id` is not defined yet
+fn check_undefined(id: &ActionId, origins: &HashMap<ActionId, String>) -> anyhow::Result<()> {
+    match origins.get(id) {
+        Some(origin) => Err(anyhow::anyhow!(format!(
+            "{id} is already defined in {origin}, set `merge = \"replace\"` to replace it"
+        ))),
+        None => Ok(()),
+    }
+}
 
+/// Make sure all actions only need gates that are defined (yet)
+fn check_needs(action_map: &ActionMap) -> anyhow::Result<()> {
+    let mut ids = action_map.keys().collect::<Vec<_>>();
+    ids.sort();
+    for id in ids {
+        for need in &action_map[id].needs {
+            match action_map.get(need) {
+                Some(gate) if gate.gate => {}
+                Some(_) => {
+                    return Err(anyhow::anyhow!(format!(
+                        "{id} needs {need}, which is no gate, set `gate = true` on it"
+                    )))
+                }
+                None => {
+                    return Err(anyhow::anyhow!(format!(
+                        "{id} needs {need}, which is not defined (yet)"
+                    )))
+                }
+            }
+        }
+    }
     Ok(())
 }
 
 fn merge_actions(
     mut action_map: ActionMap,
+    origins: &mut HashMap<ActionId, String>,
+    changed_in: &mut HashMap<ActionId, Vec<String>>,
+    os_limits: &mut HashMap<ActionId, OsLimit>,
     other: &mut ConfigurationSource,
 ) -> anyhow::Result<ActionMap> {
-    for mut action in other.actions.drain(..) {
+    let origin = other.origin();
+    for action in std::mem::take(&mut other.actions) {
+        let span = action.span();
+        let mut action = action.into_inner();
+        let id = action.name.clone();
         match action.merge {
-            MergeAction::Remove => remove_action(&action, &mut action_map)?,
+            MergeAction::Remove => remove_action(&action, &mut action_map).map(|()| {
+                origins.remove(&id);
+                changed_in.remove(&id);
+                os_limits.remove(&id);
+            }),
             MergeAction::Change => {
-                change_action(&mut action, &mut action_map)?;
+                change_action(&mut action, &mut action_map, os_limits).map(|()| {
+                    changed_in
+                        .entry(id.clone())
+                        .or_default()
+                        .push(origin.clone());
+                })
             }
-            MergeAction::Add => {
-                add_action(&mut action, &mut action_map)?;
+            MergeAction::Replace => {
+                if action_map.contains_key(&id) {
+                    add_action(&mut action, &mut action_map, os_limits).map(|()| {
+                        origins.insert(id.clone(), origin.clone());
+                        changed_in.remove(&id);
+                    })
+                } else {
+                    Err(anyhow::anyhow!(format!(
+                        "{id} is replacing an action that does not exist"
+                    )))
+                }
             }
+            MergeAction::Add => check_undefined(&id, origins)
+                .and_then(|()| add_action(&mut action, &mut action_map, os_limits))
+                .map(|()| {
+                    origins.insert(id.clone(), origin.clone());
+                }),
         }
+        .map_err(|e| other.error_at(span, "action", &id, &e))?;
     }
     Ok(action_map)
 }
 
-fn add_new_action_groups(
+fn merge_action_groups(
     mut action_groups: ActionGroups,
+    group_defaults: &mut HashMap<ActionId, GroupDefaults>,
+    origins: &mut HashMap<ActionId, String>,
     other: &mut ConfigurationSource,
-) -> ActionGroups {
-    for ag in other.action_groups.drain(..) {
+) -> anyhow::Result<ActionGroups> {
+    let origin = other.origin();
+    for ag in std::mem::take(&mut other.action_groups) {
+        let span = ag.span();
+        let ag = ag.into_inner();
+        match ag.merge {
+            MergeAction::Add => check_undefined(&ag.name, origins),
+            MergeAction::Replace => {
+                if action_groups.contains_key(&ag.name) {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!(format!(
+                        "{} is replacing a group that does not exist",
+                        ag.name
+                    )))
+                }
+            }
+            MergeAction::Remove | MergeAction::Change => {
+                Err(anyhow::anyhow!("Groups can only be added or replaced"))
+            }
+        }
+        .map_err(|e| other.error_at(span.clone(), "group", &ag.name, &e))?;
+
+        origins.insert(ag.name.clone(), origin.clone());
+        match &ag.defaults {
+            Some(defaults) => {
+                let defaults = GroupDefaults::new(defaults)
+                    .map_err(|e| other.error_at(span, "group", &ag.name, &e))?;
+                group_defaults.insert(ag.name.clone(), defaults);
+            }
+            None => {
+                group_defaults.remove(&ag.name);
+            }
+        }
         action_groups.insert(ag.name, ag.actions);
     }
 
-    action_groups
+    Ok(action_groups)
+}
+
+/// Pick the command to run on `os` out of `toml_command`
+///
+/// If there is no command for `os`, the command is empty and the operating
+/// systems the action has commands for are returned as well.
+fn map_os_command(
+    toml_command: &TomlCommand,
+    os: &str,
+) -> anyhow::Result<(Vec<String>, Option<String>)> {
+    match toml_command {
+        TomlCommand::Generic(command) => Ok((map_command(command)?, None)),
+        TomlCommand::PerOs(commands) => {
+            if let Some(command) = commands.for_os(os) {
+                return Ok((map_command(command)?, None));
+            }
+            let operating_systems = commands.operating_systems();
+            if operating_systems.is_empty() {
+                Err(anyhow::anyhow!("No command for any operating system"))
+            } else {
+                Ok((vec![], Some(operating_systems.join(","))))
+            }
+        }
+    }
 }
 
 fn map_command(toml_command: &str) -> anyhow::Result<Vec<String>> {
@@ -534,24 +1493,217 @@ fn map_command(toml_command: &str) -> anyhow::Result<Vec<String>> {
 
 impl Configuration {
     /// Merge `other` onto the base of `self`
+    ///
+    /// Actions and groups must not be defined twice, unless they are
+    /// explicitly replaced with `merge = "replace"`.
     pub fn merge(mut self, mut other: ConfigurationSource) -> anyhow::Result<Self> {
-        let action_map = merge_actions(std::mem::take(&mut self.action_map), &mut other)?;
-
-        let action_groups =
-            add_new_action_groups(std::mem::take(&mut self.action_groups), &mut other);
+        let mut origins = std::mem::take(&mut self.origins);
+        let mut changed_in = std::mem::take(&mut self.changed_in);
+        let mut os_limits = std::mem::take(&mut self.os_limits);
+        let action_map = merge_actions(
+            std::mem::take(&mut self.action_map),
+            &mut origins,
+            &mut changed_in,
+            &mut os_limits,
+            &mut other,
+        )?;
+        check_needs(&action_map).context(format!("Invalid needs in {}", other.origin()))?;
+
+        let mut group_defaults = std::mem::take(&mut self.group_defaults);
+        let action_groups = merge_action_groups(
+            std::mem::take(&mut self.action_groups),
+            &mut group_defaults,
+            &mut origins,
+            &mut other,
+        )?;
+
+        // Actions and groups may have changed, so look up the members again
+        let group_members = group_defaults
+            .keys()
+            .map(|name| {
+                let selectors = find_selectors(
+                    &action_groups,
+                    &ActionSelectors(HashSet::from([ActionSelector::from_id(name)])),
+                );
+                let members = find_actions(&action_map, &selectors)
+                    .into_iter()
+                    .map(|ad| ad.id.clone())
+                    .collect();
+                (name.clone(), members)
+            })
+            .collect();
+
+        let mut ui = std::mem::take(&mut self.ui);
+        if let Some(update) = other.ui.take() {
+            let origin = other.origin();
+            ui.merge(update)
+                .context(format!("Invalid ui settings in {origin}"))?;
+        }
+        let reporters = match other.reporters.take() {
+            Some(reporters) => reporters
+                .iter()
+                .map(|r| r.parse())
+                .collect::<anyhow::Result<Vec<_>>>()
+                .context(format!("Invalid reporters in {}", other.origin()))?,
+            None => std::mem::take(&mut self.reporters),
+        };
 
         Ok(Self {
             action_groups,
             action_map,
+            group_defaults,
+            group_members,
+            origins,
+            changed_in,
+            os_limits,
+            ui,
+            reporters,
         })
     }
 
-    pub fn actions<'a>(
-        &'a self,
-        selectors: &ActionSelectors,
-    ) -> beautytips::ActionDefinitionIterator<'a> {
+    /// The groups with defaults selected by `selectors`, directly or via
+    /// other groups
+    ///
+    /// Groups selected directly come first, then those selected via one other
+    /// group and so on. Groups on the same level are sorted by name.
+    fn groups_with_defaults(&self, selectors: &ActionSelectors) -> Vec<&ActionId> {
+        let mut result = Vec::new();
+        let mut level = selectors.clone();
+        let mut seen = HashSet::new();
+        while !level.is_empty() {
+            let mut groups = self
+                .action_groups
+                .keys()
+                .filter(|g| level.matches(g) && seen.insert(*g))
+                .collect::<Vec<_>>();
+            groups.sort();
+            level = ActionSelectors(
+                groups
+                    .iter()
+                    .flat_map(|g| self.action_groups[*g].iter().cloned())
+                    .collect(),
+            );
+            result.extend(
+                groups
+                    .into_iter()
+                    .filter(|g| self.group_members.contains_key(*g)),
+            );
+        }
+        result
+    }
+
+    pub fn actions(&self, selectors: &ActionSelectors) -> beautytips::ActionSet {
+        // An action selected via groups with defaults uses each setting of the
+        // outermost group setting it
+        let groups = self.groups_with_defaults(selectors);
         let selectors = find_selectors(&self.action_groups, selectors);
-        beautytips::ActionDefinitionIterator::new(find_actions(&self.action_map, &selectors))
+        let actions = find_actions(&self.action_map, &selectors)
+            .into_iter()
+            .map(|ad| {
+                groups
+                    .iter()
+                    .filter(|g| self.group_members[**g].contains(&ad.id))
+                    .map(|g| &self.group_defaults[*g])
+                    .fold(None, |outer: Option<GroupDefaults>, inner| {
+                        Some(match outer {
+                            Some(outer) => outer.or(inner),
+                            None => inner.clone(),
+                        })
+                    })
+                    .map_or_else(|| ad.clone(), |defaults| Arc::new(defaults.apply(ad)))
+            })
+            .collect();
+        self.add_group_gates(actions)
+    }
+
+    /// Make the actions in `actions` need the gates in `actions` that are in
+    /// a group with them
+    ///
+    /// Only the actions a group selects directly count, not those of the
+    /// groups in it.
+    fn add_group_gates(&self, actions: beautytips::ActionSet) -> beautytips::ActionSet {
+        let mut needs: HashMap<ActionId, Vec<ActionId>> = HashMap::new();
+        for group_selectors in self.action_groups.values() {
+            let selectors = ActionSelectors(group_selectors.iter().cloned().collect());
+            let members = actions.filter(|ad| selectors.matches_action(ad));
+            for gate in members.iter().filter(|ad| ad.gate) {
+                for member in members.iter().filter(|ad| !ad.gate) {
+                    let member_needs = needs.entry(member.id.clone()).or_default();
+                    if !member.needs.contains(&gate.id) && !member_needs.contains(&gate.id) {
+                        member_needs.push(gate.id.clone());
+                    }
+                }
+            }
+        }
+
+        actions
+            .into_iter()
+            .map(|ad| match needs.remove(&ad.id) {
+                Some(gates) => {
+                    let mut ad = ad.as_ref().clone();
+                    ad.needs.extend(gates);
+                    Arc::new(ad)
+                }
+                None => ad,
+            })
+            .collect()
+    }
+
+    /// Select actions just like `actions` does, but post-filter the result
+    ///
+    /// Only actions matching `only` (if that is not empty) and not matching
+    /// `skip` are kept. Groups are expanded in `only` and `skip` as well.
+    ///
+    /// Returns the kept actions and all actions filtered out.
+    pub fn filtered_actions(
+        &self,
+        selectors: &ActionSelectors,
+        only: &ActionSelectors,
+        skip: &ActionSelectors,
+    ) -> (beautytips::ActionSet, beautytips::ActionSet) {
+        let only = find_selectors(&self.action_groups, only);
+        let skip = find_selectors(&self.action_groups, skip);
+
+        self.actions(selectors).partition(|ad| {
+            (only.is_empty() || only.matches_action(ad)) && !skip.matches_action(ad)
+        })
+    }
+
+    /// The name of the builtin rule pack defining `id`, e.g. `taplo`
+    fn builtin_pack(&self, id: &ActionId) -> Option<&str> {
+        self.origins
+            .get(id)?
+            .strip_prefix("<builtin ")?
+            .strip_suffix('>')
+    }
+
+    /// Select the checks of the builtin rule packs that apply to `files`
+    /// (relative to the repository root)
+    ///
+    /// Actions are selected when they come from a builtin rule pack, write
+    /// nothing and have `files` patterns selecting at least one of `files`.
+    /// Returns the selectors of these actions and the names of their packs.
+    pub fn auto_selectors(&self, files: &[PathBuf]) -> (ActionSelectors, Vec<String>) {
+        let mut selectors = ActionSelectors::default();
+        let mut packs = Vec::new();
+        for (id, action) in &self.action_map {
+            let Some(pack) = self.builtin_pack(id) else {
+                continue;
+            };
+            let filters = &action.input_filters;
+            if action.writes != beautytips::Writes::Nothing
+                || filters.patterns("files").is_empty()
+                || !files.iter().any(|f| filters.selects("files", f))
+            {
+                continue;
+            }
+            selectors.extend([ActionSelector::from_id(id)]);
+            if !packs.iter().any(|p| p == pack) {
+                packs.push(pack.to_string());
+            }
+        }
+        packs.sort();
+        (selectors, packs)
     }
 }
 
@@ -561,7 +1713,8 @@ macro_rules! import_rules {
             let config = Configuration::default();
             $(
                 let config = config.merge(
-                    ConfigurationSource::from_string(
+                    ConfigurationSource::from_builtin(
+                        $file,
                         include_str!(std::concat!($file, ".toml")),
                     ).expect(std::concat!($file, " should parse fine"))
                 )
@@ -578,47 +1731,127 @@ pub fn builtin() -> Configuration {
         "biome",
         "builtin",
         "cargo",
+        "clang",
         "cspell",
+        "go",
         "mypy",
         "ruff",
+        "shell",
         "taplo"
     )
 }
 
-pub fn load_user_configuration() -> anyhow::Result<Configuration> {
-    let base = builtin();
-
+/// The location of the user configuration file
+pub fn user_configuration_file() -> anyhow::Result<PathBuf> {
     let config_dir = dirs::config_dir()
         .map(|cd| cd.join("beautytips"))
         .ok_or(anyhow::anyhow!("Config directory not found"))?;
-    let config_file = config_dir.join("config.toml");
+    Ok(config_dir.join("config.toml"))
+}
+
+pub fn load_user_configuration() -> anyhow::Result<Configuration> {
+    let base = builtin();
+
+    let config_file = user_configuration_file()?;
 
     if !config_file.exists() {
         return Ok(base);
     }
 
-    let user = ConfigurationSource::from_path(config_file.as_path()).context(format!(
-        "Failed to parse configuration file {config_file:?}"
-    ))?;
+    let user = ConfigurationSource::from_path(config_file.as_path())?;
     base.merge(user)
 }
 
+fn action_toml(
+    name: &ActionId,
+    description: Option<&str>,
+    command: &str,
+    filters: &[String],
+) -> String {
+    let quote = |s: &str| toml::Value::String(s.to_string()).to_string();
+
+    let mut lines = vec!["[[actions]]".to_string(), format!("name = {}", quote(name))];
+    if let Some(description) = description {
+        lines.push(format!("description = {}", quote(description)));
+    }
+    lines.push(format!("command = {}", quote(command)));
+    if !filters.is_empty() {
+        let filters = filters.iter().map(|f| quote(f)).collect::<Vec<_>>();
+        lines.push(format!("inputs.files = [{}]", filters.join(", ")));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn append_action_to_string(
+    current: &str,
+    name: &ActionId,
+    description: Option<&str>,
+    command: &str,
+    filters: &[String],
+) -> anyhow::Result<String> {
+    let mut result = current.to_string();
+    if !result.is_empty() {
+        if !result.ends_with('\n') {
+            result.push('\n');
+        }
+        result.push('\n');
+    }
+    result.push_str(&action_toml(name, description, command, filters));
+
+    let source = ConfigurationSource::from_string(&result)?;
+    builtin()
+        .merge(source)
+        .context(format!("Failed to add {name}"))?;
+
+    Ok(result)
+}
+
+/// Append a new action to the configuration file at `config_file`
+///
+/// The file is created if it does not exist yet. Nothing is written when
+/// the resulting configuration is invalid.
+pub fn append_action(
+    config_file: &Path,
+    name: &ActionId,
+    description: Option<&str>,
+    command: &str,
+    filters: &[String],
+) -> anyhow::Result<()> {
+    let current = if config_file.exists() {
+        std::fs::read_to_string(config_file)
+            .context(format!("Failed to read {}", config_file.display()))?
+    } else {
+        String::new()
+    };
+
+    let updated = append_action_to_string(&current, name, description, command, filters)?;
+
+    if let Some(dir) = config_file.parent() {
+        std::fs::create_dir_all(dir).context(format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(config_file, updated)
+        .context(format!("Failed to write {}", config_file.display()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_valid_id() {
-        assert!(is_valid_id("foo/bar"));
-        assert!(is_valid_id("_a/_b"));
-        assert!(is_valid_id("1a/1b"));
-        assert!(is_valid_id("1___a_dsd_/b___342144_zdfj"));
-        assert!(!is_valid_id(""));
-        assert!(!is_valid_id("__1/bar"));
-        assert!(!is_valid_id("foo/1__"));
-        assert!(!is_valid_id("foo"));
-        assert!(!is_valid_id("_foo_"));
-        assert!(!is_valid_id("a/Bar"));
+    fn test_action_selectors_from_str() {
+        let selectors: ActionSelectors = "cargo/*, check_bom,,builtin/fix_*".parse().unwrap();
+        assert_eq!(selectors.len(), 3);
+        assert!(selectors.matches("cargo/check_fmt"));
+        assert!(selectors.matches("builtin/check_bom"));
+        assert!(selectors.matches("other/check_bom"));
+        assert!(selectors.matches("builtin/fix_bom"));
+        assert!(!selectors.matches("builtin/check_large_file"));
+
+        let selectors: ActionSelectors = "".parse().unwrap();
+        assert!(selectors.is_empty());
+
+        assert!("foo/[".parse::<ActionSelectors>().is_err());
     }
 
     #[test]
@@ -672,27 +1905,27 @@ actions = [ "test/t1", "test/t2" ]
 
         assert_eq!(
             base.actions(&ActionSelectors::new(["test/t1"]).unwrap())
-                .count(),
+                .len(),
             1
         );
         assert_eq!(
             base.actions(&ActionSelectors::new(["test/t2"]).unwrap())
-                .count(),
+                .len(),
             1
         );
         assert_eq!(
             base.actions(&ActionSelectors::new(["test/t3"]).unwrap())
-                .count(),
+                .len(),
             0
         );
         assert_eq!(
             base.actions(&ActionSelectors::new(["test/g1"]).unwrap())
-                .count(),
+                .len(),
             2
         );
         assert_eq!(
             base.actions(&ActionSelectors::new(["test/*"]).unwrap())
-                .count(),
+                .len(),
             2
         );
     }
@@ -806,107 +2039,965 @@ actions = [ "test/t1", "test/t2" ]
     }
 
     #[test]
-    fn test_configuration_merge_empty() {
+    fn test_configuration_error_location() {
         let base = r#"[[actions]]
-description = "foo"
 name = "test/t1"
 command = "foobar x y z"
 
 [[actions]]
 name = "test/t2"
-description = "foo"
-command = "foobar \"a b c\""
-inputs.files = [ "**/*.rs", "**/Cargo.toml" ]
+id = "foobar"
+command = "foobar"
+"#;
 
-[[actions]]
-name = "test/t3b"
-description = "foo"
-command = "do something"
-inputs.files = [ "**/*.slt", "**/*.rs" ]
+        let error = ConfigurationSource::parse(base, Some(Path::new("config.toml")))
+            .unwrap_err()
+            .to_string();
+        // The list of all known fields changes too often to compare it
+        assert!(error.starts_with("config.toml:7:1: unknown field `id`, expected one of "));
+        assert!(error.ends_with("\n  in key `id`\n  |\n7 | id = \"foobar\"\n  | ^^"));
 
-[[action_groups]]
-name = "test/g1"
-actions = [ "test/t1", "test/t2" ]
-"#;
+        let base = r#"[[actions]]
+name = "test/t1"
+command = "foobar x y z"
 
-        let base = ConfigurationSource::from_string(base).unwrap();
-        let merge = Configuration::default().merge(base).unwrap();
+[[actions]]
+name = "test/t1"
+command = "foobar"
+"#;
 
-        assert_eq!(merge.action_map.len(), 3);
-        assert_eq!(
-            merge
-                .actions(&ActionSelectors::new(["test/t1"]).unwrap())
-                .count(),
-            1
-        );
-        assert_eq!(
-            merge
-                .actions(&ActionSelectors::new(["test/t3b"]).unwrap())
-                .count(),
-            1
-        );
+        let base = ConfigurationSource::parse(base, Some(Path::new("config.toml"))).unwrap();
+        let error = Configuration::default().merge(base).unwrap_err();
         assert_eq!(
-            merge
-                .actions(&ActionSelectors::new(["test/t2"]).unwrap())
-                .count(),
-            1
+            error.to_string(),
+            "config.toml:5:1: test/t1 is already defined in config.toml, set `merge = \"replace\"` to replace it\n  in action `test/t1`\n  |\n5 | [[actions]]\n  | ^^^^^^^^^^^"
         );
-        assert_eq!(merge.action_groups.len(), 1);
-        // let it = merge
-        //     .named_actions(&[QualifiedActionId::new(ActionId::try_from("test").unwrap())])
-        //     .unwrap();
-        // assert_eq!(it.count(), 2);
     }
 
     #[test]
-    fn test_configuration_merge() {
+    fn test_configuration_duplicate_ids() {
         let base = r#"[[actions]]
 name = "test/t1"
-description = "foo"
 command = "foobar x y z"
 
-[[actions]]
-name = "test/t2"
-description = "foo"
-command = "foobar \"a b c\""
-inputs.files = [ "**/*.rs", "**/Cargo.toml" ]
-
-[[actions]]
-name = "test/t3b"
-description = "foo"
-command = "do something"
-inputs.files = [ "**/*.slt", "**/*.rs" ]
-
 [[action_groups]]
 name = "test/g1"
-actions = [ "test/t1", "test/t2" ]
+actions = [ "test/t1" ]
 "#;
-
-        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = ConfigurationSource::parse(base, Some(Path::new("base.toml"))).unwrap();
         let base = Configuration::default().merge(base).unwrap();
 
-        let other = r#"[[actions]]
-name = "test/t3o"
-description = "foo"
-command = "bar foo x y z"
-
-[[actions]]
-name = "test/t2"
-merge = "change"
-command = "/dev/null"
+        let merge = |other: &str| {
+            let other = ConfigurationSource::parse(other, Some(Path::new("other.toml"))).unwrap();
+            base.clone().merge(other)
+        };
 
-[[actions]]
+        let error = merge(
+            r#"[[actions]]
 name = "test/t1"
-merge = "change"
-command = "bar foo x y z"
+command = "other"
+"#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.starts_with("other.toml:1:1: test/t1 is already defined in base.toml"));
 
-[[action_groups]]
+        let error = merge(
+            r#"[[actions]]
 name = "test/g1"
-actions = [ "test/t1", "test/t3o", "test/t3b" ]
+command = "other"
+"#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.starts_with("other.toml:1:1: test/g1 is already defined in base.toml"));
 
-[[action_groups]]
-name = "test/g2"
-actions = [ "test/t3b" ]
+        let error = merge(
+            r#"[[action_groups]]
+name = "test/g1"
+actions = [ "test/t2" ]
+"#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.starts_with("other.toml:1:1: test/g1 is already defined in base.toml"));
+
+        let error = merge(
+            r#"[[actions]]
+name = "test/t2"
+merge = "replace"
+command = "other"
+"#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            error.starts_with("other.toml:1:1: test/t2 is replacing an action that does not exist")
+        );
+
+        let merged = merge(
+            r#"[[actions]]
+name = "test/t1"
+merge = "replace"
+command = "other"
+
+[[action_groups]]
+name = "test/g1"
+merge = "replace"
+actions = [ "test/t1", "test/t2" ]
+"#,
+        )
+        .unwrap();
+        assert_eq!(merged.action_map.len(), 1);
+        assert_eq!(
+            merged.action_map[&ActionId::new("test/t1".to_string()).unwrap()].command,
+            vec!["other"]
+        );
+        assert_eq!(
+            merged.action_map[&ActionId::new("test/t1".to_string()).unwrap()].description,
+            ""
+        );
+        assert_eq!(merged.action_groups.len(), 1);
+        assert_eq!(
+            merged.action_groups[&ActionId::new("test/g1".to_string()).unwrap()].len(),
+            2
+        );
+
+        let merged = merge(
+            r#"[[actions]]
+name = "test/t1"
+merge = "remove"
+
+[[actions]]
+name = "test/t1"
+command = "other"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            merged.action_map[&ActionId::new("test/t1".to_string()).unwrap()].command,
+            vec!["other"]
+        );
+    }
+
+    #[test]
+    fn test_configuration_remove_extra_keys() {
+        let base = r#"[[actions]]
+name = "test/t1"
+command = "lint"
+"#;
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+
+        for extra in [
+            "full-run-threshold = 100",
+            "full-command = \"lint --all\"",
+            "files-via = \"file\"",
+            "enabled-when = { env = \"CI\" }",
+        ] {
+            let remove = ConfigurationSource::from_string(&format!(
+                "[[actions]]\nname = \"test/t1\"\nmerge = \"remove\"\n{extra}\n"
+            ))
+            .unwrap();
+            let error = base.clone().merge(remove).unwrap_err();
+            assert!(
+                format!("{error:#}").contains("removing an action, but has extra keys set"),
+                "{extra} is accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn test_configuration_script() {
+        let base = r##"[[actions]]
+name = "test/t1"
+script = """#!/bin/sh
+echo "$BEAUTYTIPS_FILES"
+"""
+inputs.files = [ "**/*.rs" ]
+"##;
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+        let t1 = &base.action_map[&ActionId::new("test/t1".to_string()).unwrap()];
+        assert!(t1.command.is_empty());
+        assert_eq!(
+            t1.script.as_deref(),
+            Some("#!/bin/sh\necho \"$BEAUTYTIPS_FILES\"\n")
+        );
+
+        let both = r##"[[actions]]
+name = "test/t2"
+command = "true"
+script = "#!/bin/sh"
+"##;
+        let both = ConfigurationSource::from_string(both).unwrap();
+        assert!(base.clone().merge(both).is_err());
+
+        let sandboxed = r#"[[actions]]
+name = "test/t1"
+merge = "change"
+sandbox = "copy"
+"#;
+        let sandboxed = ConfigurationSource::from_string(sandboxed).unwrap();
+        assert!(base.clone().merge(sandboxed).is_err());
+
+        let restricted = r#"[[actions]]
+name = "test/t1"
+merge = "change"
+sandbox = true
+"#;
+        let restricted = ConfigurationSource::from_string(restricted).unwrap();
+        let config = base.merge(restricted).unwrap();
+        assert_eq!(
+            config.action_map[&ActionId::new("test/t1".to_string()).unwrap()].sandbox,
+            Some(beautytips::SandboxMode::Restricted)
+        );
+    }
+
+    #[test]
+    fn test_configuration_writes() {
+        let base = r#"[[actions]]
+name = "test/t1"
+command = "foo"
+writes = true
+
+[[actions]]
+name = "test/t2"
+command = "foo"
+writes = [ "**/*.rs" ]
+
+[[actions]]
+name = "test/t3"
+command = "foo"
+"#;
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+        let writes = |id: &str| {
+            base.action_map[&ActionId::new(id.to_string()).unwrap()]
+                .writes
+                .clone()
+        };
+        assert_eq!(writes("test/t1"), beautytips::Writes::InputFiles);
+        assert_eq!(
+            writes("test/t2"),
+            beautytips::Writes::Patterns(vec![glob::Pattern::new("**/*.rs").unwrap()])
+        );
+        assert_eq!(writes("test/t3"), beautytips::Writes::Nothing);
+
+        let invalid = r#"[[actions]]
+name = "test/t4"
+command = "foo"
+writes = [ "**a" ]
+"#;
+        let invalid = ConfigurationSource::from_string(invalid).unwrap();
+        assert!(base.merge(invalid).is_err());
+    }
+
+    #[test]
+    fn test_configuration_enabled_when() {
+        let base = r#"[[actions]]
+name = "test/t1"
+command = "foo"
+enabled-when = { env = "CI", file-exists = "Cargo.lock", os = "linux" }
+
+[[actions]]
+name = "test/t2"
+command = "foo"
+"#;
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+        let enabled_when = |config: &Configuration, id: &str| {
+            config.action_map[&ActionId::new(id.to_string()).unwrap()]
+                .enabled_when
+                .clone()
+        };
+        assert_eq!(
+            enabled_when(&base, "test/t1"),
+            beautytips::Condition {
+                env: Some("CI".to_string()),
+                file_exists: Some(PathBuf::from("Cargo.lock")),
+                os: Some("linux".to_string()),
+            }
+        );
+        assert!(enabled_when(&base, "test/t2").is_empty());
+
+        let change = r#"[[actions]]
+name = "test/t2"
+merge = "change"
+enabled-when.os = "windows"
+"#;
+        let change = ConfigurationSource::from_string(change).unwrap();
+        let changed = base.clone().merge(change).unwrap();
+        assert_eq!(
+            enabled_when(&changed, "test/t2").os.as_deref(),
+            Some("windows")
+        );
+
+        // Commands missing for this OS limit the action, until changed
+        let other_os = if std::env::consts::OS == "windows" {
+            "linux"
+        } else {
+            "windows"
+        };
+        let limited = format!(
+            r#"[[actions]]
+name = "test/t1"
+merge = "change"
+command.{other_os} = "bar"
+
+[[actions]]
+name = "test/t2"
+merge = "change"
+command.{other_os} = "bar"
+"#
+        );
+        let limited = ConfigurationSource::from_string(&limited).unwrap();
+        let limited = changed.merge(limited).unwrap();
+        assert_eq!(
+            enabled_when(&limited, "test/t1").os.as_deref(),
+            Some("linux")
+        );
+        assert_eq!(
+            enabled_when(&limited, "test/t2").os.as_deref(),
+            Some("windows")
+        );
+
+        let unlimited = r#"[[actions]]
+name = "test/t1"
+merge = "change"
+enabled-when = { env = "CI" }
+
+[[actions]]
+name = "test/t2"
+merge = "change"
+command = "foo"
+enabled-when = { env = "CI" }
+"#;
+        let unlimited = ConfigurationSource::from_string(unlimited).unwrap();
+        let unlimited = limited.clone().merge(unlimited).unwrap();
+        assert_eq!(
+            enabled_when(&unlimited, "test/t1").os.as_deref(),
+            Some(other_os)
+        );
+        assert_eq!(enabled_when(&unlimited, "test/t2").os, None);
+
+        let extended = r#"[[actions]]
+name = "test/t3"
+extends = "test/t1"
+
+[[actions]]
+name = "test/t4"
+extends = "test/t1"
+command = "foo"
+"#;
+        let extended = ConfigurationSource::from_string(extended).unwrap();
+        let extended = unlimited.merge(extended).unwrap();
+        assert_eq!(
+            enabled_when(&extended, "test/t3").os.as_deref(),
+            Some(other_os)
+        );
+        assert_eq!(enabled_when(&extended, "test/t4").os, None);
+
+        let invalid = r#"[[actions]]
+name = "test/t3"
+command = "foo"
+enabled-when = { arch = "arm" }
+"#;
+        assert!(ConfigurationSource::from_string(invalid).is_err());
+    }
+
+    #[test]
+    fn test_configuration_input_kind() {
+        let base = r##"[[actions]]
+name = "test/t1"
+command = "foo"
+inputs.kind = "text"
+inputs.content = ["#!/bin/sh"]
+
+[[actions]]
+name = "test/t2"
+command = "foo {{cargo_targets}}"
+inputs.cargo_targets = []
+"##;
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+        let filters = |config: &Configuration, id: &str| {
+            config.action_map[&ActionId::new(id.to_string()).unwrap()]
+                .input_filters
+                .clone()
+        };
+        let t1 = filters(&base, "test/t1");
+        assert_eq!(t1.kind(), Some(beautytips::FileKind::Text));
+        assert_eq!(t1.content_patterns().len(), 1);
+        assert_eq!(t1.inputs().collect::<Vec<_>>(), ["files"]);
+        assert_eq!(filters(&base, "test/t2").kind(), None);
+
+        let change = r#"[[actions]]
+name = "test/t1"
+merge = "change"
+inputs.kind = []
+
+[[actions]]
+name = "test/t2"
+merge = "change"
+inputs.kind = "binary"
+"#;
+        let change = ConfigurationSource::from_string(change).unwrap();
+        let changed = base.clone().merge(change).unwrap();
+        let t1 = filters(&changed, "test/t1");
+        assert_eq!(t1.kind(), None);
+        assert_eq!(t1.content_patterns().len(), 1);
+        let t2 = filters(&changed, "test/t2");
+        assert_eq!(t2.kind(), Some(beautytips::FileKind::Binary));
+        let mut inputs = t2.inputs().collect::<Vec<_>>();
+        inputs.sort();
+        assert_eq!(inputs, ["cargo_targets", "files"]);
+
+        let invalid = r#"[[actions]]
+name = "test/t3"
+command = "foo"
+inputs.kind = "image"
+"#;
+        let invalid = ConfigurationSource::from_string(invalid).unwrap();
+        assert!(Configuration::default().merge(invalid).is_err());
+    }
+
+    #[test]
+    fn test_configuration_os_commands() {
+        let base = r#"[[actions]]
+name = "test/t1"
+command.default = "tool --check"
+command.windows = "tool.exe /check"
+
+[[actions]]
+name = "test/t2"
+command = { macos = "mactool", linux = "linuxtool" }
+"#;
+        let mut base = ConfigurationSource::from_string(base).unwrap();
+        let t1 = base.actions[0].get_ref().command.as_ref().unwrap();
+        let t2 = base.actions[1].get_ref().command.as_ref().unwrap();
+
+        assert_eq!(
+            map_os_command(t1, "windows").unwrap(),
+            (vec!["tool.exe".to_string(), "/check".to_string()], None)
+        );
+        assert_eq!(
+            map_os_command(t1, "linux").unwrap(),
+            (vec!["tool".to_string(), "--check".to_string()], None)
+        );
+        assert_eq!(
+            map_os_command(t2, "macos").unwrap(),
+            (vec!["mactool".to_string()], None)
+        );
+        assert_eq!(
+            map_os_command(t2, "windows").unwrap(),
+            (vec![], Some("linux,macos".to_string()))
+        );
+        assert_eq!(
+            map_os_command(&TomlCommand::Generic("tool".to_string()), "windows").unwrap(),
+            (vec!["tool".to_string()], None)
+        );
+
+        base.actions[1].get_mut().command = Some(TomlCommand::PerOs(TomlOsCommands {
+            default: None,
+            linux: None,
+            macos: None,
+            windows: None,
+        }));
+        assert!(Configuration::default().merge(base).is_err());
+
+        let invalid = r#"[[actions]]
+name = "test/t3"
+command.plan9 = "tool"
+"#;
+        assert!(ConfigurationSource::from_string(invalid).is_err());
+    }
+
+    #[test]
+    fn test_configuration_full_run() {
+        let base = r#"[[actions]]
+name = "test/t1"
+command = "lint {{files...}}"
+full-run-threshold = 100
+full-command = "lint --all"
+"#;
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+        let t1 = &base.action_map[&ActionId::new("test/t1".to_string()).unwrap()];
+        assert_eq!(t1.full_run_threshold, Some(100));
+        assert_eq!(t1.full_command, ["lint", "--all"]);
+
+        let no_command = r#"[[actions]]
+name = "test/t2"
+command = "lint {{files...}}"
+full-run-threshold = 100
+"#;
+        let no_command = ConfigurationSource::from_string(no_command).unwrap();
+        let error = format!("{:#}", base.clone().merge(no_command).unwrap_err());
+        assert!(error.contains("test/t2 sets a full-run-threshold"));
+
+        let empty_command = r#"[[actions]]
+name = "test/t2"
+command = "lint {{files...}}"
+full-run-threshold = 100
+full-command = ""
+"#;
+        let empty_command = ConfigurationSource::from_string(empty_command).unwrap();
+        assert!(base.clone().merge(empty_command).is_err());
+
+        let emptied = r#"[[actions]]
+name = "test/t1"
+merge = "change"
+full-command = " "
+"#;
+        let emptied = ConfigurationSource::from_string(emptied).unwrap();
+        assert!(base.clone().merge(emptied).is_err());
+
+        let script = r#"[[actions]]
+name = "test/t1"
+merge = "change"
+script = "lint"
+"#;
+        let script = ConfigurationSource::from_string(script).unwrap();
+        assert!(base.merge(script).is_err());
+    }
+
+    #[test]
+    fn test_configuration_validate() {
+        let broken = r#"[[actions]]
+name = "test/t1"
+command = "{{cache_dir}}/lint {{files}"
+diff-on-failure = true
+inputs = { files = [ "./src/*.rs" ] }
+stdout-file = "lint.txt"
+stderr-file = "lint.txt"
+"#;
+        let broken = ConfigurationSource::from_string(broken).unwrap();
+        let error = format!("{:#}", Configuration::default().merge(broken).unwrap_err());
+        assert!(error.contains("test/t1 is not valid"));
+        assert!(error.contains("executable '{{cache_dir}}/lint'"));
+        assert!(error.contains("'{{files}' is not terminated"));
+        assert!(error.contains("'./src/*.rs' for 'files' never matches"));
+        assert!(error.contains("diff-on-failure"));
+        assert!(error.contains("stdout-file and the stderr-file are the same"));
+
+        let outside = r#"[[actions]]
+name = "test/t1"
+command = "lint"
+stdout-file = "reports/../../lint.txt"
+"#;
+        let outside = ConfigurationSource::from_string(outside).unwrap();
+        let error = format!("{:#}", Configuration::default().merge(outside).unwrap_err());
+        assert!(error.contains("the stdout-file must be inside the root directory"));
+
+        let typo = r#"[[actions]]
+name = "test/t1"
+command = "lint {{flies...}} {{srcs}} {{flies}} {{files:count}}"
+"#;
+        let typo = ConfigurationSource::from_string(typo).unwrap();
+        let error = format!("{:#}", Configuration::default().merge(typo).unwrap_err());
+        assert!(error.contains(
+            "unknown inputs '{{flies}}' in the command, '{{srcs}}' in the command, known"
+        ));
+        assert!(!error.contains("'{{files:count}}'"));
+
+        let declared = r#"[[actions]]
+name = "test/t1"
+command = "lint {{generated...}}"
+hint = "{{cache_dir}} and {{files}} and {{top:directory}} and {{cargo_targets}}"
+inputs = { generated = [ "*.rs" ] }
+"#;
+        let declared = ConfigurationSource::from_string(declared).unwrap();
+        assert!(Configuration::default().merge(declared).is_ok());
+
+        let mut ad = builtin()
+            .actions(&"rust/check_fmt".parse().unwrap())
+            .iter()
+            .next()
+            .unwrap()
+            .clone();
+        assert!(ad.validate().is_ok());
+        ad.command.clear();
+        ad.hint = Some("run {{}}".to_string());
+        assert!(ad.validate().is_err());
+    }
+
+    #[test]
+    fn test_configuration_files_via() {
+        let base = r#"[[actions]]
+name = "test/t1"
+command = "lint --files-from={{files:listfile}}"
+files-via = "file"
+"#;
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+        assert_eq!(
+            base.action_map[&ActionId::new("test/t1".to_string()).unwrap()].files_via,
+            beautytips::FilesVia::File
+        );
+
+        let change = r#"[[actions]]
+name = "test/t1"
+merge = "change"
+files-via = "arguments"
+"#;
+        let change = ConfigurationSource::from_string(change).unwrap();
+        assert!(base.merge(change).is_err());
+
+        let invalid = r#"[[actions]]
+name = "test/t2"
+command = "lint {{files:listfile}}"
+"#;
+        let invalid = ConfigurationSource::from_string(invalid).unwrap();
+        assert!(Configuration::default().merge(invalid).is_err());
+    }
+
+    #[test]
+    fn test_configuration_cwd_per_target() {
+        let base = r#"[[actions]]
+name = "test/t1"
+command = "make"
+cwd-per-target = "files"
+"#;
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+        let id = ActionId::new("test/t1".to_string()).unwrap();
+        assert_eq!(
+            base.action_map[&id].cwd_per_target.as_deref(),
+            Some("files")
+        );
+
+        let change = r#"[[actions]]
+name = "test/t1"
+merge = "change"
+cwd-per-target = ""
+"#;
+        let change = ConfigurationSource::from_string(change).unwrap();
+        let changed = base.merge(change).unwrap();
+        assert_eq!(changed.action_map[&id].cwd_per_target, None);
+    }
+
+    #[test]
+    fn test_action_set() {
+        let base = r#"[[actions]]
+name = "test/t1"
+command = "one"
+
+[[actions]]
+name = "test/t2"
+command = "two"
+
+[[actions]]
+name = "other/t3"
+command = "three"
+"#;
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+
+        let tests = base.actions(&ActionSelectors::new(["test/*"]).unwrap());
+        let t2 = base.actions(&ActionSelectors::new(["t2"]).unwrap());
+        let others = base.actions(&ActionSelectors::new(["other/*"]).unwrap());
+
+        assert_eq!(
+            t2.union(&tests).ids().collect::<Vec<_>>(),
+            ["test/t2", "test/t1"]
+        );
+        assert_eq!(
+            others.union(&tests).ids().collect::<Vec<_>>(),
+            ["other/t3", "test/t1", "test/t2"]
+        );
+        assert_eq!(tests.difference(&t2).ids().collect::<Vec<_>>(), ["test/t1"]);
+        assert!(tests.contains("test/t1"));
+        assert!(!tests.contains("other/t3"));
+        assert_eq!(tests.filter(|ad| ad.command[0] == "two").len(), 1);
+        assert!(t2.difference(&tests).is_empty());
+    }
+
+    #[test]
+    fn test_configuration_group_defaults() {
+        let base = r#"[[actions]]
+name = "test/t1"
+command = "lint"
+environment = [ "LEVEL=strict" ]
+
+[[actions]]
+name = "test/t2"
+command = "check"
+run-sequentially = false
+
+[[action_groups]]
+name = "test/all"
+actions = [ "test/t1", "test/ci" ]
+defaults.show-output = "never"
+
+[[action_groups]]
+name = "test/ci"
+actions = [ "test/t2" ]
+defaults.environment = [ "CI=1", "LEVEL=lax" ]
+defaults.show-output = "always"
+defaults.run-sequentially = true
+defaults.timeout = "90s"
+"#;
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+
+        let t2 = base
+            .actions(&ActionSelectors::new(["test/ci"]).unwrap())
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(t2.show_output, beautytips::OutputCondition::Always);
+        assert!(t2.run_sequentially);
+        assert_eq!(t2.timeout, Some(std::time::Duration::from_secs(90)));
+        assert_eq!(
+            t2.environment,
+            [
+                ("CI".to_string(), "1".to_string()),
+                ("LEVEL".to_string(), "lax".to_string())
+            ]
+        );
+
+        // The outermost group setting something wins
+        let all = base
+            .actions(&ActionSelectors::new(["test/all"]).unwrap())
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(all.len(), 2);
+        assert!(all
+            .iter()
+            .all(|ad| ad.show_output == beautytips::OutputCondition::Never));
+        assert!(all[1].run_sequentially);
+        assert_eq!(all[1].timeout, Some(std::time::Duration::from_secs(90)));
+        assert_eq!(all[1].environment.len(), 2);
+        assert_eq!(
+            all[0].environment,
+            [("LEVEL".to_string(), "strict".to_string())]
+        );
+        assert_eq!(all[0].timeout, None);
+
+        // The actions themselves are unchanged
+        let t2 = base
+            .actions(&ActionSelectors::new(["test/t2"]).unwrap())
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(t2.show_output, beautytips::OutputCondition::Failure);
+        assert!(t2.environment.is_empty());
+        assert_eq!(t2.timeout, None);
+
+        let invalid = r#"[[action_groups]]
+name = "test/slow"
+actions = [ "test/t1" ]
+defaults.timeout = "0s"
+"#;
+        let invalid = ConfigurationSource::from_string(invalid).unwrap();
+        assert!(base.merge(invalid).is_err());
+    }
+
+    #[test]
+    fn test_configuration_extends() {
+        let base = r#"[[actions]]
+name = "test/t1"
+description = "Lint"
+command = "lint {{files...}}"
+inputs.files = [ "**/*.rs" ]
+show-output = "always"
+
+[[actions]]
+name = "test/t2"
+extends = "test/t1"
+command = "lint --strict {{files...}}"
+"#;
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+        let action = |id: &str| base.action_map[&ActionId::new(id.to_string()).unwrap()].clone();
+        let (t1, t2) = (action("test/t1"), action("test/t2"));
+        assert_eq!(t2.id, "test/t2");
+        assert_eq!(t2.description, "Lint");
+        assert_eq!(t2.command, ["lint", "--strict", "{{files...}}"]);
+        assert_eq!(t2.input_filters, t1.input_filters);
+        assert_eq!(t2.show_output, beautytips::OutputCondition::Always);
+        assert_eq!(t1.command, ["lint", "{{files...}}"]);
+
+        let undefined = r#"[[actions]]
+name = "test/t3"
+extends = "test/unknown"
+"#;
+        let undefined = ConfigurationSource::from_string(undefined).unwrap();
+        assert!(base.clone().merge(undefined).is_err());
+
+        let change = r#"[[actions]]
+name = "test/t2"
+merge = "change"
+extends = "test/t1"
+"#;
+        let change = ConfigurationSource::from_string(change).unwrap();
+        assert!(base.merge(change).is_err());
+    }
+
+    #[test]
+    fn test_configuration_command_append() {
+        let base = r#"[[actions]]
+name = "test/t1"
+command = "lint {{files...}}"
+inputs.files = [ "**/*.rs", "**/*.toml" ]
+
+[[actions]]
+name = "test/t2"
+script = "lint"
+"#;
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+
+        let change = r#"[[actions]]
+name = "test/t1"
+merge = "change"
+command-prepend = "nice -n 10"
+command-append = "--deny 'all warnings'"
+inputs-add.files = [ "**/*.md" ]
+inputs-remove.files = [ "**/*.toml" ]
+"#;
+        let change = ConfigurationSource::from_string(change).unwrap();
+        let changed = base.clone().merge(change).unwrap();
+        let t1 = &changed.action_map[&ActionId::new("test/t1".to_string()).unwrap()];
+        assert_eq!(
+            t1.command,
+            [
+                "nice",
+                "-n",
+                "10",
+                "lint",
+                "{{files...}}",
+                "--deny",
+                "all warnings"
+            ]
+        );
+        assert_eq!(
+            t1.input_filters
+                .patterns("files")
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["**/*.rs", "**/*.md"]
+        );
+
+        let invalid = |toml: &str| {
+            let toml = ConfigurationSource::from_string(toml).unwrap();
+            base.clone().merge(toml).is_err()
+        };
+        assert!(invalid(
+            "[[actions]]\nname = \"test/t1\"\nmerge = \"change\"\ninputs-remove.files = [ \"**/*.md\" ]\n"
+        ));
+        assert!(invalid(
+            "[[actions]]\nname = \"test/t1\"\nmerge = \"change\"\ninputs-remove.files = [ \"**/*.rs\", \"**/*.toml\" ]\n"
+        ));
+        assert!(invalid(
+            "[[actions]]\nname = \"test/t2\"\nmerge = \"change\"\ncommand-append = \"-v\"\n"
+        ));
+        assert!(invalid(
+            "[[actions]]\nname = \"test/t3\"\ncommand = \"lint\"\ncommand-append = \"-v\"\n"
+        ));
+    }
+
+    #[test]
+    fn test_configuration_merge_empty() {
+        let base = r#"[[actions]]
+description = "foo"
+name = "test/t1"
+command = "foobar x y z"
+
+[[actions]]
+name = "test/t2"
+description = "foo"
+command = "foobar \"a b c\""
+inputs.files = [ "**/*.rs", "**/Cargo.toml" ]
+
+[[actions]]
+name = "test/t3b"
+description = "foo"
+command = "do something"
+inputs.files = [ "**/*.slt", "**/*.rs" ]
+
+[[action_groups]]
+name = "test/g1"
+actions = [ "test/t1", "test/t2" ]
+"#;
+
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let merge = Configuration::default().merge(base).unwrap();
+
+        assert_eq!(merge.action_map.len(), 3);
+        assert_eq!(
+            merge
+                .actions(&ActionSelectors::new(["test/t1"]).unwrap())
+                .len(),
+            1
+        );
+        assert_eq!(
+            merge
+                .actions(&ActionSelectors::new(["test/t3b"]).unwrap())
+                .len(),
+            1
+        );
+        assert_eq!(
+            merge
+                .actions(&ActionSelectors::new(["test/t2"]).unwrap())
+                .len(),
+            1
+        );
+        assert_eq!(merge.action_groups.len(), 1);
+    }
+
+    #[test]
+    fn test_configuration_merge() {
+        let base = r#"[[actions]]
+name = "test/t1"
+description = "foo"
+command = "foobar x y z"
+
+[[actions]]
+name = "test/t2"
+description = "foo"
+command = "foobar \"a b c\""
+inputs.files = [ "**/*.rs", "**/Cargo.toml" ]
+
+[[actions]]
+name = "test/t3b"
+description = "foo"
+command = "do something"
+inputs.files = [ "**/*.slt", "**/*.rs" ]
+
+[[action_groups]]
+name = "test/g1"
+actions = [ "test/t1", "test/t2" ]
+"#;
+
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+
+        let other = r#"[[actions]]
+name = "test/t3o"
+description = "foo"
+command = "bar foo x y z"
+
+[[actions]]
+name = "test/t2"
+merge = "change"
+command = "/dev/null"
+
+[[actions]]
+name = "test/t1"
+merge = "change"
+command = "bar foo x y z"
+
+[[action_groups]]
+name = "test/g1"
+merge = "replace"
+actions = [ "test/t1", "test/t3o", "test/t3b" ]
+
+[[action_groups]]
+name = "test/g2"
+actions = [ "test/t3b" ]
 "#;
         let other = ConfigurationSource::from_string(other).unwrap();
 
@@ -916,29 +3007,272 @@ actions = [ "test/t3b" ]
         assert_eq!(
             merge
                 .actions(&ActionSelectors::new(["test/t1"]).unwrap())
-                .count(),
+                .len(),
             1
         );
         assert_eq!(
             merge
                 .actions(&ActionSelectors::new(["test/t3b"]).unwrap())
-                .count(),
+                .len(),
             1
         );
         assert_eq!(
             merge
                 .actions(&ActionSelectors::new(["test/t3o"]).unwrap())
-                .count(),
+                .len(),
             1
         );
         assert_eq!(
             merge
                 .actions(&ActionSelectors::new(["test/g1"]).unwrap())
-                .count(),
+                .len(),
             3
         );
     }
 
+    #[test]
+    fn test_configuration_auto_selectors() {
+        let config = builtin();
+
+        let (selectors, packs) =
+            config.auto_selectors(&[PathBuf::from("src/main.rs"), PathBuf::from("a.toml")]);
+        assert_eq!(packs, ["builtin", "cargo", "taplo"]);
+        assert!(selectors.matches("rust/check_fmt"));
+        assert!(selectors.matches("toml/check"));
+        assert!(selectors.matches("builtin/check_toml"));
+        assert!(!selectors.matches("rust/fix_fmt"));
+        assert!(!selectors.matches("builtin/check_json"));
+        assert!(!selectors.matches("builtin/check_bom"));
+
+        let (selectors, packs) = config.auto_selectors(&[PathBuf::from("README")]);
+        assert!(selectors.is_empty());
+        assert!(packs.is_empty());
+    }
+
+    #[test]
+    fn test_configuration_tags() {
+        let base = r#"[[actions]]
+name = "test/audit"
+command = "foobar x y z"
+tags = [ "security", "slow" ]
+
+[[actions]]
+name = "test/secrets"
+command = "foobar x y z"
+tags = [ "security" ]
+
+[[actions]]
+name = "test/lint"
+command = "foobar x y z"
+
+[[actions]]
+name = "test/lint"
+merge = "change"
+tags = [ "slow" ]
+
+[[action_groups]]
+name = "test/secure"
+actions = [ "tag:security" ]
+"#;
+
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+
+        let ids = |selectors: &[&str]| {
+            base.actions(&ActionSelectors::from(
+                selectors
+                    .iter()
+                    .map(|s| ActionSelector::new(s).unwrap())
+                    .collect::<Vec<_>>(),
+            ))
+            .ids()
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&["test/secure"]), ["test/audit", "test/secrets"]);
+        assert_eq!(ids(&["tag:slow"]), ["test/audit", "test/lint"]);
+        assert_eq!(ids(&["tag:slow", "secrets"]).len(), 3);
+        assert!(ids(&["tag:fast"]).is_empty());
+
+        let (kept, skipped) = base.filtered_actions(
+            &ActionSelectors::new(["*/*"]).unwrap(),
+            &ActionSelectors::default(),
+            &ActionSelectors::new(["tag:slow"]).unwrap(),
+        );
+        assert_eq!(kept.ids().collect::<Vec<_>>(), ["test/secrets"]);
+        assert_eq!(skipped.len(), 2);
+    }
+
+    #[test]
+    fn test_configuration_ui() {
+        let base = r#"[ui]
+theme = "colorblind"
+colors.error = "magenta"
+"#;
+        let update = r##"[ui]
+colors.ok = "#0072b2"
+"##;
+
+        let config = Configuration::default()
+            .merge(ConfigurationSource::from_string(base).unwrap())
+            .unwrap()
+            .merge(ConfigurationSource::from_string(update).unwrap())
+            .unwrap();
+        assert_eq!(config.ui.theme, theme::ThemeName::Colorblind);
+        assert_eq!(config.ui.colors.len(), 2);
+        assert_eq!(
+            config.ui.theme().look(theme::Kind::Error).color,
+            Some(crossterm::style::Color::Magenta)
+        );
+
+        let invalid = ConfigurationSource::from_string("[ui]\ncolors.warn = \"orange\"\n").unwrap();
+        assert!(Configuration::default().merge(invalid).is_err());
+        assert!(ConfigurationSource::from_string("[ui]\ntheme = \"pink\"\n").is_err());
+    }
+
+    #[test]
+    fn test_configuration_gates() {
+        let base = r#"[[actions]]
+name = "cargo/check"
+command = "cargo check"
+gate = true
+
+[[actions]]
+name = "cargo/clippy"
+command = "cargo clippy"
+
+[[actions]]
+name = "cargo/test"
+command = "cargo test"
+needs = [ "cargo/check" ]
+
+[[actions]]
+name = "toml/check"
+command = "taplo check"
+
+[[action_groups]]
+name = "test/rust"
+actions = [ "cargo/*" ]
+
+[[action_groups]]
+name = "test/all"
+actions = [ "test/rust", "toml/check" ]
+"#;
+
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+
+        let needs = |selectors: &[&str]| {
+            base.actions(&ActionSelectors::from(
+                selectors
+                    .iter()
+                    .map(|s| ActionSelector::new(s).unwrap())
+                    .collect::<Vec<_>>(),
+            ))
+            .iter()
+            .map(|ad| {
+                let needs = ad.needs.iter().map(ToString::to_string).collect::<Vec<_>>();
+                format!("{}: {}", ad.id, needs.join(","))
+            })
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            needs(&["test/all"]),
+            [
+                "cargo/check: ",
+                "cargo/clippy: cargo/check",
+                "cargo/test: cargo/check",
+                "toml/check: "
+            ]
+        );
+        // Without the gate in the run, only the explicit needs are left
+        assert_eq!(
+            needs(&["cargo/test", "cargo/clippy"]),
+            ["cargo/clippy: ", "cargo/test: cargo/check"]
+        );
+        assert_eq!(
+            needs(&["cargo/clippy", "toml/check", "cargo/check"]),
+            ["cargo/check: ", "cargo/clippy: cargo/check", "toml/check: "]
+        );
+
+        let invalid = |toml: &str| {
+            let toml = ConfigurationSource::from_string(toml).unwrap();
+            base.clone()
+                .merge(toml)
+                .unwrap_err()
+                .root_cause()
+                .to_string()
+        };
+        assert_eq!(
+            invalid(
+                "[[actions]]\nname = \"test/t1\"\ncommand = \"t1\"\nneeds = [ \"cargo/chek\" ]\n"
+            ),
+            "test/t1 needs cargo/chek, which is not defined (yet)"
+        );
+        assert_eq!(
+            invalid(
+                "[[actions]]\nname = \"test/t1\"\ncommand = \"t1\"\nneeds = [ \"toml/check\" ]\n"
+            ),
+            "test/t1 needs toml/check, which is no gate, set `gate = true` on it"
+        );
+        assert_eq!(
+            invalid("[[actions]]\nname = \"cargo/check\"\nmerge = \"change\"\ngate = false\n"),
+            "cargo/test needs cargo/check, which is no gate, set `gate = true` on it"
+        );
+    }
+
+    #[test]
+    fn test_configuration_filtered_actions() {
+        let base = r#"[[actions]]
+name = "test/check_t1"
+command = "foobar x y z"
+
+[[actions]]
+name = "test/fix_t1"
+command = "foobar x y z"
+
+[[actions]]
+name = "other/check_t2"
+command = "foobar x y z"
+
+[[action_groups]]
+name = "test/g1"
+actions = [ "test/fix_t1", "other/check_t2" ]
+"#;
+
+        let base = ConfigurationSource::from_string(base).unwrap();
+        let base = Configuration::default().merge(base).unwrap();
+
+        let all = ActionSelectors::new(["*/*"]).unwrap();
+        let none = ActionSelectors::default();
+
+        let (kept, skipped) =
+            base.filtered_actions(&all, &none, &ActionSelectors::new(["fix_*"]).unwrap());
+        assert_eq!(
+            kept.ids().collect::<Vec<_>>(),
+            ["other/check_t2", "test/check_t1"]
+        );
+        assert_eq!(skipped.ids().collect::<Vec<_>>(), ["test/fix_t1"]);
+
+        let (kept, skipped) = base.filtered_actions(
+            &all,
+            &ActionSelectors::new(["test/check_*"]).unwrap(),
+            &none,
+        );
+        assert_eq!(kept.ids().collect::<Vec<_>>(), ["test/check_t1"]);
+        assert_eq!(
+            skipped.ids().collect::<Vec<_>>(),
+            ["other/check_t2", "test/fix_t1"]
+        );
+
+        let (kept, skipped) =
+            base.filtered_actions(&all, &none, &ActionSelectors::new(["test/g1"]).unwrap());
+        assert_eq!(kept.ids().collect::<Vec<_>>(), ["test/check_t1"]);
+        assert_eq!(
+            skipped.ids().collect::<Vec<_>>(),
+            ["other/check_t2", "test/fix_t1"]
+        );
+    }
+
     #[test]
     fn test_builtins() {
         let builtin = builtin();
@@ -946,4 +3280,105 @@ actions = [ "test/t3b" ]
         assert!(!builtin.action_map.is_empty());
         assert!(builtin.action_groups.is_empty());
     }
+
+    #[test]
+    fn test_json_schema() {
+        let schema: serde_json::Value = serde_json::from_str(&json_schema()).unwrap();
+
+        let properties = schema["properties"].as_object().unwrap();
+        assert!(properties.contains_key("action_groups"));
+        assert!(properties.contains_key("actions"));
+
+        let action = &schema["definitions"]["TomlActionDefinition"];
+        assert_eq!(action["additionalProperties"], false);
+        assert_eq!(action["required"], serde_json::json!(["name"]));
+        for key in [
+            "merge",
+            "command",
+            "run-sequentially",
+            "show-output",
+            "inputs",
+        ] {
+            assert!(action["properties"].get(key).is_some(), "{key} is missing");
+        }
+    }
+
+    #[test]
+    fn test_append_action_to_string() {
+        let name = ActionId::new("mytool/check".to_string()).unwrap();
+        let filters = vec!["**/*.myt".to_string()];
+
+        let result = append_action_to_string(
+            "",
+            &name,
+            Some("Run \"mytool\""),
+            "mytool --check {{files...}}",
+            &filters,
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            r#"[[actions]]
+name = "mytool/check"
+description = 'Run "mytool"'
+command = "mytool --check {{files...}}"
+inputs.files = ["**/*.myt"]
+"#
+        );
+
+        let other = ActionId::new("mytool/fix".to_string()).unwrap();
+        let result =
+            append_action_to_string(&result, &other, None, "mytool {{files...}}", &filters)
+                .unwrap();
+        let config = builtin()
+            .merge(ConfigurationSource::from_string(&result).unwrap())
+            .unwrap();
+        assert!(config.action_map.contains_key(&name));
+        assert!(config.action_map.contains_key(&other));
+
+        // Already defined:
+        assert!(append_action_to_string(&result, &name, None, "mytool", &filters).is_err());
+        // Invalid glob:
+        assert!(append_action_to_string("", &name, None, "mytool", &["[".to_string()]).is_err());
+        // Invalid command:
+        assert!(append_action_to_string("", &name, None, "mytool \"foo", &filters).is_err());
+    }
+
+    #[test]
+    fn test_configuration_diff_on_failure() {
+        let base = r#"[[actions]]
+name = "test/check_fmt"
+command = "fmt --check {{files...}}"
+diff-on-failure = true
+diff-command = "fmt --diff {{files...}}"
+inputs.files = [ "**/*.rs" ]
+"#;
+
+        let base = Configuration::default()
+            .merge(ConfigurationSource::from_string(base).unwrap())
+            .unwrap();
+        let ad = base.action_map.values().next().unwrap();
+        assert!(ad.diff_on_failure);
+        assert_eq!(ad.diff_command, ["fmt", "--diff", "{{files...}}"]);
+
+        let disable = r#"[[actions]]
+name = "test/check_fmt"
+merge = "change"
+diff-on-failure = false
+"#;
+        let changed = base
+            .clone()
+            .merge(ConfigurationSource::from_string(disable).unwrap())
+            .unwrap();
+        assert!(!changed.action_map.values().next().unwrap().diff_on_failure);
+
+        let missing_command = r#"[[actions]]
+name = "test/check_other"
+command = "other --check"
+diff-on-failure = true
+"#;
+        assert!(base
+            .merge(ConfigurationSource::from_string(missing_command).unwrap())
+            .is_err());
+    }
 }
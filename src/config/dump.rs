@@ -0,0 +1,323 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+
+use super::Configuration;
+
+/// The format to print the configuration in
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum DumpFormat {
+    /// Pretty printed JSON
+    Json,
+    /// TOML, like the configuration files
+    Toml,
+}
+
+/// The files an action may change, like the `writes` key
+#[derive(Debug, serde::Serialize)]
+#[serde(untagged)]
+enum DumpedWrites {
+    InputFiles(bool),
+    Patterns(Vec<String>),
+}
+
+/// An action as merged from all configuration files, using the keys of the
+/// configuration files where possible
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[allow(clippy::struct_excessive_bools)]
+struct DumpedAction {
+    name: String,
+    /// The configuration file defining the action
+    source: String,
+    /// The configuration files changing the action afterwards
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    changed_in: Vec<String>,
+    description: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    command: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    script: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    environment: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    isolated_environment: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    path_prepend: Vec<String>,
+    project_tools: bool,
+    run_sequentially: bool,
+    exit_code: i32,
+    show_output: &'static str,
+    diff_on_failure: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    diff_command: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sandbox: Option<&'static str>,
+    sandbox_apply: bool,
+    writes: DumpedWrites,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    full_run_threshold: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    full_command: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_files: Option<usize>,
+    files_via: &'static str,
+    parallel_invocations: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cwd_per_target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stdout_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr_file: Option<String>,
+    severity: &'static str,
+    allow_failure: bool,
+    phase: &'static str,
+    gate: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    needs: Vec<String>,
+    /// The patterns of each input, with `content` and `kind` filters
+    inputs: BTreeMap<String, Vec<String>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    enabled_when: BTreeMap<&'static str, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    matrix: BTreeMap<String, Vec<String>>,
+}
+
+/// An action group as merged from all configuration files
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct DumpedActionGroup {
+    name: String,
+    /// The configuration file defining the group
+    source: String,
+    actions: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct DumpedConfiguration {
+    action_groups: Vec<DumpedActionGroup>,
+    actions: Vec<DumpedAction>,
+}
+
+fn environment_strings(environment: &[(String, String)]) -> Vec<String> {
+    environment
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect()
+}
+
+fn to_strings(values: &[impl ToString]) -> Vec<String> {
+    values.iter().map(ToString::to_string).collect()
+}
+
+fn dump_action(
+    action: &beautytips::ActionDefinition,
+    source: String,
+    changed_in: Vec<String>,
+) -> DumpedAction {
+    let filters = &action.input_filters;
+    let mut inputs = filters
+        .inputs()
+        .map(|i| (i.clone(), to_strings(filters.patterns(i))))
+        .collect::<BTreeMap<_, _>>();
+    if !filters.content_patterns().is_empty() {
+        inputs.insert(
+            "content".to_string(),
+            to_strings(filters.content_patterns()),
+        );
+    }
+    if let Some(kind) = filters.kind() {
+        inputs.insert("kind".to_string(), vec![kind.to_string()]);
+    }
+
+    let condition = &action.enabled_when;
+    let enabled_when = [
+        ("env", condition.env.clone()),
+        (
+            "file-exists",
+            condition
+                .file_exists
+                .as_ref()
+                .map(|f| f.display().to_string()),
+        ),
+        ("os", condition.os.clone()),
+    ]
+    .into_iter()
+    .filter_map(|(k, v)| Some((k, v?)))
+    .collect();
+
+    DumpedAction {
+        name: action.id.to_string(),
+        source,
+        changed_in,
+        description: action.description.clone(),
+        tags: action.tags.clone(),
+        command: action.command.clone(),
+        script: action.script.clone(),
+        environment: environment_strings(&action.environment),
+        isolated_environment: environment_strings(&action.isolated_environment),
+        path_prepend: action
+            .path_prepend
+            .iter()
+            .map(|d| d.display().to_string())
+            .collect(),
+        project_tools: action.project_tools,
+        run_sequentially: action.run_sequentially,
+        exit_code: action.expected_exit_code,
+        show_output: match action.show_output {
+            beautytips::OutputCondition::Never => "never",
+            beautytips::OutputCondition::Success => "success",
+            beautytips::OutputCondition::Failure => "failure",
+            beautytips::OutputCondition::Always => "always",
+            beautytips::OutputCondition::Summary => "summary",
+        },
+        diff_on_failure: action.diff_on_failure,
+        diff_command: action.diff_command.clone(),
+        sandbox: action.sandbox.as_ref().map(|s| match s {
+            beautytips::SandboxMode::Copy => "copy",
+            beautytips::SandboxMode::Worktree => "worktree",
+            beautytips::SandboxMode::Restricted => "restricted",
+        }),
+        sandbox_apply: action.sandbox_apply,
+        writes: match &action.writes {
+            beautytips::Writes::Nothing => DumpedWrites::InputFiles(false),
+            beautytips::Writes::InputFiles => DumpedWrites::InputFiles(true),
+            beautytips::Writes::Patterns(patterns) => DumpedWrites::Patterns(to_strings(patterns)),
+        },
+        full_run_threshold: action.full_run_threshold,
+        full_command: action.full_command.clone(),
+        max_files: action.max_files,
+        files_via: match action.files_via {
+            beautytips::FilesVia::Arguments => "arguments",
+            beautytips::FilesVia::File => "file",
+        },
+        parallel_invocations: action.parallel_invocations,
+        cwd_per_target: action.cwd_per_target.clone(),
+        hint: action.hint.clone(),
+        stdout_file: action.stdout_file.as_ref().map(|f| f.display().to_string()),
+        stderr_file: action.stderr_file.as_ref().map(|f| f.display().to_string()),
+        severity: match action.severity {
+            beautytips::Severity::Error => "error",
+            beautytips::Severity::Warning => "warning",
+            beautytips::Severity::Info => "info",
+        },
+        allow_failure: action.allow_failure,
+        phase: match action.phase {
+            beautytips::Phase::Fast => "fast",
+            beautytips::Phase::Slow => "slow",
+        },
+        gate: action.gate,
+        needs: action.needs.iter().map(ToString::to_string).collect(),
+        inputs,
+        enabled_when,
+        matrix: action.matrix.iter().cloned().collect(),
+    }
+}
+
+fn dump_configuration(config: &Configuration) -> DumpedConfiguration {
+    let source = |id| config.origins.get(id).cloned().unwrap_or_default();
+
+    let mut group_names = config.action_groups.keys().collect::<Vec<_>>();
+    group_names.sort();
+    let action_groups = group_names
+        .into_iter()
+        .map(|name| DumpedActionGroup {
+            name: name.to_string(),
+            source: source(name),
+            actions: to_strings(&config.action_groups[name]),
+        })
+        .collect();
+
+    let actions = config
+        .action_map
+        .iter()
+        .map(|(id, action)| {
+            dump_action(
+                action,
+                source(id),
+                config.changed_in.get(id).cloned().unwrap_or_default(),
+            )
+        })
+        .collect();
+
+    DumpedConfiguration {
+        action_groups,
+        actions,
+    }
+}
+
+/// The merged configuration in `format`, with the configuration file each
+/// action and group comes from
+pub fn dump(config: &Configuration, format: DumpFormat) -> anyhow::Result<String> {
+    let dumped = dump_configuration(config);
+    match format {
+        DumpFormat::Json => {
+            serde_json::to_string_pretty(&dumped).context("Failed to write the configuration")
+        }
+        DumpFormat::Toml => toml::to_string(&dumped).context("Failed to write the configuration"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::ConfigurationSource;
+
+    #[test]
+    fn test_dump() {
+        let base = r#"
+[[action_groups]]
+name = "test/g1"
+actions = ["test/t1"]
+
+[[actions]]
+name = "test/t1"
+command = "lint {{files}}"
+inputs.files = ["*.rs"]
+"#;
+        let change = r#"
+[[actions]]
+name = "test/t1"
+merge = "change"
+hint = "run lint --fix"
+"#;
+        let base =
+            ConfigurationSource::parse(base, Some(std::path::Path::new("base.toml"))).unwrap();
+        let change =
+            ConfigurationSource::parse(change, Some(std::path::Path::new("change.toml"))).unwrap();
+        let config = Configuration::default()
+            .merge(base)
+            .unwrap()
+            .merge(change)
+            .unwrap();
+
+        let toml = dump(&config, DumpFormat::Toml).unwrap();
+        let value: toml::Value = toml::from_str(&toml).unwrap();
+        let action = &value["actions"][0];
+        assert_eq!(action["name"].as_str(), Some("test/t1"));
+        assert_eq!(action["source"].as_str(), Some("base.toml"));
+        assert_eq!(action["changed-in"][0].as_str(), Some("change.toml"));
+        assert_eq!(action["hint"].as_str(), Some("run lint --fix"));
+        assert_eq!(action["inputs"]["files"][0].as_str(), Some("*.rs"));
+        assert_eq!(
+            value["action_groups"][0]["source"].as_str(),
+            Some("base.toml")
+        );
+
+        let json = dump(&config, DumpFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["actions"][0]["command"][0].as_str(), Some("lint"));
+        assert_eq!(
+            value["action_groups"][0]["actions"][0].as_str(),
+            Some("test/t1")
+        );
+    }
+}
@@ -0,0 +1,144 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// A position in a configuration file
+#[derive(Debug)]
+struct Location {
+    /// 1-based line number
+    line: usize,
+    /// 1-based column (in characters)
+    column: usize,
+    /// The number of characters to mark in `source_line`
+    length: usize,
+    /// The text of the line
+    source_line: String,
+}
+
+impl Location {
+    fn new(source: &str, span: Range<usize>) -> Self {
+        let start = span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |p| p + 1);
+        let source_line = source[line_start..].lines().next().unwrap_or_default();
+        let length = source
+            .get(start..span.end.min(line_start + source_line.len()))
+            .map_or(0, |s| s.chars().count());
+
+        Self {
+            line: source[..start].matches('\n').count() + 1,
+            column: source[line_start..start].chars().count() + 1,
+            length: length.max(1),
+            source_line: source_line.to_string(),
+        }
+    }
+}
+
+/// An error in a configuration file
+///
+/// This points to the offending part of the file.
+#[derive(Debug)]
+pub struct ConfigError {
+    file: Option<PathBuf>,
+    location: Option<Location>,
+    context: Option<String>,
+    message: String,
+}
+
+impl ConfigError {
+    /// Create a new error for `span` in `source`
+    ///
+    /// `context` describes what is at `span`, e.g. "key `foo`".
+    pub fn new(
+        source: &str,
+        file: Option<&Path>,
+        span: Option<Range<usize>>,
+        context: Option<String>,
+        message: String,
+    ) -> Self {
+        Self {
+            file: file.map(Path::to_path_buf),
+            location: span.map(|s| Location::new(source, s)),
+            context,
+            message,
+        }
+    }
+
+    /// Create a new error from a TOML parser error
+    pub fn from_toml(source: &str, file: Option<&Path>, error: &toml::de::Error) -> Self {
+        let span = error.span();
+        let key = span.as_ref().and_then(|s| {
+            let line = Location::new(source, s.clone()).source_line;
+            line.split_once('=')
+                .map(|(k, _)| k.trim().to_string())
+                .filter(|k| !k.is_empty())
+        });
+
+        Self::new(
+            source,
+            file,
+            span,
+            key.map(|k| format!("key `{k}`")),
+            error.message().trim().to_string(),
+        )
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let file = self.file.as_ref().map_or_else(
+            || "<configuration>".to_string(),
+            |f| f.display().to_string(),
+        );
+
+        match &self.location {
+            Some(l) => write!(f, "{file}:{}:{}: {}", l.line, l.column, self.message)?,
+            None => write!(f, "{file}: {}", self.message)?,
+        }
+        if let Some(context) = &self.context {
+            write!(f, "\n  in {context}")?;
+        }
+        if let Some(l) = &self.location {
+            let number = l.line.to_string();
+            let padding = " ".repeat(number.len());
+            write!(
+                f,
+                "\n{padding} |\n{number} | {}\n{padding} | {}{}",
+                l.source_line,
+                " ".repeat(l.column - 1),
+                "^".repeat(l.length)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_error_display() {
+        let source = "[[actions]]\nname = \"test/t1\"\nid = \"foo\"\n";
+        let error = ConfigError::new(
+            source,
+            Some(Path::new("config.toml")),
+            Some(29..31),
+            Some("key `id`".to_string()),
+            "unknown field `id`".to_string(),
+        );
+
+        assert_eq!(
+            error.to_string(),
+            "config.toml:3:1: unknown field `id`\n  in key `id`\n  |\n3 | id = \"foo\"\n  | ^^"
+        );
+
+        let error = ConfigError::new(source, None, None, None, "broken".to_string());
+        assert_eq!(error.to_string(), "<configuration>: broken");
+    }
+}
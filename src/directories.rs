@@ -0,0 +1,76 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::path::PathBuf;
+
+/// The environment variable overriding the cache directory
+pub const CACHE_DIR_VARIABLE: &str = "BEAUTY_TIPS_CACHE_DIR";
+
+/// The environment variable overriding the state directory
+pub const STATE_DIR_VARIABLE: &str = "BEAUTY_TIPS_STATE_DIR";
+
+fn directory_from(variable: &str, platform: Option<PathBuf>) -> Option<PathBuf> {
+    std::env::var_os(variable)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| platform.map(|d| d.join("beautytips")))
+}
+
+/// The directory for data that can be recreated at any time, like the cache
+/// directories of actions and the locks of running runs
+///
+/// This is `beautytips` in the platform cache directory (`XDG_CACHE_HOME` on
+/// Linux), unless overridden by `BEAUTY_TIPS_CACHE_DIR`.
+#[must_use]
+pub fn cache_directory() -> Option<PathBuf> {
+    directory_from(CACHE_DIR_VARIABLE, dirs::cache_dir())
+}
+
+/// The directory for data that should survive between runs, like the results
+/// of the last run
+///
+/// This is `beautytips` in the platform state directory (`XDG_STATE_HOME` on
+/// Linux) or the local data directory on platforms without one, unless
+/// overridden by `BEAUTY_TIPS_STATE_DIR`.
+#[must_use]
+pub fn state_directory() -> Option<PathBuf> {
+    directory_from(
+        STATE_DIR_VARIABLE,
+        dirs::state_dir().or_else(dirs::data_local_dir),
+    )
+}
+
+/// The directory for log files, `logs` in the state directory
+#[must_use]
+pub fn log_directory() -> Option<PathBuf> {
+    state_directory().map(|d| d.join("logs"))
+}
+
+/// The directory holding the cache directories of all actions
+#[must_use]
+pub fn actions_cache_directory() -> Option<PathBuf> {
+    cache_directory().map(|d| d.join("actions"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_from() {
+        let platform = Some(PathBuf::from("/home/user/.cache"));
+        assert_eq!(
+            directory_from("BEAUTY_TIPS_TEST_UNSET_VARIABLE", platform.clone()),
+            Some(PathBuf::from("/home/user/.cache/beautytips"))
+        );
+        assert_eq!(
+            directory_from("BEAUTY_TIPS_TEST_UNSET_VARIABLE", None),
+            None
+        );
+        // Set by cargo when running tests
+        assert_eq!(
+            directory_from("CARGO_MANIFEST_DIR", platform),
+            Some(PathBuf::from(env!("CARGO_MANIFEST_DIR")))
+        );
+    }
+}
@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Context;
+
+/// The environment file always loaded from the root directory
+pub const BEAUTYTIPS_ENV_FILE: &str = ".beautytips.env";
+/// The environment file loaded from the root directory on request
+pub const DOTENV_FILE: &str = ".env";
+
+/// The value of a `KEY=VALUE` line
+///
+/// Single quoted values are taken literally, double quoted ones support `\n`,
+/// `\t`, `\"` and `\\` escapes. Unquoted values end at a ` #` comment.
+fn parse_value(value: &str) -> Option<String> {
+    if let Some(value) = value.strip_prefix('\'') {
+        return value.strip_suffix('\'').map(ToString::to_string);
+    }
+    if let Some(value) = value.strip_prefix('"') {
+        let value = value.strip_suffix('"')?;
+        let mut result = String::with_capacity(value.len());
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                result.push(c);
+                continue;
+            }
+            match chars.next()? {
+                'n' => result.push('\n'),
+                't' => result.push('\t'),
+                c => result.push(c),
+            }
+        }
+        return Some(result);
+    }
+    let value = value.find(" #").map_or(value, |p| &value[..p]);
+    Some(value.trim_end().to_string())
+}
+
+/// Parse the `KEY=VALUE` lines of an environment file
+///
+/// Empty lines and lines starting with `#` are ignored, as is an `export`
+/// in front of the key.
+fn parse(text: &str) -> crate::Result<Vec<(String, String)>> {
+    let mut result = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(anyhow::anyhow!(format!(
+                "Line {} is not of the form KEY=VALUE",
+                number + 1
+            )));
+        };
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(anyhow::anyhow!(format!(
+                "Line {} has an invalid key '{key}'",
+                number + 1
+            )));
+        }
+        let Some(value) = parse_value(value.trim_start()) else {
+            return Err(anyhow::anyhow!(format!(
+                "Line {} has an unterminated quote",
+                number + 1
+            )));
+        };
+        result.push((key.to_string(), value));
+    }
+    Ok(result)
+}
+
+/// Load the environment files in `root_directory`
+///
+/// `.env` is only loaded if `dotenv` is set. Variables in `.beautytips.env`
+/// override those in `.env`. Missing files are fine.
+pub fn load(root_directory: &Path, dotenv: bool) -> crate::Result<HashMap<String, String>> {
+    let files = if dotenv {
+        vec![DOTENV_FILE, BEAUTYTIPS_ENV_FILE]
+    } else {
+        vec![BEAUTYTIPS_ENV_FILE]
+    };
+
+    let mut result = HashMap::new();
+    for f in files {
+        let path = root_directory.join(f);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(e).context(format!("Failed to read {}", path.display()));
+            }
+        };
+        tracing::debug!("Loading environment from {path:?}");
+        result.extend(parse(&text).context(format!("Failed to parse {}", path.display()))?);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = r#"
+# A comment
+RUST_LOG=debug
+export LINT_LEVEL = strict # the default
+QUOTED="a \"b\"\nc"
+LITERAL='$HOME \n'
+EMPTY=
+"#;
+        assert_eq!(
+            parse(text).unwrap(),
+            [
+                ("RUST_LOG".to_string(), "debug".to_string()),
+                ("LINT_LEVEL".to_string(), "strict".to_string()),
+                ("QUOTED".to_string(), "a \"b\"\nc".to_string()),
+                ("LITERAL".to_string(), "$HOME \\n".to_string()),
+                ("EMPTY".to_string(), String::new()),
+            ]
+        );
+
+        assert!(parse("NO_VALUE\n").is_err());
+        assert!(parse("A B=c\n").is_err());
+        assert!(parse("OPEN=\"value\n").is_err());
+    }
+}
@@ -0,0 +1,367 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::fmt::Write;
+
+fn command_to_string(command: &[String]) -> String {
+    command
+        .iter()
+        .map(|a| {
+            if a.is_empty() || a.contains(char::is_whitespace) {
+                shell_words::quote(a).to_string()
+            } else {
+                a.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn tool_name(command: &[String]) -> Option<String> {
+    let executable = command.first()?;
+    let is_self =
+        std::env::current_exe().is_ok_and(|exe| exe.to_string_lossy() == executable.as_str());
+    Some(if is_self {
+        "beautytips (builtin)".to_string()
+    } else {
+        executable.clone()
+    })
+}
+
+fn write_inputs(result: &mut String, filters: &beautytips::InputFilters) {
+    let mut inputs = filters.inputs().collect::<Vec<_>>();
+    inputs.sort();
+    if inputs.is_empty() {
+        let _ = writeln!(result, "Inputs:      none, runs once");
+    }
+    for (index, input) in inputs.iter().enumerate() {
+        let patterns = filters.patterns(input);
+        let patterns = if patterns.is_empty() {
+            "all".to_string()
+        } else {
+            patterns_to_string(patterns)
+        };
+        let label = if index == 0 { "Inputs:      " } else { INDENT };
+        let _ = writeln!(result, "{label}{input}: {patterns}");
+    }
+    if let Some(kind) = filters.kind() {
+        let _ = writeln!(result, "Kind:        {kind} files only");
+    }
+    if !filters.content_patterns().is_empty() {
+        let _ = writeln!(
+            result,
+            "Content:     {}",
+            patterns_to_string(filters.content_patterns())
+        );
+    }
+}
+
+fn patterns_to_string(patterns: &[impl ToString]) -> String {
+    patterns
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub(crate) fn condition_to_string(condition: &beautytips::Condition) -> String {
+    let mut parts = vec![];
+    if let Some(env) = &condition.env {
+        parts.push(format!("env {env}"));
+    }
+    if let Some(file) = &condition.file_exists {
+        parts.push(format!("{} exists", file.display()));
+    }
+    if let Some(os) = &condition.os {
+        parts.push(format!("on {os}"));
+    }
+    parts.join(", ")
+}
+
+/// The interpreter named in the `#!` line of `script`
+fn script_interpreter(script: &str) -> Option<String> {
+    let line = script.lines().next()?.strip_prefix("#!")?;
+    let mut parts = line.split_whitespace();
+    let interpreter = parts.next()?;
+    if interpreter.ends_with("/env") {
+        parts.next().map(ToString::to_string)
+    } else {
+        Some(interpreter.to_string())
+    }
+}
+
+const INDENT: &str = "             ";
+
+/// Write the `Script:` or `Command:` lines of `action` to `result`
+fn write_command(result: &mut String, action: &beautytips::ActionDefinition) {
+    if let Some(script) = &action.script {
+        for (index, line) in script.lines().enumerate() {
+            let label = if index == 0 { "Script:      " } else { INDENT };
+            let _ = writeln!(result, "{}", format!("{label}{line}").trim_end());
+        }
+    } else if action.command.is_empty() {
+        let _ = writeln!(result, "Command:     none for this operating system");
+    } else {
+        let _ = writeln!(
+            result,
+            "Command:     {}",
+            command_to_string(&action.command)
+        );
+    }
+    if let Some(threshold) = action.full_run_threshold {
+        let _ = writeln!(
+            result,
+            "Full run:    {} (above {threshold} files)",
+            command_to_string(&action.full_command)
+        );
+    }
+    if let Some(max_files) = action.max_files {
+        let _ = writeln!(result, "Max files:   {max_files}, refuses to run on more");
+    }
+    if action.files_via == beautytips::FilesVia::File {
+        let _ = writeln!(result, "Files via:   a temporary file, NUL separated");
+    }
+    if !action.path_prepend.is_empty() {
+        let _ = writeln!(
+            result,
+            "PATH starts: {}",
+            action
+                .path_prepend
+                .iter()
+                .map(|d| d.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    if action.project_tools {
+        let _ = writeln!(
+            result,
+            "Tools from:  {}, when they exist",
+            beautytips::PROJECT_TOOL_DIRECTORIES.join(", ")
+        );
+    }
+}
+
+/// Write the output files, matrix, severity and allowed failures of `action`
+/// to `result`
+fn write_results(result: &mut String, action: &beautytips::ActionDefinition) {
+    for (kind, file) in [
+        ("Output", &action.stdout_file),
+        ("Errors", &action.stderr_file),
+    ] {
+        if let Some(file) = file {
+            let _ = writeln!(result, "{kind}:      written to {}", file.display());
+        }
+    }
+    for (index, (name, values)) in action.matrix.iter().enumerate() {
+        let label = if index == 0 { "Matrix:      " } else { INDENT };
+        let _ = writeln!(result, "{label}{name}: {}", values.join(", "));
+    }
+    match action.severity {
+        beautytips::Severity::Error => {
+            let _ = writeln!(result, "Severity:    error, warnings fail the run");
+        }
+        beautytips::Severity::Info => {
+            let _ = writeln!(result, "Severity:    info, never fails the run");
+        }
+        beautytips::Severity::Warning => {}
+    }
+    if action.allow_failure {
+        let _ = writeln!(result, "Failures:    allowed, they never fail the run");
+    }
+}
+
+/// Write when and how `action` runs to `result`
+fn write_runs(result: &mut String, action: &beautytips::ActionDefinition) {
+    let _ = writeln!(
+        result,
+        "Runs:        {}",
+        if action.run_sequentially {
+            "after all parallel actions, one at a time"
+        } else {
+            "in parallel with other actions"
+        }
+    );
+    if action.gate {
+        let _ = writeln!(result, "{INDENT}before all other actions, as a gate");
+    } else if action.phase == beautytips::Phase::Fast {
+        let _ = writeln!(result, "{INDENT}before all slow actions");
+    }
+    if !action.needs.is_empty() {
+        let needs = action
+            .needs
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let _ = writeln!(result, "{INDENT}only when {} passed", needs.join(", "));
+    }
+    if action.parallel_invocations {
+        let _ = writeln!(result, "{INDENT}with its invocations in parallel");
+    }
+    if let Some(input) = &action.cwd_per_target {
+        let _ = writeln!(result, "{INDENT}once in each directory of {input}");
+    }
+}
+
+/// Render a help page for `action`
+///
+/// `groups` are the names of the action groups including the action.
+pub fn action_help(action: &beautytips::ActionDefinition, groups: &[String]) -> String {
+    let mut result = String::new();
+
+    if action.description.is_empty() {
+        let _ = writeln!(result, "{}", action.id);
+    } else {
+        let _ = writeln!(result, "{} - {}", action.id, action.description);
+    }
+    result.push('\n');
+
+    write_command(&mut result, action);
+    if action.diff_on_failure {
+        let _ = writeln!(
+            result,
+            "Diff:        {}",
+            command_to_string(&action.diff_command)
+        );
+    }
+
+    let mut tools = vec![];
+    tools.extend(tool_name(&action.command));
+    tools.extend(tool_name(&action.full_command).filter(|t| !tools.contains(t)));
+    tools.extend(action.script.as_deref().and_then(script_interpreter));
+    if action.diff_on_failure {
+        tools.extend(tool_name(&action.diff_command).filter(|t| !tools.contains(t)));
+    }
+    let _ = writeln!(result, "Tools:       {}", tools.join(", "));
+
+    write_inputs(&mut result, &action.input_filters);
+
+    write_runs(&mut result, action);
+    if let Some(sandbox) = &action.sandbox {
+        let _ = writeln!(
+            result,
+            "Sandbox:     {}",
+            match sandbox {
+                beautytips::SandboxMode::Copy => "copy of the input files",
+                beautytips::SandboxMode::Worktree => "git worktree",
+                beautytips::SandboxMode::Restricted => "read-only file system",
+            }
+        );
+    }
+    match &action.writes {
+        beautytips::Writes::Nothing => {}
+        beautytips::Writes::InputFiles => {
+            let _ = writeln!(result, "Writes:      its input files");
+        }
+        beautytips::Writes::Patterns(patterns) => {
+            let _ = writeln!(result, "Writes:      {}", patterns_to_string(patterns));
+        }
+    }
+    if !action.enabled_when.is_empty() {
+        let _ = writeln!(
+            result,
+            "Runs when:   {}",
+            condition_to_string(&action.enabled_when)
+        );
+    }
+    let _ = writeln!(result, "Exit code:   {}", action.expected_exit_code);
+    if let Some(hint) = &action.hint {
+        let _ = writeln!(result, "Hint:        {hint}");
+    }
+    write_results(&mut result, action);
+    if !groups.is_empty() {
+        let _ = writeln!(result, "Groups:      {}", groups.join(", "));
+    }
+    if !action.tags.is_empty() {
+        let _ = writeln!(result, "Tags:        {}", action.tags.join(", "));
+    }
+
+    result.push_str("\nExamples:\n");
+    let _ = writeln!(
+        result,
+        "  beautytips run --from-vcs {}     # changed files only",
+        action.id
+    );
+    let _ = writeln!(
+        result,
+        "  beautytips run --from-dir . {}   # all files",
+        action.id
+    );
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_action_help() {
+        let action = beautytips::ActionDefinition {
+            id: "toml/check_fmt".parse().unwrap(),
+            description: "Run taplo fmt".to_string(),
+            tags: vec![],
+            run_sequentially: false,
+            command: vec![
+                "taplo".to_string(),
+                "fmt".to_string(),
+                "--check".to_string(),
+                "{{files...}}".to_string(),
+            ],
+            script: None,
+            environment: vec![],
+            isolated_environment: vec![],
+            path_prepend: vec![],
+            project_tools: false,
+            show_output: beautytips::OutputCondition::Failure,
+            expected_exit_code: 0,
+            input_filters: beautytips::InputFilters::try_from(HashMap::from([(
+                "files".to_string(),
+                vec!["**/*.toml".to_string()],
+            )]))
+            .unwrap(),
+            diff_on_failure: false,
+            diff_command: vec![],
+            sandbox: None,
+            sandbox_apply: true,
+            writes: beautytips::Writes::Nothing,
+            enabled_when: beautytips::Condition::default(),
+            full_run_threshold: None,
+            full_command: vec![],
+            max_files: None,
+            files_via: beautytips::FilesVia::Arguments,
+            parallel_invocations: false,
+            cwd_per_target: None,
+            hint: None,
+            stdout_file: None,
+            stderr_file: None,
+            matrix: vec![],
+            variant: None,
+            severity: beautytips::Severity::Warning,
+            allow_failure: false,
+            phase: beautytips::Phase::Slow,
+            gate: false,
+            needs: vec![],
+            timeout: None,
+        };
+
+        assert_eq!(
+            action_help(&action, &["toml/all".to_string()]),
+            r"toml/check_fmt - Run taplo fmt
+
+Command:     taplo fmt --check {{files...}}
+Tools:       taplo
+Inputs:      files: **/*.toml
+Runs:        in parallel with other actions
+Exit code:   0
+Groups:      toml/all
+
+Examples:
+  beautytips run --from-vcs toml/check_fmt     # changed files only
+  beautytips run --from-dir . toml/check_fmt   # all files
+"
+        );
+    }
+}
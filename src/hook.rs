@@ -0,0 +1,327 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::config;
+
+/// A git hook beautytips can run as
+#[derive(Clone, Debug, clap::Subcommand)]
+#[command(rename_all = "kebab-case")]
+pub enum HookCommand {
+    /// Run the `pre_push` actions on the files changed in the pushed
+    /// commits, reading the pushed refs from stdin like git passes them
+    PrePush {
+        /// The name of the remote pushed to
+        #[arg(value_name = "REMOTE", default_value = "origin")]
+        remote: String,
+        /// The URL of the remote pushed to, passed by git but unused
+        #[arg(value_name = "URL")]
+        url: Option<String>,
+    },
+    /// Run the `commit_msg` actions on the file holding the commit message
+    CommitMsg {
+        /// The file holding the commit message
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+}
+
+/// A ref being pushed, as passed to the `pre-push` hook
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct PushedRef {
+    /// The commit pushed
+    local_revision: String,
+    /// The commit the remote ref is at, `None` for new refs (which git
+    /// passes as all zeros)
+    remote_revision: Option<String>,
+}
+
+/// Parse the lines of the form `<local ref> <local sha> <remote ref> <remote
+/// sha>` git passes to the `pre-push` hook
+///
+/// Deleted refs push no commits and are left out.
+fn parse_pushed_refs(input: &str) -> anyhow::Result<Vec<PushedRef>> {
+    let is_null = |revision: &str| revision.chars().all(|c| c == '0');
+
+    let mut result = Vec::new();
+    for line in input.lines().filter(|l| !l.trim().is_empty()) {
+        let [_, local_revision, _, remote_revision] =
+            line.split_whitespace().collect::<Vec<_>>()[..]
+        else {
+            return Err(anyhow::anyhow!(format!(
+                "'{line}' is not of the form '<local ref> <local sha> <remote ref> <remote sha>'"
+            )));
+        };
+        if is_null(local_revision) {
+            continue;
+        }
+        result.push(PushedRef {
+            local_revision: local_revision.to_string(),
+            remote_revision: Some(remote_revision)
+                .filter(|r| !is_null(r))
+                .map(ToString::to_string),
+        });
+    }
+    Ok(result)
+}
+
+fn git(directory: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .current_dir(directory)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// `true` if `revision` is a commit known in the repository in `directory`
+fn is_known_commit(directory: &Path, revision: &str) -> bool {
+    git(
+        directory,
+        &["cat-file", "-e", &format!("{revision}^{{commit}}")],
+    )
+    .is_ok()
+}
+
+/// The revision to compare a ref pushed to `remote` with, if the remote does
+/// not have the commit it points to yet: The parent of the oldest commit not
+/// on the remote
+///
+/// Commits on any remote count as known to `remote` if it has no refs at all.
+/// If no commit is new, like for a new branch pointing to a commit known
+/// already, `local_revision` itself is returned, so nothing changed. A root
+/// commit is compared to the empty tree.
+fn base_revision(directory: &Path, local_revision: &str, remote: &str) -> anyhow::Result<String> {
+    let remote_refs = git(
+        directory,
+        &[
+            "for-each-ref",
+            "--format=%(refname)",
+            &format!("refs/remotes/{remote}/"),
+        ],
+    )?;
+    let known = if remote_refs.is_empty() {
+        "--remotes".to_string()
+    } else {
+        format!("--remotes={remote}")
+    };
+    let not_on_remote = git(
+        directory,
+        &["rev-list", "--reverse", local_revision, "--not", &known],
+    )?;
+    let Some(oldest) = not_on_remote.lines().next() else {
+        return Ok(local_revision.to_string());
+    };
+
+    let parent = format!("{oldest}~");
+    if is_known_commit(directory, &parent) {
+        git(directory, &["rev-parse", &parent])
+    } else {
+        git(directory, &["hash-object", "-t", "tree", "--stdin"])
+    }
+}
+
+/// The revision to compare the pushed `r` with
+///
+/// That is the commit the remote ref is at, unless the ref is new or the
+/// remote commit is unknown locally, like after a force push of somebody
+/// else.
+fn compare_with(directory: &Path, r: &PushedRef, remote: &str) -> anyhow::Result<String> {
+    match &r.remote_revision {
+        Some(revision) if is_known_commit(directory, revision) => Ok(revision.clone()),
+        _ => base_revision(directory, &r.local_revision, remote),
+    }
+}
+
+/// The files changed by the commits in `pushed` that still exist
+fn pushed_files(
+    directory: &Path,
+    pushed: &[PushedRef],
+    remote: &str,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for r in pushed {
+        let from = compare_with(directory, r, remote)?;
+        let changed = git(
+            directory,
+            &[
+                "diff",
+                "--name-only",
+                "--diff-filter=d",
+                "--no-ext-diff",
+                "-z",
+                &from,
+                &r.local_revision,
+            ],
+        )?;
+        files.extend(
+            changed
+                .split('\0')
+                .filter(|f| !f.is_empty())
+                .map(PathBuf::from),
+        );
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// The files to look at for the `pushed` refs
+///
+/// A single pushed ref is checked as recorded in the pushed commit, in a
+/// snapshot of it. The files of several refs are checked in the working
+/// copy, as they may come from different commits.
+fn pushed_input(
+    directory: &Path,
+    pushed: &[PushedRef],
+    remote: &str,
+) -> anyhow::Result<beautytips::InputFiles> {
+    if let [r] = pushed {
+        return Ok(beautytips::InputFiles::Vcs(beautytips::VcsInput {
+            tool: Some("git".to_string()),
+            from_revision: Some(compare_with(directory, r, remote)?),
+            at_revision: Some(r.local_revision.clone()),
+            ..beautytips::VcsInput::default()
+        }));
+    }
+    Ok(beautytips::InputFiles::FileList(pushed_files(
+        directory, pushed, remote,
+    )?))
+}
+
+/// The files to look at and the actions to run for `hook`
+///
+/// The actions are those in the action groups named like the hook, e.g.
+/// `my/pre_push`.
+pub fn hook_inputs(
+    hook: &HookCommand,
+) -> anyhow::Result<(beautytips::InputFiles, config::ActionSelectors)> {
+    match hook {
+        HookCommand::PrePush { remote, .. } => {
+            let mut input = String::new();
+            std::io::stdin()
+                .read_to_string(&mut input)
+                .context("Failed to read the pushed refs from stdin")?;
+            let pushed = parse_pushed_refs(&input)?;
+            let directory =
+                std::env::current_dir().context("Failed to look up the current directory")?;
+            Ok((
+                pushed_input(&directory, &pushed, remote)?,
+                config::ActionSelectors::new(["pre_push"])?,
+            ))
+        }
+        HookCommand::CommitMsg { file } => Ok((
+            beautytips::InputFiles::FileList(vec![file.clone()]),
+            config::ActionSelectors::new(["commit_msg"])?,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pushed_refs() {
+        let null = "0".repeat(40);
+        let input = format!(
+            "refs/heads/main 1111 refs/heads/main 2222\n\
+             refs/heads/new 3333 refs/heads/new {null}\n\
+             (delete) {null} refs/heads/gone 4444\n"
+        );
+        assert_eq!(
+            parse_pushed_refs(&input).unwrap(),
+            vec![
+                PushedRef {
+                    local_revision: "1111".to_string(),
+                    remote_revision: Some("2222".to_string()),
+                },
+                PushedRef {
+                    local_revision: "3333".to_string(),
+                    remote_revision: None,
+                },
+            ]
+        );
+        assert!(parse_pushed_refs("").unwrap().is_empty());
+        assert!(parse_pushed_refs("refs/heads/main 1111").is_err());
+    }
+
+    #[test]
+    fn test_pushed_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let directory = directory.path();
+        let run = |args: &[&str]| {
+            git(
+                directory,
+                &[
+                    &["-c", "user.name=Test", "-c", "user.email=test@example.com"],
+                    args,
+                ]
+                .concat(),
+            )
+            .unwrap()
+        };
+        let commit = |file: &str| {
+            std::fs::write(directory.join(file), file).unwrap();
+            run(&["add", file]);
+            run(&["commit", "--quiet", "-m", file]);
+            run(&["rev-parse", "HEAD"])
+        };
+        let files = |local: &str, remote: Option<&str>| {
+            let pushed = PushedRef {
+                local_revision: local.to_string(),
+                remote_revision: remote.map(ToString::to_string),
+            };
+            pushed_files(directory, &[pushed], "origin")
+                .unwrap()
+                .iter()
+                .map(|f| f.to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+        run(&["init", "--quiet"]);
+
+        // Nothing is on the remote, so all commits are new, down to the root
+        let root = commit("a.txt");
+        let second = commit("b.txt");
+        assert_eq!(files(&second, None), ["a.txt", "b.txt"]);
+
+        // Only the commits not on the remote yet are new
+        run(&["update-ref", "refs/remotes/origin/main", &root]);
+        assert_eq!(files(&second, None), ["b.txt"]);
+        assert_eq!(files(&second, Some(&root)), ["b.txt"]);
+
+        // A new branch pointing to a pushed commit changes nothing
+        run(&["update-ref", "refs/remotes/origin/main", &second]);
+        assert!(files(&second, None).is_empty());
+
+        // The remote commit of a forced push is unknown here
+        let third = commit("c.txt");
+        assert_eq!(files(&third, Some(&"1".repeat(40))), ["c.txt"]);
+
+        // A single ref is checked in a snapshot of the pushed commit
+        let pushed = PushedRef {
+            local_revision: third.clone(),
+            remote_revision: Some(second.clone()),
+        };
+        let beautytips::InputFiles::Vcs(input) =
+            pushed_input(directory, &[pushed], "origin").unwrap()
+        else {
+            panic!("Not checked in a snapshot");
+        };
+        assert_eq!(input.from_revision, Some(second));
+        assert_eq!(input.at_revision, Some(third));
+    }
+}
@@ -0,0 +1,255 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultKind {
+    Ok,
+    Skipped,
+    NotApplicable,
+    Warn,
+    Error,
+}
+
+impl From<&beautytips::ActionResult> for ResultKind {
+    fn from(value: &beautytips::ActionResult) -> Self {
+        match value {
+            beautytips::ActionResult::Ok { .. } => Self::Ok,
+            beautytips::ActionResult::Skipped => Self::Skipped,
+            beautytips::ActionResult::NotApplicable { .. } => Self::NotApplicable,
+            beautytips::ActionResult::Warn { .. } => Self::Warn,
+            beautytips::ActionResult::Error { .. } => Self::Error,
+        }
+    }
+}
+
+/// The results of the last run
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct LastRun {
+    pub root_directory: PathBuf,
+    pub files: Vec<PathBuf>,
+    pub results: BTreeMap<String, ResultKind>,
+}
+
+/// The last runs in all repositories, by their root directory
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+struct LastRuns {
+    #[serde(default)]
+    runs: BTreeMap<String, LastRun>,
+}
+
+impl LastRuns {
+    /// Read the last runs from `file_name`, which may not exist yet
+    fn read(file_name: &Path) -> anyhow::Result<Self> {
+        if !file_name.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = std::fs::read_to_string(file_name)
+            .context(format!("Failed to read {}", file_name.display()))?;
+        Self::from_string(&data).context(format!("Failed to parse {}", file_name.display()))
+    }
+
+    fn from_string(data: &str) -> anyhow::Result<Self> {
+        toml::from_str(data).context("Failed to parse toml")
+    }
+
+    fn insert(&mut self, last_run: LastRun) {
+        self.runs.insert(
+            last_run.root_directory.to_string_lossy().to_string(),
+            last_run,
+        );
+    }
+
+    /// The last run in the repository holding `directory`, the innermost one
+    /// for nested repositories
+    fn find(self, directory: &Path) -> Option<LastRun> {
+        self.runs
+            .into_values()
+            .filter(|r| directory.starts_with(&r.root_directory))
+            .max_by_key(|r| r.root_directory.components().count())
+    }
+}
+
+impl LastRun {
+    pub fn file_name() -> anyhow::Result<PathBuf> {
+        let state_dir =
+            beautytips::state_directory().ok_or(anyhow::anyhow!("State directory not found"))?;
+        Ok(state_dir.join("last_run.toml"))
+    }
+
+    /// Load the results of the last run in the repository holding
+    /// `current_directory` (if any)
+    pub fn load(current_directory: &Path) -> anyhow::Result<Option<Self>> {
+        let directory = current_directory
+            .canonicalize()
+            .unwrap_or_else(|_| current_directory.to_path_buf());
+        Ok(LastRuns::read(&Self::file_name()?)?.find(&directory))
+    }
+
+    /// Store the results, replacing those of the last run in the same
+    /// repository
+    fn store(&self) -> anyhow::Result<()> {
+        let file_name = Self::file_name()?;
+        if let Some(dir) = file_name.parent() {
+            std::fs::create_dir_all(dir).context(format!("Failed to create {}", dir.display()))?;
+        }
+
+        let mut runs = LastRuns::read(&file_name).unwrap_or_else(|e| {
+            tracing::warn!("Dropping the results of earlier runs: {e:#}");
+            LastRuns::default()
+        });
+        runs.insert(self.clone());
+        let data = toml::to_string(&runs).context("Failed to serialize the last run")?;
+        std::fs::write(&file_name, data).context(format!("Failed to write {}", file_name.display()))
+    }
+
+    /// Ids of all actions that produced a warning or an error, sorted
+    ///
+    /// Instances of actions with a matrix like `mypy/check[3.10]` are
+    /// reported once, as the action they belong to.
+    pub fn failed_actions(&self) -> Vec<String> {
+        self.results
+            .iter()
+            .filter(|(_, r)| matches!(r, ResultKind::Warn | ResultKind::Error))
+            .map(|(id, _)| {
+                // Strip the matrix values, see `ActionDefinition::instance_id`
+                id.split_once('[')
+                    .map_or(id.as_str(), |(id, _)| id)
+                    .to_string()
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Record the results of a run into the state directory
+///
+/// All reports are forwarded to the wrapped reporter.
+pub struct RecordingReporter {
+    inner: Box<dyn beautytips::Reporter>,
+    last_run: LastRun,
+}
+
+impl RecordingReporter {
+    pub fn new(inner: Box<dyn beautytips::Reporter>) -> Self {
+        Self {
+            inner,
+            last_run: LastRun::default(),
+        }
+    }
+}
+
+impl beautytips::Reporter for RecordingReporter {
+    fn report_files_collected(&mut self, root_directory: &Path, files: &[PathBuf]) {
+        self.last_run.root_directory = root_directory.to_path_buf();
+        self.last_run.files = files.to_vec();
+        self.inner.report_files_collected(root_directory, files);
+    }
+
+    fn report_start(&mut self, action: beautytips::ActionHandle) {
+        self.inner.report_start(action);
+    }
+
+    fn report_action_output_chunk(&mut self, action_id: &str, stdout: &[u8], stderr: &[u8]) {
+        self.inner
+            .report_action_output_chunk(action_id, stdout, stderr);
+    }
+
+    fn wants_output_chunks(&self) -> bool {
+        self.inner.wants_output_chunks()
+    }
+
+    fn report_action_detail(&mut self, action_id: &str, message: &str) {
+        self.inner.report_action_detail(action_id, message);
+    }
+
+    fn report_done(&mut self, action: beautytips::ActionHandle, result: beautytips::ActionResult) {
+        self.last_run
+            .results
+            .insert(action.id.clone(), ResultKind::from(&result));
+        self.inner.report_done(action, result);
+    }
+
+    fn report_allowed_failure(
+        &mut self,
+        action: beautytips::ActionHandle,
+        result: beautytips::ActionResult,
+    ) {
+        self.last_run
+            .results
+            .insert(action.id.clone(), ResultKind::from(&result));
+        self.inner.report_allowed_failure(action, result);
+    }
+
+    fn report_summary(&mut self, summary: &beautytips::RunSummary) {
+        self.inner.report_summary(summary);
+    }
+
+    fn finish(&mut self) {
+        self.inner.finish();
+
+        // Nothing ran without files collected
+        if self.last_run.root_directory.as_os_str().is_empty() {
+            return;
+        }
+        if let Err(e) = self.last_run.store() {
+            tracing::warn!("Failed to record the results of this run: {e:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_run_round_trip() {
+        let last_run = LastRun {
+            root_directory: PathBuf::from("/tmp/foo"),
+            files: vec![
+                PathBuf::from("/tmp/foo/a.rs"),
+                PathBuf::from("/tmp/foo/b.rs"),
+            ],
+            results: BTreeMap::from([
+                ("rust/check_fmt".to_string(), ResultKind::Warn),
+                ("rust/check_clippy".to_string(), ResultKind::Ok),
+                ("toml/check".to_string(), ResultKind::NotApplicable),
+                ("builtin/check_bom".to_string(), ResultKind::Error),
+                ("mypy/check[3.10]".to_string(), ResultKind::Error),
+                ("mypy/check[3.12]".to_string(), ResultKind::Warn),
+            ]),
+        };
+
+        let mut runs = LastRuns::default();
+        runs.insert(last_run.clone());
+        runs.insert(LastRun {
+            root_directory: PathBuf::from("/tmp"),
+            ..LastRun::default()
+        });
+        runs.insert(LastRun {
+            root_directory: PathBuf::from("/tmp/foobar"),
+            ..LastRun::default()
+        });
+        let data = toml::to_string(&runs).unwrap();
+        let runs = LastRuns::from_string(&data).unwrap();
+        assert_eq!(runs.runs.len(), 3);
+        let loaded = runs.find(Path::new("/tmp/foo/src")).unwrap();
+
+        assert_eq!(loaded.root_directory, last_run.root_directory);
+        assert_eq!(loaded.files, last_run.files);
+        assert_eq!(loaded.results, last_run.results);
+        assert_eq!(
+            loaded.failed_actions(),
+            ["builtin/check_bom", "mypy/check", "rust/check_fmt"]
+        );
+    }
+}
@@ -1,15 +1,41 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
 
+pub(crate) mod action_id;
 pub(crate) mod actions;
+pub(crate) mod directories;
+pub(crate) mod dotenv;
+pub(crate) mod lock;
+pub(crate) mod rewrite;
 pub(crate) mod vcs;
 
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use actions::ActionUpdateReceiver;
+pub use action_id::{ActionId, ActionSelector};
 pub use actions::{
-    inputs::InputFilters, ActionDefinition, ActionDefinitionIterator, OutputCondition,
+    inputs::{
+        register_input_generator, BoxedInputGenerator, ContentPattern, FileKind, InputFilters,
+        InputGenerator, InputPattern, IsBinary, DEFAULT_GENERATOR_TIMEOUT, PROBE_SIZE,
+    },
+    ActionDefinition, ActionOutput, ActionSet, Condition, FilesVia, OutputCondition,
+    ReportOverflow, SandboxMode, Writes, DEFAULT_REPORT_CAPACITY, DEFAULT_SPILL_THRESHOLD,
+    PROJECT_TOOL_DIRECTORIES,
+};
+use actions::{reorder::ReorderBuffer, ActionUpdate, ActionUpdateReceiver};
+pub use directories::{
+    actions_cache_directory, cache_directory, log_directory, state_directory, CACHE_DIR_VARIABLE,
+    STATE_DIR_VARIABLE,
 };
+pub use lock::RunLock;
+pub use rewrite::safe_rewrite;
+pub use vcs::{register_vcs, BoxedVcs, Vcs};
 
 use anyhow::Context;
 
@@ -24,11 +50,29 @@ pub struct VcsInput {
     pub from_revision: Option<String>,
     /// The revision to stop the comparison at (or None for default)
     pub to_revision: Option<String>,
+    /// Look at all files changed since this revision or date (like
+    /// `2024-06-01` or `1 week ago`), including changes in the working copy.
+    /// This replaces `from_revision` and `to_revision`.
+    pub changed_since: Option<String>,
+    /// Check the files changed in this revision (since `from_revision` if
+    /// set, compared to its parent otherwise) as recorded there, ignoring
+    /// the working copy. All files of the revision are written into a
+    /// temporary directory and the actions run in there. This replaces
+    /// `to_revision` and `changed_since`.
+    pub at_revision: Option<String>,
+    /// Ignore changed files below these paths (relative to the repository
+    /// root), e.g. vendored code
+    pub excluded_paths: Vec<PathBuf>,
 }
 
 #[derive(Clone, Debug)]
 pub(crate) struct ExecutionContext {
     pub root_directory: PathBuf,
+    /// The snapshot `root_directory` points into, if any
+    pub snapshot: Option<std::sync::Arc<vcs::Snapshot>>,
+    /// The unified diff of the changed files, for runs on changes from a
+    /// version control system
+    pub diff: Option<Vec<u8>>,
     pub extra_environment: HashMap<String, String>,
     pub files_to_process: Vec<PathBuf>,
 }
@@ -46,16 +90,331 @@ impl Default for InputFiles {
     }
 }
 
-pub use actions::ActionResult;
+pub use actions::{ActionHandle, ActionResult, NotApplicableReason, Phase, Severity};
+
+/// Statistics about a run
+#[derive(Clone, Debug, Default)]
+pub struct RunSummary {
+    pub ok: usize,
+    pub skipped: usize,
+    pub not_applicable: usize,
+    pub warn: usize,
+    pub error: usize,
+    /// Warnings and errors of actions that are allowed to fail
+    pub allowed_failures: usize,
+    /// Time spent running actions
+    pub duration: std::time::Duration,
+}
+
+impl RunSummary {
+    fn count(&mut self, result: &ActionResult) {
+        match result {
+            ActionResult::Ok { .. } => self.ok += 1,
+            ActionResult::Skipped => self.skipped += 1,
+            ActionResult::NotApplicable { .. } => self.not_applicable += 1,
+            ActionResult::Warn { .. } => self.warn += 1,
+            ActionResult::Error { .. } => self.error += 1,
+        }
+    }
+
+    /// `true` if any action failed, not counting those allowed to fail
+    #[must_use]
+    pub fn failed(&self) -> bool {
+        self.warn > 0 || self.error > 0
+    }
+
+    /// Add the statistics of `other` run to these
+    pub fn add(&mut self, other: &Self) {
+        self.ok += other.ok;
+        self.skipped += other.skipped;
+        self.not_applicable += other.not_applicable;
+        self.warn += other.warn;
+        self.error += other.error;
+        self.allowed_failures += other.allowed_failures;
+        self.duration += other.duration;
+    }
+}
+
+/// Options for `run_with_options`
+#[derive(Clone, Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct RunOptions {
+    /// Report actions sorted by their id, independent of the order they
+    /// finish in
+    ///
+    /// Updates of an action are held back till all actions before it are
+    /// done. This makes the reports of different runs comparable. Reporters
+    /// measuring the time between start and done will see held back actions
+    /// finish immediately.
+    pub stable_order: bool,
+    /// Load `.env` from the root directory, next to `.beautytips.env`
+    pub dotenv: bool,
+    /// Extra environment variables for all actions, overriding those from
+    /// the environment files
+    pub environment: Vec<(String, String)>,
+    /// The number of invocations of actions with `parallel_invocations` to
+    /// run at the same time, defaults to the number of CPUs
+    pub jobs: Option<usize>,
+    /// Report details on running actions (like the commands run) to
+    /// `Reporter::report_action_detail` when above 0
+    pub verbosity: u8,
+    /// The number of reports to queue up for a reporter that can not keep
+    /// up, defaults to `DEFAULT_REPORT_CAPACITY`
+    pub report_capacity: Option<usize>,
+    /// What to do when more reports than that are waiting
+    pub report_overflow: ReportOverflow,
+    /// What to do when another run is working in the same repository
+    pub lock: RunLock,
+    /// Set the `isolated_environment` of the actions, so they do not share
+    /// state like build directories with the tools run by the developer
+    pub isolate: bool,
+    /// Run the slow actions even if fast actions failed, instead of
+    /// reporting them as skipped
+    pub keep_going: bool,
+    /// Refuse to run when there are more files to look at than this
+    pub max_files: Option<usize>,
+    /// Cancel all actions still running after this long, reporting them as
+    /// errors, and fail with `TimedOut`
+    pub timeout: Option<std::time::Duration>,
+    /// Write outputs of actions larger than this many bytes into files in
+    /// the log directory instead of keeping them in memory, defaults to
+    /// `DEFAULT_SPILL_THRESHOLD`
+    pub spill_threshold: Option<usize>,
+    /// Actions the user chose not to run, reported and counted as skipped
+    pub skipped: ActionSet,
+}
+
+/// The run took longer than `RunOptions::timeout`
+#[derive(Debug)]
+pub struct TimedOut {
+    pub timeout: std::time::Duration,
+    /// The number of actions that got cancelled
+    pub cancelled: usize,
+}
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The run timed out after {}s, {} action(s) got cancelled",
+            self.timeout.as_secs(),
+            self.cancelled
+        )
+    }
+}
+
+impl std::error::Error for TimedOut {}
 
 /// Report results of an Action
 pub trait Reporter {
-    fn report_start(&mut self, taction_id: String);
-    fn report_done(&mut self, action_id: String, result: ActionResult);
+    /// The input files got collected, all actions will work on (a subset of)
+    /// `files` in `root_directory`
+    fn report_files_collected(&mut self, _root_directory: &Path, _files: &[PathBuf]) {}
+
+    fn report_start(&mut self, action: ActionHandle);
+
+    /// One invocation of the action's command produced some output
+    ///
+    /// This is only reported for actions with `OutputCondition::Always`, as
+    /// the result is not known yet. All other actions report their output
+    /// with the result, filtered by their `OutputCondition`.
+    ///
+    /// Only reported if `wants_output_chunks` returns `true`.
+    fn report_action_output_chunk(&mut self, _action_id: &str, _stdout: &[u8], _stderr: &[u8]) {}
+
+    /// `true` if the reporter does something with
+    /// `report_action_output_chunk`, so the output needs to be copied
+    fn wants_output_chunks(&self) -> bool {
+        false
+    }
+
+    /// Some detail on running an action, like the commands run or how many
+    /// files got selected
+    ///
+    /// Only reported when running with a `verbosity` above 0.
+    fn report_action_detail(&mut self, _action_id: &str, _message: &str) {}
+
+    fn report_done(&mut self, action: ActionHandle, result: ActionResult);
+
+    /// An action that is allowed to fail is done with a warning or error
+    ///
+    /// The `result` is not counted as warning or error in the `RunSummary`.
+    fn report_allowed_failure(&mut self, action: ActionHandle, result: ActionResult) {
+        self.report_done(action, result);
+    }
+
+    /// All actions are done, this is the final tally
+    fn report_summary(&mut self, _summary: &RunSummary) {}
 
     fn finish(&mut self);
 }
 
+/// Directories holding version control system data
+pub const VCS_DIRECTORIES: [&str; 5] = [".git", ".hg", ".jj", ".pijul", ".svn"];
+
+/// Find all files below `base_dir` that git would not ignore
+///
+/// The directory tree is walked in parallel. The result is sorted.
+async fn walk_directory(base_dir: &Path) -> Result<Vec<PathBuf>> {
+    let walker = ignore::WalkBuilder::new(base_dir)
+        .hidden(false)
+        .git_global(true)
+        .git_exclude(true)
+        .git_ignore(true)
+        .filter_entry(|e| !VCS_DIRECTORIES.iter().any(|d| e.file_name() == *d))
+        .build_parallel();
+
+    let mut files = tokio::task::spawn_blocking(move || {
+        let (tx, rx) = std::sync::mpsc::channel();
+        walker.run(|| {
+            let tx = tx.clone();
+            Box::new(move |entry| match entry {
+                Ok(e) if e.file_type().is_some_and(|t| t.is_dir()) => ignore::WalkState::Continue,
+                Ok(e) => {
+                    let _ = tx.send(Ok(e.into_path()));
+                    ignore::WalkState::Continue
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                    ignore::WalkState::Quit
+                }
+            })
+        });
+        drop(tx);
+        rx.into_iter().collect::<std::result::Result<Vec<_>, _>>()
+    })
+    .await
+    .context("Directory walker failed")?
+    .context(format!(
+        "Failed to walk directory tree below '{}'",
+        base_dir.display()
+    ))?;
+    files.sort();
+
+    vcs::filter_git_ignored(base_dir, files).await
+}
+
+/// How deep below the workspace directory to look for repositories
+const MAX_WORKSPACE_DEPTH: usize = 4;
+
+/// `true` if `directory` is the root of a repository of any VCS
+fn is_repository(directory: &Path) -> bool {
+    VCS_DIRECTORIES.iter().any(|d| directory.join(d).exists())
+}
+
+fn find_repositories_in(
+    directory: &Path,
+    relative: &Path,
+    depth: usize,
+    result: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let entries = std::fs::read_dir(directory)
+        .context(format!("Failed to read directory {}", directory.display()))?;
+    let mut subdirectories = entries
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| e.file_name())
+        .collect::<Vec<_>>();
+    subdirectories.sort();
+
+    for name in subdirectories {
+        let path = directory.join(&name);
+        if is_repository(&path) {
+            result.push(relative.join(&name));
+        } else if depth < MAX_WORKSPACE_DEPTH {
+            find_repositories_in(&path, &relative.join(&name), depth + 1, result)?;
+        }
+    }
+    Ok(())
+}
+
+/// Find the repositories below `workspace`, e.g. the checkouts in a
+/// meta-repository
+///
+/// The paths are relative to `workspace` and sorted. Repositories nested in
+/// the repositories found are not reported, neither are hidden directories
+/// or those more than a few levels deep.
+///
+/// # Errors
+///
+/// Reports an error if a directory can not be read.
+pub fn find_repositories(workspace: &Path) -> Result<Vec<PathBuf>> {
+    let mut result = Vec::new();
+    find_repositories_in(workspace, Path::new(""), 1, &mut result)?;
+    Ok(result)
+}
+
+/// Remove directories from `files`
+fn remove_directories(files: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut result = Vec::with_capacity(files.len());
+    for f in files {
+        let meta =
+            std::fs::metadata(&f).context(format!("Failed to get metadata for {}", f.display()))?;
+        if !meta.is_dir() {
+            result.push(f);
+        }
+    }
+    Ok(result)
+}
+
+/// Lexically normalize `path` by removing `.` components
+///
+/// Returns `None` if `path` contains `..`: That can not be resolved without
+/// looking at the file system as there might be symlinks involved.
+fn normalize_lexically(path: &Path) -> Option<PathBuf> {
+    let mut result = PathBuf::new();
+    for c in path.components() {
+        match c {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => return None,
+            c => result.push(c),
+        }
+    }
+    Some(result)
+}
+
+/// Turn `files` into absolute paths in `root_directory`, dropping all files
+/// outside of it
+///
+/// Relative paths are relative to the root directory. `root_directory` is
+/// canonical, while `original_root` is the same directory as it was passed in
+/// (e.g. including symlinks).
+///
+/// Paths are normalized lexically, so symlinks below the root directory are
+/// not resolved. Only paths that can not be handled that way, as they contain
+/// `..` or reach the root directory via some other path, are canonicalized.
+fn normalize_files(
+    root_directory: &Path,
+    original_root: &Path,
+    files: Vec<PathBuf>,
+) -> Result<Vec<PathBuf>> {
+    let mut result = Vec::with_capacity(files.len());
+    for f in files {
+        let normalized = normalize_lexically(&root_directory.join(&f)).and_then(|p| {
+            if p.starts_with(root_directory) {
+                Some(p)
+            } else {
+                p.strip_prefix(original_root)
+                    .ok()
+                    .map(|p| root_directory.join(p))
+            }
+        });
+
+        if let Some(f) = normalized {
+            result.push(f);
+            continue;
+        }
+
+        let f = std::fs::canonicalize(root_directory.join(&f))
+            .context(format!("Could not canonicalize {}", f.display()))?;
+        if f.starts_with(root_directory) {
+            result.push(f);
+        }
+    }
+    Ok(result)
+}
+
 /// Collect the input files based on `Context` and configuration
 ///
 /// # Errors
@@ -72,20 +431,20 @@ async fn collect_input_files_impl(
         InputFiles::Vcs(config) => vcs::find_changed_files(current_directory, config).await,
         InputFiles::FileList(files) => Ok(ExecutionContext {
             root_directory: current_directory,
+            snapshot: None,
+            diff: None,
             extra_environment: HashMap::from([(
                 "BEAUTYTIPS_INPUT".to_string(),
                 "files".to_string(),
             )]),
-            files_to_process: files,
+            files_to_process: remove_directories(files)?,
         }),
         InputFiles::AllFiles(base_dir) => {
-            let files = ignore::WalkBuilder::new(base_dir.clone())
-                .build()
-                .map(|d| d.map(ignore::DirEntry::into_path))
-                .collect::<std::result::Result<Vec<_>, _>>()
-                .context("Failed to walk directory tree below '{base_dir:?}'")?;
+            let files = walk_directory(&base_dir).await?;
             Ok(ExecutionContext {
                 root_directory: current_directory,
+                snapshot: None,
+                diff: None,
                 extra_environment: HashMap::from([(
                     "BEAUTYTIPS_INPUT".to_string(),
                     "dir".to_string(),
@@ -100,67 +459,192 @@ async fn collect_input_files_impl(
     let root_directory = tokio::fs::canonicalize(&context.root_directory)
         .await
         .context(format!(
-            "Could not canonicalize '{:?}",
-            context.root_directory
+            "Could not canonicalize '{}'",
+            context.root_directory.display()
         ))?;
 
-    std::env::set_current_dir(&root_directory).context(format!(
-        "Failed to set current directory to {:?}",
-        context.root_directory
-    ))?;
+    context.files_to_process = normalize_files(
+        &root_directory,
+        &context.root_directory,
+        std::mem::take(&mut context.files_to_process),
+    )?;
 
-    let mut canonical_files = Vec::new();
-    for f in &context.files_to_process {
-        let meta = tokio::fs::metadata(&f)
-            .await
-            .context(format!("Failed to get metadata for {f:?}"))?;
-        if meta.is_dir() {
-            continue;
-        }
+    Ok(context)
+}
 
-        let f = tokio::fs::canonicalize(&f)
-            .await
-            .context(format!("Could not canonicalize {f:?}"))?;
+/// The handle of the action with `action_id` in `handles`
+fn action_handle(handles: &HashMap<String, ActionHandle>, action_id: &str) -> ActionHandle {
+    handles
+        .get(action_id)
+        .cloned()
+        .unwrap_or_else(|| ActionHandle::from_id(action_id))
+}
 
-        if f.is_absolute() {
-            if f.starts_with(&root_directory) {
-                canonical_files.push(f);
+fn report_update(
+    reporter: &mut dyn Reporter,
+    summary: &mut RunSummary,
+    handles: &HashMap<String, ActionHandle>,
+    severities: &HashMap<String, Severity>,
+    allowed_failures: &HashSet<String>,
+    m: ActionUpdate,
+) {
+    match m {
+        ActionUpdate::Started { action_id } => {
+            tracing::debug!("action {action_id} start");
+            reporter.report_start(action_handle(handles, &action_id));
+        }
+        ActionUpdate::Output {
+            action_id,
+            stdout,
+            stderr,
+        } => {
+            reporter.report_action_output_chunk(&action_id, &stdout, &stderr);
+        }
+        ActionUpdate::Detail { action_id, message } => {
+            reporter.report_action_detail(&action_id, &message);
+        }
+        ActionUpdate::Done { action_id, result } => {
+            tracing::debug!("action {action_id} complete: {result:?}");
+            let result = match severities.get(&action_id) {
+                Some(severity) => severity.apply(result),
+                None => result,
+            };
+            let failed = matches!(
+                result,
+                ActionResult::Warn { .. } | ActionResult::Error { .. }
+            );
+            let action = action_handle(handles, &action_id);
+            if failed && allowed_failures.contains(&action_id) {
+                summary.allowed_failures += 1;
+                reporter.report_allowed_failure(action, result);
+            } else {
+                summary.count(&result);
+                reporter.report_done(action, result);
             }
-        } else if !f.starts_with("..") {
-            canonical_files.push(root_directory.join(f));
         }
     }
-    context.files_to_process = canonical_files;
+}
 
-    Ok(context)
+/// How updates are passed on to the reporter
+struct ReportState {
+    reporter: Box<dyn Reporter>,
+    reorder_buffer: Option<ReorderBuffer>,
+    handles: HashMap<String, ActionHandle>,
+    severities: HashMap<String, Severity>,
+    allowed_failures: HashSet<String>,
+    summary: RunSummary,
 }
 
-#[tracing::instrument(skip(reporter))]
-async fn handle_reports(mut reporter: Box<dyn Reporter>, mut rx: ActionUpdateReceiver) {
+impl ReportState {
+    fn push(&mut self, m: ActionUpdate) {
+        let updates = match &mut self.reorder_buffer {
+            Some(b) => b.push(m),
+            None => vec![m],
+        };
+        for m in updates {
+            report_update(
+                self.reporter.as_mut(),
+                &mut self.summary,
+                &self.handles,
+                &self.severities,
+                &self.allowed_failures,
+                m,
+            );
+        }
+    }
+}
+
+/// Report the updates from `rx`
+///
+/// Once `rx` is closed after the run `timed_out`, the `action_ids` not done
+/// yet are reported as errors. Returns the summary of the run and the number
+/// of these cancelled actions.
+#[tracing::instrument(skip(state, rx))]
+async fn handle_reports(
+    mut state: ReportState,
+    mut rx: ActionUpdateReceiver,
+    action_ids: Vec<String>,
+    timed_out: Arc<AtomicBool>,
+) -> (RunSummary, usize) {
     tracing::trace!("running local reporter task");
+    let start_time = std::time::Instant::now();
+    let mut done = HashSet::new();
     loop {
         let _span = tracing::span!(tracing::Level::TRACE, "reporter_callback_handler");
         let Some(m) = rx.recv().await else {
             tracing::trace!("reporter is done");
             break;
         };
-        match m {
-            actions::ActionUpdate::Started { action_id } => {
-                tracing::debug!("action {action_id} start");
-                reporter.report_start(action_id);
-            }
-            actions::ActionUpdate::Done { action_id, result } => {
-                tracing::debug!("action {action_id} complete: {result:?}");
-                reporter.report_done(action_id, result);
-            }
+        if let ActionUpdate::Done { action_id, .. } = &m {
+            done.insert(action_id.clone());
         }
+        state.push(m);
     }
 
+    let mut cancelled = 0;
+    if timed_out.load(Ordering::SeqCst) {
+        for action_id in action_ids.into_iter().filter(|id| !done.contains(id)) {
+            cancelled += 1;
+            state.push(ActionUpdate::Done {
+                action_id,
+                result: ActionResult::Error {
+                    message: "timed out".to_string(),
+                    hint: None,
+                },
+            });
+        }
+    }
+
+    let ReportState {
+        mut reporter,
+        reorder_buffer,
+        handles,
+        severities,
+        allowed_failures,
+        mut summary,
+    } = state;
+    for m in reorder_buffer
+        .map(ReorderBuffer::finish)
+        .unwrap_or_default()
+    {
+        report_update(
+            reporter.as_mut(),
+            &mut summary,
+            &handles,
+            &severities,
+            &allowed_failures,
+            m,
+        );
+    }
+
+    summary.duration = start_time.elapsed();
+    reporter.report_summary(&summary);
     reporter.finish();
     tracing::trace!("Local reporter task is done");
+    (summary, cancelled)
+}
+
+/// The input files of a run, collected ahead of it by `collect_inputs`
+#[derive(Debug)]
+pub struct CollectedInputs(ExecutionContext);
+
+impl CollectedInputs {
+    /// The root directory the files are in
+    #[must_use]
+    pub fn root_directory(&self) -> &Path {
+        &self.0.root_directory
+    }
+
+    /// The files to run on, as absolute paths in the root directory
+    #[must_use]
+    pub fn files(&self) -> &[PathBuf] {
+        &self.0.files_to_process
+    }
 }
 
-/// Collect files only
+/// Collect the input files of a run, to pass them into `run_collected`
+///
+/// The current directory is not changed.
 ///
 /// # Errors
 ///
@@ -170,10 +654,7 @@ async fn handle_reports(mut reporter: Box<dyn Reporter>, mut rx: ActionUpdateRec
 ///
 /// Panics whenever tokio decides to panic.
 #[tracing::instrument]
-pub fn collect_input_files<'a>(
-    current_directory: PathBuf,
-    inputs: InputFiles,
-) -> Result<(PathBuf, Vec<PathBuf>)> {
+pub fn collect_inputs(current_directory: PathBuf, inputs: InputFiles) -> Result<CollectedInputs> {
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
@@ -184,15 +665,52 @@ pub fn collect_input_files<'a>(
 
             collect_input_files_impl(current_directory, inputs).await
         })
-        .map(|mut context| {
-            (
-                std::mem::take(&mut context.root_directory),
-                std::mem::take(&mut context.files_to_process),
-            )
-        })
+        .map(CollectedInputs)
 }
 
-/// Run beautytips
+/// Collect files only, returning the root directory and the files in it
+///
+/// The current directory is not changed.
+///
+/// # Errors
+///
+/// Mostly `InvalidConfiguration`, but others are possible when data collection fails.
+///
+/// # Panics
+///
+/// Panics whenever tokio decides to panic.
+pub fn collect_input_files(
+    current_directory: PathBuf,
+    inputs: InputFiles,
+) -> Result<(PathBuf, Vec<PathBuf>)> {
+    collect_inputs(current_directory, inputs)
+        .map(|CollectedInputs(context)| (context.root_directory, context.files_to_process))
+}
+
+/// Remove the files git would ignore from `files`
+///
+/// The `files` are returned unchanged if `directory` is not inside a git
+/// repository or git is not available.
+///
+/// # Errors
+///
+/// Reports an error if git failed.
+///
+/// # Panics
+///
+/// Panics whenever tokio decides to panic.
+pub fn remove_ignored_files(directory: &Path, files: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("tokio runtime setup failed")
+        .block_on(vcs::filter_git_ignored(directory, files))
+}
+
+/// Run beautytips, returning the summary of the run
+///
+/// The actions run in the root directory, the current directory is not
+/// changed.
 ///
 /// # Errors
 ///
@@ -202,12 +720,41 @@ pub fn collect_input_files<'a>(
 ///
 /// Panics whenever tokio decides to panic.
 #[tracing::instrument(skip(reporter))]
-pub fn run<'a>(
+pub fn run(
     current_directory: PathBuf,
     inputs: InputFiles,
-    actions: actions::ActionDefinitionIterator<'a>,
+    actions: ActionSet,
     reporter: Box<dyn Reporter>,
-) -> Result<()> {
+) -> Result<RunSummary> {
+    run_with_options(
+        current_directory,
+        inputs,
+        actions,
+        reporter,
+        &RunOptions::default(),
+    )
+}
+
+/// Run beautytips with extra `options`, returning the summary of the run
+///
+/// The actions run in the root directory, the current directory is not
+/// changed.
+///
+/// # Errors
+///
+/// Mostly `InvalidConfiguration`, but others are possible when data collection fails.
+///
+/// # Panics
+///
+/// Panics whenever tokio decides to panic.
+#[tracing::instrument(skip(reporter))]
+pub fn run_with_options(
+    current_directory: PathBuf,
+    inputs: InputFiles,
+    actions: ActionSet,
+    reporter: Box<dyn Reporter>,
+    options: &RunOptions,
+) -> Result<RunSummary> {
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
@@ -217,37 +764,469 @@ pub fn run<'a>(
             tracing::trace!("Inside tokio runtime block");
 
             let context = collect_input_files_impl(current_directory, inputs).await?;
+            run_context(context, actions, reporter, options).await
+        })
+}
+
+/// Run beautytips on `inputs` collected before, returning the summary of the
+/// run
+///
+/// The actions run in the root directory, the current directory is not
+/// changed.
+///
+/// # Errors
+///
+/// Mostly `InvalidConfiguration`, but others are possible when running fails.
+///
+/// # Panics
+///
+/// Panics whenever tokio decides to panic.
+#[tracing::instrument(skip(reporter))]
+pub fn run_collected(
+    inputs: CollectedInputs,
+    actions: ActionSet,
+    reporter: Box<dyn Reporter>,
+    options: &RunOptions,
+) -> Result<RunSummary> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("tokio runtime setup failed")
+        .block_on(async move {
+            let _span = tracing::span!(tracing::Level::TRACE, "tokio_runtime");
+            tracing::trace!("Inside tokio runtime block");
+
+            run_context(inputs.0, actions, reporter, options).await
+        })
+}
+
+/// Run `actions` on the files of `context`
+async fn run_context(
+    mut context: ExecutionContext,
+    actions: ActionSet,
+    mut reporter: Box<dyn Reporter>,
+    options: &RunOptions,
+) -> Result<RunSummary> {
+    let actions = actions.expand_matrix();
+    // Held till all actions are done
+    let _lock = lock::lock(&context.root_directory, options.lock).await?;
+
+    // The variables describing the inputs always win
+    let mut environment = dotenv::load(&context.root_directory, options.dotenv)?;
+    environment.extend(options.environment.iter().cloned());
+    environment.extend(std::mem::take(&mut context.extra_environment));
+    context.extra_environment = environment;
+
+    tracing::debug!(
+        "Detected root directory: {:?} with changed files: {:?}",
+        context.root_directory,
+        context.files_to_process
+    );
+    if let Some(max_files) = options
+        .max_files
+        .filter(|m| context.files_to_process.len() > *m)
+    {
+        return Err(anyhow::anyhow!(format!(
+            "Refusing to run on {} files, more than the maximum of {max_files}. \
+                     Run on fewer files (e.g. a directory or file list) or raise the maximum.",
+            context.files_to_process.len()
+        )));
+    }
+
+    // Nothing gets reported before the run is sure to start
+    let mut summary = RunSummary::default();
+    for action in options.skipped.iter() {
+        reporter.report_done(action.into(), ActionResult::Skipped);
+        summary.count(&ActionResult::Skipped);
+    }
+    reporter.report_files_collected(&context.root_directory, &context.files_to_process);
+
+    let jobs = options.jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+    });
+    let settings = actions::RunSettings::new(jobs, options.verbosity)
+        .isolated(options.isolate)
+        .keep_going(options.keep_going)
+        .stream_output(reporter.wants_output_chunks())
+        .spill_above(
+            options
+                .spill_threshold
+                .unwrap_or(actions::DEFAULT_SPILL_THRESHOLD),
+        );
+    let reorder_buffer = options
+        .stable_order
+        .then(|| ReorderBuffer::new(actions.ids().collect()));
+    let state = ReportState {
+        reporter,
+        reorder_buffer,
+        handles: actions.handles(),
+        severities: actions.severities(),
+        allowed_failures: actions.allowed_failures(),
+        summary,
+    };
+    let action_ids = actions.ids().collect();
+
+    let (tx, rx) = actions::channel(
+        options.report_capacity.unwrap_or(DEFAULT_REPORT_CAPACITY),
+        options.report_overflow,
+    );
+    let runner = tokio::task::spawn(async move {
+        let _span = tracing::span!(tracing::Level::TRACE, "runner_task");
+
+        tracing::debug!("Runner task started");
+
+        let result = actions::run(context, tx, actions, settings).await;
+
+        tracing::debug!("Runner task finished");
+
+        result
+    });
+
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let watchdog = options.timeout.map(|timeout| {
+        let runner = runner.abort_handle();
+        let timed_out = timed_out.clone();
+        tokio::task::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            tracing::debug!("Run timed out, cancelling the actions");
+            timed_out.store(true, Ordering::SeqCst);
+            runner.abort();
+        })
+    });
 
-            tracing::debug!(
-                "Detected root directory: {:?} with changed files: {:?}",
-                context.root_directory,
-                context.files_to_process
+    let (summary, cancelled) = handle_reports(state, rx, action_ids, timed_out.clone()).await;
+    if let Some(watchdog) = watchdog {
+        watchdog.abort();
+    }
+    match runner.await {
+        Err(e) if e.is_cancelled() && timed_out.load(Ordering::SeqCst) => Err(TimedOut {
+            timeout: options.timeout.unwrap_or_default(),
+            cancelled,
+        }
+        .into()),
+        result => result.expect("Join Error").map(|()| summary),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_collect_all_files() {
+        let base_dir =
+            std::env::temp_dir().join(format!("beautytips-walk-test-{}", std::process::id()));
+        for d in ["a/b", "c", ".hidden", ".git/objects"] {
+            std::fs::create_dir_all(base_dir.join(d)).unwrap();
+        }
+        for f in [
+            "z.txt",
+            "a/b/y.txt",
+            "a/x.txt",
+            "c/w.txt",
+            ".hidden/v.txt",
+            ".git/HEAD",
+        ] {
+            std::fs::write(base_dir.join(f), f).unwrap();
+        }
+        let base_dir = std::fs::canonicalize(&base_dir).unwrap();
+
+        let files = walk_directory(&base_dir).await.unwrap();
+        let expected = [".hidden/v.txt", "a/b/y.txt", "a/x.txt", "c/w.txt", "z.txt"]
+            .iter()
+            .map(|f| base_dir.join(f))
+            .collect::<Vec<_>>();
+        assert_eq!(files, expected);
+
+        let mut with_outside = files.clone();
+        with_outside.push(std::env::temp_dir());
+        let normalized = normalize_files(&base_dir, &base_dir, with_outside).unwrap();
+        assert_eq!(normalized, expected);
+
+        let link = base_dir.with_extension("link");
+        let _ = std::fs::remove_file(&link);
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&base_dir, &link).unwrap();
+            let outside = base_dir.with_extension("outside");
+            std::fs::write(&outside, "outside").unwrap();
+            let files = vec![
+                PathBuf::from("./z.txt"),
+                base_dir.join("a/b/../x.txt"),
+                PathBuf::from("a/b/../../z.txt"),
+                link.join("c/w.txt"),
+                link.join("..").join(outside.file_name().unwrap()),
+            ];
+            let normalized = normalize_files(&base_dir, &link, files).unwrap();
+            assert_eq!(
+                normalized,
+                vec![
+                    base_dir.join("z.txt"),
+                    base_dir.join("a/x.txt"),
+                    base_dir.join("z.txt"),
+                    base_dir.join("c/w.txt"),
+                ]
             );
+            std::fs::remove_file(&link).unwrap();
+            std::fs::remove_file(&outside).unwrap();
+        }
 
-            // # Safety: actions are valid during the entire time the
-            // o runtime is up. So it should be safe to treat the `actions`
-            // as static.
-            let actions = unsafe {
-                std::mem::transmute::<
-                    actions::ActionDefinitionIterator<'a>,
-                    actions::ActionDefinitionIterator<'static>,
-                >(actions)
-            };
+        std::fs::remove_dir_all(&base_dir).unwrap();
+    }
 
-            let (tx, rx) = tokio::sync::mpsc::channel(10);
-            let runner = tokio::task::spawn(async move {
-                let _span = tracing::span!(tracing::Level::TRACE, "runner_task");
+    #[test]
+    fn test_find_repositories() {
+        let workspace =
+            std::env::temp_dir().join(format!("beautytips-workspace-test-{}", std::process::id()));
+        for d in [
+            "app/.git",
+            "app/vendor/lib/.git",
+            "libs/core/.jj",
+            "libs/docs",
+            "tools/.git",
+            ".hidden/.git",
+            "a/b/c/d/e/.git",
+        ] {
+            std::fs::create_dir_all(workspace.join(d)).unwrap();
+        }
+        // Worktrees and submodules have a `.git` file
+        std::fs::create_dir_all(workspace.join("worktree")).unwrap();
+        std::fs::write(workspace.join("worktree/.git"), "gitdir: ../app/.git").unwrap();
 
-                tracing::debug!("Runner task started");
+        assert_eq!(
+            find_repositories(&workspace).unwrap(),
+            ["app", "libs/core", "tools", "worktree"].map(PathBuf::from)
+        );
+        assert!(find_repositories(&workspace.join("missing")).is_err());
 
-                let result = actions::run(context, tx, actions).await;
+        std::fs::remove_dir_all(&workspace).unwrap();
+    }
 
-                tracing::debug!("Runner task finished");
+    #[derive(Default)]
+    struct RecordingReporter {
+        done: Vec<String>,
+        allowed_failures: Vec<String>,
+    }
 
-                result
-            });
+    impl Reporter for RecordingReporter {
+        fn report_start(&mut self, _action: ActionHandle) {}
+
+        fn report_done(&mut self, action: ActionHandle, _result: ActionResult) {
+            self.done.push(action.id);
+        }
+
+        fn report_allowed_failure(&mut self, action: ActionHandle, _result: ActionResult) {
+            self.allowed_failures.push(action.id);
+        }
+
+        fn finish(&mut self) {}
+    }
 
-            handle_reports(reporter, rx).await;
-            runner.await.expect("Join Error")
+    #[test]
+    fn test_report_allowed_failure() {
+        let mut reporter = RecordingReporter::default();
+        let mut summary = RunSummary::default();
+        let allowed_failures = HashSet::from(["a/allowed".to_string(), "a/ok".to_string()]);
+
+        for (action_id, result) in [
+            (
+                "a/allowed",
+                ActionResult::Error {
+                    message: "failed".to_string(),
+                    hint: None,
+                },
+            ),
+            (
+                "a/ok",
+                ActionResult::Ok {
+                    stdout: ActionOutput::default(),
+                    stderr: ActionOutput::default(),
+                },
+            ),
+            (
+                "a/failed",
+                ActionResult::Warn {
+                    stdout: ActionOutput::default(),
+                    stderr: ActionOutput::default(),
+                    hint: None,
+                },
+            ),
+        ] {
+            report_update(
+                &mut reporter,
+                &mut summary,
+                &HashMap::new(),
+                &HashMap::new(),
+                &allowed_failures,
+                ActionUpdate::Done {
+                    action_id: action_id.to_string(),
+                    result,
+                },
+            );
+        }
+
+        assert_eq!(reporter.allowed_failures, ["a/allowed"]);
+        assert_eq!(reporter.done, ["a/ok", "a/failed"]);
+        assert_eq!(summary.allowed_failures, 1);
+        assert_eq!(summary.ok, 1);
+        assert_eq!(summary.warn, 1);
+        assert_eq!(summary.error, 0);
+    }
+
+    async fn handle_reports_of(timed_out: bool) -> (RunSummary, usize) {
+        let (tx, rx) = actions::channel(DEFAULT_REPORT_CAPACITY, ReportOverflow::Unbounded);
+        tx.send(ActionUpdate::Done {
+            action_id: "a/done".to_string(),
+            result: ActionResult::Ok {
+                stdout: ActionOutput::default(),
+                stderr: ActionOutput::default(),
+            },
         })
+        .await
+        .unwrap();
+        drop(tx);
+
+        let state = ReportState {
+            reporter: Box::new(RecordingReporter::default()),
+            reorder_buffer: None,
+            handles: HashMap::new(),
+            severities: HashMap::new(),
+            allowed_failures: HashSet::new(),
+            summary: RunSummary::default(),
+        };
+        let action_ids = ["a/done", "a/running", "a/waiting"]
+            .map(ToString::to_string)
+            .to_vec();
+        handle_reports(state, rx, action_ids, Arc::new(AtomicBool::new(timed_out))).await
+    }
+
+    #[tokio::test]
+    async fn test_handle_reports_timed_out() {
+        let (summary, cancelled) = handle_reports_of(true).await;
+        assert_eq!(cancelled, 2);
+        assert_eq!(summary.ok, 1);
+        assert_eq!(summary.error, 2);
+
+        let (summary, cancelled) = handle_reports_of(false).await;
+        assert_eq!(cancelled, 0);
+        assert_eq!(summary.ok, 1);
+        assert_eq!(summary.error, 0);
+    }
+
+    #[test]
+    fn test_run_summary() {
+        let mut summary = RunSummary::default();
+        for result in [
+            ActionResult::Ok {
+                stdout: ActionOutput::default(),
+                stderr: ActionOutput::default(),
+            },
+            ActionResult::Skipped,
+            ActionResult::Skipped,
+            ActionResult::NotApplicable {
+                reason: NotApplicableReason::ConditionNotMet,
+            },
+        ] {
+            summary.count(&result);
+        }
+        assert_eq!(summary.ok, 1);
+        assert_eq!(summary.skipped, 2);
+        assert_eq!(summary.not_applicable, 1);
+        assert!(!summary.failed());
+
+        let mut total = summary.clone();
+        summary.count(&ActionResult::Error {
+            message: "failed".to_string(),
+            hint: None,
+        });
+        assert!(summary.failed());
+        total.add(&summary);
+        assert_eq!(total.ok, 2);
+        assert_eq!(total.skipped, 4);
+        assert_eq!(total.error, 1);
+        assert!(total.failed());
+    }
+
+    #[test]
+    fn test_run_skipped() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut skipped = actions::tests::action(&["tool"]);
+        skipped.id = "test/skipped".parse().unwrap();
+        let options = RunOptions {
+            lock: RunLock::Off,
+            skipped: ActionSet::new([Arc::new(skipped)]),
+            ..RunOptions::default()
+        };
+
+        let summary = run_with_options(
+            directory.path().to_path_buf(),
+            InputFiles::FileList(vec![]),
+            ActionSet::default(),
+            Box::new(RecordingReporter::default()),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(summary.skipped, 1);
+        assert!(!summary.failed());
+    }
+
+    /// Counts the calls, even after being handed to a run
+    struct CountingReporter(std::rc::Rc<std::cell::Cell<usize>>);
+
+    impl Reporter for CountingReporter {
+        fn report_files_collected(&mut self, _root_directory: &Path, _files: &[PathBuf]) {
+            self.0.set(self.0.get() + 1);
+        }
+
+        fn report_start(&mut self, _action: ActionHandle) {
+            self.0.set(self.0.get() + 1);
+        }
+
+        fn report_done(&mut self, _action: ActionHandle, _result: ActionResult) {
+            self.0.set(self.0.get() + 1);
+        }
+
+        fn finish(&mut self) {}
+    }
+
+    #[test]
+    fn test_run_too_many_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut skipped = actions::tests::action(&["tool"]);
+        skipped.id = "test/skipped".parse().unwrap();
+        let options = RunOptions {
+            lock: RunLock::Off,
+            skipped: ActionSet::new([Arc::new(skipped)]),
+            max_files: Some(0),
+            ..RunOptions::default()
+        };
+        let calls = std::rc::Rc::new(std::cell::Cell::new(0));
+
+        let file = directory.path().join("a.txt");
+        std::fs::write(&file, "a").unwrap();
+        assert!(run_with_options(
+            directory.path().to_path_buf(),
+            InputFiles::FileList(vec![file]),
+            ActionSet::default(),
+            Box::new(CountingReporter(calls.clone())),
+            &options,
+        )
+        .is_err());
+        // The skipped action is not reported for a run that never started
+        assert_eq!(calls.get(), 0);
+    }
+
+    #[test]
+    fn test_timed_out() {
+        let error: anyhow::Error = TimedOut {
+            timeout: std::time::Duration::from_secs(90),
+            cancelled: 2,
+        }
+        .into();
+        assert_eq!(
+            error.to_string(),
+            "The run timed out after 90s, 2 action(s) got cancelled"
+        );
+        assert!(error.downcast_ref::<TimedOut>().is_some());
+    }
 }
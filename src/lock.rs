@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use fs4::fs_std::FileExt;
+
+/// How to handle other runs in the same repository
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RunLock {
+    /// Fail when another run holds the lock of the repository
+    #[default]
+    Fail,
+    /// Wait for other runs holding the lock of the repository to finish
+    Wait,
+    /// Do not lock the repository at all
+    Off,
+}
+
+/// How long to wait before checking a lock held by another run again
+const WAIT_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// The lock of a repository, released when dropped
+///
+/// The operating system releases the lock when the file gets closed, even
+/// when the run crashes. The lock file itself stays around.
+#[derive(Debug)]
+pub(crate) struct LockGuard {
+    _file: std::fs::File,
+}
+
+/// The lock file of `root_directory` in `lock_directory`
+fn lock_file(lock_directory: &Path, root_directory: &Path) -> PathBuf {
+    lock_directory.join(format!(
+        "{}.lock",
+        crate::actions::cache_dir::project_key(root_directory)
+    ))
+}
+
+/// Try to lock the `file`
+///
+/// Returns the locked file, or the process id of the run holding the lock
+/// (if it could be read) when the lock is taken.
+fn try_lock(file: &Path) -> crate::Result<Result<std::fs::File, Option<u32>>> {
+    let mut handle = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(file)
+        .context(format!("Failed to open {}", file.display()))?;
+
+    if !handle
+        .try_lock_exclusive()
+        .context(format!("Failed to lock {}", file.display()))?
+    {
+        let mut contents = String::new();
+        let _ = handle.read_to_string(&mut contents);
+        return Ok(Err(contents.trim().parse::<u32>().ok()));
+    }
+
+    // The process id is only informational, for other runs to report
+    handle
+        .set_len(0)
+        .and_then(|()| handle.write_all(std::process::id().to_string().as_bytes()))
+        .context(format!("Failed to write {}", file.display()))?;
+    Ok(Ok(handle))
+}
+
+async fn lock_in(
+    lock_directory: &Path,
+    root_directory: &Path,
+    mode: RunLock,
+) -> crate::Result<Option<LockGuard>> {
+    if mode == RunLock::Off {
+        return Ok(None);
+    }
+
+    tokio::fs::create_dir_all(lock_directory)
+        .await
+        .context(format!("Failed to create {}", lock_directory.display()))?;
+    let file = lock_file(lock_directory, root_directory);
+
+    loop {
+        match try_lock(&file)? {
+            Ok(handle) => return Ok(Some(LockGuard { _file: handle })),
+            Err(pid) if mode == RunLock::Fail => {
+                let process = pid
+                    .map(|pid| format!(" (process {pid})"))
+                    .unwrap_or_default();
+                return Err(anyhow::anyhow!(format!(
+                    "Another run{process} is working in {}",
+                    root_directory.display(),
+                )));
+            }
+            Err(pid) => {
+                tracing::debug!("Waiting for the run in process {pid:?} to finish");
+                tokio::time::sleep(WAIT_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// Lock `root_directory` against other runs
+///
+/// The lock is released when the returned guard gets dropped.
+pub(crate) async fn lock(root_directory: &Path, mode: RunLock) -> crate::Result<Option<LockGuard>> {
+    let Some(lock_directory) = crate::cache_directory().map(|cd| cd.join("locks")) else {
+        tracing::warn!(
+            "Cache directory not found, not locking {}",
+            root_directory.display()
+        );
+        return Ok(None);
+    };
+    lock_in(&lock_directory, root_directory, mode).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lock() {
+        let lock_directory =
+            std::env::temp_dir().join(format!("beautytips-lock-test-{}", std::process::id()));
+        let root_directory = Path::new("/tmp/project");
+        let file = lock_file(&lock_directory, root_directory);
+
+        let guard = lock_in(&lock_directory, root_directory, RunLock::Fail)
+            .await
+            .unwrap();
+        assert!(guard.is_some());
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            std::process::id().to_string()
+        );
+
+        let error = lock_in(&lock_directory, root_directory, RunLock::Fail)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains(&format!("process {}", std::process::id())));
+        assert!(lock_in(&lock_directory, root_directory, RunLock::Off)
+            .await
+            .unwrap()
+            .is_none());
+        // Other repositories are not affected
+        assert!(
+            lock_in(&lock_directory, Path::new("/tmp/other"), RunLock::Fail)
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        // Waiting gets the lock once it is released
+        let waiting = tokio::spawn({
+            let lock_directory = lock_directory.clone();
+            async move {
+                lock_in(&lock_directory, Path::new("/tmp/project"), RunLock::Wait)
+                    .await
+                    .unwrap()
+                    .is_some()
+            }
+        });
+        tokio::time::sleep(2 * WAIT_INTERVAL).await;
+        assert!(!waiting.is_finished());
+        drop(guard);
+        assert!(waiting.await.unwrap());
+
+        // A lock file left behind by a run that is gone is no lock
+        assert!(file.exists());
+        std::fs::write(&file, "4294967295").unwrap();
+        let guard = lock_in(&lock_directory, root_directory, RunLock::Fail)
+            .await
+            .unwrap();
+        assert!(guard.is_some());
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            std::process::id().to_string()
+        );
+        drop(guard);
+
+        std::fs::remove_dir_all(&lock_directory).unwrap();
+    }
+}
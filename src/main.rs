@@ -1,17 +1,433 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
 
+use std::{
+    cell::RefCell,
+    io::Write,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
 use anyhow::{Context, Result};
 use tracing_subscriber::prelude::*;
 
 mod arg_parse;
 mod builtin_commands;
+mod check_config;
 mod config;
+mod help;
+mod hook;
+mod last_run;
 mod reporter;
+mod self_test;
+mod self_update;
+mod watch;
+mod why;
+
+/// Run `actions` in the `repository` at `root_directory`, reporting to the
+/// shared `reporter`
+///
+/// The results are recorded as the last run of the repository.
+fn run_in_repository(
+    repository: &Path,
+    root_directory: PathBuf,
+    inputs: &beautytips::InputFiles,
+    actions: &beautytips::ActionSet,
+    reporter: &Rc<RefCell<Box<dyn beautytips::Reporter>>>,
+    options: &beautytips::RunOptions,
+) -> Result<beautytips::RunSummary> {
+    std::env::set_current_dir(&root_directory).context(format!(
+        "Failed to change into repository {}",
+        root_directory.display()
+    ))?;
+
+    let prefixed =
+        reporter::PrefixedReporter::new(repository.display().to_string(), reporter.clone());
+    beautytips::run_with_options(
+        root_directory,
+        inputs.clone(),
+        actions.clone(),
+        Box::new(last_run::RecordingReporter::new(Box::new(prefixed))),
+        options,
+    )
+    .context(format!("Failed to run actions in {}", repository.display()))
+}
+
+/// Run `actions` in all `repositories`, one after the other
+///
+/// The current directory is switched into each repository before running, so
+/// relative inputs are interpreted relative to each repository. It is
+/// restored afterwards. All results end up in one report, with the action ids
+/// prefixed by the repository.
+fn run_in_repositories(
+    repositories: &[PathBuf],
+    inputs: &beautytips::InputFiles,
+    actions: &beautytips::ActionSet,
+    reporter: Box<dyn beautytips::Reporter>,
+    options: &beautytips::RunOptions,
+) -> Result<beautytips::RunSummary> {
+    let current_directory = std::env::current_dir()?;
+    let reporter = Rc::new(RefCell::new(reporter));
+    let mut summary = beautytips::RunSummary::default();
+
+    let mut result = Ok(());
+    for r in repositories {
+        let repository_summary = run_in_repository(
+            r,
+            current_directory.join(r),
+            inputs,
+            actions,
+            &reporter,
+            options,
+        );
+        let restored = std::env::set_current_dir(&current_directory).context(format!(
+            "Failed to change back into {}",
+            current_directory.display()
+        ));
+        result = repository_summary
+            .map(|repository_summary| summary.add(&repository_summary))
+            .and(restored);
+        if result.is_err() {
+            break;
+        }
+    }
+
+    // Report what ran, even if a repository failed
+    let mut reporter = reporter.borrow_mut();
+    reporter.report_summary(&summary);
+    reporter.finish();
+    result.map(|()| summary)
+}
+
+/// The repositories below `workspace`, relative to the current directory
+fn workspace_repositories(workspace: &Path) -> Result<Vec<PathBuf>> {
+    let repositories = beautytips::find_repositories(workspace)?;
+    if repositories.is_empty() {
+        return Err(anyhow::anyhow!(format!(
+            "No repositories found below {}",
+            workspace.display()
+        )));
+    }
+    Ok(repositories
+        .iter()
+        .map(|r| {
+            workspace
+                .join(r)
+                .components()
+                .filter(|c| *c != std::path::Component::CurDir)
+                .collect()
+        })
+        .collect())
+}
+
+/// Move the actions that failed in the last run to the front
+fn failed_actions_first(actions: &beautytips::ActionSet) -> Result<beautytips::ActionSet> {
+    let failed = last_run::LastRun::load(&std::env::current_dir()?)?
+        .map(|lr| lr.failed_actions())
+        .unwrap_or_default();
+
+    let first = actions.filter(|ad| failed.iter().any(|id| ad.id == id.as_str()));
+    Ok(first.union(actions))
+}
+
+/// Write the `files` in `root_dir` to `out`, separated by NUL characters with
+/// `null` set and by newlines otherwise
+fn write_file_list(
+    out: &mut impl Write,
+    root_dir: &Path,
+    files: &[PathBuf],
+    relative: bool,
+    null: bool,
+) -> std::io::Result<()> {
+    let separator = if null { b'\0' } else { b'\n' };
+    for f in files {
+        let f = if relative {
+            f.strip_prefix(root_dir).unwrap_or(f)
+        } else {
+            f
+        };
+        out.write_all(f.as_os_str().as_encoded_bytes())?;
+        out.write_all(&[separator])?;
+    }
+    out.flush()
+}
+
+/// Print the files collected from `source`
+///
+/// Only the paths go to stdout, the root directory goes to stderr when
+/// `verbosity` is set.
+fn list_files(
+    source: beautytips::InputFiles,
+    count: bool,
+    relative: bool,
+    null: bool,
+    verbosity: u8,
+) -> Result<()> {
+    let (root_dir, files) = beautytips::collect_input_files(std::env::current_dir()?, source)?;
+    if verbosity > 0 {
+        eprintln!("root directory: {}", root_dir.display());
+    }
+    if count {
+        println!("{}", files.len());
+        return Ok(());
+    }
+
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    write_file_list(&mut out, &root_dir, &files, relative, null)?;
+    Ok(())
+}
+
+/// Add a new action to `config_file` (or the user configuration)
+fn add_action(
+    name: &config::ActionId,
+    description: Option<&str>,
+    command: &str,
+    filters: &[String],
+    config_file: Option<PathBuf>,
+) -> Result<()> {
+    let config_file = match config_file {
+        Some(cf) => cf,
+        None => config::user_configuration_file()?,
+    };
+    config::append_action(&config_file, name, description, command, filters)?;
+    println!("Added {name} to {}", config_file.display());
+    Ok(())
+}
+
+/// Print detailed information on all actions matching `selectors`
+fn help_action(config: &config::Configuration, selectors: &config::ActionSelectors) -> Result<()> {
+    let mut found = false;
+    for action in config.actions(selectors).iter() {
+        let mut groups = config
+            .action_groups
+            .iter()
+            .filter(|(_, group)| group.iter().any(|s| s.matches_action(action)))
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<_>>();
+        groups.sort();
+
+        if found {
+            println!();
+        }
+        print!("{}", help::action_help(action, &groups));
+        found = true;
+    }
+
+    if found {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("No action matches the selectors"))
+    }
+}
+
+/// Select the checks of the builtin rule packs applying to the files
+/// `collected`, which `run` does when no actions are given
+fn auto_selectors(
+    config: &config::Configuration,
+    collected: &beautytips::CollectedInputs,
+) -> config::ActionSelectors {
+    let files = collected
+        .files()
+        .iter()
+        .map(|f| {
+            f.strip_prefix(collected.root_directory())
+                .unwrap_or(f)
+                .to_path_buf()
+        })
+        .collect::<Vec<_>>();
+    let (selectors, packs) = config.auto_selectors(&files);
+    if packs.is_empty() {
+        eprintln!("No actions given and no builtin rule pack applies to the files");
+    } else {
+        eprintln!(
+            "No actions given, running the checks of the builtin rule packs for the files: {}",
+            packs.join(", ")
+        );
+    }
+    selectors
+}
+
+/// Run the actions selected by the `run` command
+fn run_actions(
+    config: &config::Configuration,
+    run: arg_parse::RunActions,
+    verbosity: u8,
+) -> Result<beautytips::RunSummary> {
+    // The files picking the rule packs are the ones the actions run on
+    let collected =
+        if run.actions.is_empty() && run.repositories.is_empty() && run.workspace.is_none() {
+            Some(beautytips::collect_inputs(
+                std::env::current_dir()?,
+                run.source.clone(),
+            )?)
+        } else {
+            None
+        };
+    let selectors = match &collected {
+        Some(collected) => auto_selectors(config, collected),
+        None => run.actions.clone(),
+    };
+    let (actions, skipped) = config.filtered_actions(&selectors, &run.only, &run.skip);
+    let options = beautytips::RunOptions {
+        stable_order: run.stable_order,
+        dotenv: run.dotenv,
+        environment: run.environment.clone(),
+        jobs: run.jobs,
+        verbosity,
+        report_capacity: run.report_capacity,
+        report_overflow: run.report_overflow,
+        lock: run.lock,
+        isolate: run.isolate,
+        keep_going: run.keep_going,
+        max_files: run.max_files,
+        timeout: run.timeout,
+        spill_threshold: run.spill_threshold,
+        skipped,
+    };
+
+    let actions = if run.failed_first {
+        failed_actions_first(&actions)?
+    } else {
+        actions
+    };
+
+    let mut reporters = match run.output {
+        Some(format) => vec![reporter::ReporterSpec::Format(format)],
+        None if !config.reporters.is_empty() => config.reporters.clone(),
+        None => vec![reporter::ReporterSpec::Format(
+            reporter::OutputFormat::detect(),
+        )],
+    };
+    reporters.extend(run.report_file.clone().map(reporter::ReporterSpec::Jsonl));
+    let reporter = reporter::create(
+        &reporters,
+        &reporter::ReporterOptions {
+            verbosity,
+            collapse: run.collapse,
+            raw_output: run.raw_output,
+            theme: config.ui.theme(),
+        },
+    )?;
+
+    let repositories = match &run.workspace {
+        Some(workspace) => workspace_repositories(workspace)?,
+        None => run.repositories,
+    };
+    if !repositories.is_empty() {
+        return run_in_repositories(&repositories, &run.source, &actions, reporter, &options);
+    }
+
+    let reporter = Box::new(last_run::RecordingReporter::new(reporter));
+    match collected {
+        Some(collected) => beautytips::run_collected(collected, actions, reporter, &options),
+        None => beautytips::run_with_options(
+            std::env::current_dir()?,
+            run.source,
+            actions,
+            reporter,
+            &options,
+        ),
+    }
+}
+
+/// Run the actions that failed in the last run on the files of the last run
+fn rerun_failed(config: &config::Configuration, verbosity: u8) -> Result<beautytips::RunSummary> {
+    let Some(last_run) = last_run::LastRun::load(&std::env::current_dir()?)? else {
+        return Err(anyhow::anyhow!("No results of a previous run found"));
+    };
+
+    let failed = last_run.failed_actions();
+    if failed.is_empty() {
+        println!("No action failed in the last run");
+        return Ok(beautytips::RunSummary::default());
+    }
+
+    let selectors = config::ActionSelectors::from(
+        failed
+            .iter()
+            .map(|id| config::ActionSelector::new(id))
+            .collect::<Result<Vec<_>>>()?,
+    );
+    let reporter = last_run::RecordingReporter::new(Box::new(
+        reporter::Reporter::new(verbosity).themed(config.ui.theme()),
+    ));
+
+    beautytips::run(
+        last_run.root_directory,
+        beautytips::InputFiles::FileList(last_run.files),
+        config.actions(&selectors),
+        Box::new(reporter),
+    )
+}
+
+/// Remove the caches, the state and the logs of beautytips
+///
+/// Only what beautytips puts into these directories is removed, as they may
+/// be shared with other tools when overridden.
+fn clean() -> Result<()> {
+    let paths = [
+        beautytips::actions_cache_directory(),
+        last_run::LastRun::file_name().ok(),
+        beautytips::log_directory(),
+    ];
+    for path in paths.into_iter().flatten() {
+        let removed = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else if path.exists() {
+            std::fs::remove_file(&path)
+        } else {
+            continue;
+        };
+        removed.context(format!("Failed to remove {}", path.display()))?;
+        println!("Removed {}", path.display());
+    }
+    Ok(())
+}
+
+/// The exit code of runs that got cancelled by `--timeout`, like the one of
+/// the `timeout` command
+const TIMED_OUT_EXIT_CODE: i32 = 124;
+
+/// The exit code of runs with actions that failed
+const FAILED_EXIT_CODE: i32 = 1;
+
+/// Exit with the code matching the `result` of a run
+///
+/// Runs that timed out exit with `TIMED_OUT_EXIT_CODE`, those with failed
+/// actions with `FAILED_EXIT_CODE`. Other errors are returned.
+fn exit_with_run_status(result: Result<beautytips::RunSummary>) -> Result<()> {
+    match result {
+        Ok(summary) if summary.failed() => std::process::exit(FAILED_EXIT_CODE),
+        Ok(_) => Ok(()),
+        Err(e) if e.is::<beautytips::TimedOut>() => {
+            eprintln!("{e}");
+            std::process::exit(TIMED_OUT_EXIT_CODE);
+        }
+        Err(e) => Err(e),
+    }
+}
 
 fn main() -> Result<()> {
     let command = arg_parse::command().context("Failed to parse command line arguments")?;
 
+    // The man page describes the command line only, so a broken configuration
+    // must not prevent generating it
+    if matches!(command.command, arg_parse::Command::Man {}) {
+        std::io::Write::write_all(&mut std::io::stdout(), &arg_parse::man_page()?)?;
+        return Ok(());
+    }
+
+    // Nothing else runs yet, and the builtin commands run by actions use the
+    // same directories
+    for (variable, directory) in [
+        (beautytips::CACHE_DIR_VARIABLE, &command.cache_directory),
+        (beautytips::STATE_DIR_VARIABLE, &command.state_directory),
+    ] {
+        if let Some(directory) = directory {
+            std::env::set_var(variable, directory);
+        }
+    }
+
     let max_level = match command.debug_level {
         0 => tracing_subscriber::filter::LevelFilter::ERROR,
         1 => tracing_subscriber::filter::LevelFilter::WARN,
@@ -29,14 +445,20 @@ fn main() -> Result<()> {
         .init();
 
     match command.command {
-        arg_parse::Command::Builtin { action, arguments } => {
-            let exit_code = builtin_commands::run_builtin_command(
-                &action,
-                &arguments,
-                command.verbosity_level,
-            )?;
+        arg_parse::Command::Builtin { command: builtin } => {
+            let exit_code =
+                builtin_commands::run_builtin_command(&builtin, command.verbosity_level)?;
             std::process::exit(exit_code);
         }
+        arg_parse::Command::DescribeBuiltin { name } => {
+            let description = builtin_commands::describe::describe(&name)
+                .ok_or(anyhow::anyhow!("'{name}' is no builtin command"))?;
+            println!("{}", serde_json::to_string_pretty(&description)?);
+            Ok(())
+        }
+        arg_parse::Command::CheckConfig { executables } => {
+            check_config::check_config(&config, executables)
+        }
         arg_parse::Command::ListActions {} => {
             for ag in config.action_groups.keys() {
                 println!("{ag} (group)");
@@ -47,31 +469,115 @@ fn main() -> Result<()> {
 
             Ok(())
         }
-        arg_parse::Command::ListFiles { source } => {
-            let (root_dir, files) =
-                beautytips::collect_input_files(std::env::current_dir()?, source)?;
-            println!("root directory: {root_dir:?}");
-            for f in &files {
-                println!("{f:?}");
-            }
+        arg_parse::Command::Clean {} => clean(),
+        arg_parse::Command::DumpConfig { format } => {
+            println!("{}", config::dump(&config, format)?);
             Ok(())
         }
-        arg_parse::Command::RunActions {
-            source: inputs,
+        arg_parse::Command::ConfigSchema {} => {
+            println!("{}", config::json_schema());
+            Ok(())
+        }
+        arg_parse::Command::HelpAction { actions } => help_action(&config, &actions),
+        arg_parse::Command::Why {
+            source,
+            path,
             actions,
-        } => {
-            let reporter = reporter::Reporter::default();
+        } => why::why(&config, &source, &path, &actions),
+        arg_parse::Command::Man {} => {
+            unreachable!("The man page is printed before loading the configuration")
+        }
+        arg_parse::Command::SelfUpdate { check_only } => self_update::self_update(check_only),
+        arg_parse::Command::AddAction {
+            name,
+            description,
+            command,
+            filters,
+            config_file,
+        } => add_action(
+            &name,
+            description.as_deref(),
+            &command,
+            &filters,
+            config_file,
+        ),
+        arg_parse::Command::ListFiles {
+            source,
+            count,
+            relative,
+            null,
+        } => list_files(source, count, relative, null, command.verbosity_level),
+        arg_parse::Command::RunActions(run) => {
+            exit_with_run_status(run_actions(&config, *run, command.verbosity_level))
+        }
+        arg_parse::Command::RerunFailed {} => {
+            exit_with_run_status(rerun_failed(&config, command.verbosity_level))
+        }
+        arg_parse::Command::Watch { debounce, run } => {
+            watch::watch(&config, &run, debounce, command.verbosity_level)
+        }
+        arg_parse::Command::SelfTest { pack, print } => {
+            self_test::self_test(pack.as_deref(), print)
+        }
+    }
+}
 
-            let actions = config.actions(&actions);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            beautytips::run(
-                std::env::current_dir()?,
-                inputs,
-                actions,
-                Box::new(reporter),
-            )?;
+    #[test]
+    fn test_write_file_list() {
+        let root = Path::new("/repo");
+        let files = [PathBuf::from("/repo/a.rs"), PathBuf::from("/repo/src/b.rs")];
+        let list = |relative, null| {
+            let mut out = Vec::new();
+            write_file_list(&mut out, root, &files, relative, null).unwrap();
+            String::from_utf8(out).unwrap()
+        };
 
-            Ok(())
+        assert_eq!(list(false, false), "/repo/a.rs\n/repo/src/b.rs\n");
+        assert_eq!(list(true, false), "a.rs\nsrc/b.rs\n");
+        assert_eq!(list(true, true), "a.rs\0src/b.rs\0");
+        assert_eq!(list(false, true), "/repo/a.rs\0/repo/src/b.rs\0");
+    }
+
+    /// Records the summaries reported and whether it got finished
+    struct FinishReporter(Rc<RefCell<(Vec<usize>, bool)>>);
+
+    impl beautytips::Reporter for FinishReporter {
+        fn report_start(&mut self, _action: beautytips::ActionHandle) {}
+
+        fn report_done(
+            &mut self,
+            _action: beautytips::ActionHandle,
+            _result: beautytips::ActionResult,
+        ) {
+        }
+
+        fn report_summary(&mut self, summary: &beautytips::RunSummary) {
+            self.0.borrow_mut().0.push(summary.ok);
+        }
+
+        fn finish(&mut self) {
+            self.0.borrow_mut().1 = true;
         }
     }
+
+    #[test]
+    fn test_run_in_missing_repository() {
+        let current_directory = std::env::current_dir().unwrap();
+        let state = Rc::new(RefCell::new((vec![], false)));
+
+        let result = run_in_repositories(
+            &[PathBuf::from("does/not/exist")],
+            &beautytips::InputFiles::FileList(vec![]),
+            &beautytips::ActionSet::default(),
+            Box::new(FinishReporter(state.clone())),
+            &beautytips::RunOptions::default(),
+        );
+        assert!(result.is_err());
+        assert_eq!(*state.borrow(), (vec![0], true));
+        assert_eq!(std::env::current_dir().unwrap(), current_directory);
+    }
 }
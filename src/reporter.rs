@@ -1,19 +1,263 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
 
-use std::io::{self, Write};
+use std::{
+    cell::RefCell,
+    fmt::Write as _,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 use crossterm::{cursor, style, terminal};
 
+mod buildkite;
+mod github;
+mod junit;
+mod report_file;
+mod teamcity;
+pub mod theme;
+
+/// The format to report results in
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Interactive output on a terminal, plain lines when not writing to one
+    Terminal,
+    /// Plain lines without colors or a status line
+    Plain,
+    /// `JUnit` XML, printed once all actions are done
+    Junit,
+    /// `TeamCity` service messages
+    Teamcity,
+    /// `Buildkite` log groups and a build annotation for failed actions
+    Buildkite,
+    /// `GitHub Actions` log groups and an annotation for each failed action
+    Github,
+}
+
+impl OutputFormat {
+    /// Pick the output format based on the CI system we run in
+    pub fn detect() -> Self {
+        if std::env::var_os("TEAMCITY_VERSION").is_some() {
+            Self::Teamcity
+        } else if std::env::var("BUILDKITE").is_ok_and(|v| v == "true") {
+            Self::Buildkite
+        } else if std::env::var("GITHUB_ACTIONS").is_ok_and(|v| v == "true") {
+            Self::Github
+        } else {
+            Self::Terminal
+        }
+    }
+}
+
+/// A reporter to create, as named in the configuration: An output format
+/// like `terminal`, or `jsonl:<file>` to append all reports to a JSON Lines
+/// file
+#[derive(Clone, Debug)]
+pub enum ReporterSpec {
+    Format(OutputFormat),
+    Jsonl(PathBuf),
+}
+
+impl std::str::FromStr for ReporterSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("jsonl:") {
+            if path.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "The jsonl reporter needs a file, like `jsonl:results.jsonl`"
+                ));
+            }
+            return Ok(Self::Jsonl(PathBuf::from(path)));
+        }
+        <OutputFormat as clap::ValueEnum>::from_str(s, false)
+            .map(Self::Format)
+            .map_err(|_| {
+                let names = <OutputFormat as clap::ValueEnum>::value_variants()
+                    .iter()
+                    .filter_map(clap::ValueEnum::to_possible_value)
+                    .map(|v| format!("`{}`", v.get_name()))
+                    .collect::<Vec<_>>();
+                anyhow::anyhow!(format!(
+                    "Unknown reporter '{s}', expected one of {} or `jsonl:<file>`",
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+/// The settings of the terminal and plain output
+///
+/// `collapse` folds skipped and not applicable actions into one line each,
+/// `raw_output` prints the output of actions without sanitizing it. Both use
+/// the prefixes of `theme`, the terminal output its colors as well.
+#[derive(Default)]
+pub struct ReporterOptions {
+    pub verbosity: u8,
+    pub collapse: bool,
+    pub raw_output: bool,
+    pub theme: theme::Theme,
+}
+
+fn create_one(
+    spec: &ReporterSpec,
+    options: &ReporterOptions,
+) -> anyhow::Result<Box<dyn beautytips::Reporter>> {
+    Ok(match spec {
+        ReporterSpec::Format(OutputFormat::Terminal) => Box::new(
+            Reporter::new(options.verbosity)
+                .collapsed(options.collapse)
+                .raw_output(options.raw_output)
+                .themed(options.theme.clone()),
+        ),
+        ReporterSpec::Format(OutputFormat::Plain) => Box::new(
+            Reporter::plain(options.verbosity)
+                .collapsed(options.collapse)
+                .raw_output(options.raw_output)
+                .themed(options.theme.clone()),
+        ),
+        ReporterSpec::Format(OutputFormat::Junit) => Box::<junit::JUnitReporter>::default(),
+        ReporterSpec::Format(OutputFormat::Teamcity) => {
+            Box::<teamcity::TeamCityReporter>::default()
+        }
+        ReporterSpec::Format(OutputFormat::Buildkite) => {
+            Box::<buildkite::BuildkiteReporter>::default()
+        }
+        ReporterSpec::Format(OutputFormat::Github) => Box::<github::GitHubReporter>::default(),
+        ReporterSpec::Jsonl(path) => Box::new(report_file::ReportFileReporter::new(path)?),
+    })
+}
+
+/// Create the reporters of `specs`, passing all reports to each of them
+///
+/// All output formats print to stdout, so only one of them can be used at a
+/// time.
+pub fn create(
+    specs: &[ReporterSpec],
+    options: &ReporterOptions,
+) -> anyhow::Result<Box<dyn beautytips::Reporter>> {
+    let formats = specs
+        .iter()
+        .filter_map(|s| match s {
+            ReporterSpec::Format(f) => clap::ValueEnum::to_possible_value(f),
+            ReporterSpec::Jsonl(_) => None,
+        })
+        .map(|v| format!("`{}`", v.get_name()))
+        .collect::<Vec<_>>();
+    if formats.len() > 1 {
+        return Err(anyhow::anyhow!(format!(
+            "The reporters {} all print to stdout, use only one of them",
+            formats.join(", ")
+        )));
+    }
+
+    let mut reporters = specs
+        .iter()
+        .map(|s| create_one(s, options))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(if reporters.len() == 1 {
+        reporters.remove(0)
+    } else {
+        Box::new(StackedReporter { reporters })
+    })
+}
+
+/// `true` if stdout is an interactive terminal that understands escape
+/// sequences
+fn is_interactive() -> bool {
+    io::stdout().is_terminal() && std::env::var("TERM").map_or(true, |t| t != "dumb")
+}
+
 #[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Reporter {
     running: Vec<String>,
     has_status: bool,
+    verbosity: u8,
+    /// Print plain lines only, without colors or a status line
+    plain: bool,
+    /// Report skipped and not applicable actions in one line each when
+    /// finishing
+    collapse: bool,
+    /// Print the output of actions without sanitizing it
+    raw_output: bool,
+    theme: theme::Theme,
+    skipped: Vec<String>,
+    not_applicable: Vec<String>,
 }
 
 impl Reporter {
+    /// A reporter for the terminal, falling back to plain lines when stdout
+    /// is no interactive terminal
+    pub fn new(verbosity: u8) -> Self {
+        Self {
+            verbosity,
+            plain: !is_interactive(),
+            ..Self::default()
+        }
+    }
+
+    /// A reporter printing plain lines
+    pub fn plain(verbosity: u8) -> Self {
+        Self {
+            verbosity,
+            plain: true,
+            ..Self::default()
+        }
+    }
+
+    /// Fold skipped and not applicable actions into one line each
+    pub fn collapsed(self, collapse: bool) -> Self {
+        Self { collapse, ..self }
+    }
+
+    /// Print the output of actions as is, see `sanitize`
+    pub fn raw_output(self, raw_output: bool) -> Self {
+        Self { raw_output, ..self }
+    }
+
+    /// Use the colors and prefixes of `theme`
+    pub fn themed(self, theme: theme::Theme) -> Self {
+        Self { theme, ..self }
+    }
+
+    /// `text` from a tool or file as printed, see `sanitize`
+    fn shown(&self, text: &str) -> String {
+        if self.raw_output {
+            text.to_string()
+        } else {
+            sanitize(text)
+        }
+    }
+
+    /// The output of an action as printed
+    fn output_to_str(&self, stdout: &[u8], stderr: &[u8]) -> String {
+        self.shown(&stdout_and_err_to_str(stdout, stderr))
+    }
+
+    /// Print `text`, in the color of its kind unless the reporter is plain
+    fn print(&self, text: &[(theme::Kind, String)]) {
+        let mut stdout = io::stdout();
+        for (kind, t) in text {
+            match self.theme.look(*kind).color {
+                Some(color) if !self.plain => {
+                    crossterm::queue!(stdout, style::SetForegroundColor(color), style::Print(t))
+                }
+                _ => crossterm::queue!(stdout, style::Print(t)),
+            }
+            .expect("print failed");
+        }
+        if !self.plain {
+            crossterm::queue!(stdout, style::ResetColor).expect("print failed");
+        }
+    }
+
     fn print_status(&mut self) {
         self.clear_status();
+        if self.plain {
+            return;
+        }
 
         let (width, _) = terminal::size().unwrap_or((80, 40));
         let mut running = self.running.join(", ");
@@ -48,6 +292,177 @@ impl Reporter {
     }
 }
 
+/// Forward all reports to each of several reporters
+struct StackedReporter {
+    reporters: Vec<Box<dyn beautytips::Reporter>>,
+}
+
+impl beautytips::Reporter for StackedReporter {
+    fn report_files_collected(&mut self, root_directory: &Path, files: &[PathBuf]) {
+        for r in &mut self.reporters {
+            r.report_files_collected(root_directory, files);
+        }
+    }
+
+    fn report_start(&mut self, action: beautytips::ActionHandle) {
+        for r in &mut self.reporters {
+            r.report_start(action.clone());
+        }
+    }
+
+    fn report_action_output_chunk(&mut self, action_id: &str, stdout: &[u8], stderr: &[u8]) {
+        for r in &mut self.reporters {
+            r.report_action_output_chunk(action_id, stdout, stderr);
+        }
+    }
+
+    fn wants_output_chunks(&self) -> bool {
+        self.reporters.iter().any(|r| r.wants_output_chunks())
+    }
+
+    fn report_action_detail(&mut self, action_id: &str, message: &str) {
+        for r in &mut self.reporters {
+            r.report_action_detail(action_id, message);
+        }
+    }
+
+    fn report_done(&mut self, action: beautytips::ActionHandle, result: beautytips::ActionResult) {
+        for r in &mut self.reporters {
+            r.report_done(action.clone(), result.clone());
+        }
+    }
+
+    fn report_allowed_failure(
+        &mut self,
+        action: beautytips::ActionHandle,
+        result: beautytips::ActionResult,
+    ) {
+        for r in &mut self.reporters {
+            r.report_allowed_failure(action.clone(), result.clone());
+        }
+    }
+
+    fn report_summary(&mut self, summary: &beautytips::RunSummary) {
+        for r in &mut self.reporters {
+            r.report_summary(summary);
+        }
+    }
+
+    fn finish(&mut self) {
+        for r in &mut self.reporters {
+            r.finish();
+        }
+    }
+}
+
+/// Forward reports to a shared `Reporter`, prefixing all action ids
+///
+/// This is used to merge the reports from runs in several repositories.
+pub struct PrefixedReporter {
+    prefix: String,
+    inner: Rc<RefCell<Box<dyn beautytips::Reporter>>>,
+}
+
+impl PrefixedReporter {
+    pub fn new(prefix: String, inner: Rc<RefCell<Box<dyn beautytips::Reporter>>>) -> Self {
+        Self { prefix, inner }
+    }
+
+    fn prefixed(&self, action_id: &str) -> String {
+        format!("{}:{action_id}", self.prefix)
+    }
+
+    fn prefixed_handle(&self, mut action: beautytips::ActionHandle) -> beautytips::ActionHandle {
+        action.id = self.prefixed(&action.id);
+        action
+    }
+}
+
+impl beautytips::Reporter for PrefixedReporter {
+    fn report_files_collected(&mut self, root_directory: &Path, files: &[PathBuf]) {
+        self.inner
+            .borrow_mut()
+            .report_files_collected(root_directory, files);
+    }
+
+    fn report_start(&mut self, action: beautytips::ActionHandle) {
+        let action = self.prefixed_handle(action);
+        self.inner.borrow_mut().report_start(action);
+    }
+
+    fn report_action_output_chunk(&mut self, action_id: &str, stdout: &[u8], stderr: &[u8]) {
+        let action_id = self.prefixed(action_id);
+        self.inner
+            .borrow_mut()
+            .report_action_output_chunk(&action_id, stdout, stderr);
+    }
+
+    fn wants_output_chunks(&self) -> bool {
+        self.inner.borrow().wants_output_chunks()
+    }
+
+    fn report_action_detail(&mut self, action_id: &str, message: &str) {
+        let action_id = self.prefixed(action_id);
+        self.inner
+            .borrow_mut()
+            .report_action_detail(&action_id, message);
+    }
+
+    fn report_done(&mut self, action: beautytips::ActionHandle, result: beautytips::ActionResult) {
+        let action = self.prefixed_handle(action);
+        self.inner.borrow_mut().report_done(action, result);
+    }
+
+    fn report_allowed_failure(
+        &mut self,
+        action: beautytips::ActionHandle,
+        result: beautytips::ActionResult,
+    ) {
+        let action = self.prefixed_handle(action);
+        self.inner
+            .borrow_mut()
+            .report_allowed_failure(action, result);
+    }
+
+    fn finish(&mut self) {
+        // The shared reporter gets finished once all runs are done, and
+        // the summaries of the individual runs are of no interest to it.
+    }
+}
+
+/// The number of action ids listed in a collapsed line without `--verbose`
+const COLLAPSED_IDS: usize = 3;
+
+/// One line for all `action_ids` with the same result, like
+/// `14 actions not applicable (ruff/check, mypy/check, ...)`
+///
+/// All ids are listed when `verbosity` is above 0.
+fn collapsed_line(action_ids: &[String], result: &str, verbosity: u8) -> String {
+    let shown = if verbosity > 0 {
+        action_ids.len()
+    } else {
+        COLLAPSED_IDS
+    };
+    let mut ids = action_ids
+        .iter()
+        .take(shown)
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if action_ids.len() > shown {
+        ids.push("...");
+    }
+    let actions = if action_ids.len() == 1 {
+        "action"
+    } else {
+        "actions"
+    };
+    format!(
+        "{} {actions} {result} ({})",
+        action_ids.len(),
+        ids.join(", ")
+    )
+}
+
 fn to_str(input: &[u8]) -> String {
     let input = if input.ends_with(b"\n") {
         &input[..input.len() - 1]
@@ -68,6 +483,60 @@ fn to_str(input: &[u8]) -> String {
     format!("{indent}{s}")
 }
 
+/// Skip the rest of the escape sequence started by an `ESC` in `chars`
+fn skip_escape_sequence(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    match chars.next() {
+        // Control sequences end with a character in `@`..=`~`
+        Some('[') => {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+        // Strings (like the window title) end with `BEL` or `ESC \`
+        Some(']' | 'P' | 'X' | '^' | '_') => {
+            while let Some(c) = chars.next() {
+                if c == '\u{7}' {
+                    break;
+                }
+                if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                    chars.next();
+                    break;
+                }
+            }
+        }
+        // Intermediate characters followed by one final character, like
+        // `ESC ( B`
+        Some(' '..='/') => {
+            while chars.next_if(|c| (' '..='/').contains(c)).is_some() {}
+            chars.next();
+        }
+        // Everything else is `ESC` followed by one character
+        _ => {}
+    }
+}
+
+/// `text` without escape sequences and with all other control characters
+/// but newlines and tabs escaped, so that the output of tools can not move
+/// the cursor, change the window title or the like
+fn sanitize(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' | '\t' => result.push(c),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\u{1b}' => skip_escape_sequence(&mut chars),
+            c if c.is_control() => {
+                let _ = write!(result, "\\x{:02x}", u32::from(c));
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
 fn stdout_and_err_to_str(stdout: &[u8], stderr: &[u8]) -> String {
     let mut output = to_str(stdout);
     if output.is_empty() {
@@ -76,83 +545,137 @@ fn stdout_and_err_to_str(stdout: &[u8], stderr: &[u8]) -> String {
         output = format!("{output}\n{}", to_str(stderr));
     }
     if !output.is_empty() {
-        output = format!("\n{output}",);
+        output = format!("\n{output}");
     }
 
     output
 }
 
 impl beautytips::Reporter for Reporter {
-    fn report_start(&mut self, action_id: String) {
-        self.running.push(action_id);
+    fn report_start(&mut self, action: beautytips::ActionHandle) {
+        self.running.push(action.id);
         self.print_status();
     }
 
-    fn report_done(&mut self, action_id: String, result: beautytips::ActionResult) {
+    fn report_action_detail(&mut self, action_id: &str, message: &str) {
         self.clear_status();
+        let message = self.shown(message);
+        self.print(&[(theme::Kind::Output, format!("   {action_id}: {message}\n"))]);
+        self.print_status();
+    }
 
-        self.running = self
-            .running
-            .iter()
-            .filter(|id| *id != &action_id)
-            .cloned()
-            .collect();
+    fn report_done(&mut self, action: beautytips::ActionHandle, result: beautytips::ActionResult) {
+        let action_id = action.id;
+        self.clear_status();
+        self.running.retain(|id| id != &action_id);
 
         match result {
             beautytips::ActionResult::Ok { stdout, stderr } => {
-                let output = stdout_and_err_to_str(&stdout, &stderr);
-                crossterm::queue!(
-                    io::stdout(),
-                    style::SetForegroundColor(style::Color::Green),
-                    style::Print(format!("✅ {action_id} [OK]")),
-                    style::SetForegroundColor(style::Color::DarkGrey),
-                    style::Print(output),
-                    style::Print('\n'),
-                    style::ResetColor
-                )
-                .expect("print failed");
+                let output = self.output_to_str(&stdout.bytes(), &stderr.bytes());
+                let prefix = self.theme.look(theme::Kind::Ok).prefix;
+                self.print(&[
+                    (theme::Kind::Ok, format!("{prefix} {action_id} [OK]")),
+                    (theme::Kind::Output, format!("{output}\n")),
+                ]);
+            }
+            beautytips::ActionResult::Skipped if self.collapse => {
+                self.skipped.push(action_id);
+            }
+            beautytips::ActionResult::NotApplicable { .. } if self.collapse => {
+                self.not_applicable.push(action_id);
             }
             beautytips::ActionResult::Skipped => {
-                crossterm::queue!(
-                    io::stdout(),
-                    style::SetForegroundColor(style::Color::Blue),
-                    style::Print(format!("🦥 {action_id} [SKIPPED]\n")),
-                    style::ResetColor,
-                )
-                .expect("print failed");
+                let prefix = self.theme.look(theme::Kind::Skipped).prefix;
+                self.print(&[(
+                    theme::Kind::Skipped,
+                    format!("{prefix} {action_id} [SKIPPED]\n"),
+                )]);
             }
-            beautytips::ActionResult::NotApplicable => {
-                crossterm::queue!(
-                    io::stdout(),
-                    style::SetForegroundColor(style::Color::Blue),
-                    style::Print(format!("🚙 {action_id} [NOT APPLICABLE]\n")),
-                    style::ResetColor,
-                )
-                .expect("print failed");
+            beautytips::ActionResult::NotApplicable { reason } => {
+                let reason = if self.verbosity > 0 {
+                    format!(": {}", self.shown(&reason.to_string()))
+                } else {
+                    String::new()
+                };
+                let prefix = self.theme.look(theme::Kind::NotApplicable).prefix;
+                self.print(&[(
+                    theme::Kind::NotApplicable,
+                    format!("{prefix} {action_id} [NOT APPLICABLE]{reason}\n"),
+                )]);
             }
-            beautytips::ActionResult::Warn { stdout, stderr } => {
-                let output = stdout_and_err_to_str(&stdout, &stderr);
-                crossterm::queue!(
-                    io::stdout(),
-                    style::SetForegroundColor(style::Color::Yellow),
-                    style::Print(format!("💡 {action_id} [WARN]")),
-                    style::SetForegroundColor(style::Color::DarkGrey),
-                    style::Print(output),
-                    style::Print('\n'),
-                    style::ResetColor,
-                )
-                .expect("print failed");
+            beautytips::ActionResult::Warn {
+                stdout,
+                stderr,
+                hint,
+            } => {
+                let output = self.output_to_str(&stdout.bytes(), &stderr.bytes());
+                let prefix = self.theme.look(theme::Kind::Warn).prefix;
+                self.print(&[
+                    (theme::Kind::Warn, format!("{prefix} {action_id} [WARN]")),
+                    (theme::Kind::Output, format!("{output}\n")),
+                ]);
+                if let Some(hint) = hint {
+                    let hint = self.shown(&hint);
+                    self.print(&[(theme::Kind::Warn, format!("    Hint: {hint}\n"))]);
+                }
             }
-            beautytips::ActionResult::Error { message } => {
-                crossterm::queue!(
-                    io::stdout(),
-                    style::SetForegroundColor(style::Color::Red),
-                    style::Print(format!("🚨 {action_id} [ERROR]: {message}\n")),
-                    style::ResetColor,
-                )
-                .expect("print failed");
+            beautytips::ActionResult::Error { message, hint } => {
+                let message = self.shown(&message);
+                let prefix = self.theme.look(theme::Kind::Error).prefix;
+                self.print(&[(
+                    theme::Kind::Error,
+                    format!("{prefix} {action_id} [ERROR]: {message}\n"),
+                )]);
+                if let Some(hint) = hint {
+                    let hint = self.shown(&hint);
+                    self.print(&[(theme::Kind::Error, format!("    Hint: {hint}\n"))]);
+                }
             }
+        }
+
+        if !self.running.is_empty() {
+            self.print_status();
+        }
+    }
+
+    fn report_allowed_failure(
+        &mut self,
+        action: beautytips::ActionHandle,
+        result: beautytips::ActionResult,
+    ) {
+        let action_id = action.id;
+        self.clear_status();
+        self.running.retain(|id| id != &action_id);
+
+        let output = match result {
+            beautytips::ActionResult::Warn {
+                stdout,
+                stderr,
+                hint,
+            } => {
+                let mut output = self.output_to_str(&stdout.bytes(), &stderr.bytes());
+                if let Some(hint) = hint {
+                    output = format!("{output}\n    Hint: {}", self.shown(&hint));
+                }
+                output
+            }
+            beautytips::ActionResult::Error { message, hint } => {
+                let mut output = format!(": {}", self.shown(&message));
+                if let Some(hint) = hint {
+                    output = format!("{output}\n    Hint: {}", self.shown(&hint));
+                }
+                output
+            }
+            _ => String::new(),
         };
+        let prefix = self.theme.look(theme::Kind::AllowedFailure).prefix;
+        self.print(&[
+            (
+                theme::Kind::AllowedFailure,
+                format!("{prefix} {action_id} [ALLOWED FAILURE]"),
+            ),
+            (theme::Kind::Output, format!("{output}\n")),
+        ]);
 
         if !self.running.is_empty() {
             self.print_status();
@@ -161,5 +684,94 @@ impl beautytips::Reporter for Reporter {
 
     fn finish(&mut self) {
         self.clear_status();
+
+        if !self.skipped.is_empty() {
+            let line = collapsed_line(&self.skipped, "skipped", self.verbosity);
+            let prefix = self.theme.look(theme::Kind::Skipped).prefix;
+            self.print(&[(theme::Kind::Skipped, format!("{prefix} {line}\n"))]);
+        }
+        if !self.not_applicable.is_empty() {
+            let line = collapsed_line(&self.not_applicable, "not applicable", self.verbosity);
+            let prefix = self.theme.look(theme::Kind::NotApplicable).prefix;
+            self.print(&[(theme::Kind::NotApplicable, format!("{prefix} {line}\n"))]);
+        }
+
+        io::stdout().flush().expect("Flushing failed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reporter_spec() {
+        assert!(matches!(
+            "github".parse::<ReporterSpec>().unwrap(),
+            ReporterSpec::Format(OutputFormat::Github)
+        ));
+        assert!(matches!(
+            "jsonl:out/results.jsonl".parse::<ReporterSpec>().unwrap(),
+            ReporterSpec::Jsonl(p) if p == Path::new("out/results.jsonl")
+        ));
+        assert!("jsonl:".parse::<ReporterSpec>().is_err());
+        let error = "tap".parse::<ReporterSpec>().unwrap_err().to_string();
+        assert!(error.contains("`terminal`, `plain`"), "{error}");
+    }
+
+    #[test]
+    fn test_create_one_stdout_reporter() {
+        let options = ReporterOptions::default();
+        let directory = tempfile::tempdir().unwrap();
+        let jsonl = format!("jsonl:{}", directory.path().join("r.jsonl").display());
+        let specs = ["github", &jsonl].map(|s| s.parse().unwrap());
+        assert!(create(&specs, &options).is_ok());
+
+        let specs = ["terminal", "github"].map(|s| s.parse().unwrap());
+        let error = create(&specs, &options).err().unwrap().to_string();
+        assert_eq!(
+            error,
+            "The reporters `terminal`, `github` all print to stdout, use only one of them"
+        );
+    }
+
+    #[test]
+    fn test_collapsed_line() {
+        let ids =
+            ["ruff/check", "mypy/check", "go/check_fmt", "toml/check"].map(ToString::to_string);
+
+        assert_eq!(
+            collapsed_line(&ids[..1], "skipped", 0),
+            "1 action skipped (ruff/check)"
+        );
+        assert_eq!(
+            collapsed_line(&ids[..3], "skipped", 0),
+            "3 actions skipped (ruff/check, mypy/check, go/check_fmt)"
+        );
+        assert_eq!(
+            collapsed_line(&ids, "not applicable", 0),
+            "4 actions not applicable (ruff/check, mypy/check, go/check_fmt, ...)"
+        );
+        assert_eq!(
+            collapsed_line(&ids, "not applicable", 1),
+            "4 actions not applicable (ruff/check, mypy/check, go/check_fmt, toml/check)"
+        );
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("plain\n\ttext ✅"), "plain\n\ttext ✅");
+        assert_eq!(sanitize("\u{1b}[1;31merror\u{1b}[0m: x"), "error: x");
+        assert_eq!(sanitize("\u{1b}]0;pwned\u{7}title"), "title");
+        assert_eq!(sanitize("\u{1b}]8;;http://x\u{1b}\\link"), "link");
+        assert_eq!(sanitize("\u{1b}(Bcharset\u{1b}7"), "charset");
+        assert_eq!(sanitize("windows\r\nline"), "windows\nline");
+        assert_eq!(sanitize("50%\r100%\u{7}"), "50%\\x0d100%\\x07");
+        assert_eq!(sanitize("c1\u{9b}2J"), "c1\\x9b2J");
+
+        let reporter = Reporter::plain(0);
+        assert_eq!(reporter.shown("\u{1b}]0;pwned\u{7}message"), "message");
+        let reporter = reporter.raw_output(true);
+        assert_eq!(reporter.shown("\u{1b}[1mbold"), "\u{1b}[1mbold");
     }
 }
@@ -0,0 +1,221 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{fmt::Write as _, io::Write as _};
+
+/// Report results as collapsible `Buildkite` log groups and add an
+/// annotation listing all failed actions to the build
+#[derive(Default)]
+pub struct BuildkiteReporter {
+    failures: Vec<(String, String)>,
+}
+
+fn output_to_string(stdout: &[u8], stderr: &[u8]) -> String {
+    format!(
+        "{}\n{}",
+        String::from_utf8_lossy(stdout).trim(),
+        String::from_utf8_lossy(stderr).trim()
+    )
+    .trim()
+    .to_string()
+}
+
+/// The log group of an action that failed, but is allowed to
+///
+/// The group is collapsed and the action is not part of the annotation.
+fn allowed_failure_log(action_id: &str, result: beautytips::ActionResult) -> String {
+    let output = match result {
+        beautytips::ActionResult::Warn {
+            stdout,
+            stderr,
+            hint,
+        } => {
+            let output = output_to_string(&stdout.bytes(), &stderr.bytes());
+            match hint {
+                Some(hint) => format!("{output}\nHint: {hint}").trim_start().to_string(),
+                None => output,
+            }
+        }
+        beautytips::ActionResult::Error { message, hint } => match hint {
+            Some(hint) => format!("{message}\nHint: {hint}"),
+            None => message,
+        },
+        _ => String::new(),
+    };
+    format!("--- :grey_exclamation: {action_id} (allowed to fail)\n{output}")
+        .trim_end()
+        .to_string()
+}
+
+/// Escape `text` for use in the HTML of an annotation
+fn escape_html(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+impl BuildkiteReporter {
+    fn annotation(&self) -> String {
+        let mut result = format!("### beautytips: {} action(s) failed\n", self.failures.len());
+        for (action_id, output) in &self.failures {
+            let _ = write!(
+                result,
+                "\n<details>\n<summary><code>{}</code></summary>\n\n",
+                escape_html(action_id)
+            );
+            if !output.is_empty() {
+                let _ = writeln!(result, "<pre><code>{}</code></pre>", escape_html(output));
+            }
+            result.push_str("\n</details>\n");
+        }
+        result
+    }
+
+    fn annotate(&self) -> anyhow::Result<()> {
+        let mut child = std::process::Command::new("buildkite-agent")
+            .args(["annotate", "--style", "error", "--context", "beautytips"])
+            .stdin(std::process::Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(self.annotation().as_bytes())?;
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            anyhow::bail!("buildkite-agent failed with {status}");
+        }
+        Ok(())
+    }
+}
+
+impl beautytips::Reporter for BuildkiteReporter {
+    fn report_start(&mut self, _action: beautytips::ActionHandle) {}
+
+    fn report_done(&mut self, action: beautytips::ActionHandle, result: beautytips::ActionResult) {
+        let action_id = action.id;
+        match result {
+            beautytips::ActionResult::Ok { stdout, stderr } => {
+                println!("--- :white_check_mark: {action_id}");
+                let output = output_to_string(&stdout.bytes(), &stderr.bytes());
+                if !output.is_empty() {
+                    println!("{output}");
+                }
+            }
+            beautytips::ActionResult::Skipped => {
+                println!("--- :fast_forward: {action_id} (skipped)");
+            }
+            beautytips::ActionResult::NotApplicable { reason } => {
+                println!("--- :heavy_minus_sign: {action_id} (not applicable: {reason})");
+            }
+            beautytips::ActionResult::Warn {
+                stdout,
+                stderr,
+                hint,
+            } => {
+                let mut output = output_to_string(&stdout.bytes(), &stderr.bytes());
+                if let Some(hint) = hint {
+                    output = format!("{output}\nHint: {hint}").trim_start().to_string();
+                }
+                println!("+++ :warning: {action_id}\n{output}");
+                self.failures.push((action_id, output));
+            }
+            beautytips::ActionResult::Error { mut message, hint } => {
+                if let Some(hint) = hint {
+                    message = format!("{message}\nHint: {hint}");
+                }
+                println!("+++ :x: {action_id}\n{message}");
+                self.failures.push((action_id, message));
+            }
+        }
+    }
+
+    fn report_allowed_failure(
+        &mut self,
+        action: beautytips::ActionHandle,
+        result: beautytips::ActionResult,
+    ) {
+        println!("{}", allowed_failure_log(&action.id, result));
+    }
+
+    fn finish(&mut self) {
+        if self.failures.is_empty() {
+            return;
+        }
+        if let Err(e) = self.annotate() {
+            tracing::warn!("Failed to annotate the build: {e:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buildkite_annotation() {
+        let reporter = BuildkiteReporter {
+            failures: vec![
+                (
+                    "rust/check_fmt".to_string(),
+                    "Diff in main.rs\n-a</code></details><script>\n+a".to_string(),
+                ),
+                ("toml/check".to_string(), String::new()),
+            ],
+        };
+
+        assert_eq!(
+            reporter.annotation(),
+            r"### beautytips: 2 action(s) failed
+
+<details>
+<summary><code>rust/check_fmt</code></summary>
+
+<pre><code>Diff in main.rs
+-a&lt;/code&gt;&lt;/details&gt;&lt;script&gt;
++a</code></pre>
+
+</details>
+
+<details>
+<summary><code>toml/check</code></summary>
+
+
+</details>
+"
+        );
+    }
+
+    #[test]
+    fn test_buildkite_allowed_failure() {
+        let mut reporter = BuildkiteReporter::default();
+        beautytips::Reporter::report_allowed_failure(
+            &mut reporter,
+            beautytips::ActionHandle::from_id("toml/check"),
+            beautytips::ActionResult::Error {
+                message: "taplo: not found".to_string(),
+                hint: None,
+            },
+        );
+        assert!(reporter.failures.is_empty());
+
+        assert_eq!(
+            allowed_failure_log(
+                "rust/check_fmt",
+                beautytips::ActionResult::Warn {
+                    stdout: b"Diff\n".to_vec().into(),
+                    stderr: beautytips::ActionOutput::default(),
+                    hint: Some("run cargo fmt".to_string()),
+                }
+            ),
+            "--- :grey_exclamation: rust/check_fmt (allowed to fail)\nDiff\nHint: run cargo fmt"
+        );
+    }
+}
@@ -0,0 +1,273 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::fmt::Write;
+
+/// Escape `input` for use as the message of a workflow command
+fn escape_data(input: &str) -> String {
+    input
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape `input` for use as a property value of a workflow command
+fn escape_property(input: &str) -> String {
+    escape_data(input).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// The sanitized output of a tool
+fn output_to_string(stdout: &[u8], stderr: &[u8]) -> String {
+    super::sanitize(
+        format!(
+            "{}\n{}",
+            String::from_utf8_lossy(stdout).trim(),
+            String::from_utf8_lossy(stderr).trim()
+        )
+        .trim(),
+    )
+}
+
+/// A random token no tool can guess
+fn random_token() -> String {
+    use std::hash::{BuildHasher, Hasher};
+
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let mut token = String::new();
+    for i in 0..2_u8 {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u8(i);
+        hasher.write_u128(nanos);
+        hasher.write_u32(std::process::id());
+        let _ = write!(token, "{:016x}", hasher.finish());
+    }
+    token
+}
+
+/// A log group named `title` holding `output`
+///
+/// Workflow commands are turned off in `output` with `token`, so tools can
+/// not issue any.
+fn group(title: &str, output: &str, token: &str) -> String {
+    let title = escape_data(&super::sanitize(title));
+    if output.is_empty() {
+        format!("::group::{title}\n::endgroup::\n")
+    } else {
+        format!("::group::{title}\n::stop-commands::{token}\n{output}\n::{token}::\n::endgroup::\n")
+    }
+}
+
+/// The workflow commands reporting `result` of `action_id`, turning off
+/// workflow commands in the output of tools with `token`
+fn result_commands(action_id: &str, result: beautytips::ActionResult, token: &str) -> String {
+    match result {
+        beautytips::ActionResult::Ok { stdout, stderr } => group(
+            &format!("✅ {action_id}"),
+            &output_to_string(&stdout.bytes(), &stderr.bytes()),
+            token,
+        ),
+        beautytips::ActionResult::Skipped => group(&format!("🦥 {action_id} (skipped)"), "", token),
+        beautytips::ActionResult::NotApplicable { reason } => group(
+            &format!("🚙 {action_id} (not applicable: {reason})"),
+            "",
+            token,
+        ),
+        beautytips::ActionResult::Warn {
+            stdout,
+            stderr,
+            hint,
+        } => {
+            let mut output = output_to_string(&stdout.bytes(), &stderr.bytes());
+            if let Some(hint) = hint {
+                output = format!("{output}\nHint: {}", super::sanitize(&hint))
+                    .trim_start()
+                    .to_string();
+            }
+            format!(
+                "{}::warning title={}::{}\n",
+                group(&format!("💡 {action_id}"), &output, token),
+                escape_property(action_id),
+                escape_data(if output.is_empty() {
+                    "The action reported a warning"
+                } else {
+                    &output
+                })
+            )
+        }
+        beautytips::ActionResult::Error { message, hint } => {
+            let mut message = super::sanitize(&message);
+            if let Some(hint) = hint {
+                message = format!("{message}\nHint: {}", super::sanitize(&hint));
+            }
+            format!(
+                "{}::error title={}::{}\n",
+                group(&format!("🚨 {action_id}"), &message, token),
+                escape_property(action_id),
+                escape_data(&message)
+            )
+        }
+    }
+}
+
+/// The workflow commands reporting `result` of `action_id`, which failed but
+/// is allowed to
+///
+/// This adds a notice instead of a warning or error annotation.
+fn allowed_failure_commands(
+    action_id: &str,
+    result: beautytips::ActionResult,
+    token: &str,
+) -> String {
+    let output = match result {
+        beautytips::ActionResult::Warn {
+            stdout,
+            stderr,
+            hint,
+        } => {
+            let output = output_to_string(&stdout.bytes(), &stderr.bytes());
+            match hint {
+                Some(hint) => format!("{output}\nHint: {}", super::sanitize(&hint))
+                    .trim_start()
+                    .to_string(),
+                None => output,
+            }
+        }
+        beautytips::ActionResult::Error { message, hint } => match hint {
+            Some(hint) => format!(
+                "{}\nHint: {}",
+                super::sanitize(&message),
+                super::sanitize(&hint)
+            ),
+            None => super::sanitize(&message),
+        },
+        result => return result_commands(action_id, result, token),
+    };
+    format!(
+        "{}::notice title={}::{}\n",
+        group(&format!("⚠️ {action_id} (allowed to fail)"), &output, token),
+        escape_property(&format!("{action_id} (allowed to fail)")),
+        escape_data(if output.is_empty() {
+            "The action failed, but is allowed to"
+        } else {
+            &output
+        })
+    )
+}
+
+/// Report results as `GitHub Actions` log groups, with an annotation for
+/// each failed action
+pub struct GitHubReporter {
+    /// Turns off workflow commands in the output of tools
+    token: String,
+}
+
+impl Default for GitHubReporter {
+    fn default() -> Self {
+        Self {
+            token: random_token(),
+        }
+    }
+}
+
+impl beautytips::Reporter for GitHubReporter {
+    fn report_start(&mut self, _action: beautytips::ActionHandle) {}
+
+    fn report_done(&mut self, action: beautytips::ActionHandle, result: beautytips::ActionResult) {
+        print!("{}", result_commands(&action.id, result, &self.token));
+    }
+
+    fn report_allowed_failure(
+        &mut self,
+        action: beautytips::ActionHandle,
+        result: beautytips::ActionResult,
+    ) {
+        print!(
+            "{}",
+            allowed_failure_commands(&action.id, result, &self.token)
+        );
+    }
+
+    fn finish(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_github_result_commands() {
+        assert_eq!(
+            result_commands("toml/check", beautytips::ActionResult::Skipped, "t0k3n"),
+            "::group::🦥 toml/check (skipped)\n::endgroup::\n"
+        );
+        assert_eq!(
+            result_commands(
+                "rust/check_fmt",
+                beautytips::ActionResult::Warn {
+                    stdout: b"Diff in main.rs:\n+ 100%\n".to_vec().into(),
+                    stderr: beautytips::ActionOutput::default(),
+                    hint: None,
+                },
+                "t0k3n"
+            ),
+            "::group::💡 rust/check_fmt\n::stop-commands::t0k3n\nDiff in main.rs:\n+ 100%\n\
+             ::t0k3n::\n::endgroup::\n\
+             ::warning title=rust/check_fmt::Diff in main.rs:%0A+ 100%25\n"
+        );
+        assert_eq!(
+            result_commands(
+                "mypy/check[3.10,x]",
+                beautytips::ActionResult::Error {
+                    message: "mypy: not found".to_string(),
+                    hint: None,
+                },
+                "t0k3n"
+            ),
+            "::group::🚨 mypy/check[3.10,x]\n::stop-commands::t0k3n\nmypy: not found\n\
+             ::t0k3n::\n::endgroup::\n\
+             ::error title=mypy/check[3.10%2Cx]::mypy: not found\n"
+        );
+    }
+
+    #[test]
+    fn test_github_tool_output() {
+        // Tools can neither end the group nor color the log
+        assert_eq!(
+            result_commands(
+                "evil/check",
+                beautytips::ActionResult::Ok {
+                    stdout: b"::endgroup::\n::error::pwned\n\x1b[31mred".to_vec().into(),
+                    stderr: beautytips::ActionOutput::default(),
+                },
+                "t0k3n"
+            ),
+            "::group::✅ evil/check\n::stop-commands::t0k3n\n::endgroup::\n::error::pwned\nred\n\
+             ::t0k3n::\n::endgroup::\n"
+        );
+        assert_ne!(random_token(), random_token());
+    }
+
+    #[test]
+    fn test_github_allowed_failure_commands() {
+        assert_eq!(
+            allowed_failure_commands(
+                "toml/check",
+                beautytips::ActionResult::Error {
+                    message: "taplo: not found".to_string(),
+                    hint: None,
+                },
+                "t0k3n"
+            ),
+            "::group::⚠️ toml/check (allowed to fail)\n::stop-commands::t0k3n\n\
+             taplo: not found\n::t0k3n::\n::endgroup::\n\
+             ::notice title=toml/check (allowed to fail)::taplo: not found\n"
+        );
+        assert_eq!(
+            allowed_failure_commands("toml/check", beautytips::ActionResult::Skipped, "t0k3n"),
+            result_commands("toml/check", beautytips::ActionResult::Skipped, "t0k3n")
+        );
+    }
+}
@@ -0,0 +1,342 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+/// Escape `input` so that it can be used in XML text and attribute values
+///
+/// Characters that are not allowed in XML documents at all (most control
+/// characters) are replaced.
+fn escape(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            '\t' | '\n' | '\r' => result.push(c),
+            c if c < ' ' => result.push('\u{fffd}'),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn output_to_string(stdout: &[u8], stderr: &[u8]) -> String {
+    let stdout = String::from_utf8_lossy(stdout);
+    let stderr = String::from_utf8_lossy(stderr);
+    let stdout = stdout.trim();
+    let stderr = stderr.trim();
+
+    match (stdout.is_empty(), stderr.is_empty()) {
+        (true, true) => String::new(),
+        (false, true) => stdout.to_string(),
+        (true, false) => stderr.to_string(),
+        (false, false) => format!("{stdout}\n{stderr}"),
+    }
+}
+
+/// The text describing a failed `result`
+fn failure_text(result: &beautytips::ActionResult) -> String {
+    match result {
+        beautytips::ActionResult::Warn {
+            stdout,
+            stderr,
+            hint,
+        } => {
+            let output = output_to_string(&stdout.bytes(), &stderr.bytes());
+            match hint {
+                Some(hint) => format!("{output}\nHint: {hint}").trim_start().to_string(),
+                None => output,
+            }
+        }
+        beautytips::ActionResult::Error { message, hint } => match hint {
+            Some(hint) => format!("{message}\nHint: {hint}"),
+            None => message.clone(),
+        },
+        _ => String::new(),
+    }
+}
+
+struct TestCase {
+    action: beautytips::ActionHandle,
+    duration: Duration,
+    result: beautytips::ActionResult,
+    /// The action failed, but is allowed to, so it passes
+    allowed_failure: bool,
+}
+
+/// Report results as `JUnit` XML on stdout, once all actions are done
+pub struct JUnitReporter {
+    run_start: Instant,
+    started: HashMap<String, Instant>,
+    test_cases: Vec<TestCase>,
+}
+
+impl Default for JUnitReporter {
+    fn default() -> Self {
+        Self {
+            run_start: Instant::now(),
+            started: HashMap::new(),
+            test_cases: Vec::new(),
+        }
+    }
+}
+
+impl JUnitReporter {
+    fn to_xml(&self, total_duration: Duration) -> String {
+        let count = |f: fn(&beautytips::ActionResult) -> bool| {
+            self.test_cases
+                .iter()
+                .filter(|tc| !tc.allowed_failure && f(&tc.result))
+                .count()
+        };
+        let tests = self.test_cases.len();
+        let failures = count(|r| matches!(r, beautytips::ActionResult::Warn { .. }));
+        let errors = count(|r| matches!(r, beautytips::ActionResult::Error { .. }));
+        let skipped = count(|r| {
+            matches!(
+                r,
+                beautytips::ActionResult::Skipped | beautytips::ActionResult::NotApplicable { .. }
+            )
+        });
+        let time = total_duration.as_secs_f64();
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<testsuites name=\"beautytips\" tests=\"{tests}\" failures=\"{failures}\" errors=\"{errors}\" skipped=\"{skipped}\" time=\"{time:.3}\">"
+        );
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"beautytips\" tests=\"{tests}\" failures=\"{failures}\" errors=\"{errors}\" skipped=\"{skipped}\" time=\"{time:.3}\">"
+        );
+
+        for tc in &self.test_cases {
+            let class_name = if tc.action.source.is_empty() {
+                &tc.action.id
+            } else {
+                &tc.action.source
+            };
+            let _ = write!(
+                xml,
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+                escape(&tc.action.id),
+                escape(class_name),
+                tc.duration.as_secs_f64()
+            );
+
+            if tc.allowed_failure {
+                let _ = write!(
+                    xml,
+                    ">\n      <system-out>{}</system-out>\n    </testcase>\n",
+                    escape(
+                        format!("Failed, but allowed to fail\n{}", failure_text(&tc.result)).trim()
+                    )
+                );
+                continue;
+            }
+
+            match &tc.result {
+                beautytips::ActionResult::Ok { stdout, stderr } => {
+                    let output = output_to_string(&stdout.bytes(), &stderr.bytes());
+                    if output.is_empty() {
+                        xml.push_str("/>\n");
+                    } else {
+                        let _ = write!(
+                            xml,
+                            ">\n      <system-out>{}</system-out>\n    </testcase>\n",
+                            escape(&output)
+                        );
+                    }
+                }
+                beautytips::ActionResult::Skipped => {
+                    xml.push_str(">\n      <skipped/>\n    </testcase>\n");
+                }
+                beautytips::ActionResult::NotApplicable { reason } => {
+                    let _ = write!(
+                        xml,
+                        ">\n      <skipped message=\"not applicable: {}\"/>\n    </testcase>\n",
+                        escape(&reason.to_string())
+                    );
+                }
+                beautytips::ActionResult::Warn {
+                    stdout,
+                    stderr,
+                    hint,
+                } => {
+                    let _ = write!(
+                        xml,
+                        ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                        escape(hint.as_deref().unwrap_or("Action failed")),
+                        escape(&output_to_string(&stdout.bytes(), &stderr.bytes()))
+                    );
+                }
+                beautytips::ActionResult::Error { message, hint } => {
+                    let close = hint.as_ref().map_or_else(
+                        || "/>".to_string(),
+                        |hint| format!(">Hint: {}</error>", escape(hint)),
+                    );
+                    let _ = write!(
+                        xml,
+                        ">\n      <error message=\"{}\"{close}\n    </testcase>\n",
+                        escape(message)
+                    );
+                }
+            }
+        }
+
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+}
+
+impl JUnitReporter {
+    fn add_test_case(
+        &mut self,
+        action: beautytips::ActionHandle,
+        result: beautytips::ActionResult,
+        allowed_failure: bool,
+    ) {
+        let duration = self
+            .started
+            .remove(&action.id)
+            .map(|s| s.elapsed())
+            .unwrap_or_default();
+        self.test_cases.push(TestCase {
+            action,
+            duration,
+            result,
+            allowed_failure,
+        });
+    }
+}
+
+impl beautytips::Reporter for JUnitReporter {
+    fn report_start(&mut self, action: beautytips::ActionHandle) {
+        self.started.insert(action.id, Instant::now());
+    }
+
+    fn report_done(&mut self, action: beautytips::ActionHandle, result: beautytips::ActionResult) {
+        self.add_test_case(action, result, false);
+    }
+
+    fn report_allowed_failure(
+        &mut self,
+        action: beautytips::ActionHandle,
+        result: beautytips::ActionResult,
+    ) {
+        self.add_test_case(action, result, true);
+    }
+
+    fn finish(&mut self) {
+        print!("{}", self.to_xml(self.run_start.elapsed()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("a < b && c > \"d\" 'e'"),
+            "a &lt; b &amp;&amp; c &gt; &quot;d&quot; &apos;e&apos;"
+        );
+        assert_eq!(escape("line\n\ttab\u{1b}[0m"), "line\n\ttab\u{fffd}[0m");
+    }
+
+    #[test]
+    fn test_junit_xml() {
+        let reporter = JUnitReporter {
+            test_cases: vec![
+                TestCase {
+                    action: beautytips::ActionHandle::from_id("rust/check_fmt"),
+                    duration: Duration::from_millis(1500),
+                    result: beautytips::ActionResult::Warn {
+                        stdout: b"Diff in <main.rs>\n".to_vec().into(),
+                        stderr: beautytips::ActionOutput::default(),
+                        hint: None,
+                    },
+                    allowed_failure: false,
+                },
+                TestCase {
+                    action: beautytips::ActionHandle::from_id("toml/check"),
+                    duration: Duration::ZERO,
+                    result: beautytips::ActionResult::NotApplicable {
+                        reason: beautytips::NotApplicableReason::NoInputFiles {
+                            input: "files".to_string(),
+                            candidates: 3,
+                            filters: vec!["**/*.toml".to_string()],
+                        },
+                    },
+                    allowed_failure: false,
+                },
+                TestCase {
+                    action: beautytips::ActionHandle::from_id("builtin/check_bom"),
+                    duration: Duration::from_millis(10),
+                    result: beautytips::ActionResult::Ok {
+                        stdout: beautytips::ActionOutput::default(),
+                        stderr: beautytips::ActionOutput::default(),
+                    },
+                    allowed_failure: false,
+                },
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            reporter.to_xml(Duration::from_secs(2)),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="beautytips" tests="3" failures="1" errors="0" skipped="1" time="2.000">
+  <testsuite name="beautytips" tests="3" failures="1" errors="0" skipped="1" time="2.000">
+    <testcase name="rust/check_fmt" classname="rust" time="1.500">
+      <failure message="Action failed">Diff in &lt;main.rs&gt;</failure>
+    </testcase>
+    <testcase name="toml/check" classname="toml" time="0.000">
+      <skipped message="not applicable: all 3 files for input &apos;files&apos; filtered out by **/*.toml"/>
+    </testcase>
+    <testcase name="builtin/check_bom" classname="builtin" time="0.010"/>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+
+    #[test]
+    fn test_junit_allowed_failure() {
+        let reporter = JUnitReporter {
+            test_cases: vec![TestCase {
+                action: beautytips::ActionHandle::from_id("toml/check"),
+                duration: Duration::ZERO,
+                result: beautytips::ActionResult::Error {
+                    message: "taplo: not found".to_string(),
+                    hint: None,
+                },
+                allowed_failure: true,
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            reporter.to_xml(Duration::from_secs(1)),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="beautytips" tests="1" failures="0" errors="0" skipped="0" time="1.000">
+  <testsuite name="beautytips" tests="1" failures="0" errors="0" skipped="0" time="1.000">
+    <testcase name="toml/check" classname="toml" time="0.000">
+      <system-out>Failed, but allowed to fail
+taplo: not found</system-out>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}
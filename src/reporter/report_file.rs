@@ -0,0 +1,232 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use serde_json::json;
+
+fn timestamp() -> f64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+fn result_to_json(result: &beautytips::ActionResult) -> serde_json::Value {
+    match result {
+        beautytips::ActionResult::Ok { stdout, stderr } => json!({
+            "kind": "ok",
+            "stdout": String::from_utf8_lossy(&stdout.bytes()),
+            "stderr": String::from_utf8_lossy(&stderr.bytes()),
+        }),
+        beautytips::ActionResult::Skipped => json!({ "kind": "skipped" }),
+        beautytips::ActionResult::NotApplicable { reason } => json!({
+            "kind": "not_applicable",
+            "reason": reason.to_string(),
+        }),
+        beautytips::ActionResult::Warn {
+            stdout,
+            stderr,
+            hint,
+        } => json!({
+            "kind": "warn",
+            "stdout": String::from_utf8_lossy(&stdout.bytes()),
+            "stderr": String::from_utf8_lossy(&stderr.bytes()),
+            "hint": hint,
+        }),
+        beautytips::ActionResult::Error { message, hint } => json!({
+            "kind": "error",
+            "message": message,
+            "hint": hint,
+        }),
+    }
+}
+
+/// Append every report to a JSON Lines file as it happens
+///
+/// Each line is written out immediately, so the file holds the results so
+/// far even when the run does not finish.
+pub struct ReportFileReporter {
+    path: PathBuf,
+    file: std::fs::File,
+}
+
+impl ReportFileReporter {
+    pub fn new(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .context(format!("Failed to open report file {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    fn write(&mut self, event: &str, mut data: serde_json::Value) {
+        data["timestamp"] = json!(timestamp());
+        data["event"] = json!(event);
+
+        let mut line = data.to_string();
+        line.push('\n');
+        if let Err(e) = self.file.write_all(line.as_bytes()) {
+            tracing::warn!("Failed to write to report file {:?}: {e}", self.path);
+        }
+    }
+}
+
+impl beautytips::Reporter for ReportFileReporter {
+    fn report_files_collected(&mut self, root_directory: &Path, files: &[PathBuf]) {
+        self.write(
+            "files_collected",
+            json!({ "root_directory": root_directory, "files": files }),
+        );
+    }
+
+    fn report_start(&mut self, action: beautytips::ActionHandle) {
+        self.write(
+            "started",
+            json!({
+                "action_id": action.id,
+                "source": action.source,
+                "description": action.description,
+                "tags": action.tags,
+            }),
+        );
+    }
+
+    fn report_action_output_chunk(&mut self, action_id: &str, stdout: &[u8], stderr: &[u8]) {
+        self.write(
+            "output",
+            json!({
+                "action_id": action_id,
+                "stdout": String::from_utf8_lossy(stdout),
+                "stderr": String::from_utf8_lossy(stderr),
+            }),
+        );
+    }
+
+    fn wants_output_chunks(&self) -> bool {
+        true
+    }
+
+    fn report_action_detail(&mut self, action_id: &str, message: &str) {
+        self.write(
+            "detail",
+            json!({ "action_id": action_id, "message": message }),
+        );
+    }
+
+    fn report_done(&mut self, action: beautytips::ActionHandle, result: beautytips::ActionResult) {
+        self.write(
+            "done",
+            json!({ "action_id": action.id, "result": result_to_json(&result) }),
+        );
+    }
+
+    fn report_allowed_failure(
+        &mut self,
+        action: beautytips::ActionHandle,
+        result: beautytips::ActionResult,
+    ) {
+        self.write(
+            "done",
+            json!({
+                "action_id": action.id,
+                "result": result_to_json(&result),
+                "allowed_failure": true,
+            }),
+        );
+    }
+
+    fn report_summary(&mut self, summary: &beautytips::RunSummary) {
+        self.write(
+            "summary",
+            json!({
+                "ok": summary.ok,
+                "skipped": summary.skipped,
+                "not_applicable": summary.not_applicable,
+                "warn": summary.warn,
+                "error": summary.error,
+                "allowed_failures": summary.allowed_failures,
+                "duration": summary.duration.as_secs_f64(),
+            }),
+        );
+    }
+
+    fn finish(&mut self) {
+        self.write("finished", json!({}));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use beautytips::Reporter as _;
+
+    #[test]
+    fn test_report_file() {
+        let path =
+            std::env::temp_dir().join(format!("beautytips-report-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut reporter = ReportFileReporter::new(&path).unwrap();
+        reporter.report_start(beautytips::ActionHandle::from_id("a/x"));
+        reporter.report_done(
+            beautytips::ActionHandle::from_id("a/x"),
+            beautytips::ActionResult::Warn {
+                stdout: b"out".to_vec().into(),
+                stderr: beautytips::ActionOutput::default(),
+                hint: None,
+            },
+        );
+        reporter.report_done(
+            beautytips::ActionHandle::from_id("b/y"),
+            beautytips::ActionResult::Skipped,
+        );
+        reporter.report_allowed_failure(
+            beautytips::ActionHandle::from_id("c/z"),
+            beautytips::ActionResult::Error {
+                message: "z: not found".to_string(),
+                hint: None,
+            },
+        );
+
+        // Lines are there before `finish`
+        let lines = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["event"], "started");
+        assert_eq!(lines[0]["action_id"], "a/x");
+        assert_eq!(lines[0]["source"], "a");
+        assert!(lines[0]["timestamp"].as_f64().unwrap() > 0.0);
+        assert_eq!(lines[1]["event"], "done");
+        assert_eq!(lines[1]["result"]["kind"], "warn");
+        assert_eq!(lines[1]["result"]["stdout"], "out");
+        assert_eq!(lines[2]["result"]["kind"], "skipped");
+        assert!(lines[2]["allowed_failure"].is_null());
+        assert_eq!(lines[3]["event"], "done");
+        assert_eq!(lines[3]["result"]["kind"], "error");
+        assert_eq!(lines[3]["allowed_failure"], true);
+
+        reporter.finish();
+        drop(reporter);
+
+        // New runs append
+        let mut reporter = ReportFileReporter::new(&path).unwrap();
+        reporter.finish();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 6);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,291 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{collections::HashMap, fmt::Write, time::Instant};
+
+const SUITE_NAME: &str = "beautytips";
+
+/// Escape `input` for use in a `TeamCity` service message value
+fn escape(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '|' => result.push_str("||"),
+            '\'' => result.push_str("|'"),
+            '\n' => result.push_str("|n"),
+            '\r' => result.push_str("|r"),
+            '[' => result.push_str("|["),
+            ']' => result.push_str("|]"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn message(name: &str, attributes: &[(&str, &str)]) -> String {
+    let mut result = format!("##teamcity[{name}");
+    for (k, v) in attributes {
+        let _ = write!(result, " {k}='{}'", escape(v));
+    }
+    result.push(']');
+    result
+}
+
+/// A message about the action `action_id`
+///
+/// Actions run in parallel, so each one gets a flow of its own to keep
+/// their messages apart.
+fn action_message(name: &str, action_id: &str, attributes: &[(&str, &str)]) -> String {
+    let mut all = vec![("name", action_id), ("flowId", action_id)];
+    all.extend_from_slice(attributes);
+    message(name, &all)
+}
+
+/// Report results as `TeamCity` service messages on stdout
+#[derive(Default)]
+pub struct TeamCityReporter {
+    suite_started: bool,
+    started: HashMap<String, Instant>,
+}
+
+impl TeamCityReporter {
+    fn print(&mut self, line: &str) {
+        if !self.suite_started {
+            println!("{}", message("testSuiteStarted", &[("name", SUITE_NAME)]));
+            self.suite_started = true;
+        }
+        println!("{line}");
+    }
+
+    fn done_messages(action_id: &str, result: &beautytips::ActionResult) -> Vec<String> {
+        match result {
+            beautytips::ActionResult::Ok { stdout, .. } => {
+                let stdout = stdout.bytes();
+                let stdout = String::from_utf8_lossy(&stdout);
+                if stdout.trim().is_empty() {
+                    vec![]
+                } else {
+                    vec![action_message(
+                        "testStdOut",
+                        action_id,
+                        &[("out", stdout.trim())],
+                    )]
+                }
+            }
+            beautytips::ActionResult::Skipped => {
+                vec![action_message(
+                    "testIgnored",
+                    action_id,
+                    &[("message", "skipped")],
+                )]
+            }
+            beautytips::ActionResult::NotApplicable { reason } => {
+                vec![action_message(
+                    "testIgnored",
+                    action_id,
+                    &[("message", &format!("not applicable: {reason}"))],
+                )]
+            }
+            beautytips::ActionResult::Warn {
+                stdout,
+                stderr,
+                hint,
+            } => {
+                let details = format!(
+                    "{}\n{}",
+                    String::from_utf8_lossy(&stdout.bytes()).trim(),
+                    String::from_utf8_lossy(&stderr.bytes()).trim()
+                );
+                vec![action_message(
+                    "testFailed",
+                    action_id,
+                    &[
+                        ("message", hint.as_deref().unwrap_or("Action failed")),
+                        ("details", details.trim()),
+                    ],
+                )]
+            }
+            beautytips::ActionResult::Error { message: m, hint } => {
+                let mut attributes = vec![("message", m.as_str())];
+                if let Some(hint) = hint {
+                    attributes.push(("details", hint.as_str()));
+                }
+                vec![action_message("testFailed", action_id, &attributes)]
+            }
+        }
+    }
+
+    /// Allowed failures pass, with their output and a warning
+    fn allowed_failure_messages(action_id: &str, result: &beautytips::ActionResult) -> Vec<String> {
+        let details = match result {
+            beautytips::ActionResult::Warn {
+                stdout,
+                stderr,
+                hint,
+            } => format!(
+                "{}\n{}\n{}",
+                String::from_utf8_lossy(&stdout.bytes()).trim(),
+                String::from_utf8_lossy(&stderr.bytes()).trim(),
+                hint.as_deref().unwrap_or_default()
+            ),
+            beautytips::ActionResult::Error { message, hint } => match hint {
+                Some(hint) => format!("{message}\n{hint}"),
+                None => message.clone(),
+            },
+            _ => return Self::done_messages(action_id, result),
+        };
+
+        let mut messages = vec![];
+        if !details.trim().is_empty() {
+            messages.push(action_message(
+                "testStdOut",
+                action_id,
+                &[("out", details.trim())],
+            ));
+        }
+        messages.push(message(
+            "message",
+            &[
+                (
+                    "text",
+                    &format!("{action_id} failed, but is allowed to fail"),
+                ),
+                ("flowId", action_id),
+                ("status", "WARNING"),
+            ],
+        ));
+        messages
+    }
+
+    fn report(&mut self, action_id: &str, messages: Vec<String>) {
+        let duration = if let Some(start) = self.started.remove(action_id) {
+            start.elapsed()
+        } else {
+            // Skipped actions are reported without being started
+            self.print(&action_message("testStarted", action_id, &[]));
+            std::time::Duration::ZERO
+        };
+
+        for m in messages {
+            self.print(&m);
+        }
+        self.print(&action_message(
+            "testFinished",
+            action_id,
+            &[("duration", &duration.as_millis().to_string())],
+        ));
+    }
+}
+
+impl beautytips::Reporter for TeamCityReporter {
+    fn report_start(&mut self, action: beautytips::ActionHandle) {
+        self.print(&action_message("testStarted", &action.id, &[]));
+        self.started.insert(action.id, Instant::now());
+    }
+
+    fn report_done(&mut self, action: beautytips::ActionHandle, result: beautytips::ActionResult) {
+        let messages = Self::done_messages(&action.id, &result);
+        self.report(&action.id, messages);
+    }
+
+    fn report_allowed_failure(
+        &mut self,
+        action: beautytips::ActionHandle,
+        result: beautytips::ActionResult,
+    ) {
+        let messages = Self::allowed_failure_messages(&action.id, &result);
+        self.report(&action.id, messages);
+    }
+
+    fn finish(&mut self) {
+        if self.suite_started {
+            println!("{}", message("testSuiteFinished", &[("name", SUITE_NAME)]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_teamcity_messages() {
+        assert_eq!(
+            action_message("testStarted", "rust/check_fmt", &[]),
+            "##teamcity[testStarted name='rust/check_fmt' flowId='rust/check_fmt']"
+        );
+        assert_eq!(
+            TeamCityReporter::done_messages(
+                "rust/check_fmt",
+                &beautytips::ActionResult::Warn {
+                    stdout: b"Diff in 'main.rs' [1|2]\n".to_vec().into(),
+                    stderr: beautytips::ActionOutput::default(),
+                    hint: None,
+                }
+            ),
+            ["##teamcity[testFailed name='rust/check_fmt' flowId='rust/check_fmt' message='Action failed' details='Diff in |'main.rs|' |[1||2|]']"]
+        );
+        assert_eq!(
+            TeamCityReporter::done_messages(
+                "rust/check_fmt",
+                &beautytips::ActionResult::Warn {
+                    stdout: beautytips::ActionOutput::default(),
+                    stderr: b"Diff\n".to_vec().into(),
+                    hint: Some("run 'cargo fmt'".to_string()),
+                }
+            ),
+            ["##teamcity[testFailed name='rust/check_fmt' flowId='rust/check_fmt' message='run |'cargo fmt|'' details='Diff']"]
+        );
+        assert_eq!(
+            TeamCityReporter::done_messages(
+                "toml/check",
+                &beautytips::ActionResult::Error {
+                    message: "line 1\nline 2".to_string(),
+                    hint: None,
+                }
+            ),
+            ["##teamcity[testFailed name='toml/check' flowId='toml/check' message='line 1|nline 2']"]
+        );
+        assert_eq!(
+            TeamCityReporter::done_messages(
+                "toml/check",
+                &beautytips::ActionResult::Error {
+                    message: "taplo: not found".to_string(),
+                    hint: Some("install taplo".to_string()),
+                }
+            ),
+            ["##teamcity[testFailed name='toml/check' flowId='toml/check' message='taplo: not found' details='install taplo']"]
+        );
+    }
+
+    #[test]
+    fn test_teamcity_allowed_failure() {
+        assert_eq!(
+            TeamCityReporter::allowed_failure_messages(
+                "rust/check_fmt",
+                &beautytips::ActionResult::Warn {
+                    stdout: b"Diff\n".to_vec().into(),
+                    stderr: beautytips::ActionOutput::default(),
+                    hint: None,
+                }
+            ),
+            [
+                "##teamcity[testStdOut name='rust/check_fmt' flowId='rust/check_fmt' out='Diff']",
+                "##teamcity[message text='rust/check_fmt failed, but is allowed to fail' flowId='rust/check_fmt' status='WARNING']"
+            ]
+        );
+        assert_eq!(
+            TeamCityReporter::allowed_failure_messages(
+                "toml/check",
+                &beautytips::ActionResult::Error {
+                    message: "taplo: not found".to_string(),
+                    hint: None,
+                }
+            ),
+            [
+                "##teamcity[testStdOut name='toml/check' flowId='toml/check' out='taplo: not found']",
+                "##teamcity[message text='toml/check failed, but is allowed to fail' flowId='toml/check' status='WARNING']"
+            ]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::collections::BTreeMap;
+
+use crossterm::style;
+
+/// The kinds of lines in the terminal output
+#[derive(
+    Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    Ok,
+    Skipped,
+    NotApplicable,
+    Warn,
+    Error,
+    AllowedFailure,
+    /// The output of actions and details on them
+    Output,
+}
+
+/// The builtin themes
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    /// Green for passing, yellow for warnings and red for errors
+    #[default]
+    Default,
+    /// Colors telling results apart with the common color vision
+    /// deficiencies: Blue for passing, orange for warnings and vermillion
+    /// for errors
+    Colorblind,
+    /// No colors, with ASCII prefixes for each result
+    Monochrome,
+}
+
+/// How one kind of line looks
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Look {
+    /// The color, `None` keeps the color of the terminal
+    pub color: Option<style::Color>,
+    /// Put in front of the action id
+    pub prefix: &'static str,
+}
+
+const fn look(color: style::Color, prefix: &'static str) -> Look {
+    Look {
+        color: Some(color),
+        prefix,
+    }
+}
+
+const fn uncolored(prefix: &'static str) -> Look {
+    Look {
+        color: None,
+        prefix,
+    }
+}
+
+const fn rgb(r: u8, g: u8, b: u8) -> style::Color {
+    style::Color::Rgb { r, g, b }
+}
+
+/// The colors and prefixes of the terminal output
+#[derive(Clone, Debug)]
+pub struct Theme {
+    looks: BTreeMap<Kind, Look>,
+}
+
+impl Theme {
+    pub fn new(name: ThemeName) -> Self {
+        let looks: [(Kind, Look); 7] = match name {
+            ThemeName::Default => [
+                (Kind::Ok, look(style::Color::Green, "✅")),
+                (Kind::Skipped, look(style::Color::Blue, "🦥")),
+                (Kind::NotApplicable, look(style::Color::Blue, "🚙")),
+                (Kind::Warn, look(style::Color::Yellow, "💡")),
+                (Kind::Error, look(style::Color::Red, "🚨")),
+                (Kind::AllowedFailure, look(style::Color::DarkYellow, "⚠️")),
+                (Kind::Output, look(style::Color::DarkGrey, "")),
+            ],
+            // The Okabe-Ito palette
+            ThemeName::Colorblind => [
+                (Kind::Ok, look(rgb(0, 114, 178), "✅")),
+                (Kind::Skipped, look(rgb(86, 180, 233), "🦥")),
+                (Kind::NotApplicable, look(rgb(86, 180, 233), "🚙")),
+                (Kind::Warn, look(rgb(230, 159, 0), "💡")),
+                (Kind::Error, look(rgb(213, 94, 0), "🚨")),
+                (Kind::AllowedFailure, look(rgb(204, 121, 167), "⚠️")),
+                (Kind::Output, look(style::Color::DarkGrey, "")),
+            ],
+            ThemeName::Monochrome => [
+                (Kind::Ok, uncolored("[+]")),
+                (Kind::Skipped, uncolored("[-]")),
+                (Kind::NotApplicable, uncolored("[.]")),
+                (Kind::Warn, uncolored("[!]")),
+                (Kind::Error, uncolored("[X]")),
+                (Kind::AllowedFailure, uncolored("[?]")),
+                (Kind::Output, uncolored("")),
+            ],
+        };
+        Self {
+            looks: BTreeMap::from(looks),
+        }
+    }
+
+    /// Override the colors of the theme for some kinds of lines
+    pub fn with_colors(mut self, colors: &BTreeMap<Kind, style::Color>) -> Self {
+        for (kind, color) in colors {
+            if let Some(look) = self.looks.get_mut(kind) {
+                look.color = Some(*color);
+            }
+        }
+        self
+    }
+
+    pub fn look(&self, kind: Kind) -> Look {
+        self.looks[&kind]
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::new(ThemeName::default())
+    }
+}
+
+/// Parse a color name like `blue` or `dark_grey`, or a hex color like
+/// `#d55e00`
+pub fn parse_color(text: &str) -> anyhow::Result<style::Color> {
+    if let Some(hex) = text.strip_prefix('#') {
+        let component = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+        };
+        if let (6, Some(r), Some(g), Some(b)) =
+            (hex.len(), component(0), component(2), component(4))
+        {
+            return Ok(rgb(r, g, b));
+        }
+    } else if let Ok(color) = style::Color::try_from(text) {
+        return Ok(color);
+    }
+    Err(anyhow::anyhow!(format!(
+        "'{text}' is no color, use a name like `blue` or `dark_grey`, or a hex color like `#d55e00`"
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("blue").unwrap(), style::Color::Blue);
+        assert_eq!(parse_color("Dark_Grey").unwrap(), style::Color::DarkGrey);
+        assert_eq!(parse_color("#d55e00").unwrap(), rgb(213, 94, 0));
+        assert!(parse_color("#d55e0").is_err());
+        assert!(parse_color("#d55e0g").is_err());
+        assert!(parse_color("purple").is_err());
+    }
+
+    #[test]
+    fn test_theme() {
+        let theme = Theme::new(ThemeName::Monochrome);
+        assert_eq!(theme.look(Kind::Error).color, None);
+        assert_ne!(
+            theme.look(Kind::Error).prefix,
+            theme.look(Kind::Warn).prefix
+        );
+
+        let theme = theme.with_colors(&BTreeMap::from([(Kind::Error, style::Color::Red)]));
+        assert_eq!(theme.look(Kind::Error).color, Some(style::Color::Red));
+        assert_eq!(theme.look(Kind::Warn).color, None);
+    }
+}
@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    fs::Metadata,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// Give the file at `path` the owner and group in `metadata`
+///
+/// Returns `false` if we are not allowed to do that.
+#[cfg(unix)]
+fn keep_owner(path: &Path, metadata: &Metadata) -> crate::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let current = path
+        .metadata()
+        .context(format!("Failed to look up {}", path.display()))?;
+    if current.uid() == metadata.uid() && current.gid() == metadata.gid() {
+        return Ok(true);
+    }
+    match std::os::unix::fs::chown(path, Some(metadata.uid()), Some(metadata.gid())) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Ok(false),
+        Err(e) => Err(e).context(format!("Failed to change owner of {}", path.display())),
+    }
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_path: &Path, _metadata: &Metadata) -> crate::Result<bool> {
+    // There is no owner to keep
+    Ok(true)
+}
+
+fn keep_times(file: &std::fs::File, path: &Path, metadata: &Metadata) -> crate::Result<()> {
+    let mut times = std::fs::FileTimes::new();
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    file.set_times(times)
+        .context(format!("Failed to set times of {}", path.display()))
+}
+
+fn write_contents(
+    mut file: std::fs::File,
+    path: &Path,
+    contents: &[u8],
+    metadata: &Metadata,
+) -> crate::Result<()> {
+    file.write_all(contents)
+        .context(format!("Failed to write {}", path.display()))?;
+    file.sync_all()
+        .context(format!("Failed to write {}", path.display()))?;
+    keep_times(&file, path, metadata)
+}
+
+/// Overwrite the file at `path`, used when a new file can not get the owner
+/// of the original
+fn overwrite(path: &Path, contents: &[u8], metadata: &Metadata) -> crate::Result<()> {
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .context(format!("Failed to open {}", path.display()))?;
+    write_contents(file, path, contents, metadata)
+}
+
+/// Replace the file at `path` by the `temporary` one
+///
+/// Returns `false` if the original file needs to be overwritten instead.
+fn replace(
+    path: &Path,
+    temporary: &Path,
+    contents: &[u8],
+    metadata: &Metadata,
+) -> crate::Result<bool> {
+    let file = std::fs::File::create(temporary)
+        .context(format!("Failed to create {}", temporary.display()))?;
+    if !keep_owner(temporary, metadata)? {
+        return Ok(false);
+    }
+    std::fs::set_permissions(temporary, metadata.permissions()).context(format!(
+        "Failed to set permissions of {}",
+        temporary.display()
+    ))?;
+    write_contents(file, temporary, contents, metadata)?;
+    std::fs::rename(temporary, path).context(format!("Failed to replace {}", path.display()))?;
+    Ok(true)
+}
+
+/// Replace the contents of the file at `path` by `contents`
+///
+/// The new contents are written into a temporary file next to `path` first,
+/// which then replaces the original. So the file is never left half-written.
+/// Permissions, owner and access and modification times of the original are
+/// kept, symbolic links are followed. If the owner can not be kept, the
+/// original file is overwritten instead.
+///
+/// # Errors
+///
+/// Reports an error if the file could not be read or written.
+pub fn safe_rewrite(path: &Path, contents: &[u8]) -> crate::Result<()> {
+    let path = std::fs::canonicalize(path).context(format!("Failed to find {}", path.display()))?;
+    let metadata = path
+        .metadata()
+        .context(format!("Failed to look up {}", path.display()))?;
+
+    let mut temporary = path.as_os_str().to_os_string();
+    temporary.push(format!(".beautytips-{}", std::process::id()));
+    let temporary = PathBuf::from(temporary);
+
+    let result = replace(&path, &temporary, contents, &metadata);
+    if !matches!(result, Ok(true)) {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    if result? {
+        Ok(())
+    } else {
+        tracing::debug!("Can not keep owner of {path:?}, overwriting it");
+        overwrite(&path, contents, &metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_rewrite() {
+        let directory =
+            std::env::temp_dir().join(format!("beautytips-rewrite-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("file.txt");
+        std::fs::write(&path, "before\n").unwrap();
+
+        let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1 << 30);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o750)).unwrap();
+        }
+
+        safe_rewrite(&path, b"after\n").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "after\n");
+        let metadata = path.metadata().unwrap();
+        assert_eq!(metadata.modified().unwrap(), modified);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o750);
+        }
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+
+        #[cfg(unix)]
+        {
+            let link = directory.join("link.txt");
+            std::os::unix::fs::symlink(&path, &link).unwrap();
+            safe_rewrite(&link, b"linked\n").unwrap();
+            assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "linked\n");
+        }
+
+        assert!(safe_rewrite(&directory.join("missing.txt"), b"").is_err());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
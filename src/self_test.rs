@@ -0,0 +1,282 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{cell::RefCell, collections::BTreeMap, path::Path, rc::Rc};
+
+use anyhow::Context;
+
+use crate::config;
+
+/// The rule packs with self tests: The name, the test cases and the golden
+/// file with the expected results
+const PACKS: [(&str, &str, &str); 1] = [(
+    "builtin",
+    include_str!("self_test/builtin.toml"),
+    include_str!("self_test/builtin.golden"),
+)];
+
+/// Sample files and the actions to run on them
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Case {
+    name: String,
+    /// Selectors of the actions to run
+    actions: Vec<String>,
+    /// The files to put into the repository, by path
+    #[serde(default)]
+    files: BTreeMap<String, String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Cases {
+    cases: Vec<Case>,
+}
+
+fn parse_cases(pack: &str, cases: &str) -> anyhow::Result<Vec<Case>> {
+    Ok(toml::from_str::<Cases>(cases)
+        .context(format!("Failed to parse the test cases of {pack}"))?
+        .cases)
+}
+
+/// The results of a case, one line per action like `case: action: result`
+///
+/// Empty lines and lines starting with `#` are ignored.
+fn result_lines(text: &str) -> BTreeMap<String, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| {
+            let mut parts = l.splitn(3, ": ");
+            let case = parts.next().unwrap_or_default();
+            let action = parts.next().unwrap_or_default();
+            (
+                format!("{case}: {action}"),
+                parts.next().unwrap_or_default().to_string(),
+            )
+        })
+        .collect()
+}
+
+/// The differences between the `expected` and the `actual` results
+fn compare(expected: &str, actual: &str) -> Vec<String> {
+    let expected = result_lines(expected);
+    let mut actual = result_lines(actual);
+
+    let mut result = Vec::new();
+    for (key, e) in expected {
+        match actual.remove(&key) {
+            Some(a) if a == e => {}
+            Some(a) => result.push(format!("{key}: expected '{e}', got '{a}'")),
+            None => result.push(format!("{key}: expected '{e}', but it did not run")),
+        }
+    }
+    for (key, a) in actual {
+        result.push(format!("{key}: got '{a}', which is not in the golden file"));
+    }
+    result
+}
+
+/// Collects the results of all actions as text
+#[derive(Default)]
+struct CollectingReporter {
+    results: Rc<RefCell<BTreeMap<String, String>>>,
+}
+
+impl beautytips::Reporter for CollectingReporter {
+    fn report_start(&mut self, _action: beautytips::ActionHandle) {}
+
+    fn report_done(&mut self, action: beautytips::ActionHandle, result: beautytips::ActionResult) {
+        let result = match result {
+            beautytips::ActionResult::Ok { .. } => "ok".to_string(),
+            beautytips::ActionResult::Skipped => "skipped".to_string(),
+            beautytips::ActionResult::NotApplicable { reason } => {
+                format!("not applicable, {reason}")
+            }
+            beautytips::ActionResult::Warn { .. } => "warn".to_string(),
+            beautytips::ActionResult::Error { .. } => "error".to_string(),
+        };
+        self.results.borrow_mut().insert(action.id, result);
+    }
+
+    fn finish(&mut self) {}
+}
+
+fn write_files(directory: &Path, files: &BTreeMap<String, String>) -> anyhow::Result<()> {
+    for (path, contents) in files {
+        let path = directory.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .context(format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, contents).context(format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Run the actions of `case` on its files in a temporary repository
+fn run_case(
+    config: &config::Configuration,
+    case: &Case,
+    directory: &Path,
+) -> anyhow::Result<String> {
+    let selectors = config::ActionSelectors::from(
+        case.actions
+            .iter()
+            .map(|s| config::ActionSelector::new(s))
+            .collect::<anyhow::Result<Vec<_>>>()?,
+    );
+    let actions = config.actions(&selectors);
+    if actions.is_empty() {
+        return Err(anyhow::anyhow!(format!(
+            "No action matches the selectors of '{}'",
+            case.name
+        )));
+    }
+
+    write_files(directory, &case.files)?;
+
+    let reporter = CollectingReporter::default();
+    let results = reporter.results.clone();
+    beautytips::run(
+        directory.to_path_buf(),
+        beautytips::InputFiles::AllFiles(directory.to_path_buf()),
+        actions,
+        Box::new(reporter),
+    )?;
+
+    let results = results.borrow();
+    Ok(results
+        .iter()
+        .map(|(id, result)| format!("{}: {id}: {result}\n", case.name))
+        .collect::<Vec<_>>()
+        .concat())
+}
+
+/// Run all cases of `pack`, returning the results as in a golden file
+fn run_pack(config: &config::Configuration, pack: &str, cases: &str) -> anyhow::Result<String> {
+    let mut result = String::new();
+    for case in &parse_cases(pack, cases)? {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("beautytips-self-test-");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(std::fs::Permissions::from_mode(0o700));
+        }
+        let directory = builder
+            .tempdir()
+            .context("Failed to create a temporary directory")?;
+
+        let case_result = run_case(config, case, directory.path());
+        drop(directory);
+
+        result.push_str(&case_result.context(format!("Case '{}' of {pack} failed", case.name))?);
+    }
+
+    Ok(result)
+}
+
+/// Run the actions of the builtin rule packs on sample files and compare
+/// the results to the golden files
+///
+/// With `print` set, the results are printed in the format of the golden
+/// files instead.
+pub fn self_test(pack: Option<&str>, print: bool) -> anyhow::Result<()> {
+    let config = config::builtin();
+
+    let mut failed = 0;
+    let mut found = false;
+    for (name, cases, golden) in PACKS {
+        if pack.is_some_and(|p| p != name) {
+            continue;
+        }
+        found = true;
+
+        let actual = run_pack(&config, name, cases)?;
+        if print {
+            print!("{actual}");
+            continue;
+        }
+
+        let differences = compare(golden, &actual);
+        if differences.is_empty() {
+            println!("{name}: ok");
+        } else {
+            failed += 1;
+            println!("{name}: failed");
+            for d in differences {
+                println!("  {d}");
+            }
+        }
+    }
+
+    if !found {
+        Err(anyhow::anyhow!(format!(
+            "No rule pack named '{}' has self tests",
+            pack.unwrap_or_default()
+        )))
+    } else if failed > 0 {
+        Err(anyhow::anyhow!(format!(
+            "The self test of {failed} rule pack(s) failed"
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let golden = r"
+# comments are ignored
+json: builtin/check_json: warn
+yaml: builtin/check_yaml: ok
+filters: toml/check: not applicable, no files for input 'files'
+";
+        assert!(compare(golden, golden).is_empty());
+
+        let actual = r"json: builtin/check_json: ok
+filters: toml/check: not applicable, no files for input 'files'
+filters: shell/check_lint: error
+";
+        assert_eq!(
+            compare(golden, actual),
+            [
+                "json: builtin/check_json: expected 'warn', got 'ok'",
+                "yaml: builtin/check_yaml: expected 'ok', but it did not run",
+                "filters: shell/check_lint: got 'error', which is not in the golden file",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_packs_are_consistent() {
+        let config = config::builtin();
+        for (name, cases, golden) in PACKS {
+            let cases = parse_cases(name, cases).unwrap();
+            for case in &cases {
+                for selector in &case.actions {
+                    let selectors = config::ActionSelectors::from(vec![
+                        config::ActionSelector::new(selector).unwrap(),
+                    ]);
+                    assert!(
+                        !config.actions(&selectors).is_empty(),
+                        "{selector} in '{}' of {name} matches no action",
+                        case.name
+                    );
+                }
+            }
+            for key in result_lines(golden).keys() {
+                let case = key.split(": ").next().unwrap_or_default();
+                assert!(
+                    cases.iter().any(|c| c.name == case),
+                    "{key} in the golden file of {name} refers to an unknown case"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,278 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::io::Read;
+
+use anyhow::Context;
+use sha2::Digest;
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/hunger/beautytips/releases/latest";
+
+/// The minisign public key the checksum files of releases are signed with
+///
+/// Release builds get it from `BEAUTYTIPS_RELEASE_PUBLIC_KEY` at build time.
+/// Builds without it can not verify releases, so they refuse to install any.
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("BEAUTYTIPS_RELEASE_PUBLIC_KEY");
+
+/// The largest download accepted, well above the size of any release
+const MAX_DOWNLOAD_SIZE: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, serde::Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset_url(&self, name: &str) -> anyhow::Result<&str> {
+        self.assets
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| a.browser_download_url.as_str())
+            .ok_or(anyhow::anyhow!(format!(
+                "Release {} has no {name}",
+                self.tag_name
+            )))
+    }
+}
+
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let version = version.split_once('-').map_or(version, |(v, _)| v);
+    let mut parts = version.split('.').map(|p| p.parse::<u64>().ok());
+    let result = (parts.next()??, parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(result)
+}
+
+fn is_newer(current: &str, latest: &str) -> bool {
+    match (parse_version(current), parse_version(latest)) {
+        (Some(current), Some(latest)) => latest > current,
+        _ => false,
+    }
+}
+
+/// The target triple of the release artifacts for this platform
+fn release_target(arch: &str, os: &str, musl: bool) -> Option<&'static str> {
+    match (arch, os) {
+        ("aarch64", "macos") => Some("aarch64-apple-darwin"),
+        ("x86_64", "macos") => Some("x86_64-apple-darwin"),
+        ("x86_64", "linux") if musl => Some("x86_64-unknown-linux-musl"),
+        ("x86_64", "linux") => Some("x86_64-unknown-linux-gnu"),
+        ("x86_64", "windows") => Some("x86_64-pc-windows-msvc"),
+        _ => None,
+    }
+}
+
+/// Check that `signature` is a minisign signature of `data` made with the
+/// key `public_key`
+fn verify_signature(public_key: &str, data: &[u8], signature: &str) -> anyhow::Result<()> {
+    let public_key = minisign_verify::PublicKey::from_base64(public_key)
+        .map_err(|e| anyhow::anyhow!(format!("Invalid public key: {e}")))?;
+    let signature = minisign_verify::Signature::decode(signature)
+        .map_err(|e| anyhow::anyhow!(format!("Invalid signature: {e}")))?;
+    public_key
+        .verify(data, &signature, false)
+        .map_err(|e| anyhow::anyhow!(format!("Signature check failed: {e}")))
+}
+
+fn verify_checksum(data: &[u8], checksum_file: &str) -> anyhow::Result<()> {
+    let expected = checksum_file
+        .split_whitespace()
+        .next()
+        .ok_or(anyhow::anyhow!("The checksum file is empty"))?;
+    let actual = format!("{:x}", sha2::Sha256::digest(data));
+
+    if expected.eq_ignore_ascii_case(&actual) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(format!(
+            "Checksum mismatch: Expected {expected}, got {actual}"
+        )))
+    }
+}
+
+fn download(agent: &ureq::Agent, url: &str) -> anyhow::Result<Vec<u8>> {
+    let response = agent
+        .get(url)
+        .call()
+        .context(format!("Failed to download {url}"))?;
+
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_DOWNLOAD_SIZE)
+        .read_to_end(&mut data)
+        .context(format!("Failed to download {url}"))?;
+    Ok(data)
+}
+
+/// Unpack the beautytips binary from the gzipped tar `archive` and replace
+/// the running executable with it
+fn install(archive: &[u8], archive_name: &str) -> anyhow::Result<()> {
+    let binary_name = format!("beautytips{}", std::env::consts::EXE_SUFFIX);
+    let directory = tempfile::Builder::new()
+        .prefix("beautytips-update-")
+        .tempdir()
+        .context("Failed to create a directory to unpack the update in")?;
+    let binary = directory.path().join(&binary_name);
+
+    let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(archive));
+    let mut found = false;
+    for entry in tar
+        .entries()
+        .context(format!("Failed to unpack {archive_name}"))?
+    {
+        let mut entry = entry.context(format!("Failed to unpack {archive_name}"))?;
+        let is_binary = entry.header().entry_type().is_file()
+            && entry
+                .path()
+                .is_ok_and(|p| p.file_name().is_some_and(|n| n == binary_name.as_str()));
+        if is_binary {
+            entry
+                .unpack(&binary)
+                .context(format!("Failed to unpack {archive_name}"))?;
+            found = true;
+            break;
+        }
+    }
+    if !found {
+        return Err(anyhow::anyhow!(format!(
+            "No beautytips binary found in {archive_name}"
+        )));
+    }
+
+    self_replace::self_replace(&binary).context("Failed to replace the current binary")
+}
+
+/// Update to the latest release (or just report it if `check_only` is set)
+pub fn self_update(check_only: bool) -> anyhow::Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let agent = ureq::AgentBuilder::new()
+        .user_agent(&format!("beautytips/{current}"))
+        .build();
+
+    let release: Release = serde_json::from_slice(&download(&agent, LATEST_RELEASE_URL)?)
+        .context("Failed to parse release information")?;
+
+    if !is_newer(current, &release.tag_name) {
+        println!("beautytips {current} is up to date");
+        return Ok(());
+    }
+    if check_only {
+        println!(
+            "beautytips {} is available (installed: {current})",
+            release.tag_name
+        );
+        return Ok(());
+    }
+
+    let public_key = RELEASE_PUBLIC_KEY.ok_or(anyhow::anyhow!(
+        "This build of beautytips can not verify releases, update it the way it was installed"
+    ))?;
+    let target = release_target(
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        cfg!(target_env = "musl"),
+    )
+    .ok_or(anyhow::anyhow!(
+        "No release builds available for this platform"
+    ))?;
+    let archive_name = format!("beautytips-{target}.tar.gz");
+
+    let checksum_name = format!("{archive_name}.sha256");
+    let archive = download(&agent, release.asset_url(&archive_name)?)?;
+    let checksum = download(&agent, release.asset_url(&checksum_name)?)?;
+    let signature = download(
+        &agent,
+        release.asset_url(&format!("{checksum_name}.minisig"))?,
+    )?;
+    verify_signature(public_key, &checksum, &String::from_utf8_lossy(&signature))
+        .context(format!("Failed to verify {checksum_name}"))?;
+    verify_checksum(&archive, &String::from_utf8_lossy(&checksum))
+        .context(format!("Failed to verify {archive_name}"))?;
+
+    install(&archive, &archive_name)?;
+
+    println!("Updated beautytips from {current} to {}", release.tag_name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions() {
+        assert_eq!(parse_version("v0.2.0"), Some((0, 2, 0)));
+        assert_eq!(parse_version("1.10.3-rc.1"), Some((1, 10, 3)));
+        assert_eq!(parse_version("1.2"), None);
+        assert_eq!(parse_version("1.2.3.4"), None);
+
+        assert!(is_newer("0.2.0", "v0.2.1"));
+        assert!(is_newer("0.2.0", "v0.10.0"));
+        assert!(!is_newer("0.2.0", "v0.2.0"));
+        assert!(!is_newer("0.2.0", "v0.1.9"));
+        assert!(!is_newer("0.2.0", "nightly"));
+    }
+
+    #[test]
+    fn test_release_target() {
+        assert_eq!(
+            release_target("x86_64", "linux", false),
+            Some("x86_64-unknown-linux-gnu")
+        );
+        assert_eq!(
+            release_target("x86_64", "linux", true),
+            Some("x86_64-unknown-linux-musl")
+        );
+        assert_eq!(release_target("riscv64", "linux", false), None);
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let checksum =
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  hello.tar.gz\n";
+        assert!(verify_checksum(b"hello", checksum).is_ok());
+        assert!(verify_checksum(b"hello!", checksum).is_err());
+        assert!(verify_checksum(b"hello", "").is_err());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let public_key = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+        let signature = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1633700835\tfile:test\tprehashed
+wLMDjy9FLAuxZ3q4NlEvkgtyhrr0gtTu6KC4KBJdITbbOeAi1zBIYo0v4iTgt8jJpIidRJnp94ABQkJAgAooBQ==";
+
+        assert!(verify_signature(public_key, b"test", signature).is_ok());
+        assert!(verify_signature(public_key, b"Test", signature).is_err());
+        assert!(verify_signature(public_key, b"test", "").is_err());
+        assert!(verify_signature("not a key", b"test", signature).is_err());
+    }
+
+    #[test]
+    fn test_install_without_binary() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "README.md", &b"hello"[..])
+            .unwrap();
+        let archive = builder.into_inner().unwrap().finish().unwrap();
+
+        let error = install(&archive, "test.tar.gz").unwrap_err().to_string();
+        assert_eq!(error, "No beautytips binary found in test.tar.gz");
+    }
+}
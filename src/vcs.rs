@@ -6,18 +6,23 @@
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
-    sync::OnceLock,
+    sync::{Mutex, OnceLock},
 };
 
+use anyhow::Context;
+
 mod git;
 mod jj;
 
+pub(crate) use git::filter_ignored as filter_git_ignored;
+
 #[allow(clippy::module_name_repetitions)]
 pub type BoxedVcs = Box<dyn Vcs + Sync + Send>;
 #[allow(clippy::module_name_repetitions)]
 pub type DynVcs = &'static (dyn Vcs + Sync + Send);
 
 static KNOWN_VCSES: OnceLock<Vec<BoxedVcs>> = OnceLock::new();
+static REGISTERED_VCSES: Registry = Registry::new();
 
 pub fn output_to_string(input: &[u8]) -> String {
     // SAFETY: This is OS output, it should be OK to convert to an OsStr (I hope)
@@ -50,15 +55,164 @@ pub trait Vcs {
 
     /// Find the directory root
     async fn repository_root(&self, current_directory: &Path) -> Option<PathBuf>;
+
+    /// Find the revision to compare the working copy against to get all
+    /// changes made since `since`, which is a revision or a date
+    ///
+    /// # Errors
+    ///
+    /// Reports an error if `since` could not be resolved. The default
+    /// implementation always does so.
+    async fn revision_since(&self, current_directory: &Path, since: &str) -> crate::Result<String> {
+        let _ = current_directory;
+        Err(anyhow::anyhow!(format!(
+            "Version control system '{}' does not support looking up changes since '{since}'",
+            self.name()
+        )))
+    }
+
+    /// The unified diff of the changes from `from_revision` to
+    /// `to_revision`, as `changed_files` reports them
+    ///
+    /// # Errors
+    ///
+    /// Reports an error if the diff could not be retrieved. The default
+    /// implementation always does so.
+    async fn diff(
+        &self,
+        current_directory: &Path,
+        from_revision: &Option<String>,
+        to_revision: &Option<String>,
+    ) -> crate::Result<Vec<u8>> {
+        let _ = (current_directory, from_revision, to_revision);
+        Err(anyhow::anyhow!(format!(
+            "Version control system '{}' does not support diffs",
+            self.name()
+        )))
+    }
+
+    /// The contents of the file at `path` (relative to the repository root)
+    /// as recorded in `revision`
+    ///
+    /// # Errors
+    ///
+    /// Reports an error if the contents could not be retrieved. The default
+    /// implementation always does so.
+    async fn file_contents(
+        &self,
+        current_directory: &Path,
+        revision: &str,
+        path: &Path,
+    ) -> crate::Result<Vec<u8>> {
+        let _ = current_directory;
+        Err(anyhow::anyhow!(format!(
+            "Version control system '{}' does not support reading {} at revision '{revision}'",
+            self.name(),
+            path.display()
+        )))
+    }
+
+    /// Write all files recorded in `revision` into the `target` directory
+    ///
+    /// # Errors
+    ///
+    /// Reports an error if the files could not be written. The default
+    /// implementation always does so.
+    async fn write_tree(
+        &self,
+        current_directory: &Path,
+        revision: &str,
+        target: &Path,
+    ) -> crate::Result<()> {
+        let _ = (current_directory, target);
+        Err(anyhow::anyhow!(format!(
+            "Version control system '{}' does not support checking out revision '{revision}'",
+            self.name(),
+        )))
+    }
+}
+
+/// A temporary directory holding all files as recorded in a revision
+///
+/// Configuration files of tools (like `rustfmt.toml`) are part of it, so
+/// they behave as they did in that revision. The directory is removed again
+/// when the `Snapshot` is dropped.
+#[derive(Debug)]
+pub(crate) struct Snapshot {
+    directory: tempfile::TempDir,
+}
+
+impl Snapshot {
+    /// Write all files as recorded in `revision` into a new snapshot
+    async fn new(vcs: DynVcs, repo_path: &Path, revision: &str) -> crate::Result<Self> {
+        let directory = tokio::task::spawn_blocking(|| {
+            crate::actions::temp_dir::create_private("beautytips-snapshot-")
+        })
+        .await
+        .expect("Join Error")?;
+        let snapshot = Self { directory };
+
+        vcs.write_tree(repo_path, revision, snapshot.directory())
+            .await
+            .context(format!("Failed to check out '{revision}'"))?;
+        Ok(snapshot)
+    }
+
+    pub(crate) fn directory(&self) -> &Path {
+        self.directory.path()
+    }
+}
+
+/// Register an additional version control system
+///
+/// Registered version control systems are tried in the order they were
+/// registered in, but before any of the builtin ones. This applies to
+/// auto-detection as well as to looking up a system by name.
+///
+/// # Panics
+///
+/// Panics if the registry lock got poisoned.
+#[allow(clippy::module_name_repetitions)]
+pub fn register_vcs(vcs: BoxedVcs) {
+    REGISTERED_VCSES.register(vcs);
+}
+
+/// The version control systems registered on top of the builtin ones
+struct Registry(Mutex<Vec<DynVcs>>);
+
+impl Registry {
+    const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    fn register(&self, vcs: BoxedVcs) {
+        let vcs: DynVcs = Box::leak(vcs);
+        self.0.lock().expect("VCS registry lock poisoned").push(vcs);
+    }
+
+    /// The registered version control systems followed by the builtin ones
+    fn known_vcses(&self) -> Vec<DynVcs> {
+        let registered = self.0.lock().expect("VCS registry lock poisoned").clone();
+
+        registered
+            .into_iter()
+            .chain(
+                KNOWN_VCSES
+                    .get_or_init(|| vec![Box::new(jj::Jj::new()), Box::new(git::Git::new())])
+                    .iter()
+                    .map(Box::as_ref),
+            )
+            .collect()
+    }
+
+    fn vcs_by_name(&self, name: &str) -> Option<DynVcs> {
+        self.known_vcses().into_iter().find(|v| v.name() == name)
+    }
 }
 
 #[must_use]
 fn known_vcses() -> Vec<DynVcs> {
-    KNOWN_VCSES
-        .get_or_init(|| vec![Box::new(jj::Jj::new()), Box::new(git::Git::new())])
-        .iter()
-        .map(Box::as_ref)
-        .collect()
+    REGISTERED_VCSES.known_vcses()
 }
 
 async fn helper(vcs: DynVcs, current_directory: &Path) -> Option<(DynVcs, PathBuf)> {
@@ -82,7 +236,7 @@ async fn auto_detect_vcs(current_directory: &Path) -> Option<(DynVcs, PathBuf)>
 
 #[must_use]
 fn vcs_by_name(name: &str) -> Option<DynVcs> {
-    known_vcses().into_iter().find(|v| v.name() == name)
+    REGISTERED_VCSES.vcs_by_name(name)
 }
 
 #[tracing::instrument]
@@ -125,8 +279,11 @@ pub(crate) async fn find_changed_files(
     current_directory: PathBuf,
     config: crate::VcsInput,
 ) -> crate::Result<crate::ExecutionContext> {
-    let to_rev = config.to_revision.clone();
-    let from_rev = config.from_revision.clone();
+    let mut to_rev = config.to_revision.clone();
+    let mut from_rev = config.from_revision.clone();
+    let changed_since = config.changed_since.clone();
+    let at_revision = config.at_revision.clone();
+    let excluded_paths = config.excluded_paths.clone();
 
     let (vcs, repo_path) = vcs_for_configuration(&current_directory, config).await?;
     tracing::trace!(
@@ -134,12 +291,53 @@ pub(crate) async fn find_changed_files(
         vcs.name()
     );
 
-    let files_to_process = vcs.changed_files(&repo_path, &from_rev, &to_rev).await?;
+    if let Some(since) = changed_since {
+        let revision = vcs
+            .revision_since(&repo_path, &since)
+            .await
+            .context(format!("Failed to find changes since '{since}'"))?;
+        tracing::debug!("Changes since {since:?} start at revision {revision:?}");
+        from_rev = Some(revision);
+        to_rev = None;
+    }
+    if let Some(revision) = &at_revision {
+        // Look at the changes made in that revision, or since `from_rev`
+        to_rev = Some(revision.clone());
+    }
+
+    let files_to_process = vcs
+        .changed_files(&repo_path, &from_rev, &to_rev)
+        .await?
+        .into_iter()
+        .filter(|f| !excluded_paths.iter().any(|p| f.starts_with(p)))
+        .collect::<Vec<_>>();
 
     tracing::debug!("VCS returned the following files to process: {files_to_process:?}");
 
+    // Actions can run without the diff, so failing to get it is no error
+    let diff = match vcs.diff(&repo_path, &from_rev, &to_rev).await {
+        Ok(diff) => Some(diff),
+        Err(e) => {
+            tracing::warn!("Failed to get the diff from {}: {e:#}", vcs.name());
+            None
+        }
+    };
+
+    let snapshot = match &at_revision {
+        Some(revision) => {
+            let snapshot = Snapshot::new(vcs, &repo_path, revision).await?;
+            tracing::debug!("Files at {revision:?} are in {:?}", snapshot.directory());
+            Some(std::sync::Arc::new(snapshot))
+        }
+        None => None,
+    };
+
     Ok(crate::ExecutionContext {
-        root_directory: repo_path,
+        root_directory: snapshot
+            .as_ref()
+            .map_or(repo_path, |s| s.directory().to_path_buf()),
+        snapshot,
+        diff,
         extra_environment: HashMap::from([
             ("BEAUTYTIPS_INPUT".to_string(), "vcs".to_string()),
             ("BEAUTYTIPS_VCS".to_string(), vcs.name().to_string()),
@@ -155,3 +353,46 @@ pub(crate) async fn find_changed_files(
         files_to_process,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestVcs {}
+
+    #[async_trait::async_trait]
+    impl Vcs for TestVcs {
+        fn name(&self) -> &'static str {
+            "test-vcs"
+        }
+
+        async fn changed_files(
+            &self,
+            _current_directory: &Path,
+            _from_revision: &Option<String>,
+            _to_revision: &Option<String>,
+        ) -> crate::Result<Vec<PathBuf>> {
+            Ok(vec![PathBuf::from("changed.txt")])
+        }
+
+        async fn repository_root(&self, current_directory: &Path) -> Option<PathBuf> {
+            Some(current_directory.to_path_buf())
+        }
+    }
+
+    #[test]
+    fn test_register_vcs() {
+        // The global registry is shared with all other tests, which must
+        // not detect the test VCS
+        let registry = Registry::new();
+        assert!(registry.vcs_by_name("test-vcs").is_none());
+        assert!(registry.vcs_by_name("git").is_some());
+
+        registry.register(Box::new(TestVcs {}));
+
+        assert_eq!(registry.vcs_by_name("test-vcs").unwrap().name(), "test-vcs");
+        assert_eq!(registry.known_vcses().first().unwrap().name(), "test-vcs");
+        assert!(registry.vcs_by_name("git").is_some());
+        assert!(vcs_by_name("test-vcs").is_none());
+    }
+}
@@ -1,11 +1,16 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
 
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
 
 use crate::vcs;
 
 use anyhow::Context;
+use tokio::io::AsyncWriteExt;
 
 pub fn zero_split_files(output: &[u8]) -> Vec<PathBuf> {
     output
@@ -15,6 +20,188 @@ pub fn zero_split_files(output: &[u8]) -> Vec<PathBuf> {
         .collect()
 }
 
+/// Parse the output of `git diff --name-status -z`
+///
+/// Deleted files are dropped, renamed and copied files are reported with
+/// their new path.
+fn parse_name_status(output: &[u8]) -> Vec<PathBuf> {
+    let mut parts = output
+        .split(|i| *i == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| PathBuf::from(&super::output_to_string(s)));
+
+    let mut result = Vec::new();
+    while let Some(status) = parts.next() {
+        let status = status.to_string_lossy();
+        if status.starts_with('R') || status.starts_with('C') {
+            let _old = parts.next();
+            result.extend(parts.next());
+        } else if status.starts_with('D') {
+            let _deleted = parts.next();
+        } else {
+            result.extend(parts.next());
+        }
+    }
+    result
+}
+
+fn parse_submodule_paths(output: &[u8]) -> Vec<PathBuf> {
+    output
+        .split(|i| *i == 0)
+        .filter_map(|entry| {
+            let (_, path) = entry.split_at(entry.iter().position(|i| *i == b'\n')? + 1);
+            (!path.is_empty()).then(|| PathBuf::from(super::output_to_string(path)))
+        })
+        .collect()
+}
+
+/// The paths of all submodules, relative to `repository_root`
+async fn submodule_paths(repository_root: &Path) -> crate::Result<Vec<PathBuf>> {
+    if !repository_root.join(".gitmodules").is_file() {
+        return Ok(vec![]);
+    }
+
+    let output = tokio::process::Command::new("git")
+        .args([
+            "config",
+            "-z",
+            "--file",
+            ".gitmodules",
+            "--get-regexp",
+            r"^submodule\..*\.path$",
+        ])
+        .current_dir(repository_root)
+        .output()
+        .await
+        .context("Failed to run git")?;
+
+    Ok(parse_submodule_paths(&output.stdout))
+}
+
+/// Remove all files git would ignore from `files`
+///
+/// The `files` are returned unchanged if `directory` is not inside a git
+/// repository or git is not available.
+pub async fn filter_ignored(directory: &Path, files: Vec<PathBuf>) -> crate::Result<Vec<PathBuf>> {
+    let child = tokio::process::Command::new("git")
+        .args(["check-ignore", "--stdin", "-z"])
+        .current_dir(directory)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let Ok(mut child) = child else {
+        tracing::debug!("Failed to run git check-ignore, not filtering files");
+        return Ok(files);
+    };
+
+    let mut input = Vec::new();
+    for f in &files {
+        input.extend_from_slice(f.as_os_str().as_encoded_bytes());
+        input.push(0);
+    }
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = tokio::spawn(async move { stdin.write_all(&input).await });
+
+    let output = child
+        .wait_with_output()
+        .await
+        .context("Failed to run git check-ignore")?;
+    let _ = writer.await;
+
+    tracing::trace!("check-ignore in {directory:?} => {:?}", output.status);
+
+    // 0: Some files are ignored, 1: No file is ignored, others: Errors
+    match output.status.code() {
+        Some(0) => {
+            let ignored = zero_split_files(&output.stdout)
+                .into_iter()
+                .collect::<HashSet<_>>();
+            Ok(files.into_iter().filter(|f| !ignored.contains(f)).collect())
+        }
+        _ => Ok(files),
+    }
+}
+
+/// Run git in `directory` and return its output if it succeeded
+async fn git_output(directory: &Path, args: &[&str]) -> crate::Result<Option<String>> {
+    let output = tokio::process::Command::new("git")
+        .args(args)
+        .current_dir(directory)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("Failed to run git")?;
+    tracing::trace!("git {args:?} => {output:?}");
+
+    Ok(output
+        .status
+        .success()
+        .then(|| super::output_to_string(&output.stdout)))
+}
+
+/// Is `date` a date git understands?
+///
+/// Git reads anything as a date in most places, so typos would silently
+/// select the newest commit. Config values of type `expiry-date` are checked
+/// though.
+async fn is_date(directory: &Path, date: &str) -> crate::Result<bool> {
+    let setting = format!("beautytips.since={date}");
+    Ok(git_output(
+        directory,
+        &[
+            "-c",
+            &setting,
+            "config",
+            "--type=expiry-date",
+            "beautytips.since",
+        ],
+    )
+    .await?
+    .is_some())
+}
+
+/// The id of the empty tree, to compare against when there is no commit
+async fn empty_tree(directory: &Path) -> crate::Result<String> {
+    git_output(directory, &["hash-object", "-t", "tree", "--stdin"])
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Failed to find the empty tree"))
+}
+
+/// The revisions to pass to `git diff` to compare `from_revision` to
+/// `to_revision`
+///
+/// Without `from_revision`, `to_revision` gets compared to its parent, or to
+/// the empty tree if it is a root commit.
+async fn diff_revisions(
+    directory: &Path,
+    from_revision: Option<&str>,
+    to_revision: Option<&str>,
+) -> crate::Result<Vec<String>> {
+    Ok(match (from_revision, to_revision) {
+        (None, None) => vec![],
+        (Some(from), None) => vec![from.to_string()],
+        (None, Some(to)) => {
+            let parent = git_output(
+                directory,
+                &[
+                    "rev-parse",
+                    "--verify",
+                    "--quiet",
+                    &format!("{to}~^{{commit}}"),
+                ],
+            )
+            .await?;
+            let parent = match parent {
+                Some(parent) => parent,
+                None => empty_tree(directory).await?,
+            };
+            vec![parent, to.to_string()]
+        }
+        (Some(from), Some(to)) => vec![from.to_string(), to.to_string()],
+    })
+}
+
 #[derive(Debug, Default)]
 pub struct Git {}
 
@@ -26,7 +213,7 @@ impl Git {
 
 #[async_trait::async_trait]
 impl vcs::Vcs for Git {
-    fn name(&self) -> &str {
+    fn name(&self) -> &'static str {
         "git"
     }
 
@@ -37,30 +224,21 @@ impl vcs::Vcs for Git {
         from_revision: &Option<String>,
         to_revision: &Option<String>,
     ) -> crate::Result<Vec<std::path::PathBuf>> {
-        let args = {
-            let mut tmp = vec![
-                "diff".to_string(),
-                "--name-only".to_string(),
-                "--no-ext-diff".to_string(),
-                "-z".to_string(),
-            ];
-            match (from_revision, to_revision) {
-                (None, None) => { /* do nothing */ }
-                (Some(from), None) => tmp.push(from.clone()),
-                (None, Some(to)) => {
-                    tmp.push(format!("{to}~"));
-                    tmp.push(to.clone());
-                }
-                (Some(from), Some(to)) => {
-                    tmp.push(from.clone());
-                    tmp.push(to.clone());
-                }
-            };
-            tmp
-        };
-
+        let revisions = diff_revisions(
+            current_directory,
+            from_revision.as_deref(),
+            to_revision.as_deref(),
+        )
+        .await?;
         let output = tokio::process::Command::new("git")
-            .args(args)
+            .args([
+                "diff",
+                "--name-status",
+                "--find-renames",
+                "--no-ext-diff",
+                "-z",
+            ])
+            .args(revisions)
             .current_dir(current_directory)
             .output()
             .await
@@ -68,10 +246,55 @@ impl vcs::Vcs for Git {
 
         tracing::trace!("diff {from_revision:?} {to_revision:?} => {output:?}");
 
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(format!(
+                "git diff failed: {}",
+                super::output_to_string(&output.stderr)
+            )));
+        }
+
+        // Files in submodules belong to another repository:
+        let submodules = submodule_paths(current_directory).await?;
+        tracing::trace!("submodules: {submodules:?}");
+
+        Ok(parse_name_status(&output.stdout)
+            .into_iter()
+            .filter(|f| !submodules.iter().any(|s| f.starts_with(s)))
+            .collect())
+    }
+
+    #[tracing::instrument]
+    async fn diff(
+        &self,
+        current_directory: &Path,
+        from_revision: &Option<String>,
+        to_revision: &Option<String>,
+    ) -> crate::Result<Vec<u8>> {
+        let revisions = diff_revisions(
+            current_directory,
+            from_revision.as_deref(),
+            to_revision.as_deref(),
+        )
+        .await?;
+        let output = tokio::process::Command::new("git")
+            .args([
+                "diff",
+                "--find-renames",
+                "--no-ext-diff",
+                "--no-color",
+                "--no-textconv",
+            ])
+            .args(revisions)
+            .current_dir(current_directory)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .context("Failed to run git")?;
         if output.status.success() {
-            return Ok(zero_split_files(&output.stdout));
+            Ok(output.stdout)
+        } else {
+            Err(anyhow::anyhow!(super::output_to_string(&output.stderr)))
         }
-        Ok(vec![])
     }
 
     #[tracing::instrument]
@@ -89,4 +312,331 @@ impl vcs::Vcs for Git {
             .success()
             .then_some(PathBuf::from(&super::output_to_string(&output.stdout)))
     }
+
+    #[tracing::instrument]
+    async fn revision_since(&self, current_directory: &Path, since: &str) -> crate::Result<String> {
+        if let Some(revision) = git_output(
+            current_directory,
+            &[
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("{since}^{{commit}}"),
+            ],
+        )
+        .await?
+        {
+            return Ok(revision);
+        }
+
+        // Not a revision, so try it as a date: Compare to the last commit
+        // before that date
+        let revision = if is_date(current_directory, since).await? {
+            git_output(
+                current_directory,
+                &["rev-list", "-1", &format!("--before={since}"), "HEAD"],
+            )
+            .await?
+        } else {
+            None
+        };
+        let Some(revision) = revision else {
+            return Err(anyhow::anyhow!(format!(
+                "'{since}' is neither a revision nor a date"
+            )));
+        };
+        if !revision.is_empty() {
+            return Ok(revision);
+        }
+
+        // All commits are newer: Compare to the empty tree
+        empty_tree(current_directory).await
+    }
+
+    #[tracing::instrument]
+    async fn file_contents(
+        &self,
+        current_directory: &Path,
+        revision: &str,
+        path: &Path,
+    ) -> crate::Result<Vec<u8>> {
+        let object = format!("{revision}:{}", path.to_string_lossy().replace('\\', "/"));
+        let output = tokio::process::Command::new("git")
+            .args(["cat-file", "blob", &object])
+            .current_dir(current_directory)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .context("Failed to run git")?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(anyhow::anyhow!(super::output_to_string(&output.stderr)))
+        }
+    }
+
+    #[tracing::instrument]
+    async fn write_tree(
+        &self,
+        current_directory: &Path,
+        revision: &str,
+        target: &Path,
+    ) -> crate::Result<()> {
+        // Check out through an index of its own, so the one of the
+        // repository stays untouched
+        let index = target.join(".beautytips-index");
+        let mut prefix = target.as_os_str().to_os_string();
+        prefix.push("/");
+        let mut prefix_arg = std::ffi::OsString::from("--prefix=");
+        prefix_arg.push(&prefix);
+
+        for args in [
+            vec!["read-tree".into(), format!("{revision}^{{tree}}").into()],
+            vec!["checkout-index".into(), "--all".into(), prefix_arg],
+        ] {
+            let output = tokio::process::Command::new("git")
+                .args(&args)
+                .env("GIT_INDEX_FILE", &index)
+                .current_dir(current_directory)
+                .stdin(Stdio::null())
+                .output()
+                .await
+                .context("Failed to run git")?;
+            if !output.status.success() {
+                let _ = tokio::fs::remove_file(&index).await;
+                return Err(anyhow::anyhow!(super::output_to_string(&output.stderr)));
+            }
+        }
+        tokio::fs::remove_file(&index)
+            .await
+            .context(format!("Failed to remove {}", index.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_name_status() {
+        assert!(parse_name_status(b"").is_empty());
+        assert_eq!(
+            parse_name_status(
+                b"M\0src/main.rs\0D\0old.txt\0R087\0a/old.rs\0a/new.rs\0A\0new file.txt\0C100\0x.rs\0y.rs\0T\0link\0"
+            ),
+            [
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("a/new.rs"),
+                PathBuf::from("new file.txt"),
+                PathBuf::from("y.rs"),
+                PathBuf::from("link"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_diff_revisions() {
+        let directory = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(directory.path())
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "--quiet"]);
+        git(&["commit", "--quiet", "--allow-empty", "-m", "root"]);
+        git(&["commit", "--quiet", "--allow-empty", "-m", "second"]);
+
+        let revisions = |from: Option<&'static str>, to: Option<&'static str>| {
+            let directory = directory.path().to_path_buf();
+            async move { diff_revisions(&directory, from, to).await.unwrap() }
+        };
+        let parent = git_output(directory.path(), &["rev-parse", "HEAD~"])
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(revisions(None, None).await.is_empty());
+        assert_eq!(revisions(Some("main"), None).await, ["main"]);
+        assert_eq!(
+            revisions(None, Some("HEAD")).await,
+            [parent.as_str(), "HEAD"]
+        );
+        // The root commit is compared to the empty tree
+        assert_eq!(
+            revisions(None, Some("HEAD~")).await,
+            ["4b825dc642cb6eb9a060e54bf8d69288fbee4904", "HEAD~"]
+        );
+        assert_eq!(revisions(Some("a"), Some("b")).await, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_at_revision() {
+        let directory = tempfile::tempdir().unwrap();
+        let directory = directory.path().canonicalize().unwrap();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(&directory)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        let commit = |files: &[(&str, &str)]| {
+            for (file, contents) in files {
+                std::fs::write(directory.join(file), contents).unwrap();
+                git(&["add", file]);
+            }
+            git(&["commit", "--quiet", "-m", files[0].0]);
+        };
+
+        git(&["init", "--quiet"]);
+        commit(&[("rustfmt.toml", "max_width = 80\n"), ("a.rs", "a")]);
+        commit(&[("b.rs", "b")]);
+        std::fs::write(directory.join("b.rs"), "changed").unwrap();
+
+        let at_revision = |revision: &str| {
+            vcs::find_changed_files(
+                directory.clone(),
+                crate::VcsInput {
+                    tool: Some("git".to_string()),
+                    at_revision: Some(revision.to_string()),
+                    ..Default::default()
+                },
+            )
+        };
+
+        // The root commit changes all its files
+        let context = at_revision("HEAD~").await.unwrap();
+        let mut files = context.files_to_process.clone();
+        files.sort();
+        assert_eq!(files, ["a.rs", "rustfmt.toml"].map(PathBuf::from));
+
+        // All files are in the snapshot, as recorded in the revision
+        let context = at_revision("HEAD").await.unwrap();
+        assert_eq!(context.files_to_process, [PathBuf::from("b.rs")]);
+        let snapshot = context.snapshot.clone().unwrap();
+        assert_eq!(context.root_directory, snapshot.directory());
+        assert_eq!(
+            std::fs::read_to_string(snapshot.directory().join("rustfmt.toml")).unwrap(),
+            "max_width = 80\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(snapshot.directory().join("b.rs")).unwrap(),
+            "b"
+        );
+        assert!(!snapshot.directory().join(".beautytips-index").exists());
+
+        let snapshot_directory = snapshot.directory().to_path_buf();
+        drop((context, snapshot));
+        assert!(!snapshot_directory.exists());
+
+        assert!(at_revision("no-such-revision").await.is_err());
+    }
+
+    #[test]
+    fn test_parse_submodule_paths() {
+        assert!(parse_submodule_paths(b"").is_empty());
+        assert_eq!(
+            parse_submodule_paths(
+                b"submodule.foo.path\nthird_party/foo\0submodule.bar baz.path\nbar baz\0"
+            ),
+            [PathBuf::from("third_party/foo"), PathBuf::from("bar baz")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_filter_ignored() {
+        let directory = tempfile::tempdir().unwrap();
+        let status = std::process::Command::new("git")
+            .args(["init", "--quiet"])
+            .current_dir(directory.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        std::fs::write(directory.path().join(".gitignore"), "*.log\n").unwrap();
+        std::fs::write(directory.path().join(".git/info/exclude"), "secret.txt\n").unwrap();
+
+        let files = ["a.txt", "b.log", "secret.txt", "sub/c.log", ".gitignore"]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        let filtered = filter_ignored(directory.path(), files).await.unwrap();
+
+        assert_eq!(
+            filtered,
+            [PathBuf::from("a.txt"), PathBuf::from(".gitignore")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_revision_since() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let directory = temp_dir.path().to_path_buf();
+        let git = |args: &[&str], date: &str| {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .env("GIT_AUTHOR_DATE", date)
+                .env("GIT_COMMITTER_DATE", date)
+                .current_dir(&directory)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        let commit = |file: &str, date: &str| {
+            std::fs::write(directory.join(file), file).unwrap();
+            git(&["add", file], date);
+            git(&["commit", "--quiet", "-m", file], date);
+        };
+
+        git(&["init", "--quiet"], "2020-01-01T00:00:00");
+        commit("old.txt", "2020-01-01T00:00:00");
+        commit("new.txt", "2022-01-01T00:00:00");
+        std::fs::write(directory.join("old.txt"), "changed").unwrap();
+
+        let changed_since = |since: &'static str| {
+            let directory = directory.clone();
+            async move {
+                let git = Git::new();
+                let revision = vcs::Vcs::revision_since(&git, &directory, since)
+                    .await
+                    .unwrap();
+                let mut files = vcs::Vcs::changed_files(&git, &directory, &Some(revision), &None)
+                    .await
+                    .unwrap();
+                files.sort();
+                files
+            }
+        };
+
+        assert_eq!(changed_since("HEAD").await, [PathBuf::from("old.txt")]);
+        assert_eq!(
+            changed_since("2021-01-01").await,
+            [PathBuf::from("new.txt"), PathBuf::from("old.txt")]
+        );
+        assert_eq!(
+            changed_since("2019-01-01").await,
+            [PathBuf::from("new.txt"), PathBuf::from("old.txt")]
+        );
+        assert!(
+            vcs::Vcs::revision_since(&Git::new(), &directory, "yesterdya")
+                .await
+                .is_err()
+        );
+        git(
+            &["commit", "--quiet", "-a", "-m", "change"],
+            "2023-01-01T00:00:00",
+        );
+        assert_eq!(changed_since("HEAD~1").await, [PathBuf::from("old.txt")]);
+        assert_eq!(
+            changed_since("2022-06-01").await,
+            [PathBuf::from("old.txt")]
+        );
+    }
 }
@@ -9,6 +9,69 @@ use crate::vcs;
 
 use anyhow::Context;
 
+/// Resolve a path as reported by `jj diff --summary` for a renamed or
+/// copied file (like `src/{old.rs => new.rs}`) to its new path
+fn rename_target(path: &str) -> String {
+    let (Some(start), Some(end)) = (path.find('{'), path.rfind('}')) else {
+        return path.to_string();
+    };
+    let Some((_, new)) = path[start + 1..end].split_once(" => ") else {
+        return path.to_string();
+    };
+
+    let result = format!("{}{new}{}", &path[..start], &path[end + 1..]);
+    result
+        .replace("//", "/")
+        .trim_start_matches('/')
+        .to_string()
+}
+
+/// Parse the output of `jj diff --summary`
+///
+/// Deleted files are dropped, renamed and copied files are reported with
+/// their new path.
+fn parse_summary(output: &str) -> Vec<PathBuf> {
+    output
+        .lines()
+        .filter(|l| l.len() > 2 && !l.starts_with("D "))
+        .map(|l| {
+            if l.starts_with("R ") || l.starts_with("C ") {
+                PathBuf::from(rename_target(&l[2..]))
+            } else {
+                PathBuf::from(&l[2..])
+            }
+        })
+        .collect()
+}
+
+/// The revset selecting the last ancestor of the working copy committed
+/// before `date`, falling back to the root commit
+fn since_date_revset(date: &str) -> String {
+    let date = date.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("latest((::@ & committer_date(before:\"{date}\")) | root())")
+}
+
+/// The fileset selecting exactly `path`, relative to the workspace root
+fn root_file_fileset(path: &Path) -> String {
+    let path = path
+        .to_string_lossy()
+        .replace('\\', "/")
+        .replace('"', "\\\"");
+    format!("root-file:\"{path}\"")
+}
+
+/// The `--from` and `--to` arguments of `jj interdiff` comparing
+/// `from_revision` to `to_revision`
+fn interdiff_args(from_revision: Option<&str>, to_revision: Option<&str>) -> [String; 2] {
+    let from = match (from_revision, to_revision) {
+        (Some(fr), _) => format!("--from={fr}"),
+        (None, Some(to)) => format!("--from={to}-"),
+        (None, None) => "--from=@-".to_string(),
+    };
+    let to = to_revision.map_or("--to=@".to_string(), |to| format!("--to={to}"));
+    [from, to]
+}
+
 #[derive(Debug, Default)]
 pub struct Jj {}
 
@@ -20,7 +83,7 @@ impl Jj {
 
 #[async_trait::async_trait]
 impl vcs::Vcs for Jj {
-    fn name(&self) -> &str {
+    fn name(&self) -> &'static str {
         "jj"
     }
 
@@ -31,12 +94,7 @@ impl vcs::Vcs for Jj {
         from_revision: &Option<String>,
         to_revision: &Option<String>,
     ) -> crate::Result<Vec<std::path::PathBuf>> {
-        let from = from_revision
-            .as_ref()
-            .map_or("--from=@-".to_string(), |fr| format!("--from={fr}"));
-        let to = to_revision
-            .as_ref()
-            .map_or("--to=@".to_string(), |to| format!("--to={to}"));
+        let [from, to] = interdiff_args(from_revision.as_deref(), to_revision.as_deref());
 
         let output = tokio::process::Command::new(self.name())
             .args(["--color=never", "interdiff", "-s", &from, &to])
@@ -55,11 +113,30 @@ impl vcs::Vcs for Jj {
             }
         }
 
-        Ok(super::output_to_string(&output.stdout)
-            .lines()
-            .filter(|l| l.len() > 2 && &l[0..2] != "D ")
-            .map(|l| PathBuf::from(&l[2..]))
-            .collect())
+        Ok(parse_summary(&super::output_to_string(&output.stdout)))
+    }
+
+    #[tracing::instrument]
+    async fn diff(
+        &self,
+        current_directory: &Path,
+        from_revision: &Option<String>,
+        to_revision: &Option<String>,
+    ) -> crate::Result<Vec<u8>> {
+        let [from, to] = interdiff_args(from_revision.as_deref(), to_revision.as_deref());
+
+        let output = tokio::process::Command::new(self.name())
+            .args(["--color=never", "interdiff", "--git", &from, &to])
+            .current_dir(current_directory)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .await
+            .context(format!("Could not run {}", self.name()))?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(anyhow::anyhow!(super::output_to_string(&output.stderr)))
+        }
     }
 
     #[tracing::instrument]
@@ -78,4 +155,142 @@ impl vcs::Vcs for Jj {
             .success()
             .then_some(PathBuf::from(&super::output_to_string(&output.stdout)))
     }
+
+    #[tracing::instrument]
+    async fn revision_since(&self, current_directory: &Path, since: &str) -> crate::Result<String> {
+        for revset in [since.to_string(), since_date_revset(since)] {
+            let output = tokio::process::Command::new(self.name())
+                .args([
+                    "--color=never",
+                    "log",
+                    "--no-graph",
+                    "--limit=1",
+                    "--template=commit_id",
+                    "-r",
+                    &revset,
+                ])
+                .current_dir(current_directory)
+                .output()
+                .await
+                .context(format!("Could not run {}", self.name()))?;
+
+            tracing::trace!("revision for {revset:?}: {output:?}");
+
+            let revision = super::output_to_string(&output.stdout);
+            if output.status.success() && !revision.is_empty() {
+                return Ok(revision);
+            }
+        }
+
+        Err(anyhow::anyhow!(format!(
+            "'{since}' is neither a revision nor a date"
+        )))
+    }
+
+    #[tracing::instrument]
+    async fn file_contents(
+        &self,
+        current_directory: &Path,
+        revision: &str,
+        path: &Path,
+    ) -> crate::Result<Vec<u8>> {
+        let output = tokio::process::Command::new(self.name())
+            .args(["--color=never", "file", "print", "-r", revision])
+            .arg(root_file_fileset(path))
+            .current_dir(current_directory)
+            .output()
+            .await
+            .context(format!("Could not run {}", self.name()))?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(anyhow::anyhow!(super::output_to_string(&output.stderr)))
+        }
+    }
+
+    #[tracing::instrument]
+    async fn write_tree(
+        &self,
+        current_directory: &Path,
+        revision: &str,
+        target: &Path,
+    ) -> crate::Result<()> {
+        let Some(root) = self.repository_root(current_directory).await else {
+            return Err(anyhow::anyhow!("No repository found"));
+        };
+        let output = tokio::process::Command::new(self.name())
+            .args(["--color=never", "file", "list", "-r", revision])
+            .current_dir(&root)
+            .output()
+            .await
+            .context(format!("Could not run {}", self.name()))?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(super::output_to_string(&output.stderr)));
+        }
+
+        for file in super::output_to_string(&output.stdout)
+            .lines()
+            .filter(|l| !l.is_empty())
+        {
+            let contents = self
+                .file_contents(&root, revision, Path::new(file))
+                .await
+                .context(format!("Failed to read {file} at '{revision}'"))?;
+            let path = target.join(file);
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .context(format!("Failed to create {}", parent.display()))?;
+            }
+            tokio::fs::write(&path, contents)
+                .await
+                .context(format!("Failed to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_since_date_revset() {
+        assert_eq!(
+            since_date_revset("1 week ago"),
+            r#"latest((::@ & committer_date(before:"1 week ago")) | root())"#
+        );
+        assert_eq!(
+            since_date_revset(r#"a"b"#),
+            r#"latest((::@ & committer_date(before:"a\"b")) | root())"#
+        );
+    }
+
+    #[test]
+    fn test_root_file_fileset() {
+        assert_eq!(
+            root_file_fileset(Path::new("src/main.rs")),
+            r#"root-file:"src/main.rs""#
+        );
+        assert_eq!(
+            root_file_fileset(Path::new(r#"a "b".txt"#)),
+            r#"root-file:"a \"b\".txt""#
+        );
+    }
+
+    #[test]
+    fn test_parse_summary() {
+        assert_eq!(
+            parse_summary(
+                "M src/main.rs\nD old.txt\nA new.txt\nR src/{old.rs => new.rs}\nR {a => b}/c.rs\nC src/{ => sub}/d.rs\n"
+            ),
+            [
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("new.txt"),
+                PathBuf::from("src/new.rs"),
+                PathBuf::from("b/c.rs"),
+                PathBuf::from("src/sub/d.rs"),
+            ]
+        );
+    }
 }
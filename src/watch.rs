@@ -0,0 +1,234 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use notify::Watcher as _;
+
+use crate::{arg_parse, config};
+
+/// File names of the temporary files editors keep next to the files being
+/// edited: vim swap files, backup files, emacs lock and auto-save files and
+/// the file vim checks write access with
+const EDITOR_TEMP_FILES: [&str; 7] = ["*.swp", "*.swo", "*.swx", "*~", ".#*", "#*#", "4913"];
+
+fn is_editor_temp_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy()) else {
+        return false;
+    };
+    EDITOR_TEMP_FILES.iter().any(|p| {
+        glob::Pattern::new(p)
+            .expect("Editor temp file patterns are valid")
+            .matches(&name)
+    })
+}
+
+/// `true` for paths not worth running actions on
+fn is_ignored(path: &Path) -> bool {
+    is_editor_temp_file(path)
+        || path.components().any(|c| {
+            beautytips::VCS_DIRECTORIES
+                .iter()
+                .any(|d| c.as_os_str() == *d)
+        })
+}
+
+/// Collects changed files till no file changed for a while
+///
+/// Editors often write a file several times in a row when saving, each
+/// file is only run on once.
+struct Coalescer {
+    debounce: Duration,
+    changed: BTreeSet<PathBuf>,
+    last_change: Option<Instant>,
+}
+
+impl Coalescer {
+    fn new(debounce: Duration) -> Self {
+        Self {
+            debounce,
+            changed: BTreeSet::new(),
+            last_change: None,
+        }
+    }
+
+    fn add(&mut self, path: PathBuf, now: Instant) {
+        self.changed.insert(path);
+        self.last_change = Some(now);
+    }
+
+    /// How long to wait for more changes, `None` to wait till there are any
+    fn timeout(&self, now: Instant) -> Option<Duration> {
+        self.last_change
+            .map(|l| self.debounce.saturating_sub(now.duration_since(l)))
+    }
+
+    /// The changed files, once nothing changed for the debounce period
+    fn take_settled(&mut self, now: Instant) -> Option<Vec<PathBuf>> {
+        if self.timeout(now)? > Duration::ZERO {
+            return None;
+        }
+        self.last_change = None;
+        Some(std::mem::take(&mut self.changed).into_iter().collect())
+    }
+}
+
+/// The files to run on out of the `changed` ones, relative to `root`
+///
+/// `root` is canonical, while the changed paths may be reported below a
+/// symlinked directory (or the other way around, like `/var` on macOS).
+fn files_to_run_on(root: &Path, changed: Vec<PathBuf>) -> anyhow::Result<Vec<PathBuf>> {
+    let files = changed
+        .into_iter()
+        .filter(|f| f.is_file())
+        .filter_map(|f| {
+            let f = f.canonicalize().unwrap_or(f);
+            f.strip_prefix(root).ok().map(Path::to_path_buf)
+        })
+        .collect();
+    beautytips::remove_ignored_files(root, files)
+}
+
+/// Run the actions of `run` on the files changed below the current
+/// directory, whenever no file changed for the `debounce` period
+///
+/// This only returns when watching fails.
+pub fn watch(
+    config: &config::Configuration,
+    run: &arg_parse::RunActions,
+    debounce: Duration,
+    verbosity: u8,
+) -> anyhow::Result<()> {
+    let root = std::env::current_dir()?
+        .canonicalize()
+        .context("Failed to find the current directory")?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to watch for changes")?;
+    watcher
+        .watch(&root, notify::RecursiveMode::Recursive)
+        .context(format!("Failed to watch {}", root.display()))?;
+    eprintln!(
+        "Watching {} for changes, press Ctrl-C to stop",
+        root.display()
+    );
+
+    let mut coalescer = Coalescer::new(debounce);
+    loop {
+        let event = match coalescer.timeout(Instant::now()) {
+            Some(timeout) => match rx.recv_timeout(timeout) {
+                Ok(event) => Some(event),
+                Err(mpsc::RecvTimeoutError::Timeout) => None,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            },
+            None => match rx.recv() {
+                Ok(event) => Some(event),
+                Err(mpsc::RecvError) => break,
+            },
+        };
+        if let Some(event) = event {
+            let event = event.context("Failed to watch for changes")?;
+            if !event.kind.is_access() {
+                for path in event.paths.into_iter().filter(|p| !is_ignored(p)) {
+                    coalescer.add(path, Instant::now());
+                }
+            }
+        }
+
+        let Some(changed) = coalescer.take_settled(Instant::now()) else {
+            continue;
+        };
+        let files = files_to_run_on(&root, changed)?;
+        if files.is_empty() {
+            continue;
+        }
+
+        tracing::debug!("Running on changed files: {files:?}");
+        let run = arg_parse::RunActions {
+            source: beautytips::InputFiles::FileList(files),
+            ..run.clone()
+        };
+        if let Err(e) = crate::run_actions(config, run, verbosity) {
+            eprintln!("Error: {e:#}");
+        }
+        // The run changes into the root directory of the files
+        std::env::set_current_dir(&root)?;
+    }
+    Err(anyhow::anyhow!("Stopped watching for changes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ignored() {
+        for ignored in [
+            "src/.main.rs.swp",
+            "src/main.rs~",
+            "src/.#main.rs",
+            "src/#main.rs#",
+            "src/4913",
+            "/repo/.git/index",
+            "/repo/.jj/repo/op_heads",
+        ] {
+            assert!(is_ignored(Path::new(ignored)), "{ignored}");
+        }
+        for watched in ["src/main.rs", "src/swp.rs", "/repo/.github/ci.yml"] {
+            assert!(!is_ignored(Path::new(watched)), "{watched}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_files_to_run_on() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().join("root");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        let link = directory.path().join("link");
+        std::os::unix::fs::symlink(&root, &link).unwrap();
+        let root = root.canonicalize().unwrap();
+
+        let changed = vec![
+            root.join("src/main.rs"),
+            root.join("src/gone.rs"),
+            link.join("src/main.rs"),
+        ];
+        assert_eq!(
+            files_to_run_on(&root, changed).unwrap(),
+            [PathBuf::from("src/main.rs"), PathBuf::from("src/main.rs")]
+        );
+    }
+
+    #[test]
+    fn test_coalescer() {
+        let start = Instant::now();
+        let mut coalescer = Coalescer::new(Duration::from_millis(500));
+        assert_eq!(coalescer.timeout(start), None);
+        assert_eq!(coalescer.take_settled(start), None);
+
+        coalescer.add(PathBuf::from("b.rs"), start);
+        coalescer.add(PathBuf::from("a.rs"), start + Duration::from_millis(100));
+        coalescer.add(PathBuf::from("b.rs"), start + Duration::from_millis(400));
+        assert_eq!(
+            coalescer.timeout(start + Duration::from_millis(600)),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(
+            coalescer.take_settled(start + Duration::from_millis(600)),
+            None
+        );
+
+        assert_eq!(
+            coalescer.take_settled(start + Duration::from_millis(900)),
+            Some(vec![PathBuf::from("a.rs"), PathBuf::from("b.rs")])
+        );
+        assert_eq!(coalescer.timeout(start + Duration::from_millis(900)), None);
+    }
+}
@@ -0,0 +1,227 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    fmt::Write as _,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::config;
+
+/// The input holding the files to look at
+const FILES_INPUT: &str = "files";
+
+/// The `.gitignore` rule ignoring `relative`, as printed by
+/// `git check-ignore -v`
+fn ignore_rule(root_directory: &Path, relative: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .args(["check-ignore", "-v", "--no-index", "--"])
+        .arg(relative)
+        .current_dir(root_directory)
+        .output()
+        .ok()?;
+    let line = String::from_utf8_lossy(&output.stdout);
+    let (rule, _) = line.trim_end().split_once('\t')?;
+    Some(rule.to_string())
+}
+
+/// Why the file at `path` (relative: `relative`) was not collected from
+/// `source`
+fn not_collected_reason(
+    source: &beautytips::InputFiles,
+    root_directory: &Path,
+    path: &Path,
+    relative: &Path,
+) -> String {
+    if !path.exists() {
+        return "it does not exist".to_string();
+    }
+    if path.is_dir() {
+        return "it is a directory, only files are collected".to_string();
+    }
+    if let Some(rule) = ignore_rule(root_directory, relative) {
+        return format!("it is ignored by {rule}");
+    }
+    match source {
+        beautytips::InputFiles::Vcs(vcs) => {
+            if let Some(excluded) = vcs.excluded_paths.iter().find(|p| relative.starts_with(p)) {
+                format!("it is below the excluded path {}", excluded.display())
+            } else {
+                "the version control system does not report it as changed".to_string()
+            }
+        }
+        beautytips::InputFiles::FileList(_) => "it is not one of the files given".to_string(),
+        beautytips::InputFiles::AllFiles(directory) => {
+            format!("it is not below {}", directory.display())
+        }
+    }
+}
+
+/// `true` if `action` gets the files, either by filtering them or by using
+/// them in its command
+fn uses_files(action: &beautytips::ActionDefinition) -> bool {
+    let placeholder = format!("{{{{{FILES_INPUT}");
+    action.input_filters.inputs().any(|i| i == FILES_INPUT)
+        || action
+            .command
+            .iter()
+            .chain(&action.full_command)
+            .any(|a| a.contains(&placeholder))
+}
+
+/// The start of the file at `path`, as looked at by `content` and `kind`
+/// filters
+fn file_head(path: &Path) -> Vec<u8> {
+    let mut head = Vec::new();
+    if let Ok(file) = std::fs::File::open(path) {
+        let _ = file.take(beautytips::PROBE_SIZE).read_to_end(&mut head);
+    }
+    head
+}
+
+/// Explain whether `action` looks at the file at `path`
+///
+/// Returns `true` if the file passes all filters of the action.
+fn explain_action(
+    result: &mut String,
+    action: &beautytips::ActionDefinition,
+    root_directory: &Path,
+    path: &Path,
+    relative: &Path,
+) -> bool {
+    let _ = writeln!(result, "{}:", action.id);
+    if !action.enabled_when.is_met(root_directory) {
+        let _ = writeln!(
+            result,
+            "  no: it is not enabled here, it needs {}",
+            crate::help::condition_to_string(&action.enabled_when)
+        );
+        return false;
+    }
+
+    let filters = &action.input_filters;
+    if !uses_files(action) {
+        let _ = writeln!(result, "  no: it does not look at files");
+        return false;
+    }
+    let selected = filters.selects(FILES_INPUT, relative);
+    let verdict = if selected { "yes" } else { "no" };
+    match filters.deciding_pattern(FILES_INPUT, relative) {
+        Some(pattern) if pattern.is_negated() => {
+            let _ = writeln!(result, "  {verdict}: it is excluded by '{pattern}'");
+        }
+        Some(pattern) => {
+            let _ = writeln!(result, "  {verdict}: it matches '{pattern}'");
+        }
+        None if filters.patterns(FILES_INPUT).is_empty() => {
+            let _ = writeln!(result, "  {verdict}: the action looks at all files");
+        }
+        None => {
+            let _ = writeln!(
+                result,
+                "  {verdict}: it matches none of {}",
+                filters
+                    .patterns(FILES_INPUT)
+                    .iter()
+                    .map(|p| format!("'{p}'"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+    if !selected {
+        return false;
+    }
+
+    if filters.kind().is_none() && filters.content_patterns().is_empty() {
+        return true;
+    }
+    let head = file_head(path);
+    if let Some(kind) = filters.kind() {
+        let actual = if beautytips::IsBinary::check(&head) {
+            beautytips::FileKind::Binary
+        } else {
+            beautytips::FileKind::Text
+        };
+        if actual != kind {
+            let _ = writeln!(
+                result,
+                "  no: it is a {actual} file, the action looks at {kind} files only"
+            );
+            return false;
+        }
+        let _ = writeln!(result, "  yes: it is a {actual} file");
+    }
+    let patterns = filters.content_patterns();
+    if !patterns.is_empty() {
+        let head = String::from_utf8_lossy(&head);
+        let Some(pattern) = patterns.iter().find(|p| p.matches(&head)) else {
+            let _ = writeln!(
+                result,
+                "  no: its start matches none of the content patterns"
+            );
+            return false;
+        };
+        let _ = writeln!(result, "  yes: its start matches the content '{pattern}'");
+    }
+    true
+}
+
+/// Explain step by step whether the actions selected by `selectors` look at
+/// the file at `path` when collecting files from `source`
+pub fn why(
+    config: &config::Configuration,
+    source: &beautytips::InputFiles,
+    path: &Path,
+    selectors: &config::ActionSelectors,
+) -> anyhow::Result<()> {
+    let actions = config.actions(selectors);
+    if actions.is_empty() {
+        return Err(anyhow::anyhow!("No action matches the selectors"));
+    }
+
+    let current_directory = std::env::current_dir()?;
+    let (root_directory, files) =
+        beautytips::collect_input_files(current_directory.clone(), source.clone())?;
+    let path = current_directory.join(path);
+    let path = path.canonicalize().unwrap_or(path);
+
+    let mut result = String::new();
+    let _ = writeln!(result, "Root directory: {}", root_directory.display());
+    let Ok(relative) = path.strip_prefix(&root_directory).map(PathBuf::from) else {
+        let _ = writeln!(
+            result,
+            "{} is not below the root directory, so it is never looked at",
+            path.display()
+        );
+        print!("{result}");
+        return Ok(());
+    };
+
+    let collected = files.iter().any(|f| root_directory.join(f) == path);
+    if collected {
+        let _ = writeln!(result, "{}: collected", relative.display());
+    } else {
+        let _ = writeln!(
+            result,
+            "{}: not collected, {}",
+            relative.display(),
+            not_collected_reason(source, &root_directory, &path, &relative)
+        );
+    }
+
+    let mut used_by = 0;
+    for action in actions.iter() {
+        if explain_action(&mut result, action, &root_directory, &path, &relative) && collected {
+            used_by += 1;
+        }
+    }
+    let _ = writeln!(
+        result,
+        "\nThe file is looked at by {used_by} of {} action(s)",
+        actions.len()
+    );
+    print!("{result}");
+    Ok(())
+}
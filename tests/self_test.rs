@@ -0,0 +1,26 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2024 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Runs the builtin rule packs on the sample files of `beautytips self-test`
+//! and compares the results to the golden files
+
+#[test]
+fn test_self_test() {
+    let directory = tempfile::tempdir().unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_beautytips"))
+        .arg("--cache-dir")
+        .arg(directory.path().join("cache"))
+        .arg("--state-dir")
+        .arg(directory.path().join("state"))
+        .arg("self-test")
+        .current_dir(directory.path())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "builtin: ok\n");
+}